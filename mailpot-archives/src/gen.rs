@@ -102,6 +102,7 @@ fn run_app() -> std::result::Result<(), Box<dyn std::error::Error>> {
         let post_policy = db.list_post_policy(list.pk)?;
         let months = db.months(list.pk)?;
         let posts = db.list_posts(list.pk, None)?;
+        let redaction = db.list_redaction(list.pk)?;
         let mut hist = months
             .iter()
             .map(|m| (m.to_string(), [0usize; 31]))
@@ -127,9 +128,9 @@ fn run_app() -> std::result::Result<(), Box<dyn std::error::Error>> {
                         pk => post.pk,
                         list => post.list,
                         subject => subject_ref,
-                        address=> post.address,
+                        address=> redaction.text(&post.address),
                         message_id => post.message_id.as_str().strip_carets(),
-                        message => post.message,
+                        message => redaction.message(&post.message),
                         timestamp => post.timestamp,
                         datetime => post.datetime,
                     root_prefix => &root_url_prefix,
@@ -186,6 +187,7 @@ fn run_app() -> std::result::Result<(), Box<dyn std::error::Error>> {
                 .map_err(|err| format!("Could not parse mail {}: {err}", post.message_id))?;
             let body = envelope.body_bytes(post.message.as_slice());
             let body_text = body.text();
+            let tombstone = db.post_tombstone(list.pk, &post.message_id)?;
             let subject = envelope.subject();
             let mut subject_ref = subject.trim();
             if subject_ref.starts_with('[')
@@ -213,10 +215,11 @@ fn run_app() -> std::result::Result<(), Box<dyn std::error::Error>> {
                 list => &list,
                 post => &post,
                 posts => &posts_ctx,
-                body => &body_text,
-                from => &envelope.field_from_to_string(),
+                body => redaction.body(&body_text),
+                tombstone => tombstone,
+                from => redaction.text(&envelope.field_from_to_string()),
                 date => &envelope.date_as_str(),
-                to => &envelope.field_to_to_string(),
+                to => redaction.text(&envelope.field_to_to_string()),
                 subject => &envelope.subject(),
                 trimmed_subject => subject_ref,
                 in_reply_to => &envelope.in_reply_to_display().map(|r| r.to_string().as_str().strip_carets().to_string()),
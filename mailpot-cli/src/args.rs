@@ -99,15 +99,40 @@ pub enum Command {
     /// Post message from STDIN to list.
     Post {
         /// Show e-mail processing result without actually consuming it.
+        ///
+        /// Prints the effects of each message filter, the final action and
+        /// the queue entries that would have been created.
         #[arg(long)]
         dry_run: bool,
+        /// Print dry run report in JSON format.
+        #[arg(long, requires = "dry_run")]
+        json: bool,
     },
     /// Flush outgoing e-mail queue.
+    ///
+    /// Scheduled posts that are due are released first, subscriptions
+    /// whose confirmation expired are removed, and subscriptions whose
+    /// re-confirmation expired are disabled.
     FlushQueue {
         /// Show e-mail processing result without actually consuming it.
         #[arg(long)]
         dry_run: bool,
     },
+    /// Show the processing audit trail of posts.
+    ///
+    /// Lists filter decisions, final actions, scheduled jobs and delivery
+    /// outcomes, oldest first.
+    PostEvents {
+        /// Only show events of this list (List-ID or primary key value).
+        #[arg(long)]
+        list: Option<String>,
+        /// Only show events of the message with this Message-ID.
+        #[arg(long)]
+        message_id: Option<String>,
+        /// Show only the most recent events.
+        #[arg(long)]
+        limit: Option<usize>,
+    },
     /// Processed mail is stored in queues.
     Queue {
         #[arg(long, value_parser = QueueValueParser)]
@@ -115,6 +140,27 @@ pub enum Command {
         #[command(subcommand)]
         cmd: QueueCommand,
     },
+    /// Delete posts and queue entries older than their retention period, and
+    /// stored blobs no message refers to any more.
+    ///
+    /// Post retention is set per list with `list <LIST_ID> update
+    /// --post-retention-days`, and queue retention in the `queue_retention`
+    /// table of the configuration file.
+    Gc {
+        /// Only show what would be deleted.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Personal data of an e-mail address.
+    Privacy {
+        #[command(subcommand)]
+        cmd: PrivacyCommand,
+    },
+    /// Yearly archive databases of old posts.
+    Archive {
+        #[command(subcommand)]
+        cmd: ArchiveCommand,
+    },
     /// Import a maildir folder into an existing list.
     ImportMaildir {
         /// List-ID or primary key value.
@@ -221,6 +267,9 @@ pub enum Command {
         /// Remove carets from message ID column.
         #[arg(long, default_value = "false")]
         fix_message_ids: bool,
+        /// Remove posts whose list does not exist.
+        #[arg(long, default_value = "false")]
+        remove_orphan_posts: bool,
     },
 }
 
@@ -282,6 +331,41 @@ pub enum QueueCommand {
     },
 }
 
+#[derive(Debug, Subcommand)]
+pub enum PrivacyCommand {
+    /// Print everything stored about an address in JSON format.
+    ///
+    /// Includes its account, list subscriptions, subscription requests,
+    /// list ownerships, bounces, authored posts and queue entries.
+    Export {
+        /// E-mail address.
+        address: String,
+    },
+    /// Erase an address.
+    ///
+    /// Its account, list subscriptions, subscription requests, list
+    /// ownerships, bounces and queue entries are deleted, and it is replaced
+    /// with a random pseudonym in the headers of stored posts.
+    Erase {
+        /// E-mail address.
+        address: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ArchiveCommand {
+    /// Move posts dated before a year to per-year archive databases in
+    /// `data_path`.
+    Rotate {
+        /// Move posts dated before the start of this year.
+        #[arg(long, value_name = "YYYY")]
+        before: i32,
+        /// Only show how many posts would be moved.
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
 /// Subscription options.
 #[derive(Debug, Args)]
 pub struct SubscriptionOptions {
@@ -426,6 +510,140 @@ pub enum ListCommand {
         /// List owner primary key.
         pk: i64,
     },
+    /// List allow and deny entries of list, including instance-wide entries.
+    AccessEntries,
+    /// Add an allow or deny entry for posting and/or subscribing.
+    ///
+    /// Instance-wide deny entries always win. Otherwise list entries are
+    /// considered before instance-wide entries, and deny entries take
+    /// precedence over allow entries. Allowed addresses bypass the post
+    /// policy and subscription approval.
+    AddAccessEntry {
+        /// Address, glob (e.g. '*@spam.example.com') or domain to match.
+        pattern: String,
+        /// Deny matching addresses instead of allowing them.
+        #[arg(long)]
+        deny: bool,
+        /// How to match the pattern.
+        #[arg(long = "match", default_value = "exact", value_parser = clap::builder::PossibleValuesParser::new(mailpot::models::AddressMatch::possible_values()))]
+        match_type: String,
+        /// Apply entry only to posting.
+        #[arg(long, conflicts_with = "subscribe_only")]
+        post_only: bool,
+        /// Apply entry only to subscribing.
+        #[arg(long)]
+        subscribe_only: bool,
+        /// Apply entry to every list instead of this list only.
+        #[arg(long)]
+        instance_wide: bool,
+        /// Entry comment.
+        #[arg(long)]
+        comment: Option<String>,
+    },
+    /// Remove an allow or deny entry.
+    RemoveAccessEntry {
+        #[arg(long)]
+        /// Access entry primary key.
+        pk: i64,
+        /// Entry is instance-wide.
+        #[arg(long)]
+        instance_wide: bool,
+    },
+    /// Print the fingerprint and the public key of an encrypted list.
+    OpenpgpKey,
+    /// Make the list an encrypted list, by generating a new OpenPGP key or
+    /// importing an existing secret key. An existing key is replaced.
+    ///
+    /// Posts must be PGP/MIME encrypted to the list key, and they are
+    /// re-encrypted to the public key of each subscriber's account.
+    /// Subscribers without a key are skipped and the list owners are
+    /// notified.
+    SetOpenpgpKey {
+        /// Import an ASCII-armored, unprotected secret key from this path
+        /// instead of generating a new one.
+        #[arg(long, value_parser)]
+        secret_key_path: Option<PathBuf>,
+    },
+    /// Remove the OpenPGP key of the list, making it a plain list.
+    RemoveOpenpgpKey,
+    /// List the lists included by this umbrella list.
+    IncludedLists,
+    /// Include another list in this list, making it an umbrella list.
+    ///
+    /// Posts to this list are also sent to the subscribers of the included
+    /// list, and of the lists it includes in turn, once per address.
+    IncludeList {
+        /// Id or pk of the list to include.
+        list_id: String,
+        /// Let the owners and subscribers of the included list post to this
+        /// list as if they were its owners and subscribers respectively.
+        #[arg(long)]
+        inherit_post_rights: bool,
+    },
+    /// Stop including a list in this list.
+    RemoveIncludedList {
+        /// Id or pk of the included list.
+        list_id: String,
+    },
+    /// Schedule a post read from stdin for later delivery.
+    ///
+    /// Only list owners can schedule posts, and only with this command or the
+    /// web compose page; the `X-Mailpot-Send-At` header of posts received by
+    /// e-mail is ignored.
+    SchedulePost {
+        /// Release time, as an RFC 2822 or RFC 3339 date or a unix
+        /// timestamp.
+        #[arg(long)]
+        send_at: String,
+    },
+    /// List the posts scheduled for later delivery, in release order.
+    ScheduledPosts,
+    /// Cancel a scheduled post, discarding it.
+    CancelScheduledPost {
+        /// Queue entry primary key of the scheduled post.
+        pk: i64,
+    },
+    /// Remove a post from the archive.
+    ///
+    /// The post's message is replaced by a placeholder that keeps its
+    /// threading headers, so that replies stay in their thread, and archives
+    /// show that it was removed by the moderators and why.
+    RemovePost {
+        /// `Message-ID` of the post.
+        message_id: String,
+        /// Why the post is removed, shown in the archives.
+        #[arg(long)]
+        reason: String,
+        /// Who removed the post. Defaults to the `USER` environment variable.
+        #[arg(long)]
+        removed_by: Option<String>,
+    },
+    /// List the posts removed from the archive.
+    RemovedPosts,
+    /// Mail a re-confirmation request to stale subscriptions.
+    ///
+    /// Subscriptions that were last confirmed more than --older-than months
+    /// ago, or that have not posted to the list for --inactive-for months,
+    /// receive a request with a confirmation token. Those that don't confirm
+    /// within the grace period are disabled by `flush-queue`. The `health`
+    /// command shows the progress of the requests.
+    Reconfirm {
+        /// Select subscriptions last confirmed more than this many months
+        /// (of 30 days) ago.
+        #[arg(long, required_unless_present = "inactive_for")]
+        older_than: Option<u64>,
+        /// Select subscriptions that have not posted to the list for this
+        /// many months (of 30 days).
+        #[arg(long)]
+        inactive_for: Option<u64>,
+        /// Days to wait for a confirmation before disabling a subscription.
+        /// Defaults to 14.
+        #[arg(long)]
+        grace_days: Option<u64>,
+        /// Only show the subscriptions that would receive a request.
+        #[arg(long)]
+        dry_run: bool,
+    },
     /// Alias for update-subscription --enabled true.
     EnableSubscription {
         /// Subscription address.
@@ -453,6 +671,11 @@ pub enum ListCommand {
         /// New list archive URL.
         #[arg(long)]
         archive_url: Option<String>,
+        /// New list topics, separated by commas.
+        ///
+        /// Subscribers can choose to receive only posts about some of them.
+        #[arg(long, value_delimiter = ',')]
+        topics: Option<Vec<String>>,
         /// New owner address local part.
         /// If empty, it defaults to '+owner'.
         #[arg(long)]
@@ -463,8 +686,8 @@ pub enum ListCommand {
         request_local_part: Option<String>,
         /// Require verification of e-mails for new subscriptions.
         ///
-        /// Subscriptions that are initiated from the subscription's address are
-        /// verified automatically.
+        /// Subscriptions requested by e-mail are confirmed by replying to a
+        /// confirmation request, or with its web link.
         #[arg(long)]
         verify: Option<bool>,
         /// Public visibility of list.
@@ -479,11 +702,49 @@ pub enum ListCommand {
         /// but e-mails and requests to it won't work.
         #[arg(long)]
         enabled: Option<bool>,
+        /// Require confirmation of unsubscription requests sent by e-mail.
+        ///
+        /// The subscription is only removed after a reply to the confirmation
+        /// request, or a visit to its web link, so that forged requests can't
+        /// remove it.
+        #[arg(long)]
+        confirm_unsubscription: Option<bool>,
+        /// Delete posts older than this many days with `mpot gc`.
+        ///
+        /// If zero, posts are kept forever.
+        #[arg(long)]
+        post_retention_days: Option<u64>,
+        /// Show, obfuscate or hide e-mail addresses in the archives.
+        ///
+        /// Addresses of subscribers who chose to hide them are always hidden.
+        #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(mailpot::models::ArchiveAddresses::possible_values()))]
+        archive_addresses: Option<String>,
+        /// Offer raw messages for download in the archives.
+        ///
+        /// Their addresses are redacted like the rest of the archives.
+        #[arg(long)]
+        archive_raw_download: Option<bool>,
     },
     /// Show mailing list health status.
     Health,
     /// Show mailing list info.
     Info,
+    /// Search the list's posts.
+    ///
+    /// Posts are matched by their subject, sender and plain text body, and
+    /// must contain all the words of the query. A word ending with `*` matches
+    /// any word it is a prefix of.
+    Search {
+        /// Words to search for.
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// Number of results to skip.
+        #[arg(long, default_value = "0")]
+        offset: usize,
+        /// Maximum number of results.
+        #[arg(long, default_value = "50")]
+        limit: usize,
+    },
     /// Import members in a local list from a remote mailman3 REST API instance.
     ///
     /// To find the id of the remote list, you can check URL/lists.
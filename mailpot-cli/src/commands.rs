@@ -220,6 +220,15 @@ pub fn list(db: &mut Connection, list_id: &str, cmd: ListCommand, quiet: bool) -
             } else {
                 println!("\tList has no subscription policy: you should add one.");
             }
+            let reconfirmation = db
+                .list_reconfirmation_status(list.pk)
+                .context("Could not retrieve list re-confirmation status.")?;
+            if reconfirmation != Default::default() {
+                println!(
+                    "\tList re-confirmation requests: {} pending, {} confirmed, {} disabled.",
+                    reconfirmation.pending, reconfirmation.confirmed, reconfirmation.disabled
+                );
+            }
         }
         Info => {
             println!("{} info:", list);
@@ -260,6 +269,36 @@ pub fn list(db: &mut Connection, list_id: &str, cmd: ListCommand, quiet: bool) -
             } else {
                 println!("Subscription policy: None");
             }
+            if let Some(days) = db
+                .list_post_retention(list.pk)
+                .context("Could not retrieve list post retention.")?
+            {
+                println!("Post retention: {days} days");
+            } else {
+                println!("Post retention: None");
+            }
+            println!(
+                "Archive addresses: {}",
+                db.list_archive_addresses(list.pk)
+                    .context("Could not retrieve list archive addresses setting.")?
+            );
+            println!(
+                "Archive raw downloads: {}",
+                if db
+                    .list_archive_raw_download(list.pk)
+                    .context("Could not retrieve list archive raw download setting.")?
+                {
+                    "yes"
+                } else {
+                    "no"
+                }
+            );
+            if let Some(key) = db
+                .list_openpgp_key(list.pk)
+                .context("Could not retrieve list OpenPGP key.")?
+            {
+                println!("Encrypted list, OpenPGP key: {}", key.fingerprint);
+            }
         }
         UpdateSubscription {
             address,
@@ -359,6 +398,287 @@ pub fn list(db: &mut Connection, list_id: &str, cmd: ListCommand, quiet: bool) -
             db.remove_list_owner(list.pk, pk)?;
             println!("Removed list owner with pk = {}", pk);
         }
+        AccessEntries => {
+            let entries = db.list_access_entries(list.pk)?;
+            if entries.is_empty() {
+                if !quiet {
+                    println!("No access entries found.");
+                }
+            } else {
+                if !quiet {
+                    println!("Access entries of list {}", list.id);
+                }
+                for e in entries {
+                    println!("- {}", &e);
+                }
+            }
+        }
+        AddAccessEntry {
+            pattern,
+            deny,
+            match_type,
+            post_only,
+            subscribe_only,
+            instance_wide,
+            comment,
+        } => {
+            let entry = AccessEntry {
+                pk: 0,
+                list: if instance_wide { None } else { Some(list.pk) },
+                kind: if deny {
+                    AccessKind::Deny
+                } else {
+                    AccessKind::Allow
+                },
+                match_type: match_type.parse()?,
+                pattern,
+                post: !subscribe_only,
+                subscribe: !post_only,
+                comment,
+            };
+            let new_val = db.add_access_entry(entry)?;
+            println!("Added new access entry {}", new_val);
+        }
+        RemoveAccessEntry { pk, instance_wide } => {
+            db.remove_access_entry(if instance_wide { None } else { Some(list.pk) }, pk)?;
+            println!("Removed access entry with pk = {}", pk);
+        }
+        OpenpgpKey => match db.list_openpgp_key(list.pk)? {
+            Some(key) => {
+                let secret_key = mailpot::openpgp::parse_secret_key(&key.secret_key)?;
+                println!("Fingerprint: {}", key.fingerprint);
+                println!("{}", mailpot::openpgp::armored_public_key(&secret_key)?);
+            }
+            None => {
+                if !quiet {
+                    println!("List {} is not an encrypted list.", list.id);
+                }
+            }
+        },
+        SetOpenpgpKey { secret_key_path } => {
+            let key = if let Some(path) = secret_key_path {
+                let secret_key = std::fs::read_to_string(&path).with_context(|| {
+                    format!("Could not read secret key from {}.", path.display())
+                })?;
+                db.set_list_openpgp_key(list.pk, &secret_key)?
+            } else {
+                db.generate_list_openpgp_key(list.pk)?
+            };
+            println!("Set OpenPGP key {}", key.fingerprint);
+        }
+        RemoveOpenpgpKey => {
+            db.remove_list_openpgp_key(list.pk)?;
+            println!("Removed OpenPGP key of list {}", list.id);
+        }
+        IncludedLists => {
+            let inclusions = db.list_inclusions(list.pk)?;
+            if inclusions.is_empty() {
+                if !quiet {
+                    println!("No included lists found.");
+                }
+            } else {
+                if !quiet {
+                    println!("Lists included by list {}", list.id);
+                }
+                for i in inclusions {
+                    let child = db.list(i.child)?.map(|l| l.id.clone()).unwrap_or_default();
+                    println!(
+                        "- {}{}",
+                        child,
+                        if i.inherit_post_rights {
+                            " (inherits post rights)"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            }
+        }
+        IncludeList {
+            list_id,
+            inherit_post_rights,
+        } => {
+            let child = match list!(db, list_id) {
+                Some(v) => v,
+                None => {
+                    return Err(format!("No list with id or pk {} was found", list_id).into());
+                }
+            };
+            let new_val = db.add_list_inclusion(ListInclusion {
+                pk: 0,
+                parent: list.pk,
+                child: child.pk,
+                inherit_post_rights,
+            })?;
+            println!("Added new list inclusion {}", new_val);
+        }
+        RemoveIncludedList { list_id } => {
+            let child = match list!(db, list_id) {
+                Some(v) => v,
+                None => {
+                    return Err(format!("No list with id or pk {} was found", list_id).into());
+                }
+            };
+            db.remove_list_inclusion(list.pk, child.pk)?;
+            println!("List {} no longer includes list {}", list.id, child.id);
+        }
+        SchedulePost { send_at } => {
+            let send_at = mailpot::scheduled::parse_send_at(&send_at)?;
+            let mut input = String::new();
+            std::io::stdin()
+                .read_to_string(&mut input)
+                .context("Could not read from stdin")?;
+            let env = Envelope::from_bytes(input.as_bytes(), None)?;
+            if !db.list_owners(list.pk)?.iter().any(|o| {
+                env.from()
+                    .iter()
+                    .any(|f| o.address.eq_ignore_ascii_case(&f.get_email()))
+            }) {
+                return Err(format!("Post is not from an owner of list {}.", list.id).into());
+            }
+            let entry = db.schedule_post(&list, &env, input.as_bytes(), send_at)?;
+            if !quiet {
+                println!("Scheduled post {} ({})", entry.pk(), entry.message_id);
+            }
+        }
+        ScheduledPosts => {
+            let entries = db.list_scheduled_posts(list.pk)?;
+            if entries.is_empty() {
+                if !quiet {
+                    println!("No scheduled posts found.");
+                }
+            } else {
+                if !quiet {
+                    println!("Scheduled posts of list {}", list.id);
+                }
+                for e in entries {
+                    println!(
+                        "- {} send at {} from {} subject {:?} message-id {}",
+                        e.pk(),
+                        e.send_at.unwrap_or_default(),
+                        e.from_address,
+                        e.subject,
+                        e.message_id
+                    );
+                }
+            }
+        }
+        CancelScheduledPost { pk } => {
+            let entry = db.cancel_scheduled_post(list.pk, pk)?;
+            if !quiet {
+                println!("Cancelled scheduled post {} ({})", pk, entry.message_id);
+            }
+        }
+        RemovePost {
+            message_id,
+            reason,
+            removed_by,
+        } => {
+            let mut input = String::new();
+            if !quiet {
+                loop {
+                    println!(
+                        "Are you sure you want to remove post {} from the archive of list {}? \
+                         [Yy/n]",
+                        message_id, list
+                    );
+                    input.clear();
+                    std::io::stdin().read_line(&mut input)?;
+                    if input.trim() == "Y" || input.trim() == "y" || input.trim() == "" {
+                        break;
+                    } else if input.trim() == "n" {
+                        return Ok(());
+                    }
+                }
+            }
+            let removed_by = removed_by
+                .or_else(|| std::env::var("USER").ok())
+                .unwrap_or_else(|| "administrator".to_string());
+            let tombstone = db.remove_post(list.pk, &message_id, &reason, &removed_by)?;
+            if !quiet {
+                println!("Removed post {}", tombstone);
+            }
+        }
+        RemovedPosts => {
+            let tombstones = db.list_post_tombstones(list.pk)?;
+            if tombstones.is_empty() {
+                if !quiet {
+                    println!("No removed posts found.");
+                }
+            } else {
+                if !quiet {
+                    println!("Removed posts of list {}", list.id);
+                }
+                for t in tombstones {
+                    println!("- {} {}", t.datetime, &t);
+                }
+            }
+        }
+        Reconfirm {
+            older_than,
+            inactive_for,
+            grace_days,
+            dry_run,
+        } => {
+            const MONTH: u64 = 30 * 24 * 60 * 60;
+            let now = mailpot::chrono::offset::Utc::now().timestamp() as u64;
+            let subscriptions = db.stale_subscriptions(
+                list.pk,
+                older_than.map(|m| now.saturating_sub(m * MONTH)),
+                inactive_for.map(|m| now.saturating_sub(m * MONTH)),
+            )?;
+            if dry_run {
+                for s in &subscriptions {
+                    println!("- {}", s.address);
+                }
+            } else {
+                let expires = now
+                    + grace_days
+                        .map_or(mailpot::reconfirmations::RECONFIRMATION_GRACE_PERIOD, |d| {
+                            d * 24 * 60 * 60
+                        });
+                for s in &subscriptions {
+                    db.send_reconfirmation_request(&list, s, expires)?;
+                }
+            }
+            if !quiet {
+                println!(
+                    "{} {} re-confirmation requests.",
+                    if dry_run { "Would send" } else { "Sent" },
+                    subscriptions.len()
+                );
+            }
+        }
+        Search {
+            query,
+            offset,
+            limit,
+        } => {
+            let results = db.search_posts(
+                list.pk,
+                &query.join(" "),
+                mailpot::search::Paging { offset, limit },
+            )?;
+            for result in &results {
+                println!(
+                    "- {} {} {}\n\t{}",
+                    result.post.message_id,
+                    result.post.datetime,
+                    result.post.address,
+                    result.subject
+                );
+                if !result.snippet.is_empty() {
+                    println!("\t{}", result.snippet.replace('\n', " "));
+                }
+            }
+            if !quiet {
+                println!(
+                    "{} result{}.",
+                    results.len(),
+                    if results.len() == 1 { "" } else { "s" }
+                );
+            }
+        }
         EnableSubscription { address } => {
             let changeset = ListSubscriptionChangeset {
                 list: list.pk,
@@ -397,11 +717,16 @@ pub fn list(db: &mut Connection, list_id: &str, cmd: ListCommand, quiet: bool) -
             address,
             description,
             archive_url,
+            topics,
             owner_local_part,
             request_local_part,
             verify,
             hidden,
             enabled,
+            confirm_unsubscription,
+            post_retention_days,
+            archive_addresses,
+            archive_raw_download,
         } => {
             let description = string_opts!(description);
             let archive_url = string_opts!(archive_url);
@@ -414,11 +739,22 @@ pub fn list(db: &mut Connection, list_id: &str, cmd: ListCommand, quiet: bool) -
                 address,
                 description,
                 archive_url,
+                topics,
                 owner_local_part,
                 request_local_part,
                 verify,
                 hidden,
                 enabled,
+                confirm_unsubscription,
+                post_retention_days: post_retention_days.map(|days| {
+                    if days == 0 {
+                        None
+                    } else {
+                        Some(days)
+                    }
+                }),
+                archive_addresses: archive_addresses.map(|s| s.parse()).transpose()?,
+                archive_raw_download,
             };
             db.update_list(changeset)?;
         }
@@ -637,7 +973,7 @@ pub fn create_list(
     Ok(())
 }
 
-pub fn post(db: &mut Connection, dry_run: bool, debug: bool) -> Result<()> {
+pub fn post(db: &mut Connection, dry_run: bool, json: bool, debug: bool) -> Result<()> {
     if debug {
         println!("Post dry_run = {:?}", dry_run);
     }
@@ -654,12 +990,29 @@ pub fn post(db: &mut Connection, dry_run: bool, debug: bool) -> Result<()> {
             if debug {
                 eprintln!("Parsed envelope is:\n{:?}", &env);
             }
-            tx.post(&env, input.as_bytes(), dry_run)?;
+            if dry_run {
+                let trace = tx.post_dry_run(&env, input.as_bytes())?;
+                if json {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&trace)
+                            .context("Could not serialize report")?
+                    );
+                } else {
+                    print!("{trace}");
+                }
+            } else {
+                tx.post(&env, input.as_bytes(), dry_run)?;
+            }
         }
         Err(err) if input.trim().is_empty() => {
             eprintln!("Empty input, abort.");
             return Err(err.into());
         }
+        Err(err) if dry_run => {
+            eprintln!("Could not parse message: {}", err);
+            return Err(err.into());
+        }
         Err(err) => {
             eprintln!("Could not parse message: {}", err);
             let p = tx.conf().save_message(input)?;
@@ -674,12 +1027,40 @@ pub fn flush_queue(db: &mut Connection, dry_run: bool, verbose: u8, debug: bool)
     let tx = db
         .transaction(TransactionBehavior::Exclusive)
         .context("Could not open Exclusive transaction in database.")?;
+    let released = if dry_run {
+        vec![]
+    } else {
+        tx.release_scheduled_posts(mailpot::chrono::offset::Utc::now().timestamp() as u64)?
+    };
+    if (verbose > 0 || debug) && !released.is_empty() {
+        println!("Released {} scheduled posts.", released.len());
+    }
+    let purged = if dry_run {
+        vec![]
+    } else {
+        tx.purge_unconfirmed_subscriptions(mailpot::chrono::offset::Utc::now().timestamp() as u64)?
+    };
+    if (verbose > 0 || debug) && !purged.is_empty() {
+        println!("Removed {} unconfirmed subscriptions.", purged.len());
+    }
+    let disabled = if dry_run {
+        vec![]
+    } else {
+        tx.disable_unconfirmed_subscriptions(mailpot::chrono::offset::Utc::now().timestamp() as u64)?
+    };
+    if (verbose > 0 || debug) && !disabled.is_empty() {
+        println!(
+            "Disabled {} subscriptions without re-confirmation.",
+            disabled.len()
+        );
+    }
     let messages = tx.delete_from_queue(mailpot::queue::Queue::Out, vec![])?;
     if verbose > 0 || debug {
         println!("Queue out has {} messages.", messages.len());
     }
 
     let mut failures = Vec::with_capacity(messages.len());
+    let mut delivered = Vec::with_capacity(messages.len());
 
     let send_mail = tx.conf().send_mail.clone();
     match send_mail {
@@ -741,28 +1122,50 @@ pub fn flush_queue(db: &mut Connection, dry_run: bool, verbose: u8, debug: bool)
                         eprintln!("Message {msg:?} failed with: {err}.");
                     }
                     failures.push((err, msg));
-                } else if verbose > 0 || debug {
-                    eprintln!("Submitted message {}", msg.message_id);
+                } else {
+                    if verbose > 0 || debug {
+                        eprintln!("Submitted message {}", msg.message_id);
+                    }
+                    delivered.push(msg);
                 }
             }
         }
         mailpot::SendMail::Smtp(_) => {
             let conn_future = tx.new_smtp_connection()?;
-            failures = smol::future::block_on(smol::spawn(async move {
+            (failures, delivered) = smol::future::block_on(smol::spawn(async move {
                 let mut conn = conn_future.await?;
                 for msg in messages {
                     if let Err(err) = Connection::submit(&mut conn, &msg, dry_run).await {
                         failures.push((err, msg));
+                    } else {
+                        delivered.push(msg);
                     }
                 }
-                Ok::<_, Error>(failures)
+                Ok::<_, Error>((failures, delivered))
             }))?;
         }
     }
 
+    for msg in delivered {
+        tx.insert_post_event(
+            msg.list,
+            &msg.message_id,
+            PostEventKind::Delivery,
+            "delivered",
+            Some(&msg.to_addresses),
+        )?;
+    }
+
     for (err, mut msg) in failures {
         log::error!("Message {msg:?} failed with: {err}. Inserting to Deferred queue.");
 
+        tx.insert_post_event(
+            msg.list,
+            &msg.message_id,
+            PostEventKind::Delivery,
+            "deferred",
+            Some(&format!("{}: {err}", msg.to_addresses)),
+        )?;
         msg.queue = mailpot::queue::Queue::Deferred;
         tx.insert_to_queue(msg)?;
     }
@@ -773,6 +1176,35 @@ pub fn flush_queue(db: &mut Connection, dry_run: bool, verbose: u8, debug: bool)
     Ok(())
 }
 
+pub fn post_events(
+    db: &mut Connection,
+    list_id: Option<String>,
+    message_id: Option<String>,
+    limit: Option<usize>,
+    quiet: bool,
+) -> Result<()> {
+    let list_pk = match list_id {
+        Some(list_id) => match list!(db, list_id) {
+            Some(v) => Some(v.pk),
+            None => {
+                return Err(format!("No list with id or pk {} was found", list_id).into());
+            }
+        },
+        None => None,
+    };
+    let events = db.post_events(list_pk, message_id.as_deref(), limit)?;
+    if events.is_empty() {
+        if !quiet {
+            println!("No post events found.");
+        }
+    } else {
+        for e in events {
+            println!("- {}", e);
+        }
+    }
+    Ok(())
+}
+
 pub fn queue_(db: &mut Connection, queue: Queue, cmd: QueueCommand, quiet: bool) -> Result<()> {
     match cmd {
         QueueCommand::List => {
@@ -830,6 +1262,118 @@ pub fn queue_(db: &mut Connection, queue: Queue, cmd: QueueCommand, quiet: bool)
     Ok(())
 }
 
+pub fn gc(db: &mut Connection, dry_run: bool, quiet: bool) -> Result<()> {
+    let report = db.gc(
+        mailpot::chrono::offset::Utc::now().timestamp() as u64,
+        dry_run,
+    )?;
+    if report.is_empty() {
+        if !quiet {
+            println!("Nothing to delete.");
+        }
+        return Ok(());
+    }
+    let prefix = if dry_run { "[dry run] " } else { "" };
+    for posts in report.posts {
+        println!(
+            "{prefix}list {}: {} post{}",
+            posts.list_id,
+            posts.posts,
+            if posts.posts == 1 { "" } else { "s" }
+        );
+    }
+    for queue in report.queues {
+        println!(
+            "{prefix}queue {}: {} entr{}",
+            queue.queue,
+            queue.entries,
+            if queue.entries == 1 { "y" } else { "ies" }
+        );
+    }
+    if report.blobs > 0 {
+        println!(
+            "{prefix}{} blob{}",
+            report.blobs,
+            if report.blobs == 1 { "" } else { "s" }
+        );
+    }
+    Ok(())
+}
+
+pub fn privacy(db: &mut Connection, cmd: PrivacyCommand, quiet: bool) -> Result<()> {
+    match cmd {
+        PrivacyCommand::Export { address } => {
+            let data = db.personal_data(&address)?;
+            let mut stdout = std::io::stdout();
+            serde_json::to_writer_pretty(&mut stdout, &data)?;
+            writeln!(stdout)?;
+        }
+        PrivacyCommand::Erase { address } => {
+            let mut input = String::new();
+            if !quiet {
+                loop {
+                    println!(
+                        "Are you sure you want to erase all personal data of {}? [Yy/n]",
+                        address
+                    );
+                    input.clear();
+                    std::io::stdin().read_line(&mut input)?;
+                    if input.trim() == "Y" || input.trim() == "y" || input.trim() == "" {
+                        break;
+                    } else if input.trim() == "n" {
+                        return Ok(());
+                    }
+                }
+            }
+            let report = db.erase_personal_data(&address)?;
+            if !quiet {
+                println!("Replaced with {} in stored posts.", report.pseudonym);
+                println!(
+                    "Deleted account: {}",
+                    if report.account { "yes" } else { "no" }
+                );
+                println!("Deleted subscriptions: {}", report.subscriptions);
+                println!(
+                    "Deleted subscription requests: {}",
+                    report.candidate_subscriptions
+                );
+                println!("Deleted list ownerships: {}", report.list_ownerships);
+                println!("Deleted bounces: {}", report.bounces);
+                println!("Deleted queue entries: {}", report.queue_entries);
+                println!("Pseudonymised posts: {}", report.posts);
+                println!("Pseudonymised post events: {}", report.post_events);
+                println!("Deleted blobs: {}", report.blobs);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn archive(db: &mut Connection, cmd: ArchiveCommand, quiet: bool) -> Result<()> {
+    match cmd {
+        ArchiveCommand::Rotate { before, dry_run } => {
+            let rotations = db.rotate_archives(before, dry_run)?;
+            if rotations.is_empty() {
+                if !quiet {
+                    println!("No posts dated before {before}.");
+                }
+                return Ok(());
+            }
+            for rotation in rotations {
+                println!(
+                    "{}{}: {} post{} to {}",
+                    if dry_run { "[dry run] " } else { "" },
+                    rotation.year,
+                    rotation.posts,
+                    if rotation.posts == 1 { "" } else { "s" },
+                    db.archive_path(rotation.year).display()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
 // [ref:TODO]: verify config works with an integration test
 fn make_config(path: &Path) -> mailpot::melib::maildir::Configuration {
     mailpot::melib::maildir::Configuration {
@@ -1105,6 +1649,7 @@ pub struct RepairConfig {
     pub remove_accepted_subscription_requests: bool,
     pub warn_list_no_owner: bool,
     pub fix_message_ids: bool,
+    pub remove_orphan_posts: bool,
 }
 
 pub fn repair(db: &mut Connection, fix: bool, all: bool, mut config: RepairConfig) -> Result<()> {
@@ -1116,13 +1661,15 @@ pub fn repair(db: &mut Connection, fix: bool, all: bool, mut config: RepairConfi
         config.remove_accepted_subscription_requests = true;
         config.warn_list_no_owner = true;
         config.fix_message_ids = true;
+        config.remove_orphan_posts = true;
     }
 
     if !(config.datetime_header_value
         | config.remove_empty_accounts
         | config.remove_accepted_subscription_requests
         | config.warn_list_no_owner
-        | config.fix_message_ids)
+        | config.fix_message_ids
+        | config.remove_orphan_posts)
     {
         return Err("No lints selected: specify them with flag arguments. See --help".into());
     }
@@ -1157,6 +1704,11 @@ pub fn repair(db: &mut Connection, fix: bool, all: bool, mut config: RepairConfi
             config.fix_message_ids,
             fix_message_ids_lint as _,
         ),
+        (
+            "remove_orphan_posts",
+            config.remove_orphan_posts,
+            remove_orphan_posts_lint as _,
+        ),
     ] {
         if flag {
             lint_fn(db, dry_run).with_context(|| format!("Lint {name} failed."))?;
@@ -312,3 +312,51 @@ pub fn fix_message_ids_lint(db: &mut Connection, dry_run: bool) -> Result<()> {
     }
     Ok(())
 }
+
+pub fn remove_orphan_posts_lint(db: &mut Connection, dry_run: bool) -> Result<()> {
+    let mut schemas = vec!["main".to_string()];
+    schemas.extend(
+        db.archives()?
+            .into_iter()
+            .map(|year| format!("{}{year}", mailpot::archives::ARCHIVE_SCHEMA_PREFIX)),
+    );
+    let mut col = vec![];
+    for schema in schemas {
+        let mut stmt = db.connection.prepare(&format!(
+            "SELECT pk, list, message_id FROM {schema}.post AS p WHERE NOT EXISTS (SELECT 1 FROM \
+             main.list AS l WHERE l.pk = p.list) ORDER BY pk"
+        ))?;
+        let iter = stmt.query_map([], |row| {
+            let pk: i64 = row.get("pk")?;
+            let list: i64 = row.get("list")?;
+            let msg_id: String = row.get("message_id")?;
+            Ok((pk, list, msg_id))
+        })?;
+
+        for entry in iter {
+            let (pk, list, msg_id) = entry?;
+            col.push((schema.clone(), pk, list, msg_id));
+        }
+    }
+    if col.is_empty() {
+        println!("remove_orphan_posts: ok");
+    } else {
+        let tx = if dry_run {
+            None
+        } else {
+            Some(db.connection.transaction()?)
+        };
+        println!("remove_orphan_posts: found {} entries", col.len());
+        println!("schema\tpk\tList\tMessage-ID");
+        for (schema, pk, list, msg_id) in &col {
+            println!("{schema}\t{pk}\t{list}\t{msg_id}");
+        }
+        if let Some(tx) = tx {
+            for (schema, pk, _, _) in col {
+                tx.execute(&format!("DELETE FROM {schema}.post WHERE pk = ?"), [pk])?;
+            }
+            tx.commit()?;
+        }
+    }
+    Ok(())
+}
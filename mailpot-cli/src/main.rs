@@ -102,18 +102,35 @@ fn run_app(
             create_list(&mut db, name, id, address, description, archive_url, quiet)
                 .context("Could not create list.")?;
         }
-        Post { dry_run } => {
-            post(&mut db, dry_run, debug).context("Could not process post.")?;
+        Post { dry_run, json } => {
+            post(&mut db, dry_run, json, debug).context("Could not process post.")?;
         }
         FlushQueue { dry_run } => {
             flush_queue(&mut db, dry_run, verbose, debug).with_context(|| {
                 format!("Could not flush queue {}.", mailpot::queue::Queue::Out)
             })?;
         }
+        PostEvents {
+            list,
+            message_id,
+            limit,
+        } => {
+            post_events(&mut db, list, message_id, limit, quiet)
+                .context("Could not retrieve post events.")?;
+        }
         Queue { queue, cmd } => {
             queue_(&mut db, queue, cmd, quiet)
                 .with_context(|| format!("Could not perform queue command for queue `{queue}`."))?;
         }
+        Gc { dry_run } => {
+            gc(&mut db, dry_run, quiet).context("Could not garbage collect.")?;
+        }
+        Privacy { cmd } => {
+            privacy(&mut db, cmd, quiet).context("Could not perform privacy command.")?;
+        }
+        Archive { cmd } => {
+            archive(&mut db, cmd, quiet).context("Could not perform archive command.")?;
+        }
         ImportMaildir {
             list_id,
             maildir_path,
@@ -181,6 +198,7 @@ fn run_app(
             remove_accepted_subscription_requests,
             warn_list_no_owner,
             fix_message_ids,
+            remove_orphan_posts,
         } => {
             repair(
                 &mut db,
@@ -192,6 +210,7 @@ fn run_app(
                     remove_accepted_subscription_requests,
                     warn_list_no_owner,
                     fix_message_ids,
+                    remove_orphan_posts,
                 },
             )
             .context("Could not perform database repair.")?;
@@ -21,7 +21,7 @@
 use std::path::Path;
 
 use assert_cmd::{assert::OutputAssertExt, cargo, Command};
-use mailpot::{models::*, Configuration, Connection, SendMail};
+use mailpot::{models::*, Configuration, Connection};
 use predicates::prelude::*;
 use tempfile::TempDir;
 
@@ -103,12 +103,7 @@ For more information, try '--help'."#,
     let conf_path = tmp_dir.path().join("conf.toml");
     let db_path = tmp_dir.path().join("mpot.db");
 
-    let config = Configuration {
-        send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
-    };
+    let config = Configuration::new(db_path);
 
     let config_str = config.to_toml();
 
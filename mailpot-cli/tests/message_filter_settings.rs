@@ -17,7 +17,7 @@
 // along with this program. If not, see <https://www.gnu.org/licenses/>.
 
 use assert_cmd::{assert::OutputAssertExt, cargo};
-use mailpot::{Configuration, Connection, SendMail};
+use mailpot::{Configuration, Connection};
 use mailpot_tests::*;
 use predicates::prelude::*;
 use tempfile::TempDir;
@@ -36,12 +36,7 @@ fn test_message_filter_settings_command() {
             .unwrap();
         conn.close().unwrap();
     }
-    let config = Configuration {
-        send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
-    };
+    let config = Configuration::new(db_path);
     let db = Connection::open_or_create_db(config.clone())
         .unwrap()
         .trusted();
@@ -64,9 +59,19 @@ fn test_message_filter_settings_command() {
         .unwrap()
         .assert();
     output.code(0).stderr(predicates::str::is_empty()).stdout(
-        predicate::eq("AddSubjectTagPrefixSettings\nArchivedAtLinkSettings\nMimeRejectSettings")
-            .trim()
-            .normalize(),
+        predicate::eq(
+            [
+                "AddListHeadersSettings",
+                "AddSubjectTagPrefixSettings",
+                "ArchivedAtLinkSettings",
+                "MimeRejectSettings",
+                "PersonaliseSettings",
+                "VerifySignatureSettings",
+            ]
+            .join("\n"),
+        )
+        .trim()
+        .normalize(),
     );
 
     println!("Testing that inserting settings works…");
@@ -19,7 +19,10 @@
 use assert_cmd::{assert::OutputAssertExt, cargo};
 use mailpot::{
     melib,
-    models::{changesets::ListSubscriptionChangeset, *},
+    models::{
+        changesets::{self, ListSubscriptionChangeset},
+        *,
+    },
     queue::Queue,
     Configuration, Connection, SendMail,
 };
@@ -59,9 +62,7 @@ fn test_out_queue_flush() {
     let smtp_handler = TestSmtpHandler::builder().address("127.0.0.1:8826").build();
     let config = Configuration {
         send_mail: SendMail::Smtp(smtp_handler.smtp_conf()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
+        ..Configuration::new(db_path)
     };
 
     let config_str = config.to_toml();
@@ -88,6 +89,13 @@ fn test_out_queue_flush() {
             .unwrap();
 
         assert_eq!(foo_chat.pk(), 1);
+        /* subscriptions take effect immediately, without a confirmation request */
+        db.update_list(changesets::MailingListChangeset {
+            pk: foo_chat.pk(),
+            verify: Some(false),
+            ..Default::default()
+        })
+        .unwrap();
         post_policy = db
             .set_list_post_policy(PostPolicy {
                 pk: -1,
@@ -266,9 +274,7 @@ fn test_list_requests_submission() {
     let smtp_handler = TestSmtpHandler::builder().address("127.0.0.1:8827").build();
     let config = Configuration {
         send_mail: SendMail::Smtp(smtp_handler.smtp_conf()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
+        ..Configuration::new(db_path)
     };
 
     let config_str = config.to_toml();
@@ -295,6 +301,13 @@ fn test_list_requests_submission() {
             .unwrap();
 
         assert_eq!(foo_chat.pk(), 1);
+        /* subscriptions take effect immediately, without a confirmation request */
+        db.update_list(changesets::MailingListChangeset {
+            pk: foo_chat.pk(),
+            verify: Some(false),
+            ..Default::default()
+        })
+        .unwrap();
         post_policy = db
             .set_list_post_policy(PostPolicy {
                 pk: -1,
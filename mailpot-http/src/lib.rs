@@ -17,6 +17,8 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>.
  */
 
+#![allow(clippy::result_large_err)]
+
 pub use std::{net::SocketAddr, sync::Arc};
 
 pub use axum::Router;
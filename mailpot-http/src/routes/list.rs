@@ -206,7 +206,7 @@ mod tests {
         body::Body,
         http::{method::Method, Request, StatusCode},
     };
-    use mailpot::{models::*, Configuration, Connection, SendMail};
+    use mailpot::{models::*, Configuration, Connection};
     use mailpot_tests::init_stderr_logging;
     use serde_json::json;
     use tempfile::TempDir;
@@ -227,12 +227,7 @@ mod tests {
                 .unwrap();
             conn.close().unwrap();
         }
-        let config = Configuration {
-            send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-            db_path,
-            data_path: tmp_dir.path().to_path_buf(),
-            administrators: vec![],
-        };
+        let config = Configuration::new(db_path);
 
         let db = Connection::open_db(config.clone()).unwrap().trusted();
         assert!(!db.lists().unwrap().is_empty());
@@ -128,18 +128,15 @@ pub async fn ssh_signin(
         None
     };
 
-    let (token, timestamp): (String, i64) = prev_token.map_or_else(
-        || {
-            use rand::{distributions::Alphanumeric, thread_rng, Rng};
-
-            let mut rng = thread_rng();
-            let chars: String = (0..7).map(|_| rng.sample(Alphanumeric) as char).collect();
-            println!("Random chars: {}", chars);
-            session.insert(TOKEN_KEY, (&chars, now)).unwrap();
-            (chars, now)
-        },
-        |tok| tok,
-    );
+    let (token, timestamp): (String, i64) = prev_token.unwrap_or_else(|| {
+        use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+        let mut rng = thread_rng();
+        let chars: String = (0..7).map(|_| rng.sample(Alphanumeric) as char).collect();
+        println!("Random chars: {}", chars);
+        session.insert(TOKEN_KEY, (&chars, now)).unwrap();
+        (chars, now)
+    });
     let timeout_left = ((timestamp + EXPIRY_IN_SECS) - now) as f64 / 60.0;
 
     let crumbs = vec![
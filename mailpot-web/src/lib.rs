@@ -157,6 +157,10 @@ mod auth_impls {
         pub async fn insert_user(&self, pk: UserId, user: User) {
             self.user_store.write().await.insert(pk, user);
         }
+
+        pub async fn remove_user(&self, pk: UserId) {
+            self.user_store.write().await.remove(&pk);
+        }
     }
 
     #[axum::async_trait]
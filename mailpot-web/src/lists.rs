@@ -20,7 +20,7 @@
 use std::{collections::HashMap, sync::Arc};
 
 use axum::{
-    extract::State,
+    extract::{Query, State},
     response::{Html, IntoResponse, Redirect},
     Extension, Form,
 };
@@ -28,11 +28,12 @@ use axum_extra::routing::TypedPath;
 use axum_sessions::extractors::WritableSession;
 use chrono::{Datelike, TimeZone};
 use http::StatusCode;
-use indexmap::IndexMap;
 use mailpot::{
     melib,
     models::{DbVal, Post},
     rusqlite::OptionalExtension,
+    search::Paging,
+    threads::ThreadAction,
     StripCarets, StripCaretsInplace,
 };
 use minijinja::value::Value;
@@ -41,10 +42,11 @@ use crate::{
     auth::User,
     minijinja_utils::{MailingList, TEMPLATES},
     typed_paths::{
-        IntoCrumb, ListEditCandidatesPath, ListEditPath, ListEditSubscribersPath, ListPath,
-        ListPathIdentifier, ListPostEmlPath, ListPostMboxPath, ListPostPath, ListPostRawPath,
+        IntoCrumb, ListEditCandidatesPath, ListEditComposePath, ListEditEventsPath, ListEditPath,
+        ListEditSubscribersPath, ListPath, ListPathIdentifier, ListPostEmlPath, ListPostMboxPath,
+        ListPostPath, ListPostRawPath, ListSearchPath,
     },
-    utils::{thread_roots, BoolPOST, Crumb, IntPOST, Level, Message, SessionMessages},
+    utils::{BoolPOST, Crumb, IntPOST, Level, Message, SessionMessages},
     AppState, AuthContext, Connection, IntoResponseErrorResult, ResponseError,
 };
 
@@ -73,43 +75,15 @@ pub async fn list(
         .as_ref()
         .map(|user| db.list_subscription_by_address(list.pk, &user.address).ok());
 
-    let posts = db.list_posts(list.pk, None)?;
-    let post_map = posts
-        .iter()
-        .map(|p| (p.message_id.as_str(), p))
-        .collect::<IndexMap<&str, &mailpot::models::DbVal<mailpot::models::Post>>>();
     let mut hist = months
         .iter()
         .map(|m| (m.to_string(), [0usize; 31]))
         .collect::<HashMap<String, [usize; 31]>>();
-    let envelopes: Arc<std::sync::RwLock<HashMap<melib::EnvelopeHash, melib::Envelope>>> =
-        Default::default();
-    {
-        let mut env_lock = envelopes.write().unwrap();
-
-        for post in &posts {
-            let Ok(mut envelope) = melib::Envelope::from_bytes(post.message.as_slice(), None)
-            else {
-                continue;
-            };
-            if envelope.message_id != post.message_id.as_str() {
-                // If they don't match, the raw envelope doesn't contain a Message-ID and it was
-                // randomly generated. So set the envelope's Message-ID to match the
-                // post's, which is the "permanent" one since our source of truth is
-                // the database.
-                envelope.set_message_id(post.message_id.as_bytes());
-            }
-            env_lock.insert(envelope.hash(), envelope);
-        }
-    }
-    let mut threads: melib::Threads = melib::Threads::new(posts.len());
-    threads.amend(&envelopes);
-    let roots = thread_roots(&envelopes, &threads);
+    let roots = db.list_thread_roots(list.pk)?;
+    let redaction = db.list_redaction(list.pk)?;
     let posts_ctx = roots
         .into_iter()
-        .filter_map(|(thread, length, _timestamp)| {
-            let key = thread.message_id.as_str();
-            let post = post_map.get(key.strip_carets())?;
+        .filter_map(|(post, length, _last_active)| {
             //2019-07-14T14:21:02
             if let Some(day) =
                 chrono::DateTime::<chrono::FixedOffset>::parse_from_rfc2822(post.datetime.trim())
@@ -118,8 +92,14 @@ pub async fn list(
             {
                 hist.get_mut(&post.month_year).unwrap()[day.saturating_sub(1) as usize] += 1;
             }
-            let envelope = melib::Envelope::from_bytes(post.message.as_slice(), None).ok()?;
-            let subject = envelope.subject();
+            // Posts stored before their headers were parsed are parsed here.
+            let subject = match post.header("subject") {
+                Some(subject) => subject.to_string(),
+                None => melib::Envelope::from_bytes(post.message.as_slice(), None)
+                    .ok()?
+                    .subject()
+                    .to_string(),
+            };
             let mut subject_ref = subject.trim();
             if subject_ref.starts_with('[')
                 && subject_ref[1..].starts_with(&list.id)
@@ -131,13 +111,13 @@ pub async fn list(
                 pk => post.pk,
                 list => post.list,
                 subject => subject_ref,
-                address => post.address,
+                address => redaction.text(&post.address),
                 message_id => post.message_id.as_str().strip_carets(),
-                message => post.message,
+                message => redaction.message(&post.message),
                 timestamp => post.timestamp,
                 datetime => post.datetime,
                 replies => length.saturating_sub(1),
-                last_active => thread.datetime,
+                last_active => post.datetime,
             };
             Some(ret)
         })
@@ -176,6 +156,116 @@ pub async fn list(
     ))
 }
 
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ListSearchQuery {
+    q: Option<String>,
+    page: Option<usize>,
+}
+
+/// Mailing list archive search page.
+pub async fn list_search(
+    ListSearchPath(id): ListSearchPath,
+    Query(ListSearchQuery { q, page }): Query<ListSearchQuery>,
+    mut session: WritableSession,
+    auth: AuthContext,
+    State(state): State<Arc<AppState>>,
+) -> Result<Html<String>, ResponseError> {
+    let db = Connection::open_db(state.conf.clone())?;
+    let Some(list) = (match id {
+        ListPathIdentifier::Pk(id) => db.list(id)?,
+        ListPathIdentifier::Id(id) => db.list_by_id(id)?,
+    }) else {
+        return Err(ResponseError::new(
+            "List not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ));
+    };
+    let list_owners = db.list_owners(list.pk)?;
+    // Hidden lists can only be searched by their owners and administrators.
+    if db.list_is_hidden(list.pk)?
+        && !auth.current_user.as_ref().is_some_and(|user| {
+            list_owners.iter().any(|o| o.address == user.address)
+                || state.conf.administrators.contains(&user.address)
+        })
+    {
+        return Err(ResponseError::new(
+            "List not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ));
+    }
+
+    let term = q.unwrap_or_default();
+    let page = page.unwrap_or(1).max(1);
+    let page_size = Paging::default().limit;
+    let redaction = db.list_redaction(list.pk)?;
+    let paging = Paging {
+        offset: (page - 1).saturating_mul(page_size),
+        limit: page_size + 1,
+    };
+    // The indexed sender includes the address, which must not be searchable
+    // if it is hidden or obfuscated.
+    let mut results = if redaction.is_noop() {
+        db.search_posts(list.pk, &term, paging)?
+    } else {
+        db.search_posts_without_sender(list.pk, &term, paging)?
+    };
+    let next_page = results.len() > page_size;
+    results.truncate(page_size);
+    let results = results
+        .into_iter()
+        .map(|result| {
+            let mut subject_ref = result.subject.trim();
+            if subject_ref.starts_with('[')
+                && subject_ref[1..].starts_with(&list.id)
+                && subject_ref[1 + list.id.len()..].starts_with(']')
+            {
+                subject_ref = subject_ref[2 + list.id.len()..].trim();
+            }
+            minijinja::context! {
+                subject => subject_ref,
+                snippet => redaction.text(&result.snippet),
+                address => redaction.text(&result.post.address),
+                message_id => result.post.message_id.as_str().strip_carets(),
+                datetime => result.post.datetime,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let crumbs = vec![
+        Crumb {
+            label: "Home".into(),
+            url: "/".into(),
+        },
+        Crumb {
+            label: list.name.clone().into(),
+            url: ListPath(list.id.to_string().into()).to_crumb(),
+        },
+        Crumb {
+            label: "Search".into(),
+            url: ListSearchPath(list.id.to_string().into()).to_crumb(),
+        },
+    ];
+    let mut list_obj = MailingList::from(list.clone());
+    list_obj.set_safety(list_owners.as_slice(), &state.conf.administrators);
+    let context = minijinja::context! {
+        canonical_url => ListSearchPath(list.id.to_string().into()).to_crumb(),
+        page_title => format!("Search {}", list.name),
+        term,
+        page,
+        next_page,
+        results,
+        list => Value::from_object(list_obj),
+        current_user => auth.current_user,
+        messages => session.drain_messages(),
+        crumbs,
+    };
+    Ok(Html(
+        TEMPLATES
+            .get_template("lists/search.html")?
+            .render(context)?,
+    ))
+}
+
 /// Mailing list post page.
 pub async fn list_post(
     ListPostPath(id, msg_id): ListPostPath,
@@ -206,22 +296,39 @@ pub async fn list_post(
             StatusCode::NOT_FOUND,
         ));
     };
+    let redaction = db.list_redaction(list.pk)?;
     let thread: Vec<(i64, DbVal<Post>, String, String)> = {
         let thread: Vec<(i64, DbVal<Post>)> = db.list_thread(list.pk, &post.message_id)?;
 
         thread
             .into_iter()
-            .map(|(depth, p)| {
+            .map(|(depth, mut p)| {
                 let envelope = melib::Envelope::from_bytes(p.message.as_slice(), None).unwrap();
                 let body = envelope.body_bytes(p.message.as_slice());
                 let body_text = body.text(melib::attachment_types::Text::Rfc822);
+                let body_text = redaction.body(&body_text).into_owned();
                 let date = envelope.date_as_str().to_string();
+                p.address = redaction.text(&p.address).into_owned();
                 (depth, p, body_text, date)
             })
             .collect()
     };
+    let mut tombstones = HashMap::new();
+    for message_id in
+        std::iter::once(&post.message_id).chain(thread.iter().map(|t| &t.1.message_id))
+    {
+        if let Some(tombstone) = db.post_tombstone(list.pk, message_id)? {
+            tombstones.insert(message_id.as_str().strip_carets().to_string(), tombstone);
+        }
+    }
     let envelope = melib::Envelope::from_bytes(post.message.as_slice(), None)
         .with_status(StatusCode::BAD_REQUEST)?;
+    let thread_preference = match user_context {
+        Some(Some(ref subscription)) => {
+            db.thread_preference(subscription.pk(), &db.list_thread_root(list.pk, &envelope)?)?
+        }
+        _ => None,
+    };
     let body = envelope.body_bytes(post.message.as_slice());
     let body_text = body.text(melib::attachment_types::Text::Rfc822);
     let subject = envelope.subject();
@@ -257,21 +364,24 @@ pub async fn list_post(
         description => &list.description,
         list => Value::from_object(list_obj),
         pk => post.pk,
-        body => &body_text,
-        from => &envelope.field_from_to_string(),
+        body => redaction.body(&body_text),
+        from => redaction.text(&envelope.field_from_to_string()),
         date => &envelope.date_as_str(),
-        to => &envelope.field_to_to_string(),
+        to => redaction.text(&envelope.field_to_to_string()),
         subject => &envelope.subject(),
         trimmed_subject => subject_ref,
         in_reply_to => &envelope.in_reply_to().map(|r| r.refs().iter().map(|m| m.to_string().strip_carets_inplace()).collect::<Vec<String>>()),
         references => &envelope.references().iter().map(|m| m.to_string().strip_carets_inplace()).collect::<Vec<String>>(),
         message_id => msg_id,
-        message => post.message,
+        message => redaction.message(&post.message),
         timestamp => post.timestamp,
         datetime => post.datetime,
         thread => thread,
+        tombstones => tombstones,
+        raw_download => db.list_serves_raw_messages(list.pk)?,
         current_user => auth.current_user,
         user_context => user_context,
+        thread_preference => thread_preference,
         messages => session.drain_messages(),
         crumbs => crumbs,
     };
@@ -280,6 +390,65 @@ pub async fn list_post(
     ))
 }
 
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ThreadPreferencePayload {
+    pub action: ThreadAction,
+}
+
+/// Mute, unmute or follow the thread of a post.
+#[allow(non_snake_case)]
+pub async fn list_post_POST(
+    ListPostPath(id, msg_id): ListPostPath,
+    mut session: WritableSession,
+    Extension(user): Extension<User>,
+    Form(payload): Form<ThreadPreferencePayload>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Redirect, ResponseError> {
+    let db = Connection::open_db(state.conf.clone())?.trusted();
+    let Some(list) = (match id {
+        ListPathIdentifier::Pk(id) => db.list(id)?,
+        ListPathIdentifier::Id(id) => db.list_by_id(id)?,
+    }) else {
+        return Err(ResponseError::new(
+            "List not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ));
+    };
+    let Some(post) = db.list_post_by_message_id(list.pk, &msg_id)? else {
+        return Err(ResponseError::new(
+            format!("Post with Message-ID {} not found", msg_id),
+            StatusCode::NOT_FOUND,
+        ));
+    };
+    let subscription = db
+        .list_subscription_by_address(list.pk(), &user.address)
+        .map_err(|_| {
+            ResponseError::new(
+                "Subscription not found".to_string(),
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+    let envelope = melib::Envelope::from_bytes(post.message.as_slice(), None)
+        .with_status(StatusCode::BAD_REQUEST)?;
+    db.change_thread_preference(
+        subscription.pk(),
+        &db.list_thread_root(list.pk, &envelope)?,
+        payload.action,
+    )
+    .with_status(StatusCode::BAD_REQUEST)?;
+
+    session.add_message(Message {
+        message: "Thread preference saved.".into(),
+        level: Level::Success,
+    })?;
+
+    Ok(Redirect::to(&format!(
+        "{}{}",
+        &state.root_url_prefix,
+        ListPostPath(list.id.clone().into(), msg_id.strip_carets_inplace()).to_uri()
+    )))
+}
+
 pub async fn list_edit(
     ListEditPath(id): ListEditPath,
     mut session: WritableSession,
@@ -307,6 +476,9 @@ pub async fn list_edit(
 
     let post_policy = db.list_post_policy(list.pk)?;
     let subscription_policy = db.list_subscription_policy(list.pk)?;
+    let archive_addresses = db.list_archive_addresses(list.pk)?;
+    let archive_raw_download = db.list_archive_raw_download(list.pk)?;
+    let access_entries = db.list_access_entries(list.pk)?;
     let post_count = {
         let mut stmt = db
             .connection
@@ -364,6 +536,9 @@ pub async fn list_edit(
         description => &list.description,
         post_policy,
         subscription_policy,
+        archive_addresses,
+        archive_raw_download,
+        access_entries,
         list_owners,
         post_count,
         subs_count,
@@ -510,6 +685,77 @@ pub async fn list_edit_POST(
                 },
             )?;
         }
+        ChangeSetting::ArchiveAddresses {
+            archive_addresses,
+            raw_download: BoolPOST(raw_download),
+        } => {
+            session.add_message(
+                if let Err(err) =
+                    db.update_list(mailpot::models::changesets::MailingListChangeset {
+                        pk: list.pk,
+                        archive_addresses: Some(archive_addresses),
+                        archive_raw_download: Some(raw_download),
+                        ..Default::default()
+                    })
+                {
+                    Message {
+                        message: err.to_string().into(),
+                        level: Level::Error,
+                    }
+                } else {
+                    Message {
+                        message: "Archive settings saved.".into(),
+                        level: Level::Success,
+                    }
+                },
+            )?;
+        }
+        ChangeSetting::AddAccessEntry {
+            pattern,
+            kind,
+            match_type,
+            post: BoolPOST(post),
+            subscribe: BoolPOST(subscribe),
+            comment,
+        } => {
+            session.add_message(
+                if let Err(err) = db.add_access_entry(mailpot::models::AccessEntry {
+                    pk: -1,
+                    list: Some(list.pk),
+                    kind,
+                    match_type,
+                    pattern,
+                    post,
+                    subscribe,
+                    comment: comment.filter(|s| !s.is_empty()),
+                }) {
+                    Message {
+                        message: err.to_string().into(),
+                        level: Level::Error,
+                    }
+                } else {
+                    Message {
+                        message: "Access entry added.".into(),
+                        level: Level::Success,
+                    }
+                },
+            )?;
+        }
+        ChangeSetting::RemoveAccessEntry { pk: IntPOST(pk) } => {
+            session.add_message(
+                if let Err(err) = db.remove_access_entry(Some(list.pk), pk) {
+                    Message {
+                        message: err.to_string().into(),
+                        level: Level::Error,
+                    }
+                } else {
+                    Message {
+                        message: "Access entry removed.".into(),
+                        level: Level::Success,
+                    }
+                },
+            )?;
+        }
         ChangeSetting::AcceptSubscriptionRequest { pk: IntPOST(pk) } => {
             session.add_message(match db.accept_candidate_subscription(pk) {
                 Ok(subscription) => Message {
@@ -563,6 +809,27 @@ pub enum ChangeSetting {
         #[serde(default)]
         archive_url: Option<String>,
     },
+    ArchiveAddresses {
+        #[serde(rename = "archive-addresses")]
+        archive_addresses: mailpot::models::ArchiveAddresses,
+        #[serde(rename = "raw-download", default)]
+        raw_download: BoolPOST,
+    },
+    AddAccessEntry {
+        pattern: String,
+        kind: mailpot::models::AccessKind,
+        #[serde(rename = "match-type")]
+        match_type: mailpot::models::AddressMatch,
+        #[serde(default)]
+        post: BoolPOST,
+        #[serde(default)]
+        subscribe: BoolPOST,
+        #[serde(default)]
+        comment: Option<String>,
+    },
+    RemoveAccessEntry {
+        pk: IntPOST,
+    },
     AcceptSubscriptionRequest {
         pk: IntPOST,
     },
@@ -603,6 +870,12 @@ pub async fn list_post_raw(
         ));
     };
 
+    if !db.list_serves_raw_messages(list.pk)? {
+        return Err(ResponseError::new(
+            "Raw downloads are disabled for this list".to_string(),
+            StatusCode::NOT_FOUND,
+        ));
+    }
     let post = if let Some(post) = db.list_post_by_message_id(list.pk, &msg_id)? {
         post
     } else {
@@ -630,6 +903,12 @@ pub async fn list_post_eml(
         ));
     };
 
+    if !db.list_serves_raw_messages(list.pk)? {
+        return Err(ResponseError::new(
+            "Raw downloads are disabled for this list".to_string(),
+            StatusCode::NOT_FOUND,
+        ));
+    }
     let post = if let Some(post) = db.list_post_by_message_id(list.pk, &msg_id)? {
         post
     } else {
@@ -671,6 +950,12 @@ pub async fn list_post_mbox(
         ));
     };
 
+    if !db.list_serves_raw_messages(list.pk)? {
+        return Err(ResponseError::new(
+            "Raw downloads are disabled for this list".to_string(),
+            StatusCode::NOT_FOUND,
+        ));
+    }
     let post = if let Some(post) = db.list_post_by_message_id(list.pk, &msg_id)? {
         post
     } else {
@@ -882,3 +1167,355 @@ pub async fn list_candidates(
             .render(context)?,
     ))
 }
+
+pub async fn list_events(
+    ListEditEventsPath(id): ListEditEventsPath,
+    mut session: WritableSession,
+    auth: AuthContext,
+    State(state): State<Arc<AppState>>,
+) -> Result<Html<String>, ResponseError> {
+    let db = Connection::open_db(state.conf.clone())?;
+    let Some(list) = (match id {
+        ListPathIdentifier::Pk(id) => db.list(id)?,
+        ListPathIdentifier::Id(id) => db.list_by_id(id)?,
+    }) else {
+        return Err(ResponseError::new(
+            "Not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ));
+    };
+    let list_owners = db.list_owners(list.pk)?;
+    let user_address = &auth.current_user.as_ref().unwrap().address;
+    if !list_owners.iter().any(|o| &o.address == user_address) {
+        return Err(ResponseError::new(
+            "Not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ));
+    };
+
+    let events = db
+        .post_events(Some(list.pk), None, Some(500))?
+        .into_iter()
+        .rev()
+        .map(|e| {
+            minijinja::context! {
+                datetime => e.datetime.clone(),
+                message_id => e.message_id.clone(),
+                kind => e.kind.as_str(),
+                name => e.name.clone(),
+                detail => e.detail.clone(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let crumbs = vec![
+        Crumb {
+            label: "Home".into(),
+            url: "/".into(),
+        },
+        Crumb {
+            label: list.name.clone().into(),
+            url: ListPath(list.id.to_string().into()).to_crumb(),
+        },
+        Crumb {
+            label: format!("Edit {}", list.name).into(),
+            url: ListEditPath(ListPathIdentifier::from(list.id.clone())).to_crumb(),
+        },
+        Crumb {
+            label: format!("Post events of {}", list.name).into(),
+            url: ListEditEventsPath(list.id.to_string().into()).to_crumb(),
+        },
+    ];
+    let mut list_obj: MailingList = MailingList::from(list.clone());
+    list_obj.set_safety(list_owners.as_slice(), &state.conf.administrators);
+    let context = minijinja::context! {
+        canonical_url => ListEditEventsPath(ListPathIdentifier::from(list.id.clone())).to_crumb(),
+        page_title => format!("Post events of {}", list.name),
+        events,
+        list => Value::from_object(list_obj),
+        current_user => auth.current_user,
+        messages => session.drain_messages(),
+        crumbs,
+    };
+    Ok(Html(
+        TEMPLATES
+            .get_template("lists/events.html")?
+            .render(context)?,
+    ))
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum ComposeAction {
+    Preview,
+    Send,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct ComposePayload {
+    pub subject: String,
+    pub body: String,
+    /// Release time in `YYYY-MM-DDTHH:MM` format (UTC), if the post should be
+    /// scheduled.
+    #[serde(rename = "send-at", default)]
+    pub send_at: String,
+    pub action: ComposeAction,
+}
+
+impl ComposePayload {
+    fn send_at(&self) -> Result<Option<u64>, String> {
+        let value = self.send_at.trim();
+        if value.is_empty() {
+            return Ok(None);
+        }
+        if let Ok(datetime) = chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M") {
+            return Ok(Some(datetime.and_utc().timestamp() as u64));
+        }
+        mailpot::scheduled::parse_send_at(value)
+            .map(Some)
+            .map_err(|err| err.to_string())
+    }
+}
+
+pub async fn list_compose(
+    ListEditComposePath(id): ListEditComposePath,
+    mut session: WritableSession,
+    auth: AuthContext,
+    State(state): State<Arc<AppState>>,
+) -> Result<Html<String>, ResponseError> {
+    let db = Connection::open_db(state.conf.clone())?;
+    let Some(list) = (match id {
+        ListPathIdentifier::Pk(id) => db.list(id)?,
+        ListPathIdentifier::Id(id) => db.list_by_id(id)?,
+    }) else {
+        return Err(ResponseError::new(
+            "Not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ));
+    };
+    let list_owners = db.list_owners(list.pk)?;
+    let user_address = &auth.current_user.as_ref().unwrap().address;
+    if !list_owners.iter().any(|o| &o.address == user_address) {
+        return Err(ResponseError::new(
+            "Not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ));
+    };
+
+    compose_page(
+        &state,
+        &list,
+        &list_owners,
+        auth.current_user,
+        &mut session,
+        None,
+        None,
+    )
+}
+
+#[allow(non_snake_case)]
+pub async fn list_compose_POST(
+    ListEditComposePath(id): ListEditComposePath,
+    mut session: WritableSession,
+    Extension(user): Extension<User>,
+    Form(payload): Form<ComposePayload>,
+    State(state): State<Arc<AppState>>,
+) -> Result<axum::response::Response, ResponseError> {
+    let db = Connection::open_db(state.conf.clone())?;
+    let Some(list) = (match id {
+        ListPathIdentifier::Pk(id) => db.list(id)?,
+        ListPathIdentifier::Id(ref id) => db.list_by_id(id)?,
+    }) else {
+        return Err(ResponseError::new(
+            "Not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ));
+    };
+    let list_owners = db.list_owners(list.pk)?;
+    if !list_owners.iter().any(|o| o.address == user.address) {
+        return Err(ResponseError::new(
+            "Not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ));
+    };
+
+    let from = melib::Address::new(user.name.clone(), user.address.clone());
+    let send_at = payload.send_at();
+    let draft = send_at.clone().and_then(|send_at| {
+        db.compose_post(&list, &from, &payload.subject, &payload.body, send_at)
+            .map_err(|err| err.to_string())
+    });
+    match (payload.action, draft) {
+        (_, Err(err)) => {
+            session.add_message(Message {
+                message: err.into(),
+                level: Level::Error,
+            })?;
+            Ok(compose_page(
+                &state,
+                &list,
+                &list_owners,
+                Some(user),
+                &mut session,
+                Some(&payload),
+                None,
+            )?
+            .into_response())
+        }
+        (ComposeAction::Preview, Ok(draft)) => {
+            let env = melib::Envelope::from_bytes(&draft, None)?;
+            let trace = db.trusted().post_dry_run(&env, &draft)?;
+            Ok(compose_page(
+                &state,
+                &list,
+                &list_owners,
+                Some(user),
+                &mut session,
+                Some(&payload),
+                Some((
+                    String::from_utf8_lossy(&draft).into_owned(),
+                    trace.to_string(),
+                )),
+            )?
+            .into_response())
+        }
+        (ComposeAction::Send, Ok(draft)) => {
+            let env = melib::Envelope::from_bytes(&draft, None)?;
+            let mut db = db.trusted();
+            let tx = db.transaction(mailpot::transaction::TransactionBehavior::Exclusive)?;
+            // Only owners authenticated here can schedule posts, see
+            // [`mailpot::scheduled`].
+            let (message, level) = if let Some(send_at) = send_at
+                .ok()
+                .flatten()
+                .filter(|send_at| *send_at > chrono::offset::Utc::now().timestamp() as u64)
+            {
+                tx.schedule_post(&list, &env, &draft, send_at)?;
+                (
+                    format!(
+                        "Post {} scheduled for {}.",
+                        env.message_id(),
+                        melib::utils::datetime::timestamp_to_string(
+                            send_at,
+                            Some(melib::utils::datetime::formats::RFC822_DATE),
+                            true,
+                        )
+                    ),
+                    Level::Success,
+                )
+            } else {
+                match tx.post(&env, &draft, false) {
+                    Ok(()) => compose_outcome(&tx, &list, &env)?,
+                    Err(err) => (
+                        format!(
+                            "Post {} could not be processed and was moved to the error queue: \
+                             {err}",
+                            env.message_id()
+                        ),
+                        Level::Error,
+                    ),
+                }
+            };
+            tx.commit()?;
+            session.add_message(Message {
+                message: message.into(),
+                level,
+            })?;
+            Ok(Redirect::to(&format!(
+                "{}{}",
+                &state.root_url_prefix,
+                ListEditEventsPath(id).to_uri()
+            ))
+            .into_response())
+        }
+    }
+}
+
+/// Describe the final action the list took on a composed post, from its
+/// [`PostEvent`](mailpot::models::PostEvent)s.
+fn compose_outcome(
+    db: &Connection,
+    list: &DbVal<mailpot::models::MailingList>,
+    env: &melib::Envelope,
+) -> Result<(String, Level), ResponseError> {
+    let message_id = env.message_id().to_string();
+    let action = db
+        .post_events(Some(list.pk), Some(&message_id), None)?
+        .into_iter()
+        .rev()
+        .find(|e| e.kind == mailpot::models::PostEventKind::Action);
+    let detail = action
+        .as_ref()
+        .and_then(|e| e.detail.as_deref())
+        .map_or_else(String::new, |d| format!(": {d}"));
+    Ok(match action.as_ref().map(|e| e.name.as_str()) {
+        Some("accept") => (
+            format!("Post {} submitted.", env.message_id()),
+            Level::Success,
+        ),
+        Some("hold") => (
+            format!("Post {} is held for moderation.", env.message_id()),
+            Level::Warning,
+        ),
+        Some(name) => (
+            format!(
+                "Post {} was not accepted ({name}){detail}",
+                env.message_id()
+            ),
+            Level::Error,
+        ),
+        None => (
+            format!("Post {} was not processed by the list.", env.message_id()),
+            Level::Error,
+        ),
+    })
+}
+
+fn compose_page(
+    state: &AppState,
+    list: &DbVal<mailpot::models::MailingList>,
+    list_owners: &[DbVal<mailpot::models::ListOwner>],
+    current_user: Option<User>,
+    session: &mut WritableSession,
+    payload: Option<&ComposePayload>,
+    preview: Option<(String, String)>,
+) -> Result<Html<String>, ResponseError> {
+    let crumbs = vec![
+        Crumb {
+            label: "Home".into(),
+            url: "/".into(),
+        },
+        Crumb {
+            label: list.name.clone().into(),
+            url: ListPath(list.id.to_string().into()).to_crumb(),
+        },
+        Crumb {
+            label: format!("Edit {}", list.name).into(),
+            url: ListEditPath(ListPathIdentifier::from(list.id.clone())).to_crumb(),
+        },
+        Crumb {
+            label: format!("Compose a post to {}", list.name).into(),
+            url: ListEditComposePath(list.id.to_string().into()).to_crumb(),
+        },
+    ];
+    let mut list_obj: MailingList = MailingList::from(list.clone());
+    list_obj.set_safety(list_owners, &state.conf.administrators);
+    let context = minijinja::context! {
+        canonical_url => ListEditComposePath(ListPathIdentifier::from(list.id.clone())).to_crumb(),
+        page_title => format!("Compose a post to {}", list.name),
+        subject => payload.map(|p| p.subject.as_str()),
+        body => payload.map(|p| p.body.as_str()),
+        send_at => payload.map(|p| p.send_at.as_str()),
+        preview => preview.as_ref().map(|(draft, _)| draft),
+        dry_run => preview.as_ref().map(|(_, trace)| trace),
+        list => Value::from_object(list_obj),
+        current_user,
+        messages => session.drain_messages(),
+        crumbs,
+    };
+    Ok(Html(
+        TEMPLATES
+            .get_template("lists/compose.html")?
+            .render(context)?,
+    ))
+}
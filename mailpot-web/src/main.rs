@@ -17,6 +17,8 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>.
  */
 
+#![allow(clippy::result_large_err)]
+
 use std::{borrow::Cow, collections::HashMap, sync::Arc};
 
 use axum::{extract::State, handler::Handler, response::Html, routing::get, Router};
@@ -28,11 +30,17 @@ use mailpot_web::{
     auth::{logout_handler, Role},
     help::help,
     lists::{
-        list, list_candidates, list_edit, list_edit_POST, list_post, list_post_eml, list_post_mbox,
-        list_post_raw, list_subscribers,
+        list, list_candidates, list_compose, list_compose_POST, list_edit, list_edit_POST,
+        list_events, list_post, list_post_POST, list_post_eml, list_post_mbox, list_post_raw,
+        list_search, list_subscribers,
     },
     minijinja_utils::{MailingList, TEMPLATES},
-    settings::{settings, settings_POST, user_list_subscription, user_list_subscription_POST},
+    settings::{
+        settings, settings_POST, settings_privacy_erase_POST, settings_privacy_export,
+        subscription_confirm, subscription_confirm_POST, subscription_token,
+        subscription_token_POST, subscription_unsubscribe, subscription_unsubscribe_POST,
+        user_list_subscription, user_list_subscription_POST,
+    },
     topics::list_topics,
     typed_paths::{tsr::RouterExt, IntoCrumb, LoginPath},
     utils::{Crumb, SessionMessages},
@@ -73,8 +81,22 @@ fn create_app(shared_state: Arc<AppState>) -> Router {
         .route("/", get(root))
         .typed_get(list)
         .typed_get(list_post)
+        .typed_post(
+            {
+                let shared_state = Arc::clone(&shared_state);
+                move |path, session, user, payload| {
+                    list_post_POST(path, session, user, payload, State(shared_state))
+                }
+            }
+            .layer(RequireAuth::login_with_role_or_redirect(
+                Role::User..,
+                Arc::clone(&login_url),
+                Some(Arc::new("next".into())),
+            )),
+        )
         .typed_get(list_post_raw)
         .typed_get(list_topics)
+        .typed_get(list_search)
         .typed_get(list_post_eml)
         .typed_get(list_post_mbox)
         .typed_get(list_edit.layer(RequireAuth::login_with_role_or_redirect(
@@ -109,6 +131,29 @@ fn create_app(shared_state: Arc<AppState>) -> Router {
                 Some(Arc::new("next".into())),
             )),
         )
+        .typed_get(list_events.layer(RequireAuth::login_with_role_or_redirect(
+            Role::User..,
+            Arc::clone(&login_url),
+            Some(Arc::new("next".into())),
+        )))
+        .typed_get(list_compose.layer(RequireAuth::login_with_role_or_redirect(
+            Role::User..,
+            Arc::clone(&login_url),
+            Some(Arc::new("next".into())),
+        )))
+        .typed_post(
+            {
+                let shared_state = Arc::clone(&shared_state);
+                move |path, session, user, payload| {
+                    list_compose_POST(path, session, user, payload, State(shared_state))
+                }
+            }
+            .layer(RequireAuth::login_with_role_or_redirect(
+                Role::User..,
+                Arc::clone(&login_url),
+                Some(Arc::new("next".into())),
+            )),
+        )
         .typed_get(help)
         .typed_get(auth::ssh_signin)
         .typed_post({
@@ -141,6 +186,28 @@ fn create_app(shared_state: Arc<AppState>) -> Router {
                 Some(Arc::new("next".into())),
             )),
         )
+        .typed_get(
+            {
+                let shared_state = Arc::clone(&shared_state);
+                move |path, user| settings_privacy_export(path, user, shared_state)
+            }
+            .layer(RequireAuth::login_or_redirect(
+                Arc::clone(&login_url),
+                Some(Arc::new("next".into())),
+            )),
+        )
+        .typed_post(
+            {
+                let shared_state = Arc::clone(&shared_state);
+                move |path, session, auth, user, body| {
+                    settings_privacy_erase_POST(path, session, auth, user, body, shared_state)
+                }
+            }
+            .layer(RequireAuth::login_or_redirect(
+                Arc::clone(&login_url),
+                Some(Arc::new("next".into())),
+            )),
+        )
         .typed_get(
             user_list_subscription.layer(RequireAuth::login_with_role_or_redirect(
                 Role::User..,
@@ -161,6 +228,32 @@ fn create_app(shared_state: Arc<AppState>) -> Router {
                 Some(Arc::new("next".into())),
             )),
         )
+        .typed_get({
+            let shared_state = Arc::clone(&shared_state);
+            move |path, session, auth| subscription_token(path, session, auth, shared_state)
+        })
+        .typed_post({
+            let shared_state = Arc::clone(&shared_state);
+            move |path, session, body| subscription_token_POST(path, session, body, shared_state)
+        })
+        .typed_get({
+            let shared_state = Arc::clone(&shared_state);
+            move |path| subscription_unsubscribe(path, shared_state)
+        })
+        .typed_post({
+            let shared_state = Arc::clone(&shared_state);
+            move |path, headers, body| {
+                subscription_unsubscribe_POST(path, headers, body, shared_state)
+            }
+        })
+        .typed_get({
+            let shared_state = Arc::clone(&shared_state);
+            move |path, session, auth| subscription_confirm(path, session, auth, shared_state)
+        })
+        .typed_post({
+            let shared_state = Arc::clone(&shared_state);
+            move |path, session| subscription_confirm_POST(path, session, shared_state)
+        })
         .layer(auth_layer)
         .layer(session_layer)
         .with_state(shared_state)
@@ -257,7 +350,7 @@ mod tests {
         },
     };
     use axum_extra::routing::TypedPath;
-    use mailpot::{Configuration, Connection, SendMail};
+    use mailpot::{Configuration, Connection};
     use mailpot_tests::init_stderr_logging;
     use mailpot_web::{
         auth::{AuthFormPayload, User},
@@ -303,12 +396,7 @@ mod tests {
                 .unwrap();
             conn.close().unwrap();
         }
-        let config = Configuration {
-            send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-            db_path,
-            data_path: tmp_dir.path().to_path_buf(),
-            administrators: vec![],
-        };
+        let config = Configuration::new(db_path);
         let db = Connection::open_db(config.clone()).unwrap();
         let list = db.lists().unwrap().remove(0);
 
@@ -459,6 +547,319 @@ mod tests {
             vnu(res).await;
         }
 
+        // ------------------------------------------------------------
+        // subscription_token(), subscription_token_POST()
+
+        {
+            let token = db
+                .subscription_token(
+                    mailpot::tokens::TokenAction::Settings,
+                    list.pk(),
+                    "user@example.com",
+                )
+                .unwrap();
+            let res = create_app(state.clone())
+                .oneshot(req!(get & format!("/subscription/{token}/")))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            vnu(res).await;
+
+            let res = create_app(state.clone())
+                .oneshot(req!(get "/subscription/invalid.token/"))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+            let res = create_app(state.clone())
+                .oneshot(req!(
+                    post & format!("/subscription/{token}/"),
+                    [("type", "settings"), ("digest", "true")]
+                ))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::SEE_OTHER);
+            assert!(
+                db.list_subscription_by_address(list.pk(), "user@example.com")
+                    .unwrap()
+                    .digest
+            );
+        }
+
+        // ------------------------------------------------------------
+        // subscription_unsubscribe(), subscription_unsubscribe_POST()
+
+        {
+            let trusted = Connection::open_db(config.clone()).unwrap().trusted();
+            let new_subscription = || mailpot::models::ListSubscription {
+                pk: 0,
+                list: list.pk(),
+                address: "one-click@example.com".to_string(),
+                name: None,
+                account: None,
+                enabled: true,
+                verified: true,
+                digest: false,
+                hide_address: false,
+                receive_duplicates: true,
+                receive_own_posts: false,
+                receive_confirmation: true,
+            };
+            trusted
+                .add_subscription(list.pk(), new_subscription())
+                .unwrap();
+            let token = db
+                .subscription_token(
+                    mailpot::tokens::TokenAction::Unsubscribe,
+                    list.pk(),
+                    "one-click@example.com",
+                )
+                .unwrap();
+            let path = format!("/subscription/{token}/unsubscribe/");
+            let res = create_app(state.clone())
+                .oneshot(req!(get & path))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::SEE_OTHER);
+
+            let res = create_app(state.clone())
+                .oneshot(req!(post & path, [("List-Unsubscribe", "Other")]))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+            // The field must be `List-Unsubscribe`, not just contain its text.
+            let res = create_app(state.clone())
+                .oneshot(req!(
+                    post & path,
+                    [("comment", "List-Unsubscribe=One-Click")]
+                ))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let multipart = |name: &str| {
+                Request::builder()
+                    .uri(&path)
+                    .method(Method::POST)
+                    .header(
+                        "Content-Type",
+                        "multipart/form-data; boundary=--form-boundary",
+                    )
+                    .body(Body::from(format!(
+                        "----form-boundary\r\nContent-Disposition: form-data; \
+                         name=\"{name}\"\r\n\r\nOne-Click\r\n----form-boundary--\r\n"
+                    )))
+                    .unwrap()
+            };
+            let res = create_app(state.clone())
+                .oneshot(multipart("comment"))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            assert!(db
+                .list_subscription_by_address(list.pk(), "one-click@example.com")
+                .is_ok());
+
+            let res = create_app(state.clone())
+                .oneshot(multipart("List-Unsubscribe"))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(db
+                .list_subscription_by_address(list.pk(), "one-click@example.com")
+                .is_err());
+
+            let res = create_app(state.clone())
+                .oneshot(req!(post & path, [("List-Unsubscribe", "One-Click")]))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+
+            // A token issued before the address subscribed again is refused.
+            trusted
+                .add_subscription(list.pk(), new_subscription())
+                .unwrap();
+            let stale = mailpot::tokens::SubscriptionToken {
+                action: mailpot::tokens::TokenAction::Unsubscribe,
+                list: list.pk(),
+                address: "one-click@example.com".to_string(),
+                issued: chrono::Utc::now().timestamp() as u64 - 60,
+            }
+            .sign(&db.token_secret().unwrap());
+            let res = create_app(state.clone())
+                .oneshot(req!(
+                    post & format!("/subscription/{stale}/unsubscribe/"),
+                    [("List-Unsubscribe", "One-Click")]
+                ))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+            assert!(db
+                .list_subscription_by_address(list.pk(), "one-click@example.com")
+                .is_ok());
+        }
+
+        // ------------------------------------------------------------
+        // subscription_confirm(), subscription_confirm_POST()
+
+        {
+            let trusted = Connection::open_db(config.clone()).unwrap().trusted();
+            let subscription = trusted
+                .add_subscription(
+                    list.pk(),
+                    mailpot::models::ListSubscription {
+                        pk: 0,
+                        list: list.pk(),
+                        address: "opt-in@example.com".to_string(),
+                        name: None,
+                        account: None,
+                        enabled: true,
+                        verified: false,
+                        digest: false,
+                        hide_address: false,
+                        receive_duplicates: true,
+                        receive_own_posts: false,
+                        receive_confirmation: true,
+                    },
+                )
+                .unwrap();
+            let confirmation = trusted
+                .add_subscription_confirmation(subscription.pk(), u64::MAX >> 1)
+                .unwrap();
+            let path = format!("/subscription/confirm/{}/", confirmation.token);
+            let res = create_app(state.clone())
+                .oneshot(req!(get & path))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            vnu(res).await;
+
+            let res = create_app(state.clone())
+                .oneshot(req!(post & path, [("confirm", "")]))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::SEE_OTHER);
+            assert!(
+                db.list_subscription_by_address(list.pk(), "opt-in@example.com")
+                    .unwrap()
+                    .verified
+            );
+
+            let res = create_app(state.clone())
+                .oneshot(req!(get & path))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+            let subscription = db
+                .list_subscription_by_address(list.pk(), "opt-in@example.com")
+                .unwrap();
+            let confirmation = trusted
+                .add_unsubscription_confirmation(subscription.pk(), u64::MAX >> 1)
+                .unwrap();
+            let path = format!("/subscription/confirm/{}/", confirmation.token);
+            let res = create_app(state.clone())
+                .oneshot(req!(get & path))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            vnu(res).await;
+
+            let res = create_app(state.clone())
+                .oneshot(req!(post & path, [("confirm", "")]))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::SEE_OTHER);
+            assert!(db
+                .list_subscription_by_address(list.pk(), "opt-in@example.com")
+                .is_err());
+
+            let subscription = trusted
+                .add_subscription(
+                    list.pk(),
+                    mailpot::models::ListSubscription {
+                        pk: 0,
+                        list: list.pk(),
+                        address: "stale@example.com".to_string(),
+                        name: None,
+                        account: None,
+                        enabled: true,
+                        verified: true,
+                        digest: false,
+                        hide_address: false,
+                        receive_duplicates: true,
+                        receive_own_posts: false,
+                        receive_confirmation: true,
+                    },
+                )
+                .unwrap();
+            let reconfirmation = trusted
+                .add_reconfirmation(subscription.pk(), u64::MAX >> 1)
+                .unwrap();
+            let path = format!("/subscription/confirm/{}/", reconfirmation.token);
+            let res = create_app(state.clone())
+                .oneshot(req!(get & path))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            vnu(res).await;
+
+            let res = create_app(state.clone())
+                .oneshot(req!(post & path, [("confirm", "")]))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::SEE_OTHER);
+            assert!(db
+                .reconfirmation(subscription.pk())
+                .unwrap()
+                .unwrap()
+                .confirmed
+                .is_some());
+        }
+
+        // ------------------------------------------------------------
+        // list_search()
+
+        {
+            let path = format!("/list/{}/search/?q=post", list.id);
+            let res = create_app(state.clone())
+                .oneshot(req!(get & path))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            assert!(String::from_utf8_lossy(&body).contains("abcdefgh@sator.example.com"));
+
+            let res = create_app(state.clone())
+                .oneshot(req!(get & format!("/list/{}/search/", list.id)))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            vnu(res).await;
+
+            // Hidden lists are not searchable by the public.
+            let trusted = Connection::open_db(config.clone()).unwrap().trusted();
+            trusted
+                .update_list(mailpot::models::changesets::MailingListChangeset {
+                    pk: list.pk(),
+                    hidden: Some(true),
+                    ..Default::default()
+                })
+                .unwrap();
+            let res = create_app(state.clone())
+                .oneshot(req!(get & path))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+            trusted
+                .update_list(mailpot::models::changesets::MailingListChangeset {
+                    pk: list.pk(),
+                    hidden: Some(false),
+                    ..Default::default()
+                })
+                .unwrap();
+        }
+
         if cfg!(not(debug_assertions)) {
             return;
         }
@@ -563,6 +964,57 @@ mod tests {
                 )
             );
         }
+        // ------------------------------------------------------------
+        // settings_privacy_export()
+
+        {
+            let mut request = req!(get & SettingsPrivacyExportPath.to_uri().to_string());
+            request
+                .headers_mut()
+                .insert(COOKIE, session_cookie.to_owned());
+            let res = login_app.clone().oneshot(request).await.unwrap();
+
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(
+                res.headers().get(http::header::CONTENT_TYPE),
+                Some(&http::HeaderValue::from_static("application/json"))
+            );
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let data: mailpot::privacy::PersonalData = serde_json::from_slice(&body).unwrap();
+            assert_eq!(data.address, "user@example.com");
+            assert!(data.account.is_some());
+            assert!(!data.subscriptions.is_empty());
+        }
+
+        // ------------------------------------------------------------
+        // settings_privacy_erase_POST() with a mismatched confirmation
+
+        {
+            let mut request = req!(
+                post & SettingsPrivacyErasePath.to_uri().to_string(),
+                crate::settings::PrivacyErasePayload {
+                    address: "someone-else@example.com".to_string(),
+                }
+            );
+            request
+                .headers_mut()
+                .insert(COOKIE, session_cookie.to_owned());
+            let res = login_app.clone().oneshot(request).await.unwrap();
+
+            assert_eq!(
+                res.headers().get(http::header::LOCATION),
+                Some(
+                    &SettingsPath
+                        .to_uri()
+                        .to_string()
+                        .as_str()
+                        .try_into()
+                        .unwrap()
+                )
+            );
+            assert!(db.account_by_address("user@example.com").unwrap().is_some());
+        }
+
         // ------------------------------------------------------------
         // user_list_subscription() TODO
 
@@ -649,5 +1101,163 @@ mod tests {
             let policy = db.list_post_policy(list.pk()).unwrap().unwrap();
             assert!(policy.custom);
         }
+
+        // ------------------------------------------------------------
+        // list_compose(), list_compose_POST()
+
+        {
+            let mut request = req!(get "/list/new-name/edit/compose/");
+            request
+                .headers_mut()
+                .insert(COOKIE, session_cookie.to_owned());
+            let res = login_app.clone().oneshot(request).await.unwrap();
+
+            assert_eq!(res.status(), StatusCode::OK);
+            vnu(res).await;
+        }
+        for (action, status) in [
+            (crate::lists::ComposeAction::Preview, StatusCode::OK),
+            (crate::lists::ComposeAction::Send, StatusCode::SEE_OTHER),
+        ] {
+            let mut request = req!(post "/list/new-name/edit/compose/",
+                crate::lists::ComposePayload {
+                    subject: "News from {{ list.name }}".to_string(),
+                    body: "Hello.".to_string(),
+                    send_at: String::new(),
+                    action,
+                }
+            );
+            request
+                .headers_mut()
+                .insert(COOKIE, session_cookie.to_owned());
+            let res = login_app.clone().oneshot(request).await.unwrap();
+
+            assert_eq!(res.status(), status);
+            if status == StatusCode::OK {
+                let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                let body = String::from_utf8_lossy(&body);
+                assert!(body.contains("Subject: News from new name"));
+                // The preview includes the dry run of the post.
+                assert!(body.contains("Final action: "));
+            }
+        }
+        assert!(db
+            .post_events(Some(list.pk()), None, None)
+            .unwrap()
+            .iter()
+            .any(|e| e.kind == mailpot::models::PostEventKind::Action));
+
+        // ------------------------------------------------------------
+        // list_post(), list_search() and raw downloads with redacted addresses
+
+        {
+            let msg_id = "<abcdefgh@sator.example.com>";
+            let trusted_db = Connection::open_db(config.clone()).unwrap().trusted();
+            trusted_db
+                .update_list(mailpot::models::changesets::MailingListChangeset {
+                    pk: list.pk(),
+                    archive_addresses: Some(mailpot::models::ArchiveAddresses::Obfuscate),
+                    ..Default::default()
+                })
+                .unwrap();
+            let res = create_app(state.clone())
+                .oneshot(req!(
+                    get & format!(
+                        "/list/{pk}/posts/{msgid}/",
+                        pk = list.pk(),
+                        msgid = utf8_percent_encode(msg_id, mailpot::PATH_SEGMENT)
+                    )
+                ))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body = String::from_utf8_lossy(&body);
+            assert!(body.contains("Name &lt;user at example.com&gt;"), "{body}");
+            assert!(!body.contains("user@example.com"), "{body}");
+            assert!(!body.contains("/raw/"), "{body}");
+
+            // Raw messages may have encoded headers and bodies, which cannot
+            // be redacted.
+            for path in ["/raw/", "/eml/", "/mbox/"] {
+                let res = create_app(state.clone())
+                    .oneshot(req!(
+                        get & format!(
+                            "/list/{pk}/posts/{msgid}{path}",
+                            pk = list.pk(),
+                            msgid = utf8_percent_encode(msg_id, mailpot::PATH_SEGMENT)
+                        )
+                    ))
+                    .await
+                    .unwrap();
+                assert_eq!(res.status(), StatusCode::NOT_FOUND, "{path}");
+            }
+
+            // The sender's address is not searchable, but the rest of the
+            // post is.
+            for (query, found) in [("user", false), ("post", true)] {
+                let res = create_app(state.clone())
+                    .oneshot(req!(get & format!("/list/{}/search/?q={query}", list.pk())))
+                    .await
+                    .unwrap();
+                let status = res.status();
+                let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                let body = String::from_utf8_lossy(&body);
+                assert_eq!(status, StatusCode::OK, "{body}");
+                assert_eq!(
+                    body.contains("abcdefgh@sator.example.com"),
+                    found,
+                    "{query}: {body}"
+                );
+                assert!(!body.contains("user@example.com"), "{query}: {body}");
+            }
+
+            trusted_db
+                .update_list(mailpot::models::changesets::MailingListChangeset {
+                    pk: list.pk(),
+                    archive_raw_download: Some(false),
+                    ..Default::default()
+                })
+                .unwrap();
+            let res = create_app(state.clone())
+                .oneshot(req!(
+                    get & format!(
+                        "/list/{pk}/posts/{msgid}/raw/",
+                        pk = list.pk(),
+                        msgid = utf8_percent_encode(msg_id, mailpot::PATH_SEGMENT)
+                    )
+                ))
+                .await
+                .unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+
+        // ------------------------------------------------------------
+        // list_post() of a removed post
+
+        {
+            let msg_id = "<abcdefgh@sator.example.com>";
+            Connection::open_db(config.clone())
+                .unwrap()
+                .trusted()
+                .remove_post(list.pk(), msg_id, "Off-topic", "admin")
+                .unwrap();
+            let res = create_app(state.clone())
+                .oneshot(req!(
+                    get & format!(
+                        "/list/{pk}/posts/{msgid}/",
+                        pk = list.pk(),
+                        msgid = utf8_percent_encode(msg_id, mailpot::PATH_SEGMENT)
+                    )
+                ))
+                .await
+                .unwrap();
+
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body = String::from_utf8_lossy(&body);
+            assert!(body.contains("This post was removed by the moderators"));
+            assert!(body.contains("Reason: Off-topic"));
+        }
     }
 }
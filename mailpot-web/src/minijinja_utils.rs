@@ -22,9 +22,10 @@
 use minijinja::{value::Value, Environment};
 
 use crate::typed_paths::{
-    help_path, list_candidates_path, list_edit_path, list_path, list_post_path, list_settings_path,
-    list_subscribers_path, login_path, logout_path, post_eml_path, post_mbox_path, post_raw_path,
-    settings_path,
+    help_path, list_candidates_path, list_compose_path, list_edit_path, list_events_path,
+    list_path, list_post_path, list_search_path, list_settings_path, list_subscribers_path,
+    login_path, logout_path, post_eml_path, post_mbox_path, post_raw_path, settings_path,
+    settings_privacy_erase_path, settings_privacy_export_path,
 };
 
 mod compressed;
@@ -55,12 +56,17 @@ lazy_static::lazy_static! {
             login_path,
             logout_path,
             settings_path,
+            settings_privacy_export_path,
+            settings_privacy_erase_path,
             help_path,
             list_path,
             list_settings_path,
             list_edit_path,
             list_subscribers_path,
             list_candidates_path,
+            list_events_path,
+            list_compose_path,
+            list_search_path,
             list_post_path,
             post_raw_path,
             post_eml_path,
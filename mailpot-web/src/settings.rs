@@ -21,23 +21,35 @@ use std::sync::Arc;
 
 use axum::{
     extract::State,
-    response::{Html, Redirect},
+    response::{Html, IntoResponse, Redirect},
     Extension, Form,
 };
 use axum_extra::routing::TypedPath;
 use axum_sessions::extractors::WritableSession;
 use http::StatusCode;
-use mailpot::models::{
-    changesets::{AccountChangeset, ListSubscriptionChangeset},
-    DbVal, ListSubscription,
+use mailpot::{
+    melib::{
+        self,
+        email::{attachment_types::ContentType, attachments::AttachmentBuilder},
+        HeaderName,
+    },
+    models::{
+        changesets::{AccountChangeset, ListSubscriptionChangeset},
+        DbVal, ListSubscription,
+    },
+    tokens::TokenAction,
 };
 
 use crate::{
     auth::User,
     minijinja_utils::TEMPLATES,
-    typed_paths::{IntoCrumb, ListPathIdentifier, ListSettingsPath, SettingsPath},
+    typed_paths::{
+        IntoCrumb, ListPath, ListPathIdentifier, ListSettingsPath, SettingsPath,
+        SettingsPrivacyErasePath, SettingsPrivacyExportPath, SubscriptionConfirmPath,
+        SubscriptionTokenPath, SubscriptionUnsubscribePath,
+    },
     utils::{Crumb, IntPOST, Level, Message, SessionMessages},
-    AppState, Connection, IntoResponseErrorResult, ResponseError,
+    AppState, AuthContext, Connection, IntoResponseErrorResult, ResponseError,
 };
 
 pub async fn settings(
@@ -259,6 +271,64 @@ pub async fn settings_POST(
     )))
 }
 
+/// Download everything stored about the account's address in JSON format.
+pub async fn settings_privacy_export(
+    _: SettingsPrivacyExportPath,
+    Extension(user): Extension<User>,
+    state: Arc<AppState>,
+) -> Result<impl IntoResponse, ResponseError> {
+    let db = Connection::open_db(state.conf.clone())?;
+    let data = db.personal_data(&user.address)?;
+    let mut response = serde_json::to_vec_pretty(&data)?.into_response();
+    response.headers_mut().insert(
+        http::header::CONTENT_TYPE,
+        http::HeaderValue::from_static("application/json"),
+    );
+    response.headers_mut().insert(
+        http::header::CONTENT_DISPOSITION,
+        http::HeaderValue::from_static("attachment; filename=\"personal-data.json\""),
+    );
+    Ok(response)
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct PrivacyErasePayload {
+    /// The account's address, retyped as confirmation.
+    pub address: String,
+}
+
+/// Erase the account's address and everything stored about it, then log out.
+#[allow(non_snake_case)]
+pub async fn settings_privacy_erase_POST(
+    _: SettingsPrivacyErasePath,
+    mut session: WritableSession,
+    mut auth: AuthContext,
+    Extension(user): Extension<User>,
+    Form(payload): Form<PrivacyErasePayload>,
+    state: Arc<AppState>,
+) -> Result<Redirect, ResponseError> {
+    if !payload.address.trim().eq_ignore_ascii_case(&user.address) {
+        session.add_message(Message {
+            message: "The address you entered does not match your account's address.".into(),
+            level: Level::Error,
+        })?;
+        return Ok(Redirect::to(&format!(
+            "{}{}",
+            &state.root_url_prefix,
+            SettingsPath.to_uri()
+        )));
+    }
+    let db = Connection::open_db(state.conf.clone())?.trusted();
+    db.erase_personal_data(&user.address)?;
+    auth.logout().await;
+    state.remove_user(user.pk).await;
+    session.add_message(Message {
+        message: "Your personal data have been erased.".into(),
+        level: Level::Success,
+    })?;
+    Ok(Redirect::to(&format!("{}/", &state.root_url_prefix)))
+}
+
 pub async fn user_list_subscription(
     ListSettingsPath(id): ListSettingsPath,
     mut session: WritableSession,
@@ -318,6 +388,7 @@ pub async fn user_list_subscription(
         },
     ];
 
+    let topics = db.subscription_topics(subscription.pk())?;
     let list_owners = db.list_owners(list.pk)?;
     let mut list = crate::minijinja_utils::MailingList::from(list);
     list.set_safety(list_owners.as_slice(), &state.conf.administrators);
@@ -326,6 +397,7 @@ pub async fn user_list_subscription(
         user => user,
         list => list,
         subscription => subscription,
+        topics => topics,
         current_user => user,
         messages => session.drain_messages(),
         crumbs => crumbs,
@@ -349,6 +421,33 @@ pub struct SubscriptionFormPayload {
     pub receive_own_posts: bool,
     #[serde(default)]
     pub receive_confirmation: bool,
+    /// Chosen list topics, see [`mailpot::topics`].
+    #[serde(default)]
+    pub topics: Vec<String>,
+    #[serde(default)]
+    pub everything_else: bool,
+}
+
+/// Collect the form's fields; checkboxes of chosen topics share the `topic`
+/// name, which can't be deserialized to a struct field directly.
+impl FromIterator<(String, String)> for SubscriptionFormPayload {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        let mut ret = Self::default();
+        for (key, value) in iter {
+            let toggle = value == "true";
+            match key.as_str() {
+                "digest" => ret.digest = toggle,
+                "hide_address" => ret.hide_address = toggle,
+                "receive_duplicates" => ret.receive_duplicates = toggle,
+                "receive_own_posts" => ret.receive_own_posts = toggle,
+                "receive_confirmation" => ret.receive_confirmation = toggle,
+                "topic" => ret.topics.push(value),
+                "everything_else" => ret.everything_else = toggle,
+                _ => {}
+            }
+        }
+        ret
+    }
 }
 
 #[allow(non_snake_case)]
@@ -356,7 +455,7 @@ pub async fn user_list_subscription_POST(
     ListSettingsPath(id): ListSettingsPath,
     mut session: WritableSession,
     Extension(user): Extension<User>,
-    Form(payload): Form<SubscriptionFormPayload>,
+    Form(payload): Form<Vec<(String, String)>>,
     state: Arc<AppState>,
 ) -> Result<Redirect, ResponseError> {
     let db = Connection::open_db(state.conf.clone())?;
@@ -395,7 +494,9 @@ pub async fn user_list_subscription_POST(
         receive_duplicates,
         receive_own_posts,
         receive_confirmation,
-    } = payload;
+        mut topics,
+        everything_else,
+    } = payload.into_iter().collect();
 
     let cset = ListSubscriptionChangeset {
         list: s.list,
@@ -414,6 +515,25 @@ pub async fn user_list_subscription_POST(
     db.update_subscription(cset)
         .with_status(StatusCode::BAD_REQUEST)?;
 
+    if !list.topics.is_empty() {
+        if everything_else {
+            topics.push(mailpot::topics::EVERYTHING_ELSE.to_string());
+        }
+        match mailpot::topics::parse_topics_request(&list, s.pk(), &topics)
+            .with_status(StatusCode::BAD_REQUEST)?
+        {
+            Some(topics) => {
+                db.set_subscription_topics(topics)
+                    .with_status(StatusCode::BAD_REQUEST)?;
+            }
+            None => {
+                if db.subscription_topics(s.pk())?.is_some() {
+                    db.remove_subscription_topics(s.pk())?;
+                }
+            }
+        }
+    }
+
     session.add_message(Message {
         message: "Settings saved successfully.".into(),
         level: Level::Success,
@@ -425,3 +545,333 @@ pub async fn user_list_subscription_POST(
         ListSettingsPath(list.id.clone().into()).to_uri()
     )))
 }
+
+pub async fn subscription_token(
+    SubscriptionTokenPath(token): SubscriptionTokenPath,
+    mut session: WritableSession,
+    auth: AuthContext,
+    state: Arc<AppState>,
+) -> Result<Html<String>, ResponseError> {
+    let db = Connection::open_db(state.conf.clone())?;
+    let claim = db
+        .verify_subscription_token(&token)
+        .with_status(StatusCode::NOT_FOUND)?;
+    let Some(list) = db.list(claim.list)? else {
+        return Err(ResponseError::new(
+            "List not found".to_string(),
+            StatusCode::NOT_FOUND,
+        ));
+    };
+    let subscription = db
+        .list_subscription_by_address(list.pk(), &claim.address)
+        .ok();
+
+    let crumbs = vec![
+        Crumb {
+            label: "Home".into(),
+            url: "/".into(),
+        },
+        Crumb {
+            label: list.name.clone().into(),
+            url: ListPath(list.id.to_string().into()).to_crumb(),
+        },
+        Crumb {
+            label: "Your subscription".into(),
+            url: SubscriptionTokenPath(token).to_crumb(),
+        },
+    ];
+    let list_owners = db.list_owners(list.pk)?;
+    let mut list = crate::minijinja_utils::MailingList::from(list);
+    list.set_safety(list_owners.as_slice(), &state.conf.administrators);
+    let context = minijinja::context! {
+        page_title => "Your subscription",
+        list => list,
+        address => claim.address,
+        subscription => subscription,
+        settings => claim.action == TokenAction::Settings,
+        current_user => auth.current_user,
+        messages => session.drain_messages(),
+        crumbs => crumbs,
+    };
+    Ok(Html(
+        TEMPLATES
+            .get_template("subscription_token.html")?
+            .render(context)?,
+    ))
+}
+
+#[allow(non_snake_case)]
+pub async fn subscription_token_POST(
+    SubscriptionTokenPath(token): SubscriptionTokenPath,
+    mut session: WritableSession,
+    Form(payload): Form<Vec<(String, String)>>,
+    state: Arc<AppState>,
+) -> Result<Redirect, ResponseError> {
+    let db = Connection::open_db(state.conf.clone())?;
+    let claim = db
+        .verify_subscription_token(&token)
+        .with_status(StatusCode::NOT_FOUND)?;
+    let Ok(subscription) = db.list_subscription_by_address(claim.list, &claim.address) else {
+        session.add_message(Message {
+            message: "You are already not subscribed to this list.".into(),
+            level: Level::Info,
+        })?;
+        return Ok(Redirect::to(&format!(
+            "{}{}",
+            &state.root_url_prefix,
+            SubscriptionTokenPath(token).to_uri()
+        )));
+    };
+
+    let db = db.trusted();
+    if payload
+        .iter()
+        .any(|(k, v)| k == "type" && v == "unsubscribe")
+    {
+        db.unsubscribe_with_token(&token)
+            .with_status(StatusCode::NOT_FOUND)?;
+        session.add_message(Message {
+            message: "You have unsubscribed from this list.".into(),
+            level: Level::Success,
+        })?;
+    } else if claim.action == TokenAction::Settings {
+        let SubscriptionFormPayload {
+            digest,
+            hide_address,
+            receive_duplicates,
+            receive_own_posts,
+            receive_confirmation,
+            ..
+        } = payload.into_iter().collect();
+        db.update_subscription(ListSubscriptionChangeset {
+            list: claim.list,
+            address: subscription.address.clone(),
+            account: None,
+            name: None,
+            digest: Some(digest),
+            hide_address: Some(hide_address),
+            receive_duplicates: Some(receive_duplicates),
+            receive_own_posts: Some(receive_own_posts),
+            receive_confirmation: Some(receive_confirmation),
+            enabled: None,
+            verified: None,
+        })
+        .with_status(StatusCode::BAD_REQUEST)?;
+        session.add_message(Message {
+            message: "Settings saved successfully.".into(),
+            level: Level::Success,
+        })?;
+    } else {
+        return Err(ResponseError::new(
+            "This link can only be used to unsubscribe.".to_string(),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    Ok(Redirect::to(&format!(
+        "{}{}",
+        &state.root_url_prefix,
+        SubscriptionTokenPath(token).to_uri()
+    )))
+}
+
+/// Opening a one-click unsubscription link shows the subscription page, which
+/// asks for confirmation.
+pub async fn subscription_unsubscribe(
+    SubscriptionUnsubscribePath(token): SubscriptionUnsubscribePath,
+    state: Arc<AppState>,
+) -> Redirect {
+    Redirect::to(&format!(
+        "{}{}",
+        &state.root_url_prefix,
+        SubscriptionTokenPath(token).to_uri()
+    ))
+}
+
+/// Returns whether `body` has a `List-Unsubscribe` form field with the value
+/// `One-Click`, either form URL encoded or as `multipart/form-data`.
+fn is_one_click_request(content_type: &str, body: &[u8]) -> bool {
+    const NAME: &str = "List-Unsubscribe";
+    const VALUE: &str = "One-Click";
+
+    if !content_type
+        .trim_start()
+        .to_ascii_lowercase()
+        .starts_with("multipart/form-data")
+    {
+        return serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+            .is_ok_and(|fields| fields.iter().any(|(n, v)| n == NAME && v == VALUE));
+    }
+    let mut entity = format!("Content-Type: {content_type}\r\n\r\n").into_bytes();
+    entity.extend_from_slice(body);
+    let entity = AttachmentBuilder::new(&entity).build();
+    let ContentType::Multipart { ref parts, .. } = entity.content_type else {
+        return false;
+    };
+    parts.iter().any(|part| {
+        let Ok((_, (headers, value))) = melib::email::parser::attachments::attachment(part.raw())
+        else {
+            return false;
+        };
+        let is_field = headers
+            .iter()
+            .filter(|(h, _)| *h == HeaderName::CONTENT_DISPOSITION)
+            .any(|(_, v)| {
+                String::from_utf8_lossy(v).split(';').skip(1).any(|param| {
+                    param.split_once('=').is_some_and(|(n, v)| {
+                        n.trim().eq_ignore_ascii_case("name") && v.trim().trim_matches('"') == NAME
+                    })
+                })
+            });
+        is_field && value.trim_ascii() == VALUE.as_bytes()
+    })
+}
+
+/// Remove a subscription on an [RFC 8058](https://www.rfc-editor.org/rfc/rfc8058)
+/// one-click unsubscription request.
+///
+/// The request must have a `List-Unsubscribe` field with the value
+/// `One-Click`, either form URL encoded or as `multipart/form-data`. No login
+/// is needed, the signed token is the proof of ownership of the address.
+#[allow(non_snake_case)]
+pub async fn subscription_unsubscribe_POST(
+    SubscriptionUnsubscribePath(token): SubscriptionUnsubscribePath,
+    headers: http::HeaderMap,
+    body: axum::body::Bytes,
+    state: Arc<AppState>,
+) -> Result<&'static str, ResponseError> {
+    let content_type = headers
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if !is_one_click_request(content_type, &body) {
+        return Err(ResponseError::new(
+            "Expected a List-Unsubscribe=One-Click request.".to_string(),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    let db = Connection::open_db(state.conf.clone())?.trusted();
+    Ok(
+        match db
+            .unsubscribe_with_token(&token)
+            .with_status(StatusCode::NOT_FOUND)?
+        {
+            Some(_) => "You have unsubscribed from this list.",
+            None => "You are already not subscribed to this list.",
+        },
+    )
+}
+
+pub async fn subscription_confirm(
+    SubscriptionConfirmPath(token): SubscriptionConfirmPath,
+    mut session: WritableSession,
+    auth: AuthContext,
+    state: Arc<AppState>,
+) -> Result<Html<String>, ResponseError> {
+    let db = Connection::open_db(state.conf.clone())?;
+    let not_found = || {
+        ResponseError::new(
+            "Invalid or expired confirmation.".to_string(),
+            StatusCode::NOT_FOUND,
+        )
+    };
+    let now = chrono::offset::Utc::now().timestamp() as u64;
+    let (subscription, unsubscribe, reconfirm) =
+        if let Some(subscription) = db.pending_subscription(&token, now)? {
+            (subscription, false, false)
+        } else if let Some(subscription) = db.pending_reconfirmation(&token, now)? {
+            (subscription, false, true)
+        } else {
+            (
+                db.pending_unsubscription(&token, now)?
+                    .ok_or_else(not_found)?,
+                true,
+                false,
+            )
+        };
+    let list = db.list(subscription.list)?.ok_or_else(not_found)?;
+    let page_title = if unsubscribe {
+        "Confirm unsubscription"
+    } else {
+        "Confirm subscription"
+    };
+
+    let crumbs = vec![
+        Crumb {
+            label: "Home".into(),
+            url: "/".into(),
+        },
+        Crumb {
+            label: list.name.clone().into(),
+            url: ListPath(list.id.to_string().into()).to_crumb(),
+        },
+        Crumb {
+            label: page_title.into(),
+            url: SubscriptionConfirmPath(token).to_crumb(),
+        },
+    ];
+    let list_owners = db.list_owners(list.pk)?;
+    let mut list = crate::minijinja_utils::MailingList::from(list);
+    list.set_safety(list_owners.as_slice(), &state.conf.administrators);
+    let context = minijinja::context! {
+        page_title => page_title,
+        list => list,
+        subscription => subscription,
+        unsubscribe => unsubscribe,
+        reconfirm => reconfirm,
+        current_user => auth.current_user,
+        messages => session.drain_messages(),
+        crumbs => crumbs,
+    };
+    Ok(Html(
+        TEMPLATES
+            .get_template("subscription_confirm.html")?
+            .render(context)?,
+    ))
+}
+
+fn list_of(
+    db: &Connection,
+    subscription: &DbVal<ListSubscription>,
+) -> Result<DbVal<mailpot::models::MailingList>, ResponseError> {
+    db.list(subscription.list)?
+        .ok_or_else(|| ResponseError::new("List not found".to_string(), StatusCode::NOT_FOUND))
+}
+
+#[allow(non_snake_case)]
+pub async fn subscription_confirm_POST(
+    SubscriptionConfirmPath(token): SubscriptionConfirmPath,
+    mut session: WritableSession,
+    state: Arc<AppState>,
+) -> Result<Redirect, ResponseError> {
+    let db = Connection::open_db(state.conf.clone())?.trusted();
+    let now = chrono::offset::Utc::now().timestamp() as u64;
+    let (list, message) = if db.pending_subscription(&token, now)?.is_some() {
+        let subscription = db.confirm_subscription(&token, now)?;
+        let list = list_of(&db, &subscription)?;
+        db.send_subscription_confirmation(&list, &subscription.address())?;
+        (list, "You have successfully subscribed to this list.")
+    } else if db.pending_reconfirmation(&token, now)?.is_some() {
+        let subscription = db.reconfirm_subscription(&token, now)?;
+        (
+            list_of(&db, &subscription)?,
+            "You have successfully confirmed your subscription to this list.",
+        )
+    } else {
+        let subscription = db
+            .confirm_unsubscription(&token, now)
+            .with_status(StatusCode::NOT_FOUND)?;
+        let list = list_of(&db, &subscription)?;
+        db.send_unsubscription_confirmation(&list, &subscription.address())?;
+        (list, "You have successfully unsubscribed from this list.")
+    };
+    session.add_message(Message {
+        message: message.into(),
+        level: Level::Success,
+    })?;
+    Ok(Redirect::to(&format!(
+        "{}{}",
+        &state.root_url_prefix,
+        ListPath(list.id.clone().into()).to_uri()
+    )))
+}
@@ -99,6 +99,10 @@ pub struct ListPostEmlPath(pub ListPathIdentifier, pub String);
 #[typed_path("/list/:id/posts/:msgid/mbox/")]
 pub struct ListPostMboxPath(pub ListPathIdentifier, pub String);
 
+#[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize, TypedPath)]
+#[typed_path("/list/:id/search/")]
+pub struct ListSearchPath(pub ListPathIdentifier);
+
 #[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize, TypedPath)]
 #[typed_path("/list/:id/edit/")]
 pub struct ListEditPath(pub ListPathIdentifier);
@@ -111,10 +115,36 @@ pub struct ListEditSubscribersPath(pub ListPathIdentifier);
 #[typed_path("/list/:id/edit/candidates/")]
 pub struct ListEditCandidatesPath(pub ListPathIdentifier);
 
+#[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize, TypedPath)]
+#[typed_path("/list/:id/edit/events/")]
+pub struct ListEditEventsPath(pub ListPathIdentifier);
+
+#[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize, TypedPath)]
+#[typed_path("/list/:id/edit/compose/")]
+pub struct ListEditComposePath(pub ListPathIdentifier);
+
 #[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize, TypedPath)]
 #[typed_path("/settings/list/:id/")]
 pub struct ListSettingsPath(pub ListPathIdentifier);
 
+/// Subscription management without logging in, with a signed
+/// [`SubscriptionToken`](mailpot::tokens::SubscriptionToken).
+#[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize, TypedPath)]
+#[typed_path("/subscription/:token/")]
+pub struct SubscriptionTokenPath(pub String);
+
+/// [RFC 8058](https://www.rfc-editor.org/rfc/rfc8058) one-click unsubscription
+/// with a signed [`SubscriptionToken`](mailpot::tokens::SubscriptionToken).
+#[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize, TypedPath)]
+#[typed_path("/subscription/:token/unsubscribe/")]
+pub struct SubscriptionUnsubscribePath(pub String);
+
+/// Confirmation of an e-mail subscription, see
+/// [`confirmations`](mailpot::confirmations).
+#[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize, TypedPath)]
+#[typed_path("/subscription/confirm/:token/")]
+pub struct SubscriptionConfirmPath(pub String);
+
 #[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize, TypedPath)]
 #[typed_path("/login/")]
 pub struct LoginPath;
@@ -127,6 +157,14 @@ pub struct LogoutPath;
 #[typed_path("/settings/")]
 pub struct SettingsPath;
 
+#[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize, TypedPath)]
+#[typed_path("/settings/privacy/export/")]
+pub struct SettingsPrivacyExportPath;
+
+#[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize, TypedPath)]
+#[typed_path("/settings/privacy/erase/")]
+pub struct SettingsPrivacyErasePath;
+
 #[derive(Debug, PartialEq, Eq, Clone, serde::Deserialize, serde::Serialize, TypedPath)]
 #[typed_path("/help/")]
 pub struct HelpPath;
@@ -146,6 +184,8 @@ macro_rules! unit_impl {
 unit_impl!(login_path, LoginPath);
 unit_impl!(logout_path, LogoutPath);
 unit_impl!(settings_path, SettingsPath);
+unit_impl!(settings_privacy_export_path, SettingsPrivacyExportPath);
+unit_impl!(settings_privacy_erase_path, SettingsPrivacyErasePath);
 unit_impl!(help_path, HelpPath);
 
 macro_rules! list_id_impl {
@@ -173,6 +213,9 @@ list_id_impl!(list_settings_path, ListSettingsPath);
 list_id_impl!(list_edit_path, ListEditPath);
 list_id_impl!(list_subscribers_path, ListEditSubscribersPath);
 list_id_impl!(list_candidates_path, ListEditCandidatesPath);
+list_id_impl!(list_events_path, ListEditEventsPath);
+list_id_impl!(list_compose_path, ListEditComposePath);
+list_id_impl!(list_search_path, ListSearchPath);
 
 macro_rules! list_post_impl {
     ($ident:ident, $ty:tt) => {
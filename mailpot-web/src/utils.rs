@@ -326,59 +326,6 @@ pub fn thread(
     ret
 }
 
-pub fn thread_roots(
-    envelopes: &Arc<std::sync::RwLock<HashMap<melib::EnvelopeHash, melib::Envelope>>>,
-    threads: &melib::Threads,
-) -> Vec<(ThreadEntry, usize, u64)> {
-    let items = threads.roots();
-    let env_lock = envelopes.read().unwrap();
-    let mut ret = vec![];
-    'items_for_loop: for thread in items {
-        let mut iter_ptr = threads.thread_ref(thread).root();
-        let thread_node = &threads.thread_nodes()[&iter_ptr];
-        let root_env_hash = if let Some(h) = thread_node.message().or_else(|| {
-            if thread_node.children().is_empty() {
-                return None;
-            }
-            iter_ptr = thread_node.children()[0];
-            while threads.thread_nodes()[&iter_ptr].message().is_none() {
-                if threads.thread_nodes()[&iter_ptr].children().is_empty() {
-                    return None;
-                }
-                iter_ptr = threads.thread_nodes()[&iter_ptr].children()[0];
-            }
-            threads.thread_nodes()[&iter_ptr].message()
-        }) {
-            h
-        } else {
-            continue 'items_for_loop;
-        };
-        if !env_lock.contains_key(&root_env_hash) {
-            panic!("key = {}", root_env_hash);
-        }
-        let envelope: &melib::Envelope = &env_lock[&root_env_hash];
-        let tref = threads.thread_ref(thread);
-        ret.push((
-            ThreadEntry {
-                hash: root_env_hash,
-                depth: 0,
-                thread_node: iter_ptr,
-                thread,
-                message_id: envelope.message_id().to_string(),
-                from: envelope.field_from_to_string(),
-                datetime: envelope.date_as_str().to_string(),
-                timestamp: envelope.timestamp,
-            },
-            tref.len,
-            tref.date,
-        ));
-    }
-    // clippy: error: temporary with significant `Drop` can be early dropped
-    drop(env_lock);
-    ret.sort_by_key(|(_, _, key)| std::cmp::Reverse(*key));
-    ret
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
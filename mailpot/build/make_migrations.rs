@@ -101,7 +101,7 @@ pub fn make_migrations<M: AsRef<::std::path::Path>, O: AsRef<::std::path::Path>>
             schema_file.extend(b"\n\n-- ".iter());
             schema_file.extend(num.as_bytes().iter());
             schema_file.extend(b".data.sql\n\n".iter());
-            schema_file.extend(redo.into_bytes().into_iter());
+            schema_file.extend(redo.into_bytes());
         }
     }
     migr_rs.write_all(b"]").unwrap();
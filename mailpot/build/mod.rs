@@ -165,7 +165,7 @@ use clap::builder::TypedValueParser;
             type Err = String;
 
             fn from_str(s: &str) -> Result<Self, Self::Err> {
-                #![allow(clippy::suspicious_else_formatting)]
+                #![allow(clippy::suspicious_else_formatting, clippy::possible_missing_else)]
 
                 #(if s.eq_ignore_ascii_case(stringify!(#names)) {
                     return Ok(Self::#names);
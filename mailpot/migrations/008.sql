@@ -0,0 +1,29 @@
+PRAGMA foreign_keys=ON;
+
+CREATE TABLE IF NOT EXISTS access_entry (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  list             INTEGER,
+  kind             TEXT CHECK (kind IN ('allow', 'deny')) NOT NULL,
+  match_type       TEXT CHECK (match_type IN ('exact', 'glob', 'domain')) NOT NULL,
+  pattern          TEXT NOT NULL,
+  post             BOOLEAN CHECK (post IN (0, 1)) NOT NULL DEFAULT 1, -- BOOLEAN FALSE == 0, BOOLEAN TRUE == 1
+  subscribe        BOOLEAN CHECK (subscribe IN (0, 1)) NOT NULL DEFAULT 1,
+  comment          TEXT,
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  last_modified    INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (list) REFERENCES list(pk) ON DELETE CASCADE,
+  UNIQUE (list, kind, match_type, pattern) ON CONFLICT ROLLBACK
+);
+
+CREATE INDEX IF NOT EXISTS access_entry_list_idx ON access_entry(list);
+
+-- [tag:last_modified_access_entry]: update last_modified on every change.
+CREATE TRIGGER
+IF NOT EXISTS last_modified_access_entry
+AFTER UPDATE ON access_entry
+FOR EACH ROW
+WHEN NEW.last_modified == OLD.last_modified
+BEGIN
+  UPDATE access_entry SET last_modified = unixepoch()
+  WHERE pk = NEW.pk;
+END;
@@ -0,0 +1,5 @@
+PRAGMA foreign_keys=ON;
+
+DROP TRIGGER IF EXISTS last_modified_access_entry;
+DROP INDEX IF EXISTS access_entry_list_idx;
+DROP TABLE access_entry;
@@ -0,0 +1,24 @@
+PRAGMA foreign_keys=ON;
+
+CREATE TABLE IF NOT EXISTS post_event (
+  pk              INTEGER PRIMARY KEY NOT NULL,
+  list            INTEGER,
+  message_id      TEXT NOT NULL,
+  kind            TEXT
+                  CHECK (
+                    kind IN
+                    ('filter',
+                     'action',
+                     'job',
+                     'delivery',
+                     'error')
+                  ) NOT NULL,
+  name            TEXT NOT NULL,
+  detail          TEXT,
+  timestamp       INTEGER NOT NULL DEFAULT (unixepoch()),
+  datetime        TEXT NOT NULL DEFAULT (datetime()),
+  FOREIGN KEY (list) REFERENCES list(pk) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS post_event_msgid_idx ON post_event(message_id);
+CREATE INDEX IF NOT EXISTS post_event_list_idx ON post_event(list);
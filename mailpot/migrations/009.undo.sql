@@ -0,0 +1,5 @@
+PRAGMA foreign_keys=ON;
+
+DROP INDEX IF EXISTS post_event_list_idx;
+DROP INDEX IF EXISTS post_event_msgid_idx;
+DROP TABLE post_event;
@@ -0,0 +1,22 @@
+PRAGMA foreign_keys=ON;
+
+CREATE TABLE IF NOT EXISTS list_openpgp_key (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  list             INTEGER NOT NULL UNIQUE,
+  fingerprint      TEXT NOT NULL,
+  secret_key       TEXT NOT NULL,
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  last_modified    INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (list) REFERENCES list(pk) ON DELETE CASCADE
+);
+
+-- [tag:last_modified_list_openpgp_key]: update last_modified on every change.
+CREATE TRIGGER
+IF NOT EXISTS last_modified_list_openpgp_key
+AFTER UPDATE ON list_openpgp_key
+FOR EACH ROW
+WHEN NEW.last_modified == OLD.last_modified
+BEGIN
+  UPDATE list_openpgp_key SET last_modified = unixepoch()
+  WHERE pk = NEW.pk;
+END;
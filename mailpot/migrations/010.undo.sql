@@ -0,0 +1,4 @@
+PRAGMA foreign_keys=ON;
+
+DROP TRIGGER IF EXISTS last_modified_list_openpgp_key;
+DROP TABLE list_openpgp_key;
@@ -0,0 +1,35 @@
+INSERT OR REPLACE INTO settings_json_schema(id, value) VALUES('VerifySignatureSettings', '{
+  "$schema": "http://json-schema.org/draft-07/schema",
+  "$ref": "#/$defs/VerifySignatureSettings",
+  "$defs": {
+    "VerifySignatureSettings": {
+      "title": "VerifySignatureSettings",
+      "description": "Settings for VerifySignature message filter",
+      "type": "object",
+      "properties": {
+        "enabled": {
+          "title": "If true, list posts must be PGP/MIME signed with the OpenPGP key of the account of the sender.",
+          "type": "boolean"
+        },
+        "allow_subscribers": {
+          "title": "Accept signatures by keys of subscribers",
+          "description": "By default only keys of the accounts of list owners are accepted. If true, keys of the accounts of subscribers are accepted as well.",
+          "type": "boolean",
+          "default": false
+        },
+        "on_failure": {
+          "title": "What to do with unsigned or badly signed posts",
+          "type": "string",
+          "enum": [
+            "reject",
+            "hold"
+          ],
+          "default": "reject"
+        }
+      },
+      "required": [
+        "enabled"
+      ]
+    }
+  }
+}');
@@ -0,0 +1 @@
+DELETE FROM settings_json_schema WHERE id = 'VerifySignatureSettings';
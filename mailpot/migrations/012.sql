@@ -0,0 +1,28 @@
+PRAGMA foreign_keys=ON;
+
+CREATE TABLE IF NOT EXISTS list_inclusion (
+  pk                   INTEGER PRIMARY KEY NOT NULL,
+  parent               INTEGER NOT NULL,
+  child                INTEGER NOT NULL,
+  inherit_post_rights  BOOLEAN CHECK (inherit_post_rights IN (0, 1)) NOT NULL
+                       DEFAULT 0, -- BOOLEAN FALSE == 0, BOOLEAN TRUE == 1
+  created              INTEGER NOT NULL DEFAULT (unixepoch()),
+  last_modified        INTEGER NOT NULL DEFAULT (unixepoch()),
+  CHECK (parent != child),
+  FOREIGN KEY (parent) REFERENCES list(pk) ON DELETE CASCADE,
+  FOREIGN KEY (child) REFERENCES list(pk) ON DELETE CASCADE,
+  UNIQUE (parent, child) ON CONFLICT ROLLBACK
+);
+
+CREATE INDEX IF NOT EXISTS list_inclusion_parent_idx ON list_inclusion(parent);
+
+-- [tag:last_modified_list_inclusion]: update last_modified on every change.
+CREATE TRIGGER
+IF NOT EXISTS last_modified_list_inclusion
+AFTER UPDATE ON list_inclusion
+FOR EACH ROW
+WHEN NEW.last_modified == OLD.last_modified
+BEGIN
+  UPDATE list_inclusion SET last_modified = unixepoch()
+  WHERE pk = NEW.pk;
+END;
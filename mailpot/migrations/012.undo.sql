@@ -0,0 +1,5 @@
+PRAGMA foreign_keys=ON;
+
+DROP TRIGGER IF EXISTS last_modified_list_inclusion;
+DROP INDEX IF EXISTS list_inclusion_parent_idx;
+DROP TABLE list_inclusion;
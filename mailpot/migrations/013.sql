@@ -0,0 +1,23 @@
+PRAGMA foreign_keys=ON;
+
+CREATE TABLE IF NOT EXISTS subscription_topics (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  subscription     INTEGER NOT NULL UNIQUE,
+  topics           JSON NOT NULL CHECK (json_type(topics) = 'array') DEFAULT '[]',
+  everything_else  BOOLEAN CHECK (everything_else IN (0, 1)) NOT NULL
+                   DEFAULT 1, -- BOOLEAN FALSE == 0, BOOLEAN TRUE == 1
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  last_modified    INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (subscription) REFERENCES subscription(pk) ON DELETE CASCADE
+);
+
+-- [tag:last_modified_subscription_topics]: update last_modified on every change.
+CREATE TRIGGER
+IF NOT EXISTS last_modified_subscription_topics
+AFTER UPDATE ON subscription_topics
+FOR EACH ROW
+WHEN NEW.last_modified == OLD.last_modified
+BEGIN
+  UPDATE subscription_topics SET last_modified = unixepoch()
+  WHERE pk = NEW.pk;
+END;
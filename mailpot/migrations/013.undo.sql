@@ -0,0 +1,4 @@
+PRAGMA foreign_keys=ON;
+
+DROP TRIGGER IF EXISTS last_modified_subscription_topics;
+DROP TABLE subscription_topics;
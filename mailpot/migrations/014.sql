@@ -0,0 +1,28 @@
+PRAGMA foreign_keys=ON;
+
+ALTER TABLE subscription ADD COLUMN follow_only BOOLEAN CHECK (follow_only IN (0, 1)) NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS thread_preference (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  subscription     INTEGER NOT NULL,
+  thread           TEXT NOT NULL,
+  muted            BOOLEAN CHECK (muted IN (0, 1)) NOT NULL
+                   DEFAULT 0, -- BOOLEAN FALSE == 0, BOOLEAN TRUE == 1
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  last_modified    INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (subscription) REFERENCES subscription(pk) ON DELETE CASCADE,
+  UNIQUE (subscription, thread)
+);
+
+CREATE INDEX IF NOT EXISTS thread_preference_thread_idx ON thread_preference(thread);
+
+-- [tag:last_modified_thread_preference]: update last_modified on every change.
+CREATE TRIGGER
+IF NOT EXISTS last_modified_thread_preference
+AFTER UPDATE ON thread_preference
+FOR EACH ROW
+WHEN NEW.last_modified == OLD.last_modified
+BEGIN
+  UPDATE thread_preference SET last_modified = unixepoch()
+  WHERE pk = NEW.pk;
+END;
@@ -0,0 +1,6 @@
+PRAGMA foreign_keys=ON;
+
+DROP TRIGGER IF EXISTS last_modified_thread_preference;
+DROP INDEX IF EXISTS thread_preference_thread_idx;
+DROP TABLE thread_preference;
+ALTER TABLE subscription DROP COLUMN follow_only;
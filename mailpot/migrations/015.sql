@@ -0,0 +1,32 @@
+PRAGMA foreign_keys=ON;
+
+CREATE TABLE IF NOT EXISTS queue_new (
+  pk              INTEGER PRIMARY KEY NOT NULL,
+  which           TEXT
+                  CHECK (
+                    which IN
+                    ('maildrop',
+                     'hold',
+                     'deferred',
+                     'corrupt',
+                     'error',
+                     'out',
+                     'scheduled')
+                  ) NOT NULL,
+  list            INTEGER,
+  comment         TEXT,
+  to_addresses    TEXT NOT NULL,
+  from_address    TEXT NOT NULL,
+  subject         TEXT NOT NULL,
+  message_id      TEXT NOT NULL,
+  message         BLOB NOT NULL,
+  timestamp       INTEGER NOT NULL DEFAULT (unixepoch()),
+  datetime        TEXT NOT NULL DEFAULT (datetime()),
+  send_at         INTEGER,
+  FOREIGN KEY (list) REFERENCES list(pk) ON DELETE CASCADE,
+  UNIQUE (to_addresses, message_id) ON CONFLICT ROLLBACK
+);
+
+INSERT INTO queue_new SELECT *, NULL FROM queue;
+DROP TABLE queue;
+ALTER TABLE queue_new RENAME TO queue;
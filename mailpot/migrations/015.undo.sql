@@ -0,0 +1,30 @@
+PRAGMA foreign_keys=ON;
+
+CREATE TABLE IF NOT EXISTS queue_old (
+  pk              INTEGER PRIMARY KEY NOT NULL,
+  which           TEXT
+                  CHECK (
+                    which IN
+                    ('maildrop',
+                     'hold',
+                     'deferred',
+                     'corrupt',
+                     'error',
+                     'out')
+                  ) NOT NULL,
+  list            INTEGER,
+  comment         TEXT,
+  to_addresses    TEXT NOT NULL,
+  from_address    TEXT NOT NULL,
+  subject         TEXT NOT NULL,
+  message_id      TEXT NOT NULL,
+  message         BLOB NOT NULL,
+  timestamp       INTEGER NOT NULL DEFAULT (unixepoch()),
+  datetime        TEXT NOT NULL DEFAULT (datetime()),
+  FOREIGN KEY (list) REFERENCES list(pk) ON DELETE CASCADE,
+  UNIQUE (to_addresses, message_id) ON CONFLICT ROLLBACK
+);
+
+INSERT INTO queue_old SELECT pk, which, list, comment, to_addresses, from_address, subject, message_id, message, timestamp, datetime FROM queue WHERE which != 'scheduled';
+DROP TABLE queue;
+ALTER TABLE queue_old RENAME TO queue;
@@ -0,0 +1,9 @@
+PRAGMA foreign_keys=ON;
+
+CREATE TABLE IF NOT EXISTS token_secret (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  secret           BLOB NOT NULL DEFAULT (randomblob(32)),
+  created          INTEGER NOT NULL DEFAULT (unixepoch())
+);
+
+INSERT INTO token_secret DEFAULT VALUES;
@@ -0,0 +1,31 @@
+INSERT OR REPLACE INTO settings_json_schema(id, value) VALUES('PersonaliseSettings', '{
+  "$schema": "http://json-schema.org/draft-07/schema",
+  "$ref": "#/$defs/PersonaliseSettings",
+  "$defs": {
+    "PersonaliseSettings": {
+      "title": "PersonaliseSettings",
+      "description": "Settings for Personalise message filter",
+      "type": "object",
+      "properties": {
+        "enabled": {
+          "title": "If true, each recipient gets a copy of the post with its merge fields rendered.",
+          "description": "The post body is rendered as a Jinja template with the variables `name`, `address`, `unsubscribe_url` and `settings_url` of the recipient. The archived post is rendered with empty values, so use defaults such as `{{ name or \"reader\" }}`.",
+          "type": "boolean"
+        },
+        "base_url": {
+          "title": "URL of the web interface",
+          "description": "Personal unsubscribe and settings links point to this instance of mailpot-web.",
+          "examples": [
+            "https://lists.example.com"
+          ],
+          "type": "string",
+          "pattern": "^https?://.+"
+        }
+      },
+      "required": [
+        "enabled",
+        "base_url"
+      ]
+    }
+  }
+}');
@@ -0,0 +1,31 @@
+INSERT OR REPLACE INTO settings_json_schema(id, value) VALUES('AddListHeadersSettings', '{
+  "$schema": "http://json-schema.org/draft-07/schema",
+  "$ref": "#/$defs/AddListHeadersSettings",
+  "$defs": {
+    "AddListHeadersSettings": {
+      "title": "AddListHeadersSettings",
+      "description": "Settings for AddListHeaders message filter",
+      "type": "object",
+      "properties": {
+        "one_click_unsubscribe": {
+          "title": "If true, each recipient gets an RFC 8058 one-click unsubscribe link.",
+          "description": "The List-Unsubscribe header of the copy of each recipient gets an HTTPS URL with a personal signed token, and a List-Unsubscribe-Post header is added, as required by some providers for bulk senders.",
+          "type": "boolean"
+        },
+        "base_url": {
+          "title": "URL of the web interface",
+          "description": "One-click unsubscribe links point to this instance of mailpot-web.",
+          "examples": [
+            "https://lists.example.com"
+          ],
+          "type": "string",
+          "pattern": "^https://.+"
+        }
+      },
+      "required": [
+        "one_click_unsubscribe",
+        "base_url"
+      ]
+    }
+  }
+}');
@@ -0,0 +1,10 @@
+PRAGMA foreign_keys=ON;
+
+CREATE TABLE IF NOT EXISTS subscription_confirmation (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  subscription     INTEGER NOT NULL UNIQUE,
+  token            TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+  expires          INTEGER NOT NULL,
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (subscription) REFERENCES subscription(pk) ON DELETE CASCADE
+);
@@ -0,0 +1,12 @@
+PRAGMA foreign_keys=ON;
+
+ALTER TABLE list ADD COLUMN confirm_unsubscription BOOLEAN CHECK (confirm_unsubscription IN (0, 1)) NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS unsubscription_confirmation (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  subscription     INTEGER NOT NULL UNIQUE,
+  token            TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+  expires          INTEGER NOT NULL,
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (subscription) REFERENCES subscription(pk) ON DELETE CASCADE
+);
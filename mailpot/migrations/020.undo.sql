@@ -0,0 +1,4 @@
+PRAGMA foreign_keys=ON;
+
+DROP TABLE unsubscription_confirmation;
+ALTER TABLE list DROP COLUMN confirm_unsubscription;
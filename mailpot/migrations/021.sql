@@ -0,0 +1,12 @@
+PRAGMA foreign_keys=ON;
+
+CREATE TABLE IF NOT EXISTS reconfirmation (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  subscription     INTEGER NOT NULL UNIQUE,
+  token            TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+  expires          INTEGER NOT NULL,
+  confirmed        INTEGER,
+  disabled         BOOLEAN CHECK (disabled IN (0, 1)) NOT NULL DEFAULT 0,
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (subscription) REFERENCES subscription(pk) ON DELETE CASCADE
+);
@@ -0,0 +1,13 @@
+PRAGMA foreign_keys=ON;
+
+ALTER TABLE post ADD COLUMN in_reply_to TEXT;
+ALTER TABLE post ADD COLUMN refs TEXT;
+ALTER TABLE post ADD COLUMN thread_root TEXT;
+
+UPDATE post SET
+  in_reply_to = post_in_reply_to(message),
+  refs = post_references(message),
+  thread_root = coalesce(post_thread_root(message), message_id);
+
+CREATE INDEX IF NOT EXISTS post_in_reply_to_idx ON post(list, in_reply_to);
+CREATE INDEX IF NOT EXISTS post_thread_root_idx ON post(list, thread_root);
@@ -0,0 +1,7 @@
+PRAGMA foreign_keys=ON;
+
+DROP INDEX post_in_reply_to_idx;
+DROP INDEX post_thread_root_idx;
+ALTER TABLE post DROP COLUMN in_reply_to;
+ALTER TABLE post DROP COLUMN refs;
+ALTER TABLE post DROP COLUMN thread_root;
@@ -0,0 +1,18 @@
+PRAGMA foreign_keys=ON;
+
+CREATE VIRTUAL TABLE IF NOT EXISTS post_fts USING fts5 (
+  subject,
+  sender,
+  body,
+  tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO post_fts(rowid, subject, sender, body) SELECT pk, post_search_subject(message), post_search_sender(message), post_search_body(message) FROM post;
+
+CREATE TRIGGER
+IF NOT EXISTS remove_post_fts
+AFTER DELETE ON post
+FOR EACH ROW
+BEGIN
+  DELETE FROM post_fts WHERE rowid = OLD.pk;
+END;
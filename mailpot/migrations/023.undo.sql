@@ -0,0 +1,4 @@
+PRAGMA foreign_keys=ON;
+
+DROP TRIGGER IF EXISTS remove_post_fts;
+DROP TABLE IF EXISTS post_fts;
@@ -0,0 +1,3 @@
+PRAGMA foreign_keys=ON;
+
+UPDATE post SET headers_json = post_headers_json(message) WHERE headers_json IS NULL;
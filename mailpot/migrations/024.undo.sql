@@ -0,0 +1,3 @@
+PRAGMA foreign_keys=ON;
+
+UPDATE post SET headers_json = NULL;
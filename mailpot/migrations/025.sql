@@ -0,0 +1,10 @@
+PRAGMA foreign_keys=ON;
+
+CREATE TABLE IF NOT EXISTS blob (
+  hash             TEXT PRIMARY KEY NOT NULL,
+  size             INTEGER NOT NULL,
+  created          INTEGER NOT NULL DEFAULT (unixepoch())
+);
+
+ALTER TABLE post ADD COLUMN blobs JSON CHECK (blobs IS NULL OR json_type(blobs) = 'array');
+ALTER TABLE queue ADD COLUMN blobs JSON CHECK (blobs IS NULL OR json_type(blobs) = 'array');
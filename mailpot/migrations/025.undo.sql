@@ -0,0 +1,7 @@
+PRAGMA foreign_keys=ON;
+
+UPDATE post SET message = reassemble_message(message, blobs) WHERE blobs IS NOT NULL;
+UPDATE queue SET message = reassemble_message(message, blobs) WHERE blobs IS NOT NULL;
+ALTER TABLE post DROP COLUMN blobs;
+ALTER TABLE queue DROP COLUMN blobs;
+DROP TABLE IF EXISTS blob;
@@ -0,0 +1,3 @@
+PRAGMA foreign_keys=ON;
+
+ALTER TABLE list ADD COLUMN post_retention_days INTEGER CHECK (post_retention_days IS NULL OR post_retention_days > 0);
@@ -0,0 +1,3 @@
+PRAGMA foreign_keys=ON;
+
+ALTER TABLE list DROP COLUMN post_retention_days;
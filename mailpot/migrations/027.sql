@@ -0,0 +1,13 @@
+PRAGMA foreign_keys=ON;
+
+CREATE TABLE IF NOT EXISTS post_tombstone (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  list             INTEGER NOT NULL,
+  message_id       TEXT NOT NULL,
+  reason           TEXT NOT NULL,
+  removed_by       TEXT NOT NULL,
+  timestamp        INTEGER NOT NULL DEFAULT (unixepoch()),
+  datetime         TEXT NOT NULL DEFAULT (datetime()),
+  FOREIGN KEY (list) REFERENCES list(pk) ON DELETE CASCADE,
+  UNIQUE (list, message_id)
+);
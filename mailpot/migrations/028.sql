@@ -0,0 +1,4 @@
+PRAGMA foreign_keys=ON;
+
+ALTER TABLE list ADD COLUMN archive_addresses TEXT CHECK (archive_addresses IN ('show', 'obfuscate', 'hide')) NOT NULL DEFAULT 'show';
+ALTER TABLE list ADD COLUMN archive_raw_download BOOLEAN CHECK (archive_raw_download IN (0, 1)) NOT NULL DEFAULT 1;
@@ -0,0 +1,4 @@
+PRAGMA foreign_keys=ON;
+
+ALTER TABLE list DROP COLUMN archive_addresses;
+ALTER TABLE list DROP COLUMN archive_raw_download;
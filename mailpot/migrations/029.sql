@@ -0,0 +1,4 @@
+PRAGMA foreign_keys=ON;
+
+DELETE FROM access_entry WHERE list IS NULL AND pk NOT IN (SELECT min(pk) FROM access_entry WHERE list IS NULL GROUP BY kind, match_type, pattern);
+CREATE UNIQUE INDEX IF NOT EXISTS access_entry_instance_idx ON access_entry(kind, match_type, pattern) WHERE list IS NULL;
@@ -0,0 +1,3 @@
+PRAGMA foreign_keys=ON;
+
+DROP INDEX IF EXISTS access_entry_instance_idx;
@@ -0,0 +1,31 @@
+INSERT OR REPLACE INTO settings_json_schema(id, value) VALUES('PersonaliseSettings', '{
+  "$schema": "http://json-schema.org/draft-07/schema",
+  "$ref": "#/$defs/PersonaliseSettings",
+  "$defs": {
+    "PersonaliseSettings": {
+      "title": "PersonaliseSettings",
+      "description": "Settings for Personalise message filter",
+      "type": "object",
+      "properties": {
+        "enabled": {
+          "title": "If true, each recipient gets a copy of the post with its merge fields substituted.",
+          "description": "The placeholders `{{ name }}`, `{{ address }}`, `{{ unsubscribe_url }}` and `{{ settings_url }}` in the plain text parts of the post are replaced with the values of the recipient. Any other text is left as is. The archived post gets empty values.",
+          "type": "boolean"
+        },
+        "base_url": {
+          "title": "URL of the web interface",
+          "description": "Personal unsubscribe and settings links point to this instance of mailpot-web.",
+          "examples": [
+            "https://lists.example.com"
+          ],
+          "type": "string",
+          "pattern": "^https?://.+"
+        }
+      },
+      "required": [
+        "enabled",
+        "base_url"
+      ]
+    }
+  }
+}');
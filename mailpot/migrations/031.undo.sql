@@ -0,0 +1,35 @@
+PRAGMA foreign_keys=ON;
+
+CREATE TABLE IF NOT EXISTS post_new (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  list             INTEGER NOT NULL,
+  envelope_from    TEXT,
+  address          TEXT NOT NULL,
+  message_id       TEXT NOT NULL,
+  message          BLOB NOT NULL,
+  headers_json     TEXT,
+  in_reply_to      TEXT,
+  refs             TEXT,
+  thread_root      TEXT,
+  timestamp        INTEGER NOT NULL DEFAULT (unixepoch()),
+  datetime         TEXT NOT NULL DEFAULT (datetime()),
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  blobs            JSON CHECK (blobs IS NULL OR json_type(blobs) = 'array')
+);
+
+INSERT INTO post_new (pk, list, envelope_from, address, message_id, message, headers_json, in_reply_to, refs, thread_root, timestamp, datetime, created, blobs) SELECT pk, list, envelope_from, address, message_id, message, headers_json, in_reply_to, refs, thread_root, timestamp, datetime, created, blobs FROM post;
+DROP TABLE post;
+ALTER TABLE post_new RENAME TO post;
+
+CREATE INDEX IF NOT EXISTS post_listpk_idx ON post(list);
+CREATE INDEX IF NOT EXISTS post_msgid_idx ON post(message_id);
+CREATE INDEX IF NOT EXISTS post_in_reply_to_idx ON post(list, in_reply_to);
+CREATE INDEX IF NOT EXISTS post_thread_root_idx ON post(list, thread_root);
+
+CREATE TRIGGER
+IF NOT EXISTS remove_post_fts
+AFTER DELETE ON post
+FOR EACH ROW
+BEGIN
+  DELETE FROM post_fts WHERE rowid = OLD.pk;
+END;
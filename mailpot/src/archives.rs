@@ -0,0 +1,366 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Yearly archive databases.
+//!
+//! [`Connection::rotate_archives`] moves posts older than a given year out of
+//! the main database into one SQLite file per year, named
+//! `archive-YYYY.db` and kept under
+//! [`Configuration::data_path`](crate::Configuration::data_path).
+//! [`Connection::open_db`] attaches every such file as schema `archive_YYYY`,
+//! and [`Connection::list_posts`], [`Connection::list_post_by_message_id`],
+//! [`Connection::months`], [`Connection::list_thread`],
+//! [`Connection::list_thread_roots`], [`Connection::search_posts`] and
+//! [`Connection::export_mbox`] read across the main database and the archives.
+//!
+//! Each archive keeps its own [full-text search](crate::search) index:
+//! rotated posts take their index entries with them.
+
+use std::path::{Path, PathBuf};
+
+use log::{info, trace};
+
+use crate::{errors::*, Connection};
+
+/// File name prefix of archive databases.
+pub const ARCHIVE_FILE_PREFIX: &str = "archive-";
+
+/// Schema name prefix of attached archive databases.
+pub const ARCHIVE_SCHEMA_PREFIX: &str = "archive_";
+
+/// The year of an archive database file, if `path` names one.
+pub fn archive_year(path: &Path) -> Option<i32> {
+    let year = path
+        .file_name()?
+        .to_str()?
+        .strip_prefix(ARCHIVE_FILE_PREFIX)?
+        .strip_suffix(".db")?;
+    if year.len() != 4 || !year.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    year.parse().ok()
+}
+
+/// Number of posts moved to the archive of a year.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ArchiveRotation {
+    /// Year of the archive.
+    pub year: i32,
+    /// Number of posts moved.
+    pub posts: usize,
+}
+
+/// Unix timestamp of the start of a year, in UTC.
+fn year_start(year: i32) -> Result<i64> {
+    use chrono::TimeZone;
+
+    chrono::Utc
+        .with_ymd_and_hms(year, 1, 1, 0, 0, 0)
+        .single()
+        .map(|dt| dt.timestamp())
+        .ok_or_else(|| Error::new_external(format!("Invalid year {year}.")))
+}
+
+impl Connection {
+    /// The path of the archive database of a year.
+    pub fn archive_path(&self, year: i32) -> PathBuf {
+        self.conf
+            .data_directory()
+            .join(format!("{ARCHIVE_FILE_PREFIX}{year}.db"))
+    }
+
+    /// Attaches the archive databases found in
+    /// [`Configuration::data_path`](crate::Configuration::data_path) that are
+    /// not attached already.
+    pub fn load_archives(&self) -> Result<()> {
+        let Ok(entries) = std::fs::read_dir(self.conf.data_directory()) else {
+            return Ok(());
+        };
+        let mut years = vec![];
+        for entry in entries {
+            if let Some(year) = archive_year(&entry?.path()) {
+                years.push(year);
+            }
+        }
+        years.sort_unstable();
+        for year in years {
+            self.attach_archive(year)?;
+        }
+        Ok(())
+    }
+
+    /// The years of the attached archive databases, in order.
+    pub fn archives(&self) -> Result<Vec<i32>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT name FROM pragma_database_list;")?;
+        let iter = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut ret = vec![];
+        for name in iter {
+            if let Some(year) = name?
+                .strip_prefix(ARCHIVE_SCHEMA_PREFIX)
+                .and_then(|year| year.parse().ok())
+            {
+                ret.push(year);
+            }
+        }
+        ret.sort_unstable();
+        Ok(ret)
+    }
+
+    /// Attaches the archive database of a year, creating it if it does not
+    /// exist.
+    fn attach_archive(&self, year: i32) -> Result<()> {
+        if self.archives()?.contains(&year) {
+            return Ok(());
+        }
+        let path = self.archive_path(year);
+        trace!("Attaching archive {}.", path.display());
+        self.connection.execute(
+            "ATTACH ? AS ?;",
+            rusqlite::params![
+                path.to_str().ok_or_else(|| Error::new_external(format!(
+                    "Archive path {} is not valid UTF-8.",
+                    path.display()
+                )))?,
+                format!("{ARCHIVE_SCHEMA_PREFIX}{year}"),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Names of the columns of the `post` table of a schema.
+    fn post_columns(&self, schema: &str) -> Result<Vec<(String, String)>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT name, type FROM pragma_table_info('post', ?);")?;
+        let iter = stmt.query_map([schema], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        let mut ret = vec![];
+        for column in iter {
+            ret.push(column?);
+        }
+        Ok(ret)
+    }
+
+    /// Creates the `post` table, its indices and its [full-text
+    /// search](crate::search) index in an archive schema, or adds the columns
+    /// the main database gained since the archive was created. Posts archived
+    /// before the archive had a search index are indexed.
+    fn create_archive_tables(&self, schema: &str) -> Result<()> {
+        let has_fts = self.has_post_fts(schema)?;
+        let mut stmt = self.connection.prepare(
+            "SELECT type, name, sql FROM main.sqlite_master WHERE (name = 'post_fts' OR (tbl_name \
+             = 'post' AND type IN ('table', 'index', 'trigger'))) AND sql IS NOT NULL ORDER BY \
+             CASE type WHEN 'table' THEN 0 WHEN 'index' THEN 1 ELSE 2 END;",
+        )?;
+        let statements = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        drop(stmt);
+        for (kind, name, sql) in statements {
+            // `sqlite_master` keeps statements without their `IF NOT EXISTS`,
+            // and with the object name quoted if it was renamed. Triggers act
+            // on the tables of their own schema.
+            let keyword = match kind.as_str() {
+                "table" => "TABLE",
+                "index" => "INDEX",
+                _ => "TRIGGER",
+            };
+            let replacement = format!("{keyword} IF NOT EXISTS {schema}.{name}");
+            let sql = sql
+                .replacen(&format!("{keyword} {name}"), &replacement, 1)
+                .replacen(&format!("{keyword} \"{name}\""), &replacement, 1);
+            self.connection.execute_batch(&sql)?;
+        }
+
+        let archived = self.post_columns(schema)?;
+        for (name, ty) in self.post_columns("main")? {
+            if !archived.iter().any(|(n, _)| *n == name) {
+                self.connection.execute_batch(&format!(
+                    "ALTER TABLE {schema}.post ADD COLUMN {name} {ty};"
+                ))?;
+            }
+        }
+
+        if !has_fts {
+            let indexed = self.connection.execute(
+                &format!(
+                    "INSERT INTO {schema}.post_fts(rowid, subject, sender, body) SELECT pk, \
+                     post_search_subject(message), post_search_sender(message), \
+                     post_search_body(message) FROM {schema}.post;"
+                ),
+                [],
+            )?;
+            trace!("Indexed {indexed} posts of {schema}.");
+        }
+        Ok(())
+    }
+
+    /// Whether a schema has a `post_fts` full-text search index.
+    pub(crate) fn has_post_fts(&self, schema: &str) -> Result<bool> {
+        Ok(self.connection.query_row(
+            &format!(
+                "SELECT count(*) > 0 FROM {schema}.sqlite_master WHERE type = 'table' AND name = \
+                 'post_fts';"
+            ),
+            [],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// The schemas holding posts: `main`, then the attached archives in
+    /// order.
+    pub(crate) fn post_schemas(&self) -> Result<Vec<String>> {
+        Ok(std::iter::once("main".to_string())
+            .chain(
+                self.archives()?
+                    .into_iter()
+                    .map(|year| format!("{ARCHIVE_SCHEMA_PREFIX}{year}")),
+            )
+            .collect())
+    }
+
+    /// The columns of the main database `post` table, to select from the
+    /// `post` table of `schema`: columns the schema lacks are selected as
+    /// `NULL`. Returns `None` if the schema has no `post` table.
+    pub(crate) fn post_select_columns(&self, schema: &str) -> Result<Option<String>> {
+        let archived = self.post_columns(schema)?;
+        if archived.is_empty() {
+            return Ok(None);
+        }
+        let columns = self
+            .post_columns("main")?
+            .into_iter()
+            .map(|(name, _)| {
+                if archived.iter().any(|(n, _)| *n == name) {
+                    format!("post.{name}")
+                } else {
+                    format!("NULL AS {name}")
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok(Some(columns.join(", ")))
+    }
+
+    /// The source to select posts from: the main database `post` table alone
+    /// if no archives are attached, or otherwise a union of it and the
+    /// archives' `post` tables, aliased `post`.
+    pub(crate) fn posts_source(&self) -> Result<String> {
+        if self.archives()?.is_empty() {
+            return Ok("post".to_string());
+        }
+        let mut selects = vec![];
+        for schema in self.post_schemas()? {
+            if let Some(columns) = self.post_select_columns(&schema)? {
+                selects.push(format!("SELECT {columns} FROM {schema}.post AS post"));
+            }
+        }
+        Ok(format!("({}) AS post", selects.join(" UNION ALL ")))
+    }
+
+    /// Like [`Connection::posts_source`], for queries that refer to posts more
+    /// than once: returns a common table expression to add to their `WITH`
+    /// clause if archives are attached, and the name to select posts from.
+    pub(crate) fn posts_cte(&self) -> Result<(Option<String>, &'static str)> {
+        let source = self.posts_source()?;
+        if source == "post" {
+            return Ok((None, "post"));
+        }
+        Ok((
+            Some(format!("all_posts AS (SELECT * FROM {source})")),
+            "all_posts",
+        ))
+    }
+
+    /// Moves posts dated before the start of year `before` to yearly archive
+    /// databases, and returns how many posts were moved for each year. With
+    /// `dry_run`, only counts the posts that would be moved.
+    pub fn rotate_archives(&self, before: i32, dry_run: bool) -> Result<Vec<ArchiveRotation>> {
+        let boundary = year_start(before)?;
+        let mut ret = vec![];
+        {
+            let mut stmt = self.connection.prepare(
+                "SELECT CAST(strftime('%Y', CAST(timestamp AS INTEGER), 'unixepoch') AS INTEGER) \
+                 AS year, count(*) FROM main.post WHERE timestamp < ? GROUP BY year ORDER BY year;",
+            )?;
+            let iter = stmt.query_map([boundary], |row| {
+                Ok(ArchiveRotation {
+                    year: row.get(0)?,
+                    posts: row.get(1)?,
+                })
+            })?;
+            for rotation in iter {
+                ret.push(rotation?);
+            }
+        }
+        if dry_run {
+            return Ok(ret);
+        }
+
+        for year in self.archives()? {
+            self.create_archive_tables(&format!("{ARCHIVE_SCHEMA_PREFIX}{year}"))?;
+        }
+        for rotation in &ret {
+            let schema = format!("{ARCHIVE_SCHEMA_PREFIX}{}", rotation.year);
+            self.attach_archive(rotation.year)?;
+            self.create_archive_tables(&schema)?;
+            let start = year_start(rotation.year)?;
+            let end = year_start(rotation.year + 1)?.min(boundary);
+            let columns = self
+                .post_columns("main")?
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+                .join(", ");
+            let tx = self.savepoint(Some(stringify!(rotate_archives)))?;
+            tx.connection.execute(
+                &format!(
+                    "INSERT INTO {schema}.post ({columns}) SELECT {columns} FROM main.post WHERE \
+                     timestamp >= ?1 AND timestamp < ?2;"
+                ),
+                [start, end],
+            )?;
+            tx.connection.execute(
+                &format!(
+                    "INSERT INTO {schema}.post_fts(rowid, subject, sender, body) SELECT rowid, \
+                     subject, sender, body FROM main.post_fts WHERE rowid IN (SELECT pk FROM \
+                     main.post WHERE timestamp >= ?1 AND timestamp < ?2);"
+                ),
+                [start, end],
+            )?;
+            tx.connection.execute(
+                "DELETE FROM main.post WHERE timestamp >= ?1 AND timestamp < ?2;",
+                [start, end],
+            )?;
+            tx.commit()?;
+            info!(
+                "Moved {} posts to archive {}.",
+                rotation.posts,
+                self.archive_path(rotation.year).display()
+            );
+        }
+        Ok(ret)
+    }
+}
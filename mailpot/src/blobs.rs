@@ -0,0 +1,344 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Content-addressed storage of large message parts.
+//!
+//! When [`Configuration::blob_store`](crate::Configuration::blob_store) is
+//! set, the bodies of MIME parts larger than [`BLOB_MIN_SIZE`] are cut out of
+//! posts and queue entries before they are stored, and written once to
+//! `data_path/blobs/`, named by the hex SHA-256 hash of their bytes. The
+//! stored message keeps everything else, and its `blobs` column lists a
+//! [`BlobRef`] for each part that was cut out.
+//!
+//! Messages are reassembled byte for byte when they are read back, so
+//! [`Post::message`](crate::models::Post::message) and
+//! [`QueueEntry::message`](crate::queue::QueueEntry::message) always hold the
+//! whole message, whether the blob store is used or not.
+//!
+//! Blobs are shared by every message that has the same part, and
+//! [`Connection::gc_blobs`] deletes those that no post, archived post or
+//! queue entry refers to any more. Dry runs
+//! ([`Connection::post_dry_run`]) store messages whole, since rolling back
+//! their savepoint would leave their blobs behind.
+
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    io::Write,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{errors::*, Connection};
+
+/// MIME part bodies of at least this many bytes are moved to the blob store.
+pub const BLOB_MIN_SIZE: usize = 32 * 1024;
+
+/// Files in the blob directory without a `blob` row are deleted by
+/// [`Connection::gc_blobs`] only once they are this old, since a blob is
+/// written before the transaction that adds its row commits.
+pub const STRAY_BLOB_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// A message part moved to the blob store.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct BlobRef {
+    /// Offset in the stored message where the part is spliced back.
+    pub offset: usize,
+    /// Hex SHA-256 hash of the part.
+    pub hash: String,
+}
+
+/// Hex SHA-256 hash of bytes, which names their blob.
+pub fn blob_hash(bytes: &[u8]) -> String {
+    data_encoding::HEXLOWER.encode(&Sha256::digest(bytes))
+}
+
+/// Path of the blob of `hash` in the blob directory `dir`.
+fn blob_path(dir: &Path, hash: &str) -> PathBuf {
+    dir.join(&hash[..2]).join(hash)
+}
+
+/// Byte ranges of the bodies of the MIME parts of `message` that are at least
+/// [`BLOB_MIN_SIZE`] bytes long, in order.
+pub fn large_parts(message: &[u8]) -> Vec<Range<usize>> {
+    fn walk(root: &[u8], part: &[u8], ret: &mut Vec<Range<usize>>) {
+        let Ok((_, (headers, body))) = melib::email::parser::attachments::attachment(part) else {
+            return;
+        };
+        let boundary = headers
+            .iter()
+            .find(|(name, _)| name == melib::email::headers::HeaderName::CONTENT_TYPE)
+            .and_then(|(_, value)| melib::email::parser::attachments::content_type(value).ok())
+            .filter(|(_, (ty, _, _))| ty.eq_ignore_ascii_case(b"multipart"))
+            .and_then(|(_, (_, _, params))| {
+                params
+                    .into_iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(b"boundary"))
+                    .map(|(_, value)| value)
+            });
+        if let Some(boundary) = boundary {
+            if let Ok((_, parts)) = melib::email::parser::attachments::parts(body, boundary) {
+                for part in parts {
+                    walk(root, part, ret);
+                }
+            }
+        } else if body.len() >= BLOB_MIN_SIZE {
+            let offset = body.as_ptr() as usize - root.as_ptr() as usize;
+            ret.push(offset..offset + body.len());
+        }
+    }
+
+    let mut ret = vec![];
+    walk(message, message, &mut ret);
+    ret
+}
+
+/// Splice the blobs of `refs`, read from the blob directory `dir`, back into
+/// a stored message.
+pub fn reassemble(dir: &Path, stored: &[u8], refs: &[BlobRef]) -> Result<Vec<u8>> {
+    let mut ret = Vec::with_capacity(stored.len());
+    let mut prev = 0;
+    for blob in refs {
+        if blob.offset < prev
+            || blob.offset > stored.len()
+            || blob.hash.len() != 64
+            || !blob.hash.bytes().all(|b| b.is_ascii_hexdigit())
+        {
+            return Err(Error::new_external(format!(
+                "Invalid blob reference {blob:?}."
+            )));
+        }
+        ret.extend_from_slice(&stored[prev..blob.offset]);
+        let path = blob_path(dir, &blob.hash);
+        let bytes = std::fs::read(&path)
+            .with_context(|| format!("Could not read blob {}.", path.display()))?;
+        ret.extend_from_slice(&bytes);
+        prev = blob.offset;
+    }
+    ret.extend_from_slice(&stored[prev..]);
+    Ok(ret)
+}
+
+/// Parse the `blobs` column of a post or queue entry.
+pub(crate) fn refs_from_json(blobs: Option<serde_json::Value>) -> Result<Vec<BlobRef>> {
+    Ok(blobs
+        .map(serde_json::from_value)
+        .transpose()?
+        .unwrap_or_default())
+}
+
+impl Connection {
+    /// The directory of the blob store.
+    pub fn blob_directory(&self) -> PathBuf {
+        self.conf.data_directory().join("blobs")
+    }
+
+    /// Write bytes to the blob store, if they are not already there, and
+    /// return their hash.
+    pub fn put_blob(&self, bytes: &[u8]) -> Result<String> {
+        let hash = blob_hash(bytes);
+        // The row is inserted before the file is checked, so that a
+        // concurrent [`Connection::gc_blobs`] that is deleting the file has
+        // committed by then.
+        self.connection.execute(
+            "INSERT OR IGNORE INTO blob(hash, size) VALUES(?, ?);",
+            rusqlite::params![&hash, &bytes.len()],
+        )?;
+        let path = blob_path(&self.blob_directory(), &hash);
+        if !path.exists() {
+            let dir = path.parent().unwrap();
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Could not create directory {}.", dir.display()))?;
+            // Write to a temporary file first, so that a blob is never seen
+            // half-written.
+            let tmp_path = dir.join(format!("{hash}.{}.tmp", std::process::id()));
+            let mut file = std::fs::File::create(&tmp_path)
+                .with_context(|| format!("Could not create file {}.", tmp_path.display()))?;
+            file.write_all(bytes)
+                .and_then(|()| file.sync_all())
+                .with_context(|| format!("Could not write to file {}.", tmp_path.display()))?;
+            std::fs::rename(&tmp_path, &path)
+                .with_context(|| format!("Could not rename file {}.", tmp_path.display()))?;
+        }
+        Ok(hash)
+    }
+
+    /// Read a blob from the blob store.
+    pub fn blob(&self, hash: &str) -> Result<Vec<u8>> {
+        reassemble(
+            &self.blob_directory(),
+            b"",
+            &[BlobRef {
+                offset: 0,
+                hash: hash.to_string(),
+            }],
+        )
+    }
+
+    /// Prepare a message to be stored: if the blob store is enabled and this
+    /// is not a dry run, move its large parts to it and return what remains of
+    /// the message along with the value of its `blobs` column.
+    pub(crate) fn split_message<'m>(
+        &self,
+        message: &'m [u8],
+    ) -> Result<(Cow<'m, [u8]>, Option<serde_json::Value>)> {
+        if !self.conf.blob_store || self.dry_run.get() {
+            return Ok((Cow::Borrowed(message), None));
+        }
+        let parts = large_parts(message);
+        if parts.is_empty() {
+            return Ok((Cow::Borrowed(message), None));
+        }
+        let mut stored = Vec::with_capacity(message.len());
+        let mut refs = Vec::with_capacity(parts.len());
+        let mut prev = 0;
+        for part in parts {
+            stored.extend_from_slice(&message[prev..part.start]);
+            refs.push(BlobRef {
+                offset: stored.len(),
+                hash: self.put_blob(&message[part.clone()])?,
+            });
+            prev = part.end;
+        }
+        stored.extend_from_slice(&message[prev..]);
+        Ok((Cow::Owned(stored), Some(serde_json::to_value(refs)?)))
+    }
+
+    /// Reassemble a stored message with the value of its `blobs` column.
+    pub fn reassemble_message(
+        &self,
+        stored: Vec<u8>,
+        blobs: Option<serde_json::Value>,
+    ) -> Result<Vec<u8>> {
+        let refs = refs_from_json(blobs)?;
+        if refs.is_empty() {
+            return Ok(stored);
+        }
+        reassemble(&self.blob_directory(), &stored, &refs)
+    }
+
+    /// Read and reassemble the `message` of a post or queue entry row. Rows
+    /// of databases that predate the blob store have no `blobs` column.
+    pub(crate) fn message_from_row(&self, row: &rusqlite::Row) -> rusqlite::Result<Vec<u8>> {
+        let blobs = match row.get("blobs") {
+            Err(rusqlite::Error::InvalidColumnName(_)) => None,
+            other => other?,
+        };
+        self.reassemble_message(row.get("message")?, blobs)
+            .map_err(|err| rusqlite::Error::UserFunctionError(Box::new(err)))
+    }
+
+    /// Hashes of the blobs that posts, archived posts and queue entries refer
+    /// to.
+    fn referenced_blobs(&self) -> Result<HashSet<String>> {
+        let mut tables = vec!["main.post".to_string(), "main.queue".to_string()];
+        tables.extend(
+            self.archives()?
+                .into_iter()
+                .map(|year| format!("{}{year}.post", crate::archives::ARCHIVE_SCHEMA_PREFIX)),
+        );
+        let mut ret = HashSet::new();
+        for table in tables {
+            let mut stmt = self.connection.prepare(&format!(
+                "SELECT json_extract(refs.value, '$.hash') FROM {table}, json_each({table}.blobs) \
+                 AS refs WHERE {table}.blobs IS NOT NULL;"
+            ))?;
+            for hash in stmt.query_map([], |row| row.get::<_, String>(0))? {
+                ret.insert(hash?);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Delete the blobs that no post, archived post or queue entry refers to,
+    /// and return how many there were. With `dry_run`, only count them.
+    ///
+    /// Files of the blob directory without a `blob` row, such as those left
+    /// behind by interrupted writes, are deleted too once they are older than
+    /// [`STRAY_BLOB_AGE`].
+    pub fn gc_blobs(&self, dry_run: bool) -> Result<usize> {
+        let tx = self.savepoint(Some(stringify!(gc_blobs)))?;
+        if !dry_run {
+            // Take the write lock before reading, and keep it until the files
+            // are deleted: [`Connection::put_blob`] inserts its row before
+            // checking for the file, so it can't reuse a file deleted here.
+            tx.connection.execute("DELETE FROM blob WHERE 0;", [])?;
+        }
+        let referenced = tx.referenced_blobs()?;
+        let known = {
+            let mut stmt = tx.connection.prepare("SELECT hash FROM blob;")?;
+            let iter = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            iter.collect::<std::result::Result<HashSet<_>, _>>()?
+        };
+        let mut unreferenced = known
+            .iter()
+            .filter(|hash| !referenced.contains(*hash))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !dry_run {
+            for hash in &unreferenced {
+                tx.connection
+                    .execute("DELETE FROM blob WHERE hash = ?;", [hash])?;
+            }
+        }
+
+        let dir = tx.blob_directory();
+        let now = std::time::SystemTime::now();
+        for entry in std::fs::read_dir(&dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| std::fs::read_dir(entry.path()).ok())
+            .flatten()
+            .flatten()
+        {
+            let Some(hash) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            let stray = hash.len() == 64
+                && hash.bytes().all(|b| b.is_ascii_hexdigit())
+                && !known.contains(&hash)
+                && !referenced.contains(&hash)
+                && entry
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|modified| now.duration_since(modified).ok())
+                    .is_some_and(|age| age >= STRAY_BLOB_AGE);
+            if stray {
+                unreferenced.push(hash);
+            }
+        }
+        if !dry_run {
+            for hash in &unreferenced {
+                let path = blob_path(&dir, hash);
+                match std::fs::remove_file(&path) {
+                    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                    other => other
+                        .with_context(|| format!("Could not delete blob {}.", path.display()))?,
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(unreferenced.len())
+    }
+}
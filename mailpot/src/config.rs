@@ -26,6 +26,7 @@ use std::{
 use chrono::prelude::*;
 
 use super::errors::*;
+use crate::retention::QueueRetention;
 
 /// How to send e-mail.
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -50,6 +51,14 @@ pub struct Configuration {
     /// Instance administrators (List of e-mail addresses). Optional.
     #[serde(default)]
     pub administrators: Vec<String>,
+    /// Store large message parts once, in a content-addressed blob store
+    /// under `data_path` (See [`blobs`](crate::blobs)). Optional.
+    #[serde(default)]
+    pub blob_store: bool,
+    /// How long entries are kept in each queue before `mpot gc` deletes them
+    /// (See [`retention`](crate::retention)). Optional.
+    #[serde(default)]
+    pub queue_retention: QueueRetention,
 }
 
 impl Configuration {
@@ -68,6 +77,8 @@ impl Configuration {
                 .map(Path::to_path_buf)
                 .unwrap_or_else(|| db_path.clone()),
             administrators: vec![],
+            blob_store: false,
+            queue_retention: Default::default(),
             db_path,
         }
     }
@@ -0,0 +1,481 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Double opt-in of e-mail subscriptions.
+//!
+//! Lists with the `verify` flag mark new subscriptions as not verified. When
+//! such a subscription is requested by e-mail, a [`SubscriptionConfirmation`]
+//! token is mailed to the address with the
+//! [`SUBSCRIPTION_CONFIRMATION`](crate::Template::SUBSCRIPTION_CONFIRMATION)
+//! template. The subscriber confirms by replying to it, which sends a
+//! `confirm <token>` request, or by visiting the link of the web interface if
+//! the list has a `base_url` in its message filter settings. Subscriptions
+//! that are not confirmed within [`CONFIRMATION_EXPIRY`] seconds are removed
+//! by [`Connection::purge_unconfirmed_subscriptions`], which the
+//! `flush-queue` command calls. Until then, the subscription neither
+//! receives posts nor counts as a subscription for post policies.
+//!
+//! Lists with the `confirm_unsubscription` flag likewise don't remove a
+//! subscription on an unsubscription request. Instead, a token is mailed to
+//! the subscribed address with the
+//! [`UNSUBSCRIPTION_CONFIRMATION_REQUEST`](crate::Template::UNSUBSCRIPTION_CONFIRMATION_REQUEST)
+//! template, and the subscription is removed only when the token is confirmed
+//! in the same ways, within [`UNSUBSCRIPTION_CONFIRMATION_EXPIRY`] seconds.
+//! This way a forged `From:` header is not enough to unsubscribe someone.
+
+use std::{borrow::Cow, collections::HashSet};
+
+use log::trace;
+use rusqlite::OptionalExtension;
+
+use crate::{
+    errors::*,
+    models::{changesets::ListSubscriptionChangeset, DbVal, ListSubscription, MailingList},
+    posts::TemplateRenderContext,
+    queue::Queue,
+    Connection, Template,
+};
+
+/// Seconds an unconfirmed subscription waits for its confirmation.
+pub const CONFIRMATION_EXPIRY: u64 = 7 * 24 * 60 * 60;
+
+/// Seconds an unsubscription request waits for its confirmation.
+pub const UNSUBSCRIPTION_CONFIRMATION_EXPIRY: u64 = 24 * 60 * 60;
+
+/// A pending confirmation of an e-mail subscription or unsubscription.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SubscriptionConfirmation {
+    /// Database primary key.
+    pub pk: i64,
+    /// Subscription foreign key (See [`ListSubscription`]).
+    pub subscription: i64,
+    /// Random token mailed to the subscriber.
+    pub token: String,
+    /// Unix timestamp after which the token is no longer valid.
+    pub expires: u64,
+}
+
+impl Connection {
+    /// Create a confirmation token for a subscription, replacing any previous
+    /// one.
+    pub fn add_subscription_confirmation(
+        &self,
+        subscription_pk: i64,
+        expires: u64,
+    ) -> Result<DbVal<SubscriptionConfirmation>> {
+        let val = self.connection.query_row(
+            "INSERT OR REPLACE INTO subscription_confirmation(subscription, expires) VALUES(?, ?) \
+             RETURNING *;",
+            rusqlite::params![&subscription_pk, &expires],
+            |row| {
+                let pk = row.get("pk")?;
+                Ok(DbVal(
+                    SubscriptionConfirmation {
+                        pk,
+                        subscription: row.get("subscription")?,
+                        token: row.get("token")?,
+                        expires: row.get("expires")?,
+                    },
+                    pk,
+                ))
+            },
+        )?;
+        trace!("add_subscription_confirmation {:?}.", &val);
+        Ok(val)
+    }
+
+    /// Fetch the primary keys of the subscriptions of a list that wait for
+    /// their confirmation.
+    pub fn list_pending_subscriptions(&self, list_pk: i64) -> Result<HashSet<i64>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT sub.pk FROM subscription_confirmation AS conf, subscription AS sub WHERE \
+             conf.subscription = sub.pk AND sub.list = ?;",
+        )?;
+        let iter = stmt.query_map([&list_pk], |row| row.get(0))?;
+        let mut ret = HashSet::new();
+        for pk in iter {
+            ret.insert(pk?);
+        }
+        Ok(ret)
+    }
+
+    /// Fetch the subscriptions of a list that don't wait for their
+    /// confirmation.
+    pub fn list_confirmed_subscriptions(
+        &self,
+        list_pk: i64,
+    ) -> Result<Vec<DbVal<ListSubscription>>> {
+        let pending = self.list_pending_subscriptions(list_pk)?;
+        let mut ret = self.list_subscriptions(list_pk)?;
+        ret.retain(|s| !pending.contains(&s.pk));
+        Ok(ret)
+    }
+
+    /// Fetch the pending confirmation of a subscription, if any.
+    pub fn subscription_confirmation(
+        &self,
+        subscription_pk: i64,
+    ) -> Result<Option<DbVal<SubscriptionConfirmation>>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT * FROM subscription_confirmation WHERE subscription = ?;",
+                [&subscription_pk],
+                |row| {
+                    let pk = row.get("pk")?;
+                    Ok(DbVal(
+                        SubscriptionConfirmation {
+                            pk,
+                            subscription: row.get("subscription")?,
+                            token: row.get("token")?,
+                            expires: row.get("expires")?,
+                        },
+                        pk,
+                    ))
+                },
+            )
+            .optional()?)
+    }
+
+    /// Fetch the subscription of a confirmation token that has not expired by
+    /// `now`.
+    pub fn pending_subscription(
+        &self,
+        token: &str,
+        now: u64,
+    ) -> Result<Option<DbVal<ListSubscription>>> {
+        let Some((list, subscription_pk)) = self
+            .connection
+            .query_row(
+                "SELECT sub.list, sub.pk FROM subscription_confirmation AS conf, subscription AS \
+                 sub WHERE conf.subscription = sub.pk AND conf.token = ? AND conf.expires >= ?;",
+                rusqlite::params![&token.trim().to_ascii_lowercase(), &now],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+            )
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        self.list_subscription(list, subscription_pk).map(Some)
+    }
+
+    /// Confirm the subscription of a token that has not expired by `now`: the
+    /// subscription is marked as verified, and the token is removed.
+    pub fn confirm_subscription(&self, token: &str, now: u64) -> Result<DbVal<ListSubscription>> {
+        let subscription = self
+            .pending_subscription(token, now)?
+            .ok_or_else(|| Error::new_external("Invalid or expired confirmation."))?;
+        let (list, subscription_pk) = (subscription.list, subscription.pk);
+        self.update_subscription(ListSubscriptionChangeset {
+            list,
+            address: subscription.address.clone(),
+            account: None,
+            name: None,
+            digest: None,
+            enabled: None,
+            verified: Some(true),
+            hide_address: None,
+            receive_duplicates: None,
+            receive_own_posts: None,
+            receive_confirmation: None,
+        })?;
+        self.connection.execute(
+            "DELETE FROM subscription_confirmation WHERE subscription = ?;",
+            [&subscription_pk],
+        )?;
+        self.list_subscription(list, subscription_pk)
+    }
+
+    /// Remove the subscriptions whose confirmation expired before `now`, and
+    /// return them. Expired unsubscription tokens are removed as well.
+    pub fn purge_unconfirmed_subscriptions(
+        &self,
+        now: u64,
+    ) -> Result<Vec<DbVal<ListSubscription>>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT sub.list, sub.pk FROM subscription_confirmation AS conf, subscription AS sub \
+             WHERE conf.subscription = sub.pk AND conf.expires < ? AND sub.verified = 0;",
+        )?;
+        let expired = stmt
+            .query_map([&now], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        drop(stmt);
+        self.connection.execute(
+            "DELETE FROM unsubscription_confirmation WHERE expires < ?;",
+            [&now],
+        )?;
+        let mut ret = Vec::with_capacity(expired.len());
+        for (list, pk) in expired {
+            let subscription = self.list_subscription(list, pk)?;
+            self.remove_subscription(list, &subscription.address)?;
+            trace!("purged unconfirmed subscription {:?}", &subscription);
+            ret.push(subscription);
+        }
+        Ok(ret)
+    }
+
+    /// Mail a confirmation token to a subscription that is not verified.
+    pub fn send_subscription_confirmation_request(
+        &self,
+        list: &DbVal<MailingList>,
+        subscription: &DbVal<ListSubscription>,
+    ) -> Result<()> {
+        let confirmation = self.add_subscription_confirmation(
+            subscription.pk,
+            chrono::offset::Utc::now().timestamp() as u64 + CONFIRMATION_EXPIRY,
+        )?;
+        let url = self.list_base_url(list.pk)?.map(|base_url| {
+            format!(
+                "{}/subscription/confirm/{}/",
+                base_url.trim_end_matches('/'),
+                confirmation.token
+            )
+        });
+        let expires = i64::try_from(confirmation.expires)
+            .ok()
+            .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+            .map(|d| d.to_rfc2822())
+            .unwrap_or_default();
+        log::trace!(
+            "Added unverified subscription to list {list:?} for address {:?}, sending \
+             confirmation request.",
+            subscription.address
+        );
+        self.send_reply_with_list_template(
+            TemplateRenderContext {
+                template: Template::SUBSCRIPTION_CONFIRMATION,
+                default_fn: Some(Template::default_subscription_confirmation),
+                list,
+                context: minijinja::context! {
+                    list => &list,
+                    confirmation => minijinja::context! {
+                        token => &confirmation.token,
+                        url => url,
+                        expires => expires,
+                    },
+                },
+                queue: Queue::Out,
+                comment: format!("{} request", Template::SUBSCRIPTION_CONFIRMATION).into(),
+            },
+            std::iter::once(Cow::Owned(subscription.address())),
+        )
+    }
+
+    /// The URL of the web interface, from the `base_url` of the list's
+    /// `AddListHeadersSettings` or, failing that, of its
+    /// `PersonaliseSettings`.
+    pub(crate) fn list_base_url(&self, list_pk: i64) -> Result<Option<String>> {
+        let settings = self.get_settings(list_pk)?;
+        Ok(["AddListHeadersSettings", "PersonaliseSettings"]
+            .into_iter()
+            .find_map(|name| {
+                settings
+                    .get(name)?
+                    .get("base_url")
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string)
+            }))
+    }
+
+    /// Whether unsubscription requests of a list must be confirmed.
+    pub fn list_confirms_unsubscription(&self, list_pk: i64) -> Result<bool> {
+        Ok(self.connection.query_row(
+            "SELECT confirm_unsubscription FROM list WHERE pk = ?;",
+            [&list_pk],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Create a confirmation token for the removal of a subscription,
+    /// replacing any previous one.
+    pub fn add_unsubscription_confirmation(
+        &self,
+        subscription_pk: i64,
+        expires: u64,
+    ) -> Result<DbVal<SubscriptionConfirmation>> {
+        let val = self.connection.query_row(
+            "INSERT OR REPLACE INTO unsubscription_confirmation(subscription, expires) VALUES(?, \
+             ?) RETURNING *;",
+            rusqlite::params![&subscription_pk, &expires],
+            |row| {
+                let pk = row.get("pk")?;
+                Ok(DbVal(
+                    SubscriptionConfirmation {
+                        pk,
+                        subscription: row.get("subscription")?,
+                        token: row.get("token")?,
+                        expires: row.get("expires")?,
+                    },
+                    pk,
+                ))
+            },
+        )?;
+        trace!("add_unsubscription_confirmation {:?}.", &val);
+        Ok(val)
+    }
+
+    /// Fetch the pending unsubscription confirmation of a subscription, if
+    /// any.
+    pub fn unsubscription_confirmation(
+        &self,
+        subscription_pk: i64,
+    ) -> Result<Option<DbVal<SubscriptionConfirmation>>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT * FROM unsubscription_confirmation WHERE subscription = ?;",
+                [&subscription_pk],
+                |row| {
+                    let pk = row.get("pk")?;
+                    Ok(DbVal(
+                        SubscriptionConfirmation {
+                            pk,
+                            subscription: row.get("subscription")?,
+                            token: row.get("token")?,
+                            expires: row.get("expires")?,
+                        },
+                        pk,
+                    ))
+                },
+            )
+            .optional()?)
+    }
+
+    /// Fetch the subscription of an unsubscription token that has not expired
+    /// by `now`.
+    pub fn pending_unsubscription(
+        &self,
+        token: &str,
+        now: u64,
+    ) -> Result<Option<DbVal<ListSubscription>>> {
+        let Some((list, subscription_pk)) = self
+            .connection
+            .query_row(
+                "SELECT sub.list, sub.pk FROM unsubscription_confirmation AS conf, subscription \
+                 AS sub WHERE conf.subscription = sub.pk AND conf.token = ? AND conf.expires >= ?;",
+                rusqlite::params![&token.trim().to_ascii_lowercase(), &now],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+            )
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        self.list_subscription(list, subscription_pk).map(Some)
+    }
+
+    /// Confirm the unsubscription of a token that has not expired by `now`:
+    /// the subscription is removed along with the token, and returned.
+    pub fn confirm_unsubscription(&self, token: &str, now: u64) -> Result<DbVal<ListSubscription>> {
+        let subscription = self
+            .pending_unsubscription(token, now)?
+            .ok_or_else(|| Error::new_external("Invalid or expired confirmation."))?;
+        self.remove_subscription(subscription.list, &subscription.address)?;
+        Ok(subscription)
+    }
+
+    /// Mail an unsubscription confirmation token to a subscription.
+    pub fn send_unsubscription_confirmation_request(
+        &self,
+        list: &DbVal<MailingList>,
+        subscription: &DbVal<ListSubscription>,
+    ) -> Result<()> {
+        let confirmation = self.add_unsubscription_confirmation(
+            subscription.pk,
+            chrono::offset::Utc::now().timestamp() as u64 + UNSUBSCRIPTION_CONFIRMATION_EXPIRY,
+        )?;
+        let url = self.list_base_url(list.pk)?.map(|base_url| {
+            format!(
+                "{}/subscription/confirm/{}/",
+                base_url.trim_end_matches('/'),
+                confirmation.token
+            )
+        });
+        let expires = i64::try_from(confirmation.expires)
+            .ok()
+            .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+            .map(|d| d.to_rfc2822())
+            .unwrap_or_default();
+        log::trace!(
+            "Unsubscription requested from list {list:?} for address {:?}, sending confirmation \
+             request.",
+            subscription.address
+        );
+        self.send_reply_with_list_template(
+            TemplateRenderContext {
+                template: Template::UNSUBSCRIPTION_CONFIRMATION_REQUEST,
+                default_fn: Some(Template::default_unsubscription_confirmation_request),
+                list,
+                context: minijinja::context! {
+                    list => &list,
+                    confirmation => minijinja::context! {
+                        token => &confirmation.token,
+                        url => url,
+                        expires => expires,
+                    },
+                },
+                queue: Queue::Out,
+                comment: Template::UNSUBSCRIPTION_CONFIRMATION_REQUEST.into(),
+            },
+            std::iter::once(Cow::Owned(subscription.address())),
+        )
+    }
+
+    /// Confirm a subscription, unsubscription or re-confirmation with a
+    /// `confirm <token>` request of `address`.
+    pub(crate) fn confirm_subscription_request(
+        &self,
+        list: &DbVal<MailingList>,
+        address: &str,
+        token: &str,
+    ) -> Result<String> {
+        let Ok(subscription) = self.list_subscription_by_address(list.pk, address) else {
+            return Err(Error::new_external("Invalid or expired confirmation."));
+        };
+        let now = chrono::offset::Utc::now().timestamp() as u64;
+        let matches = |c: Option<DbVal<SubscriptionConfirmation>>| {
+            c.is_some_and(|c| c.token.eq_ignore_ascii_case(token.trim()))
+        };
+        if matches(self.subscription_confirmation(subscription.pk)?) {
+            self.confirm_subscription(token, now)?;
+            Ok(format!(
+                "You have successfully subscribed to {}.",
+                list.name
+            ))
+        } else if matches(self.unsubscription_confirmation(subscription.pk)?) {
+            self.confirm_unsubscription(token, now)?;
+            Ok(format!(
+                "You have successfully unsubscribed from {}.",
+                list.name
+            ))
+        } else if self
+            .reconfirmation(subscription.pk)?
+            .is_some_and(|r| r.token.eq_ignore_ascii_case(token.trim()))
+        {
+            self.reconfirm_subscription(token, now)?;
+            Ok(format!(
+                "You have successfully confirmed your subscription to {}.",
+                list.name
+            ))
+        } else {
+            Err(Error::new_external("Invalid or expired confirmation."))
+        }
+    }
+}
@@ -40,6 +40,9 @@ pub struct Connection {
     /// The `rusqlite` connection handle.
     pub connection: DbConnection,
     pub(crate) conf: Configuration,
+    /// Set during [`Connection::post_dry_run`], whose rollback can't undo
+    /// writes to the [blob store](crate::blobs).
+    pub(crate) dry_run: std::cell::Cell<bool>,
 }
 
 impl std::fmt::Debug for Connection {
@@ -86,11 +89,48 @@ fn user_authorizer_callback(
 
     // [ref:sync_auth_doc] sync with `untrusted()` rustdoc when changing this.
     match auth_context.action {
+        AuthAction::Read {
+            table_name: "list_openpgp_key",
+            column_name: "secret_key",
+            ..
+        } => Authorization::Ignore,
         AuthAction::Delete {
-            table_name: "queue" | "candidate_subscription" | "subscription",
+            table_name:
+                "queue"
+                | "candidate_subscription"
+                | "subscription"
+                | "subscription_topics"
+                | "subscription_confirmation"
+                | "unsubscription_confirmation"
+                | "thread_preference"
+                // These two are only deleted by the `ON DELETE CASCADE` of their
+                // subscription, which SQLite authorizes like a `DELETE` on them.
+                | "bounce"
+                | "reconfirmation"
+                | "post_fts"
+                | "post_fts_content"
+                | "post_fts_data"
+                | "post_fts_docsize"
+                | "post_fts_idx",
         }
         | AuthAction::Insert {
-            table_name: "post" | "queue" | "candidate_subscription" | "subscription" | "account",
+            table_name:
+                "post"
+                | "blob"
+                | "post_fts"
+                | "post_fts_content"
+                | "post_fts_data"
+                | "post_fts_docsize"
+                | "post_fts_idx"
+                | "post_event"
+                | "queue"
+                | "candidate_subscription"
+                | "subscription"
+                | "subscription_topics"
+                | "subscription_confirmation"
+                | "unsubscription_confirmation"
+                | "thread_preference"
+                | "account",
         }
         | AuthAction::Update {
             table_name: "candidate_subscription" | "template",
@@ -110,14 +150,37 @@ fn user_authorizer_callback(
                 | "hide_address"
                 | "receive_duplicates"
                 | "receive_own_posts"
-                | "receive_confirmation",
+                | "receive_confirmation"
+                | "follow_only",
+        }
+        | AuthAction::Update {
+            table_name: "subscription_topics",
+            column_name: "last_modified" | "topics" | "everything_else",
+        }
+        | AuthAction::Update {
+            table_name: "thread_preference",
+            column_name: "last_modified" | "muted",
+        }
+        | AuthAction::Update {
+            table_name: "reconfirmation",
+            column_name: "confirmed",
         }
         | AuthAction::Select
+        | AuthAction::Recursive
         | AuthAction::Savepoint { .. }
         | AuthAction::Transaction { .. }
         | AuthAction::Read { .. }
+        | AuthAction::Pragma {
+            pragma_name: "data_version" | "database_list",
+            pragma_value: None,
+        }
+        | AuthAction::Pragma {
+            pragma_name: "table_info",
+            ..
+        }
         | AuthAction::Function {
-            function_name: "count" | "strftime" | "unixepoch" | "datetime",
+            function_name:
+                "count" | "min" | "max" | "strftime" | "unixepoch" | "datetime" | "snippet" | "match",
         } => Authorization::Allow,
         _ => Authorization::Deny,
     }
@@ -164,9 +227,8 @@ impl Connection {
     ///             extensions: Default::default(),
     ///         }
     ///     ),
-    ///     db_path,
     ///     data_path,
-    ///     administrators: vec![],
+    ///     ..Configuration::new(db_path)
     /// };
     /// # assert_eq!(&Connection::open_db(config.clone()).unwrap_err().to_string(), "Database doesn't exist");
     ///
@@ -241,9 +303,82 @@ impl Connection {
             },
         )?;
 
+        // Used by migrations to backfill the threading columns, the
+        // full-text search index and the parsed headers of posts.
+        for (name, f) in [
+            (
+                "post_in_reply_to",
+                crate::threads::in_reply_to as fn(&melib::Envelope) -> Option<String>,
+            ),
+            ("post_references", crate::threads::references),
+            ("post_thread_root", crate::threads::replied_thread_root),
+            ("post_search_subject", crate::search::subject),
+            ("post_search_sender", crate::search::sender),
+        ] {
+            conn.create_scalar_function(
+                name,
+                1,
+                FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+                move |ctx| {
+                    let message = ctx
+                        .get_raw(0)
+                        .as_blob()
+                        .map_err(|err| rusqlite::Error::UserFunctionError(err.into()))?;
+                    Ok(melib::Envelope::from_bytes(message, None)
+                        .ok()
+                        .and_then(|env| f(&env)))
+                },
+            )?;
+        }
+
+        conn.create_scalar_function(
+            "post_search_body",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            move |ctx| {
+                let message = ctx
+                    .get_raw(0)
+                    .as_blob()
+                    .map_err(|err| rusqlite::Error::UserFunctionError(err.into()))?;
+                Ok(melib::Envelope::from_bytes(message, None)
+                    .ok()
+                    .and_then(|env| crate::search::body_text(&env, message)))
+            },
+        )?;
+        // Used by migrations to restore messages whose parts are in the blob
+        // store.
+        let blob_directory = conf.data_directory().join("blobs");
+        conn.create_scalar_function(
+            "reassemble_message",
+            2,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            move |ctx| {
+                let message = ctx
+                    .get_raw(0)
+                    .as_blob()
+                    .map_err(|err| rusqlite::Error::UserFunctionError(err.into()))?;
+                crate::blobs::refs_from_json(ctx.get(1)?)
+                    .and_then(|refs| crate::blobs::reassemble(&blob_directory, message, &refs))
+                    .map_err(|err| rusqlite::Error::UserFunctionError(Box::new(err)))
+            },
+        )?;
+        conn.create_scalar_function(
+            "post_headers_json",
+            1,
+            FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+            move |ctx| {
+                let message = ctx
+                    .get_raw(0)
+                    .as_blob()
+                    .map_err(|err| rusqlite::Error::UserFunctionError(err.into()))?;
+                Ok(Post::parse_headers(message))
+            },
+        )?;
+
         let ret = Self {
             conf,
             connection: conn,
+            dry_run: Default::default(),
         };
         if let Some(&(latest, _, _)) = Self::MIGRATIONS.last() {
             let version = ret.schema_version()?;
@@ -255,6 +390,7 @@ impl Connection {
             }
             ret.migrate(version, latest)?;
         }
+        ret.load_archives()?;
 
         ret.connection.authorizer(Some(user_authorizer_callback));
         Ok(ret)
@@ -322,12 +458,23 @@ impl Connection {
     /// Sets operational limits for this connection.
     ///
     /// - Allow `INSERT`, `DELETE` only for "queue", "candidate_subscription",
-    ///   "subscription".
-    /// - Allow `UPDATE` only for "subscription" user facing settings.
-    /// - Allow `INSERT` only for "post".
-    /// - Allow read access to all tables.
-    /// - Allow `SELECT`, `TRANSACTION`, `SAVEPOINT`, and the `strftime`
-    ///   function.
+    ///   "subscription", "subscription_topics", "subscription_confirmation",
+    ///   "unsubscription_confirmation", "thread_preference".
+    /// - Allow `DELETE` for "bounce" and "reconfirmation": removing a
+    ///   subscription deletes their rows with an `ON DELETE CASCADE` foreign
+    ///   key action, which SQLite authorizes like a `DELETE` statement on them.
+    /// - Allow `UPDATE` only for "subscription" user facing settings,
+    ///   "subscription_topics", "thread_preference" and the confirmation of
+    ///   "reconfirmation" requests.
+    /// - Allow `INSERT` only for "post", "post_event" and "blob".
+    /// - Allow `INSERT`, `DELETE` for the "post_fts" full-text search index and
+    ///   its shadow tables, which are kept in sync with "post".
+    /// - Allow read access to all tables and the `data_version`,
+    ///   `database_list` and `table_info` pragmas, except for the
+    ///   "list_openpgp_key" secret keys, which read as `NULL`.
+    /// - Allow `SELECT`, recursive `SELECT`, `TRANSACTION`, `SAVEPOINT`, and
+    ///   the `count`, `min`, `max`, `strftime`, `unixepoch`, `datetime`,
+    ///   `snippet` and `match` functions.
     /// - Deny everything else.
     pub fn untrusted(self) -> Self {
         self.connection.authorizer(Some(user_authorizer_callback));
@@ -411,29 +558,6 @@ impl Connection {
         &self.conf
     }
 
-    /// Loads archive databases from [`Configuration::data_path`], if any.
-    pub fn load_archives(&self) -> Result<()> {
-        let tx = self.savepoint(Some(stringify!(load_archives)))?;
-        {
-            let mut stmt = tx.connection.prepare("ATTACH ? AS ?;")?;
-            for archive in std::fs::read_dir(&self.conf.data_path)? {
-                let archive = archive?;
-                let path = archive.path();
-                let name = path.file_name().unwrap_or_default();
-                if path == self.conf.db_path {
-                    continue;
-                }
-                stmt.execute(rusqlite::params![
-                    path.to_str().unwrap(),
-                    name.to_str().unwrap()
-                ])?;
-            }
-        }
-        tx.commit()?;
-
-        Ok(())
-    }
-
     /// Returns a vector of existing mailing lists.
     pub fn lists(&self) -> Result<Vec<DbVal<MailingList>>> {
         let mut stmt = self.connection.prepare("SELECT * FROM list;")?;
@@ -519,6 +643,15 @@ impl Connection {
         Ok(ret)
     }
 
+    /// Whether a mailing list is hidden from the public.
+    pub fn list_is_hidden(&self, pk: i64) -> Result<bool> {
+        Ok(self
+            .connection
+            .query_row("SELECT hidden FROM list WHERE pk = ?;", [&pk], |row| {
+                row.get(0)
+            })?)
+    }
+
     /// Create a new list.
     pub fn create_list(&self, new_val: MailingList) -> Result<DbVal<MailingList>> {
         let mut stmt = self.connection.prepare(
@@ -563,27 +696,12 @@ impl Connection {
         list_pk: i64,
         _date_range: Option<(String, String)>,
     ) -> Result<Vec<DbVal<Post>>> {
-        let mut stmt = self.connection.prepare(
+        let mut stmt = self.connection.prepare(&format!(
             "SELECT *, strftime('%Y-%m', CAST(timestamp AS INTEGER), 'unixepoch') AS month_year \
-             FROM post WHERE list = ? ORDER BY timestamp ASC;",
-        )?;
-        let iter = stmt.query_map(rusqlite::params![&list_pk], |row| {
-            let pk = row.get("pk")?;
-            Ok(DbVal(
-                Post {
-                    pk,
-                    list: row.get("list")?,
-                    envelope_from: row.get("envelope_from")?,
-                    address: row.get("address")?,
-                    message_id: row.get::<_, String>("message_id")?.strip_carets_inplace(),
-                    message: row.get("message")?,
-                    timestamp: row.get("timestamp")?,
-                    datetime: row.get("datetime")?,
-                    month_year: row.get("month_year")?,
-                },
-                pk,
-            ))
-        })?;
+             FROM {} WHERE list = ? ORDER BY timestamp ASC;",
+            self.posts_source()?
+        ))?;
+        let iter = stmt.query_map(rusqlite::params![&list_pk], |row| self.post_from_row(row))?;
         let mut ret = vec![];
         for post in iter {
             ret.push(post?);
@@ -595,109 +713,93 @@ impl Connection {
 
     /// Fetch the contents of a single thread in the form of `(depth, post)`
     /// where `depth` is the reply distance between a message and the thread
-    /// root message.
+    /// root message. The root message itself is not included.
+    ///
+    /// Replies whose parent is not in the list are placed directly under the
+    /// root, if they belong to its thread.
     pub fn list_thread(&self, list_pk: i64, root: &str) -> Result<Vec<(i64, DbVal<Post>)>> {
-        let mut stmt = self
-            .connection
-            .prepare(
-                "WITH RECURSIVE cte_replies AS MATERIALIZED
-            (
-                SELECT
-                pk,
-                message_id,
-                REPLACE(
-                    TRIM(
-                        SUBSTR(
-                            CAST(message AS TEXT),
-                            INSTR(
-                                CAST(message AS TEXT),
-                                'In-Reply-To: '
-                            )
-                            +
-                            LENGTH('in-reply-to: '),
-                            INSTR(
-                                SUBSTR(
-                                    CAST(message AS TEXT),
-                                    INSTR(
-                                        CAST(message AS TEXT),
-                                        'In-Reply-To: ')
-                                        +
-                                        LENGTH('in-reply-to: ')
-                                ),
-                                '>'
-                            )
-                        )
-                    ),
-                    ' ',
-                    ''
-                ) AS in_reply_to,
-                INSTR(
-                    CAST(message AS TEXT),
-                    'In-Reply-To: '
-                ) AS offset
-                FROM post
-                WHERE
-                    offset > 0
-                UNION
-                SELECT
-                pk,
-                message_id,
-                NULL AS in_reply_to,
-                INSTR(
-                    CAST(message AS TEXT),
-                    'In-Reply-To: '
-                ) AS offset
-                FROM post
-                WHERE
-                offset = 0
-            ),
-            cte_thread(parent, root, depth) AS (
-              SELECT DISTINCT
-                message_id AS parent,
-                message_id AS root,
-                0 AS depth
-              FROM cte_replies
-              WHERE
-                in_reply_to IS NULL
-              UNION ALL
-              SELECT
-                t.message_id AS parent,
-                cte_thread.root AS root,
-                (cte_thread.depth + 1) AS depth
-              FROM cte_replies
-              AS t
-              JOIN
-              cte_thread
-              ON cte_thread.parent = trim(t.in_reply_to, '<>')
-              WHERE t.in_reply_to IS NOT NULL
-)
-SELECT * FROM cte_thread WHERE root = ? ORDER BY root, depth;",
-            )
-            .unwrap();
-        let iter = stmt.query_map(rusqlite::params![root], |row| {
-            let parent: String = row.get("parent")?;
-            let root: String = row.get("root")?;
-            let depth: i64 = row.get("depth")?;
-            Ok((parent, root, depth))
+        let root = root.strip_carets();
+        let (cte, posts) = self.posts_cte()?;
+        let mut stmt = self.connection.prepare(&format!(
+            "WITH RECURSIVE {}thread(message_id, depth) AS (SELECT message_id, 0 FROM {posts} \
+             WHERE list = ?1 AND message_id = ?2 UNION SELECT p.message_id, 1 FROM {posts} AS p \
+             WHERE p.list = ?1 AND p.thread_root = ?2 AND p.message_id <> ?2 AND NOT EXISTS \
+             (SELECT 1 FROM {posts} AS parent WHERE parent.list = ?1 AND parent.message_id = \
+             p.in_reply_to) UNION SELECT p.message_id, thread.depth + 1 FROM {posts} AS p JOIN \
+             thread ON p.in_reply_to = thread.message_id WHERE p.list = ?1 AND thread.depth < \
+             256) SELECT post.*, thread.depth AS depth, strftime('%Y-%m', CAST(post.timestamp AS \
+             INTEGER), 'unixepoch') AS month_year FROM thread JOIN {posts} AS post ON post.list = \
+             ?1 AND post.message_id = thread.message_id ORDER BY thread.depth, post.timestamp, \
+             post.pk;",
+            cte.map(|cte| format!("{cte}, ")).unwrap_or_default(),
+        ))?;
+        let iter = stmt.query_map(rusqlite::params![&list_pk, &root], |row| {
+            Ok((row.get::<_, i64>("depth")?, self.post_from_row(row)?))
         })?;
+        let mut seen = std::collections::HashSet::new();
         let mut ret = vec![];
-        for post in iter {
-            ret.push(post?);
+        for item in iter {
+            let (depth, post) = item?;
+            // A post reached through several paths is kept at its least depth.
+            if seen.insert(post.pk) && depth > 0 {
+                ret.push((depth, post));
+            }
         }
-        let posts = self.list_posts(list_pk, None)?;
-        let ret = ret
-            .into_iter()
-            .filter_map(|(m, _, depth)| {
-                posts
-                    .iter()
-                    .find(|p| m.as_str().strip_carets() == p.message_id.as_str().strip_carets())
-                    .map(|p| (depth, p.clone()))
-            })
-            .skip(1)
-            .collect();
         Ok(ret)
     }
 
+    /// Fetch the threads of a list in the form of `(root, length,
+    /// last_active)`, where `length` is the number of posts in the thread and
+    /// `last_active` is the timestamp of its latest post, most recently
+    /// active first.
+    ///
+    /// If the root message of a thread is not in the list, the first stored
+    /// post of the thread stands in for it.
+    pub fn list_thread_roots(&self, list_pk: i64) -> Result<Vec<(DbVal<Post>, usize, u64)>> {
+        let (cte, posts) = self.posts_cte()?;
+        let mut stmt = self.connection.prepare(&format!(
+            "{}SELECT root.*, t.length AS length, t.last_active AS last_active, strftime('%Y-%m', \
+             CAST(root.timestamp AS INTEGER), 'unixepoch') AS month_year FROM (SELECT \
+             thread_root, count(*) AS length, max(timestamp) AS last_active, min(pk) AS first_pk, \
+             (SELECT r.pk FROM {posts} AS r WHERE r.list = ?1 AND r.message_id = g.thread_root) \
+             AS root_pk FROM {posts} AS g WHERE list = ?1 GROUP BY thread_root) AS t JOIN {posts} \
+             AS root ON root.pk = CASE WHEN t.root_pk IS NULL THEN t.first_pk ELSE t.root_pk END \
+             ORDER BY t.last_active DESC, root.pk DESC;",
+            cte.map(|cte| format!("WITH {cte} ")).unwrap_or_default(),
+        ))?;
+        let iter = stmt.query_map([&list_pk], |row| {
+            Ok((
+                self.post_from_row(row)?,
+                row.get::<_, usize>("length")?,
+                row.get::<_, u64>("last_active")?,
+            ))
+        })?;
+        let mut ret = vec![];
+        for item in iter {
+            ret.push(item?);
+        }
+        Ok(ret)
+    }
+
+    pub(crate) fn post_from_row(&self, row: &rusqlite::Row) -> rusqlite::Result<DbVal<Post>> {
+        let pk = row.get("pk")?;
+        Ok(DbVal(
+            Post {
+                pk,
+                list: row.get("list")?,
+                envelope_from: row.get("envelope_from")?,
+                address: row.get("address")?,
+                message_id: row.get::<_, String>("message_id")?.strip_carets_inplace(),
+                message: self.message_from_row(row)?,
+                timestamp: row.get("timestamp")?,
+                datetime: row.get("datetime")?,
+                month_year: row.get("month_year")?,
+                headers_json: row.get("headers_json")?,
+            },
+            pk,
+        ))
+    }
+
     /// Export a list, message, or thread in mbox format
     pub fn export_mbox(
         &self,
@@ -849,11 +951,16 @@ SELECT * FROM cte_thread WHERE root = ? ORDER BY root, depth;",
                 address: None,
                 description: None,
                 archive_url: None,
+                topics: None,
                 owner_local_part: None,
                 request_local_part: None,
                 verify: None,
                 hidden: None,
                 enabled: None,
+                confirm_unsubscription: None,
+                post_retention_days: None,
+                archive_addresses: None,
+                archive_raw_download: None,
             }
         ) {
             return self.list(change_set.pk).map(|_| ());
@@ -866,11 +973,16 @@ SELECT * FROM cte_thread WHERE root = ? ORDER BY root, depth;",
             address,
             description,
             archive_url,
+            topics,
             owner_local_part,
             request_local_part,
             verify,
             hidden,
             enabled,
+            confirm_unsubscription,
+            post_retention_days,
+            archive_addresses,
+            archive_raw_download,
         } = change_set;
         let tx = self.savepoint(Some(stringify!(update_list)))?;
 
@@ -889,11 +1001,21 @@ SELECT * FROM cte_thread WHERE root = ? ORDER BY root, depth;",
         update!(address);
         update!(description);
         update!(archive_url);
+        if let Some(topics) = topics {
+            tx.connection.execute(
+                "UPDATE list SET topics = ? WHERE pk = ?;",
+                rusqlite::params![serde_json::json!(topics.as_slice()), &pk],
+            )?;
+        }
         update!(owner_local_part);
         update!(request_local_part);
         update!(verify);
         update!(hidden);
         update!(enabled);
+        update!(confirm_unsubscription);
+        update!(post_retention_days);
+        update!(archive_addresses);
+        update!(archive_raw_download);
 
         tx.commit()?;
         Ok(())
@@ -1183,9 +1305,8 @@ mod tests {
                 security: SmtpSecurity::None,
                 extensions: Default::default(),
             }),
-            db_path,
             data_path,
-            administrators: vec![],
+            ..Configuration::new(db_path)
         };
         assert_eq!(
             &Connection::open_db(config.clone()).unwrap_err().to_string(),
@@ -1215,9 +1336,8 @@ mod tests {
                 security: SmtpSecurity::None,
                 extensions: Default::default(),
             }),
-            db_path,
             data_path,
-            administrators: vec![],
+            ..Configuration::new(db_path)
         };
         let list = MailingList {
             pk: 0,
@@ -1320,17 +1440,9 @@ mod tests {
     fn test_mbox_export() {
         use tempfile::TempDir;
 
-        use crate::SendMail;
-
         let tmp_dir = TempDir::new().unwrap();
         let db_path = tmp_dir.path().join("mpot.db");
-        let data_path = tmp_dir.path().to_path_buf();
-        let config = Configuration {
-            send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-            db_path,
-            data_path,
-            administrators: vec![],
-        };
+        let config = Configuration::new(db_path);
         let list = MailingList {
             pk: 0,
             name: "test".into(),
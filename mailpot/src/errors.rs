@@ -82,6 +82,9 @@ pub enum ErrorKind {
     /// Error returned from minijinja template engine.
     #[error("Error returned from minijinja template engine: {0}")]
     Template(#[from] minijinja::Error),
+    /// Error returned from OpenPGP operations.
+    #[error("Error returned from OpenPGP operation: {0}")]
+    OpenPgp(#[from] pgp::errors::Error),
 }
 
 impl std::fmt::Display for Error {
@@ -116,6 +119,7 @@ impl_from! { ::std::io::Error }
 impl_from! { melib::error::Error }
 impl_from! { serde_json::Error }
 impl_from! { minijinja::Error }
+impl_from! { pgp::errors::Error }
 
 impl Error {
     /// Helper function to create a new generic error message.
@@ -66,17 +66,12 @@
 //! # Example
 //!
 //! ```
-//! use mailpot::{models::*, Configuration, Connection, SendMail};
+//! use mailpot::{models::*, Configuration, Connection};
 //! # use tempfile::TempDir;
 //!
 //! # let tmp_dir = TempDir::new().unwrap();
 //! # let db_path = tmp_dir.path().join("mpot.db");
-//! # let config = Configuration {
-//! #     send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-//! #     db_path: db_path.clone(),
-//! #     data_path: tmp_dir.path().to_path_buf(),
-//! #     administrators: vec![],
-//! # };
+//! # let config = Configuration::new(db_path.clone());
 //! #
 //! # fn do_test(config: Configuration) -> mailpot::Result<()> {
 //! let db = Connection::open_or_create_db(config)?.trusted();
@@ -124,6 +119,30 @@
 //! assert_eq!(db.list_subscriptions(list_pk)?.len(), 1);
 //! assert_eq!(db.list_posts(list_pk, None)?.len(), 0);
 //!
+//! // The list verifies e-mail addresses: the subscription takes effect when the
+//! // subscriber replies to the confirmation request they were sent.
+//! let subscription = &db.list_subscriptions(list_pk)?[0];
+//! let token = db
+//!     .subscription_confirmation(subscription.pk)?
+//!     .unwrap()
+//!     .token
+//!     .clone();
+//! let confirm_bytes = format!(
+//!     "From: Name <user@example.com>
+//! To: <foo-chat+request@example.com>
+//! Subject: Re: confirm {token}
+//! Date: Thu, 29 Oct 2020 13:59:02 +0000
+//! Message-ID: <3@example.com>
+//!
+//! "
+//! );
+//! let envelope = melib::Envelope::from_bytes(confirm_bytes.as_bytes(), None)?;
+//! db.post(
+//!     &envelope,
+//!     confirm_bytes.as_bytes(),
+//!     /* dry_run */ false,
+//! )?;
+//!
 //! // Process a post
 //! let post_bytes = b"From: Name <user@example.com>
 //! To: <foo-chat@example.com>
@@ -176,20 +195,37 @@ pub extern crate melib;
 /// serde_json
 pub extern crate serde_json;
 
+pub mod archives;
+pub mod blobs;
 mod config;
+pub mod confirmations;
 mod connection;
 mod errors;
 pub mod mail;
+pub mod mailing_sets;
 pub mod message_filters;
 pub mod models;
+pub mod openpgp;
 pub mod policies;
+pub mod post_events;
 #[cfg(not(target_os = "windows"))]
 pub mod postfix;
 pub mod posts;
+pub mod privacy;
 pub mod queue;
+pub mod reconfirmations;
+pub mod redaction;
+pub mod retention;
+pub mod scheduled;
+pub mod search;
 pub mod submission;
 pub mod subscriptions;
 mod templates;
+pub mod threads;
+pub mod tokens;
+pub mod tombstones;
+pub mod topics;
+pub mod umbrella;
 
 pub use config::{Configuration, SendMail};
 pub use connection::{transaction, *};
@@ -19,20 +19,24 @@
 
 //! Types for processing new posts:
 //! [`PostFilter`](crate::message_filters::PostFilter), [`ListContext`],
-//! [`MailJob`] and [`PostAction`].
+//! [`MailJob`] and [`PostAction`], and the [`PostTrace`] report of a dry run.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use log::trace;
 use melib::{Address, MessageID};
 
 use crate::{
-    models::{ListOwner, ListSubscription, MailingList, PostPolicy, SubscriptionPolicy},
+    models::{
+        AccessEntry, ListOpenPgpKey, ListOwner, ListSubscription, MailingList, PostPolicy,
+        SubscriptionPolicy, SubscriptionTopics,
+    },
+    threads::ThreadAction,
     DbVal,
 };
 /// Post action returned from a list's
 /// [`PostFilter`](crate::message_filters::PostFilter) stack.
-#[derive(Debug)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 pub enum PostAction {
     /// Add to `hold` queue.
     Hold,
@@ -50,6 +54,27 @@ pub enum PostAction {
     },
 }
 
+impl PostAction {
+    /// Short name of the action, used in the
+    /// [`PostEvent`](crate::models::PostEvent) audit trail.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Hold => "hold",
+            Self::Accept => "accept",
+            Self::Reject { .. } => "reject",
+            Self::Defer { .. } => "defer",
+        }
+    }
+
+    /// Human readable reason of the action, if any.
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Self::Hold | Self::Accept => None,
+            Self::Reject { reason } | Self::Defer { reason } => Some(reason),
+        }
+    }
+}
+
 /// List context passed to a list's
 /// [`PostFilter`](crate::message_filters::PostFilter) stack.
 #[derive(Debug)]
@@ -60,18 +85,110 @@ pub struct ListContext<'list> {
     pub list_owners: &'list [DbVal<ListOwner>],
     /// The mailing list subscriptions.
     pub subscriptions: &'list [DbVal<ListSubscription>],
+    /// The enabled subscriptions of the lists included by the mailing list,
+    /// recursively, see [`umbrella`](crate::umbrella).
+    pub member_subscriptions: Vec<DbVal<ListSubscription>>,
+    /// Lowercase addresses of the owners of included lists whose post rights
+    /// are inherited.
+    pub inherited_owners: Vec<String>,
+    /// Lowercase addresses of the owners and subscribers of included lists
+    /// whose post rights are inherited.
+    pub inherited_posters: Vec<String>,
+    /// The topics chosen by the mailing list's subscribers, keyed by
+    /// subscription primary key, see [`topics`](crate::topics).
+    pub subscription_topics: HashMap<i64, SubscriptionTopics>,
+    /// Subscriptions that don't receive the post because they muted its
+    /// thread, or are in follow-only mode and don't follow it, see
+    /// [`threads`](crate::threads).
+    pub thread_exclusions: HashSet<i64>,
     /// The mailing list post policy.
     pub post_policy: Option<DbVal<PostPolicy>>,
     /// The mailing list subscription policy.
     pub subscription_policy: Option<DbVal<SubscriptionPolicy>>,
+    /// The allow and deny entries of the mailing list, including
+    /// instance-wide entries.
+    pub access_entries: Vec<DbVal<AccessEntry>>,
+    /// The OpenPGP key of the mailing list, if it is an encrypted list.
+    pub openpgp_key: Option<DbVal<ListOpenPgpKey>>,
+    /// The OpenPGP public keys of the accounts of the list's subscribers and
+    /// owners, keyed by lowercase address.
+    pub public_keys: HashMap<String, String>,
+    /// The key of the signatures of subscription tokens, see
+    /// [`tokens`](crate::tokens).
+    pub token_secret: Vec<u8>,
+    /// The URL of the web interface, if each recipient's copy gets an
+    /// [RFC 8058](https://www.rfc-editor.org/rfc/rfc8058) one-click
+    /// `List-Unsubscribe` header, set by
+    /// [`AddListHeaders`](crate::message_filters::AddListHeaders).
+    pub one_click_unsubscribe: Option<String>,
     /// The scheduled jobs added by each filter in a list's
     /// [`PostFilter`](crate::message_filters::PostFilter) stack.
     pub scheduled_jobs: Vec<MailJob>,
+    /// Notes added by the currently running
+    /// [`PostFilter`](crate::message_filters::PostFilter), recorded in the
+    /// post's [`PostEvent`](crate::models::PostEvent) audit trail.
+    pub notes: Vec<String>,
     /// Saved settings for message filters, which process a
     /// received e-mail before taking a final decision/action.
     pub filter_settings: HashMap<String, DbVal<serde_json::Value>>,
 }
 
+impl ListContext<'_> {
+    /// Find the subscription, of the list or of an included list, that
+    /// receives posts at `address`.
+    pub fn recipient_subscription(&self, address: &str) -> Option<&DbVal<ListSubscription>> {
+        self.subscriptions
+            .iter()
+            .chain(self.member_subscriptions.iter())
+            .find(|s| s.address.eq_ignore_ascii_case(address))
+    }
+
+    /// Returns the copy of `bytes` sent to `recipient`, with a one-click
+    /// `List-Unsubscribe` header if
+    /// [`one_click_unsubscribe`](Self::one_click_unsubscribe) is set.
+    pub fn recipient_copy<'b>(
+        &self,
+        recipient: &Address,
+        bytes: &'b [u8],
+    ) -> std::borrow::Cow<'b, [u8]> {
+        let Some(ref base_url) = self.one_click_unsubscribe else {
+            return std::borrow::Cow::Borrowed(bytes);
+        };
+        let address = recipient.get_email();
+        let token = crate::tokens::SubscriptionToken {
+            action: crate::tokens::TokenAction::Unsubscribe,
+            list: self
+                .recipient_subscription(&address)
+                .map_or(self.list.pk, |s| s.list),
+            address,
+            issued: chrono::offset::Utc::now().timestamp() as u64,
+        }
+        .sign(&self.token_secret);
+        let url = crate::tokens::one_click_unsubscribe_url(base_url, &token);
+        let Ok((headers, body)) = melib::email::parser::mail(bytes) else {
+            return std::borrow::Cow::Borrowed(bytes);
+        };
+        let mut list_unsubscribe = format!("<{url}>").into_bytes();
+        let mut new_vec = Vec::with_capacity(bytes.len() + 2 * list_unsubscribe.len());
+        for (h, v) in headers {
+            if h == melib::email::headers::HeaderName::LIST_UNSUBSCRIBE {
+                list_unsubscribe.extend_from_slice(b", ");
+                list_unsubscribe.extend_from_slice(v);
+                continue;
+            }
+            new_vec.extend_from_slice(h.as_str().as_bytes());
+            new_vec.extend_from_slice(b": ");
+            new_vec.extend_from_slice(v);
+            new_vec.extend_from_slice(b"\r\n");
+        }
+        new_vec.extend_from_slice(b"List-Unsubscribe: ");
+        new_vec.extend_from_slice(&list_unsubscribe);
+        new_vec.extend_from_slice(b"\r\nList-Unsubscribe-Post: List-Unsubscribe=One-Click\r\n\r\n");
+        new_vec.extend_from_slice(body);
+        std::borrow::Cow::Owned(new_vec)
+    }
+}
+
 /// Post to be considered by the list's
 /// [`PostFilter`](crate::message_filters::PostFilter) stack.
 pub struct PostEntry {
@@ -109,6 +226,12 @@ pub enum MailJob {
         /// The post recipients addresses.
         recipients: Vec<Address>,
     },
+    /// Send a separately prepared copy of the post to each recipient, e.g.
+    /// encrypted to the recipient's key.
+    SendIndividual {
+        /// The recipient addresses and their copy of the post.
+        messages: Vec<(Address, Vec<u8>)>,
+    },
     /// Send error to submitter.
     Error {
         /// Human readable description of the error.
@@ -129,6 +252,220 @@ pub enum MailJob {
         /// The submitter address.
         recipient: Address,
     },
+    /// Send a notice to the list owners.
+    NotifyOwners {
+        /// Human readable description of the notice.
+        description: String,
+    },
+}
+
+impl MailJob {
+    /// Short name of the job, used in the
+    /// [`PostEvent`](crate::models::PostEvent) audit trail.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Send { .. } => "send",
+            Self::SendIndividual { .. } => "send-individual",
+            Self::Error { .. } => "error",
+            Self::StoreDigest { .. } => "store-digest",
+            Self::ConfirmSubscription { .. } => "confirm-subscription",
+            Self::ConfirmUnsubscription { .. } => "confirm-unsubscription",
+            Self::NotifyOwners { .. } => "notify-owners",
+        }
+    }
+}
+
+impl std::fmt::Display for MailJob {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let join = |addrs: &[Address]| {
+            addrs
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        match self {
+            Self::Send { recipients } => write!(fmt, "Send to [{}]", join(recipients)),
+            Self::SendIndividual { messages } => write!(
+                fmt,
+                "Send individual copies to [{}]",
+                join(&messages.iter().map(|(a, _)| a.clone()).collect::<Vec<_>>())
+            ),
+            Self::Error { description } => write!(fmt, "Error: {description}"),
+            Self::StoreDigest { recipients } => {
+                write!(fmt, "Store digest for [{}]", join(recipients))
+            }
+            Self::ConfirmSubscription { recipient } => {
+                write!(fmt, "Confirm subscription to {recipient}")
+            }
+            Self::ConfirmUnsubscription { recipient } => {
+                write!(fmt, "Confirm unsubscription to {recipient}")
+            }
+            Self::NotifyOwners { description } => write!(fmt, "Notify owners: {description}"),
+        }
+    }
+}
+
+/// Report of a simulated post, returned by
+/// [`Connection::post_dry_run`](crate::Connection::post_dry_run).
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PostTrace {
+    /// The request parsed from the recipient sub-address, if the post was a
+    /// list request.
+    pub request: Option<ListRequest>,
+    /// The filter traces of each list the post was addressed to.
+    pub lists: Vec<ListTrace>,
+    /// Queue entries that would have been created.
+    pub queue_entries: Vec<QueueTrace>,
+    /// The processing error, if any. The post would have been inserted to the
+    /// `error` queue.
+    pub error: Option<String>,
+}
+
+/// How a list's [`PostFilter`](crate::message_filters::PostFilter) stack
+/// processed a post, part of a [`PostTrace`].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ListTrace {
+    /// The list's id.
+    pub list: String,
+    /// The effects of each filter, in order of execution.
+    pub filters: Vec<FilterTrace>,
+    /// The final action.
+    pub action: PostAction,
+    /// Addresses that would receive the post.
+    pub recipients: Vec<String>,
+    /// Addresses that would receive the post in a digest.
+    pub digest_recipients: Vec<String>,
+}
+
+/// The effects of a single [`PostFilter`](crate::message_filters::PostFilter),
+/// part of a [`ListTrace`].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct FilterTrace {
+    /// Filter name.
+    pub filter: String,
+    /// Headers added by the filter, as `Name: value` strings.
+    pub headers_added: Vec<String>,
+    /// Headers removed by the filter, as `Name: value` strings.
+    pub headers_removed: Vec<String>,
+    /// Whether the filter modified the message body.
+    pub body_changed: bool,
+    /// The post action after the filter was run.
+    pub action: PostAction,
+    /// Jobs scheduled by the filter.
+    pub jobs_scheduled: Vec<String>,
+    /// Notes added by the filter.
+    pub notes: Vec<String>,
+    /// Whether the filter stopped processing.
+    pub stopped: bool,
+}
+
+impl FilterTrace {
+    /// Compare post bytes before and after a filter was run.
+    pub fn diff(&mut self, before: &[u8], after: &[u8]) {
+        let (Ok((before_headers, before_body)), Ok((after_headers, after_body))) = (
+            melib::email::parser::mail(before),
+            melib::email::parser::mail(after),
+        ) else {
+            self.body_changed = before != after;
+            return;
+        };
+        let fmt =
+            |(k, v): &(melib::HeaderName, &[u8])| format!("{}: {}", k, String::from_utf8_lossy(v));
+        let mut removed = after_headers.clone();
+        for h in &before_headers {
+            if let Some(pos) = removed.iter().position(|a| a == h) {
+                removed.remove(pos);
+            } else {
+                self.headers_removed.push(fmt(h));
+            }
+        }
+        let mut added = before_headers;
+        for h in &after_headers {
+            if let Some(pos) = added.iter().position(|a| a == h) {
+                added.remove(pos);
+            } else {
+                self.headers_added.push(fmt(h));
+            }
+        }
+        self.body_changed = before_body != after_body;
+    }
+}
+
+/// A queue entry that would have been created, part of a [`PostTrace`].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct QueueTrace {
+    /// Destination queue.
+    pub queue: String,
+    /// Entry recipients.
+    pub to_addresses: String,
+    /// Entry subject.
+    pub subject: String,
+    /// Entry comment.
+    pub comment: Option<String>,
+}
+
+impl std::fmt::Display for PostTrace {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(ref request) = self.request {
+            writeln!(fmt, "Request: {request}")?;
+        }
+        for list in &self.lists {
+            writeln!(fmt, "List {}:", list.list)?;
+            for f in &list.filters {
+                writeln!(
+                    fmt,
+                    "  {}{}: action {:?}",
+                    f.filter,
+                    if f.stopped {
+                        " (stopped processing)"
+                    } else {
+                        ""
+                    },
+                    f.action
+                )?;
+                for h in &f.headers_added {
+                    writeln!(fmt, "    + {h}")?;
+                }
+                for h in &f.headers_removed {
+                    writeln!(fmt, "    - {h}")?;
+                }
+                if f.body_changed {
+                    writeln!(fmt, "    body changed")?;
+                }
+                for job in &f.jobs_scheduled {
+                    writeln!(fmt, "    job: {job}")?;
+                }
+                for note in &f.notes {
+                    writeln!(fmt, "    note: {note}")?;
+                }
+            }
+            writeln!(fmt, "  Final action: {:?}", list.action)?;
+            writeln!(fmt, "  Recipients: {}", list.recipients.len())?;
+            for r in &list.recipients {
+                writeln!(fmt, "    {r}")?;
+            }
+            if !list.digest_recipients.is_empty() {
+                writeln!(fmt, "  Digest recipients: {}", list.digest_recipients.len())?;
+                for r in &list.digest_recipients {
+                    writeln!(fmt, "    {r}")?;
+                }
+            }
+        }
+        writeln!(fmt, "Queue entries: {}", self.queue_entries.len())?;
+        for e in &self.queue_entries {
+            write!(fmt, "  [{}] {} {:?}", e.queue, e.to_addresses, e.subject)?;
+            if let Some(ref comment) = e.comment {
+                write!(fmt, " ({comment})")?;
+            }
+            writeln!(fmt)?;
+        }
+        if let Some(ref err) = self.error {
+            writeln!(fmt, "Error: {err}")?;
+            writeln!(fmt, "The post would have been inserted to the error queue.")?;
+        }
+        Ok(())
+    }
 }
 
 /// Type of mailing list request.
@@ -140,6 +477,9 @@ pub enum ListRequest {
     Subscribe,
     /// Request removal of subscription.
     Unsubscribe,
+    /// Confirm a subscription with the token of its confirmation request.
+    /// See [`confirmations`](crate::confirmations).
+    Confirm(String),
     /// Request reception of list posts from a month-year range, inclusive.
     RetrieveArchive(String, String),
     /// Request reception of specific mailing list posts from `Message-ID`
@@ -148,6 +488,15 @@ pub enum ListRequest {
     /// Request change in subscription settings.
     /// See [`ListSubscription`].
     ChangeSetting(String, bool),
+    /// Request change in the topics the subscriber receives posts about.
+    /// See [`topics`](crate::topics).
+    ChangeTopics(Vec<String>),
+    /// Request change in the subscriber's preference for the thread of the
+    /// post the request replies to. See [`threads`](crate::threads).
+    ChangeThread(ThreadAction),
+    /// Request switching to follow-only mode, or back to receiving every
+    /// thread. See [`threads`](crate::threads).
+    FollowOnly(bool),
     /// Other type of request.
     Other(String),
 }
@@ -158,6 +507,54 @@ impl std::fmt::Display for ListRequest {
     }
 }
 
+/// Split a `topics` request subject into words, skipping the `topics`
+/// command itself.
+fn topic_words(subject: &str) -> Vec<String> {
+    let mut words = subject
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|w| !w.is_empty())
+        .peekable();
+    if words
+        .peek()
+        .is_some_and(|w| w.eq_ignore_ascii_case("topics"))
+    {
+        words.next();
+    }
+    words.map(str::to_string).collect()
+}
+
+/// Find the token of a `confirm <token>` command anywhere in a subject, such
+/// as in a reply to a confirmation request.
+fn confirm_request(subject: &str) -> Option<ListRequest> {
+    let words = subject.split_whitespace().collect::<Vec<_>>();
+    words.windows(2).find_map(|w| {
+        (w[0].eq_ignore_ascii_case("confirm")
+            && w[1].len() == 32
+            && w[1].chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| ListRequest::Confirm(w[1].to_ascii_lowercase()))
+    })
+}
+
+/// Parse a thread request command, ignoring any `Re:` prefixes of a reply's
+/// subject.
+fn thread_request(command: &str) -> Option<ListRequest> {
+    let mut command = command.trim();
+    while command
+        .get(..3)
+        .is_some_and(|p| p.eq_ignore_ascii_case("re:"))
+    {
+        command = command[3..].trim_start();
+    }
+    Some(match command.to_ascii_lowercase().as_str() {
+        "mute" => ListRequest::ChangeThread(ThreadAction::Mute),
+        "unmute" => ListRequest::ChangeThread(ThreadAction::Unmute),
+        "follow" => ListRequest::ChangeThread(ThreadAction::Follow),
+        "follow-only" => ListRequest::FollowOnly(true),
+        "follow-all" => ListRequest::FollowOnly(false),
+        _ => return None,
+    })
+}
+
 impl<S: AsRef<str>> TryFrom<(S, &melib::Envelope)> for ListRequest {
     type Error = crate::Error;
 
@@ -170,12 +567,26 @@ impl<S: AsRef<str>> TryFrom<(S, &melib::Envelope)> for ListRequest {
             "request" if env.subject().trim() == "unsubscribe" => Self::Unsubscribe,
             "help" => Self::Help,
             "request" if env.subject().trim() == "help" => Self::Help,
-            "request" => Self::Other(env.subject().trim().to_string()),
-            _ => {
+            "topics" => Self::ChangeTopics(topic_words(&env.subject())),
+            "request"
+                if env
+                    .subject()
+                    .split_whitespace()
+                    .next()
+                    .is_some_and(|w| w.eq_ignore_ascii_case("topics")) =>
+            {
+                Self::ChangeTopics(topic_words(&env.subject()))
+            }
+            "request" | "confirm" if confirm_request(&env.subject()).is_some() => {
+                confirm_request(&env.subject()).unwrap()
+            }
+            "request" => thread_request(&env.subject())
+                .unwrap_or_else(|| Self::Other(env.subject().trim().to_string())),
+            _ => thread_request(val).unwrap_or_else(|| {
                 // [ref:TODO] add ChangeSetting parsing
                 trace!("unknown action = {} for addresses {:?}", val, env.from(),);
                 Self::Other(val.trim().to_string())
-            }
+            }),
         })
     }
 }
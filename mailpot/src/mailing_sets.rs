@@ -0,0 +1,457 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Mailing sets: set-algebraic expressions on mailing lists, after
+//! [dtolnay's `mailingset`](https://github.com/dtolnay/mailingset).
+//!
+//! Mail sent to an address whose local part is a set expression of list
+//! local parts goes to the result of the expression on the lists'
+//! subscribers. For lists `rust@example.com`, `python@example.com` and
+//! `interns@example.com`:
+//!
+//! | Address                                | Recipients                       |
+//! |----------------------------------------|----------------------------------|
+//! | `rust\|python@example.com`             | union of both lists              |
+//! | `rust&python@example.com`              | subscribers of both lists        |
+//! | `rust~interns@example.com`             | `rust` minus `interns`           |
+//! | `{rust\|python}~interns@example.com`   | grouping with braces             |
+//!
+//! `&` binds tighter than `|` and `~`, which are left-associative. `-` is not
+//! an operator since it is common in list addresses, and parentheses are not
+//! allowed in unquoted local parts.
+//!
+//! All lists of an expression must share the domain of the address, and none
+//! of them can be an encrypted list (see [`crate::openpgp`]). Posting rights
+//! are checked against every list of the expression (see
+//! [`PostRightsCheck`](crate::message_filters::PostRightsCheck) and
+//! [`VerifySignature`](crate::message_filters::VerifySignature)); if any of
+//! them does not accept the post outright, it is rejected, since set posts
+//! cannot be moderated.
+//!
+//! The operands of the expression are the recipients each list would choose
+//! for the post (see
+//! [`FinalizeRecipients`](crate::message_filters::FinalizeRecipients)), so
+//! umbrella members, topics, thread preferences and `receive_own_posts` are
+//! respected. Digest subscribers get the post in their digest.
+//!
+//! The post is sent unmodified, without `List-*` headers, and it is archived
+//! in every list of the expression that has at least one of its recipients.
+//! A difference's right-hand side lists are therefore never archived in.
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+};
+
+use log::trace;
+use melib::{Address, Envelope};
+
+use crate::{
+    errors::*,
+    mail::{FilterTrace, ListTrace, MailJob, PostAction, PostEntry, PostTrace},
+    message_filters::{FinalizeRecipients, FixCRLF, PostFilter, PostRightsCheck, VerifySignature},
+    models::{DbVal, MailingList, PostEventKind},
+    posts::TemplateRenderContext,
+    queue::{Queue, QueueEntry},
+    templates::Template,
+    Connection,
+};
+
+/// The operator characters of set expressions.
+pub const OPERATORS: &[char] = &['|', '&', '~', '{', '}'];
+
+/// A set-algebraic expression on mailing lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SetExpr {
+    /// A mailing list, by the local part of its address.
+    List(String),
+    /// Subscribers of either side.
+    Union(Box<Self>, Box<Self>),
+    /// Subscribers of both sides.
+    Intersection(Box<Self>, Box<Self>),
+    /// Subscribers of the left side that are not subscribers of the right
+    /// side.
+    Difference(Box<Self>, Box<Self>),
+}
+
+impl std::fmt::Display for SetExpr {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::List(l) => write!(fmt, "{l}"),
+            Self::Union(a, b) => write!(fmt, "{{{a}|{b}}}"),
+            Self::Intersection(a, b) => write!(fmt, "{{{a}&{b}}}"),
+            Self::Difference(a, b) => write!(fmt, "{{{a}~{b}}}"),
+        }
+    }
+}
+
+impl SetExpr {
+    /// Whether `local_part` is a set expression, i.e. contains any of the
+    /// [`OPERATORS`].
+    pub fn is_set_expression(local_part: &str) -> bool {
+        local_part.contains(OPERATORS)
+    }
+
+    /// Parse a set expression from an address local part.
+    pub fn parse(local_part: &str) -> Result<Self> {
+        let mut parser = Parser {
+            input: local_part,
+            pos: 0,
+        };
+        let ret = parser.union()?;
+        if parser.pos != local_part.len() {
+            return Err(format!(
+                "Invalid mailing set expression `{local_part}`: unexpected `{}` at position {}.",
+                &local_part[parser.pos..],
+                parser.pos
+            )
+            .into());
+        }
+        Ok(ret)
+    }
+
+    /// The list local parts of the expression, in order of appearance.
+    pub fn operands(&self) -> Vec<&str> {
+        match self {
+            Self::List(l) => vec![l.as_str()],
+            Self::Union(a, b) | Self::Intersection(a, b) | Self::Difference(a, b) => {
+                let mut ret = a.operands();
+                ret.extend(b.operands());
+                ret
+            }
+        }
+    }
+
+    /// Evaluate the expression, given the set of each operand.
+    ///
+    /// Operands missing from `sets` evaluate to the empty set.
+    pub fn evaluate<T: Ord + Clone>(&self, sets: &BTreeMap<String, BTreeSet<T>>) -> BTreeSet<T> {
+        match self {
+            Self::List(l) => sets.get(l).cloned().unwrap_or_default(),
+            Self::Union(a, b) => &a.evaluate(sets) | &b.evaluate(sets),
+            Self::Intersection(a, b) => &a.evaluate(sets) & &b.evaluate(sets),
+            Self::Difference(a, b) => &a.evaluate(sets) - &b.evaluate(sets),
+        }
+    }
+}
+
+/// Recursive descent parser for [`SetExpr`].
+///
+/// ```text
+/// union        := intersection (('|' | '~') intersection)*
+/// intersection := atom ('&' atom)*
+/// atom         := list | '{' union '}'
+/// ```
+struct Parser<'s> {
+    input: &'s str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn union(&mut self) -> Result<SetExpr> {
+        let mut ret = self.intersection()?;
+        while let Some(op @ ('|' | '~')) = self.peek() {
+            self.pos += 1;
+            let rhs = Box::new(self.intersection()?);
+            ret = if op == '|' {
+                SetExpr::Union(Box::new(ret), rhs)
+            } else {
+                SetExpr::Difference(Box::new(ret), rhs)
+            };
+        }
+        Ok(ret)
+    }
+
+    fn intersection(&mut self) -> Result<SetExpr> {
+        let mut ret = self.atom()?;
+        while self.peek() == Some('&') {
+            self.pos += 1;
+            ret = SetExpr::Intersection(Box::new(ret), Box::new(self.atom()?));
+        }
+        Ok(ret)
+    }
+
+    fn atom(&mut self) -> Result<SetExpr> {
+        if self.peek() == Some('{') {
+            self.pos += 1;
+            let ret = self.union()?;
+            if self.peek() != Some('}') {
+                return Err(format!(
+                    "Invalid mailing set expression `{}`: unclosed `{{`.",
+                    self.input
+                )
+                .into());
+            }
+            self.pos += 1;
+            return Ok(ret);
+        }
+        let len = self.input[self.pos..]
+            .find(OPERATORS)
+            .unwrap_or(self.input.len() - self.pos);
+        if len == 0 {
+            return Err(format!(
+                "Invalid mailing set expression `{}`: expected a list at position {}.",
+                self.input, self.pos
+            )
+            .into());
+        }
+        let ret = SetExpr::List(self.input[self.pos..self.pos + len].to_string());
+        self.pos += len;
+        Ok(ret)
+    }
+}
+
+/// A [`SetExpr`] address and the mailing lists it refers to.
+#[derive(Debug, Clone)]
+pub struct MailingSet {
+    /// The address the post was sent to.
+    pub address: Address,
+    /// The parsed local part of `address`.
+    pub expr: SetExpr,
+    /// The lists of the expression, keyed by the local part used in `expr`.
+    pub lists: BTreeMap<String, DbVal<MailingList>>,
+}
+
+impl Connection {
+    /// Resolve a mailing set address.
+    ///
+    /// Returns `None` if the local part of `address` is not a set expression,
+    /// and an error if it is not valid or refers to unknown lists.
+    pub fn mailing_set(&self, address: &Address) -> Result<Option<MailingSet>> {
+        let email = address.get_email();
+        let Some((local_part, domain)) = email.rsplit_once('@') else {
+            return Ok(None);
+        };
+        if !SetExpr::is_set_expression(local_part) {
+            return Ok(None);
+        }
+        let expr = SetExpr::parse(local_part)?;
+        let all_lists = self.lists()?;
+        let mut lists = BTreeMap::new();
+        for operand in expr.operands() {
+            let Some(list) = all_lists.iter().find(|l| {
+                l.address.rsplit_once('@').is_some_and(|(l, d)| {
+                    l.eq_ignore_ascii_case(operand) && d.eq_ignore_ascii_case(domain)
+                })
+            }) else {
+                return Err(format!(
+                    "No mailing list {operand}@{domain} found for mailing set address {email}."
+                )
+                .into());
+            };
+            lists.insert(operand.to_string(), list.clone());
+        }
+        Ok(Some(MailingSet {
+            address: address.clone(),
+            expr,
+            lists,
+        }))
+    }
+
+    /// Process a post to a [`MailingSet`].
+    pub(crate) fn post_to_set(
+        &self,
+        env: &Envelope,
+        raw: &[u8],
+        set: &MailingSet,
+        trace: Option<&mut PostTrace>,
+    ) -> Result<()> {
+        trace!("Posting to mailing set {}", set.expr);
+        let message_id = env.message_id().to_string();
+        let mut filter_traces = vec![];
+        let mut subscribers = BTreeMap::new();
+        let mut addresses = BTreeMap::new();
+        let mut immediate = BTreeMap::new();
+        let mut bytes = raw.to_vec();
+        let mut failure = None;
+        for (operand, list) in &set.lists {
+            if self.list_is_encrypted(list.pk)? {
+                failure = Some((
+                    list,
+                    PostAction::Reject {
+                        reason: format!(
+                            "{} is an encrypted list, and posts to mailing sets cannot be \
+                             encrypted for its subscribers.",
+                            list.id
+                        ),
+                    },
+                ));
+                break;
+            }
+            let subscriptions = self.list_confirmed_subscriptions(list.pk)?;
+            let owners = self.list_owners(list.pk)?;
+            let mut list_ctx = self.list_context(list, &owners, &subscriptions, env, None)?;
+            let mut post = PostEntry {
+                message_id: env.message_id().clone(),
+                from: env.from()[0].clone(),
+                bytes,
+                to: vec![set.address.clone()],
+                action: PostAction::Accept,
+            };
+            let filters: Vec<Box<dyn PostFilter>> = vec![
+                Box::new(PostRightsCheck),
+                Box::new(VerifySignature),
+                Box::new(FixCRLF),
+                Box::new(FinalizeRecipients),
+            ];
+            filter_traces.extend(
+                self.run_filters(
+                    filters,
+                    &mut post,
+                    &mut list_ctx,
+                    &message_id,
+                    &format!("mailing set {}, ", set.expr),
+                    trace.is_some(),
+                )?
+                .into_iter()
+                .map(|filter_trace| FilterTrace {
+                    filter: format!("{} ({})", filter_trace.filter, list.id),
+                    ..filter_trace
+                }),
+            );
+            bytes = post.bytes;
+            if post.action != PostAction::Accept {
+                failure = Some((list, post.action));
+                break;
+            }
+            let mut set = BTreeSet::new();
+            for job in std::mem::take(&mut list_ctx.scheduled_jobs) {
+                let (recipients, digest) = match job {
+                    MailJob::Send { recipients } => (recipients, false),
+                    MailJob::StoreDigest { recipients } => (recipients, true),
+                    _ => continue,
+                };
+                for recipient in recipients {
+                    let email = recipient.get_email().to_lowercase();
+                    set.insert(email.clone());
+                    if !digest {
+                        immediate.entry(email.clone()).or_insert(list);
+                    }
+                    addresses.entry(email).or_insert(recipient);
+                }
+            }
+            subscribers.insert(operand.clone(), set);
+        }
+
+        if let Some((list, action)) = failure {
+            let reason = match action {
+                PostAction::Reject { reason } => reason,
+                _ => format!(
+                    "Your post needs approval from the moderators of {}, but posts to mailing \
+                     sets cannot be moderated.",
+                    list.id
+                ),
+            };
+            if let Some(trace) = trace {
+                trace.lists.push(ListTrace {
+                    list: set.expr.to_string(),
+                    filters: filter_traces,
+                    action: PostAction::Reject {
+                        reason: reason.clone(),
+                    },
+                    recipients: vec![],
+                    digest_recipients: vec![],
+                });
+            }
+            self.insert_post_event(
+                Some(list.pk),
+                &message_id,
+                PostEventKind::Action,
+                "reject",
+                Some(&reason),
+            )?;
+            log::info!("PostAction::Reject {{ reason: {} }}", reason);
+            for f in env.from() {
+                /* send error notice to e-mail sender */
+                self.send_reply_with_list_template(
+                    TemplateRenderContext {
+                        template: Template::GENERIC_FAILURE,
+                        default_fn: Some(Template::default_generic_failure),
+                        list,
+                        context: minijinja::context! {
+                            list => &list,
+                            subject => format!("Your post to {} was rejected.", set.address.get_email()),
+                            details => &reason,
+                        },
+                        queue: Queue::Out,
+                        comment: format!("PostAction::Reject {{ reason: {} }}", reason).into(),
+                    },
+                    std::iter::once(Cow::Borrowed(f)),
+                )?;
+            }
+            /* error handled by notifying submitter */
+            return Ok(());
+        }
+
+        let recipients = set.expr.evaluate(&subscribers);
+        trace!("Mailing set recipients: {:?}", &recipients);
+        if let Some(trace) = trace {
+            let (recipients, digest_recipients) = recipients
+                .iter()
+                .partition::<Vec<&String>, _>(|r| immediate.contains_key(*r));
+            trace.lists.push(ListTrace {
+                list: set.expr.to_string(),
+                filters: filter_traces,
+                action: PostAction::Accept,
+                recipients: recipients
+                    .into_iter()
+                    .map(|r| addresses[r].to_string())
+                    .collect(),
+                digest_recipients: digest_recipients
+                    .into_iter()
+                    .map(|r| addresses[r].to_string())
+                    .collect(),
+            });
+        }
+        let post_env = Envelope::from_bytes(&bytes, None)?;
+        for (operand, list) in &set.lists {
+            if subscribers[operand].is_disjoint(&recipients) {
+                continue;
+            }
+            self.insert_post(list.pk, &bytes, &post_env)?;
+            self.insert_post_event(
+                Some(list.pk),
+                &message_id,
+                PostEventKind::Action,
+                "accept",
+                Some(&format!("mailing set {}", set.expr)),
+            )?;
+        }
+        for recipient in &recipients {
+            // Digest subscribers get the post from the archive of their list.
+            let Some(list) = immediate.get(recipient) else {
+                continue;
+            };
+            let mut env = post_env.clone();
+            env.set_to(melib::smallvec::smallvec![addresses[recipient].clone()]);
+            self.insert_to_queue(QueueEntry::new(
+                Queue::Out,
+                Some(list.pk),
+                Some(Cow::Owned(env)),
+                &bytes,
+                None,
+            )?)?;
+        }
+        Ok(())
+    }
+}
@@ -37,15 +37,24 @@
 //! ```
 //!
 //! so the processing stops at the first returned error.
+//!
+//! [`Connection::post_dry_run`] runs the filters the same way, recording the
+//! effects of each one in a [`PostTrace`](crate::mail::PostTrace).
 
 mod settings;
 use log::trace;
-use melib::{Address, HeaderName};
+use melib::{
+    email::{
+        attachment_types::{ContentType, Text},
+        attachments::AttachmentBuilder,
+    },
+    Address, HeaderName,
+};
 use percent_encoding::utf8_percent_encode;
 
 use crate::{
     mail::{ListContext, MailJob, PostAction, PostEntry},
-    models::{DbVal, MailingList},
+    models::{AccessEntry, AccessKind, AccessScope, DbVal, MailingList},
     Connection, StripCarets, PATH_SEGMENT,
 };
 
@@ -54,12 +63,16 @@ impl Connection {
     pub fn list_filters(&self, _list: &DbVal<MailingList>) -> Vec<Box<dyn PostFilter>> {
         vec![
             Box::new(PostRightsCheck),
+            Box::new(DecryptPost),
+            Box::new(VerifySignature),
             Box::new(MimeReject),
             Box::new(FixCRLF),
             Box::new(AddListHeaders),
             Box::new(ArchivedAtLink),
             Box::new(AddSubjectTagPrefix),
             Box::new(FinalizeRecipients),
+            Box::new(Personalise),
+            Box::new(EncryptForSubscribers),
         ]
     }
 }
@@ -76,9 +89,19 @@ pub trait PostFilter {
         post: &'p mut PostEntry,
         ctx: &'p mut ListContext<'list>,
     ) -> std::result::Result<(&'p mut PostEntry, &'p mut ListContext<'list>), ()>;
+
+    /// Filter name, used in reports.
+    fn name(&self) -> &'static str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
 }
 
-/// Check that submitter can post to list, for now it accepts everything.
+/// Check that submitter can post to list, according to the list's access
+/// entries (see [`AccessEntry`]) and post policy.
+///
+/// Post rights inherited from included lists (see [`crate::umbrella`]) count
+/// as being an owner or a subscriber of the list.
 pub struct PostRightsCheck;
 impl PostFilter for PostRightsCheck {
     fn feed<'p, 'list>(
@@ -87,6 +110,25 @@ impl PostFilter for PostRightsCheck {
         ctx: &'p mut ListContext<'list>,
     ) -> std::result::Result<(&'p mut PostEntry, &'p mut ListContext<'list>), ()> {
         trace!("Running PostRightsCheck filter");
+        let email_from = post.from.get_email();
+        match AccessEntry::verdict(
+            ctx.access_entries.iter().map(|e| &**e),
+            &email_from,
+            AccessScope::Post,
+        ) {
+            Some(AccessKind::Deny) => {
+                trace!("Envelope from is denied by an access entry");
+                post.action = PostAction::Reject {
+                    reason: "You are not allowed to post on this list.".to_string(),
+                };
+                return Err(());
+            }
+            Some(AccessKind::Allow) => {
+                trace!("Envelope from is allowed by an access entry, skipping post policy");
+                return Ok((post, ctx));
+            }
+            None => {}
+        }
         if let Some(ref policy) = ctx.post_policy {
             if policy.announce_only {
                 trace!("post policy is announce_only");
@@ -97,7 +139,9 @@ impl PostFilter for PostRightsCheck {
                     .collect::<Vec<Address>>();
                 trace!("Owner addresses are: {:#?}", &owner_addresses);
                 trace!("Envelope from is: {:?}", &post.from);
-                if !owner_addresses.contains(&post.from) {
+                if !owner_addresses.contains(&post.from)
+                    && !ctx.inherited_owners.contains(&email_from.to_lowercase())
+                {
                     trace!("Envelope From does not include any owner");
                     post.action = PostAction::Reject {
                         reason: "You are not allowed to post on this list.".to_string(),
@@ -109,7 +153,9 @@ impl PostFilter for PostRightsCheck {
                 let email_from = post.from.get_email();
                 trace!("post from is {:?}", &email_from);
                 trace!("post subscriptions are {:#?}", &ctx.subscriptions);
-                if !ctx.subscriptions.iter().any(|lm| lm.address == email_from) {
+                if !ctx.subscriptions.iter().any(|lm| lm.address == email_from)
+                    && !ctx.inherited_posters.contains(&email_from.to_lowercase())
+                {
                     trace!("Envelope from is not subscribed to this list");
                     post.action = PostAction::Reject {
                         reason: "Only subscriptions can post to this list.".to_string(),
@@ -121,7 +167,9 @@ impl PostFilter for PostRightsCheck {
                 let email_from = post.from.get_email();
                 trace!("post from is {:?}", &email_from);
                 trace!("post subscriptions are {:#?}", &ctx.subscriptions);
-                if !ctx.subscriptions.iter().any(|lm| lm.address == email_from) {
+                if !ctx.subscriptions.iter().any(|lm| lm.address == email_from)
+                    && !ctx.inherited_posters.contains(&email_from.to_lowercase())
+                {
                     trace!("Envelope from is not subscribed to this list");
                     post.action = PostAction::Defer {
                         reason: "Your posting has been deferred. Approval from the list's \
@@ -161,6 +209,14 @@ impl PostFilter for FixCRLF {
 }
 
 /// Add `List-*` headers
+///
+/// If `one_click_unsubscribe` is enabled in the list's
+/// `AddListHeadersSettings`, each recipient's copy also gets an
+/// [RFC 8058](https://www.rfc-editor.org/rfc/rfc8058) one-click
+/// `List-Unsubscribe` URL with a signed
+/// [`SubscriptionToken`](crate::tokens::SubscriptionToken), and a
+/// `List-Unsubscribe-Post` header. The archived post only has the `mailto:`
+/// URI.
 pub struct AddListHeaders;
 impl PostFilter for AddListHeaders {
     fn feed<'p, 'list>(
@@ -169,6 +225,21 @@ impl PostFilter for AddListHeaders {
         ctx: &'p mut ListContext<'list>,
     ) -> std::result::Result<(&'p mut PostEntry, &'p mut ListContext<'list>), ()> {
         trace!("Running AddListHeaders filter");
+        if let Some(mut settings) = ctx.filter_settings.remove("AddListHeadersSettings") {
+            let map = settings.as_object_mut().unwrap();
+            let one_click_unsubscribe =
+                serde_json::from_value::<bool>(map.remove("one_click_unsubscribe").unwrap())
+                    .unwrap();
+            if one_click_unsubscribe {
+                let base_url = map
+                    .remove("base_url")
+                    .and_then(|v| serde_json::from_value::<String>(v).ok())
+                    .unwrap_or_default();
+                ctx.notes
+                    .push("recipients get a one-click unsubscribe link".to_string());
+                ctx.one_click_unsubscribe = Some(base_url);
+            }
+        }
         let (mut headers, body) = melib::email::parser::mail(&post.bytes).unwrap();
 
         let map_fn = |x| crate::encode_header_owned(String::into_bytes(x));
@@ -348,6 +419,14 @@ impl PostFilter for ArchivedAtLink {
 
 /// Assuming there are no more changes to be done on the post, it finalizes
 /// which list subscriptions will receive the post in `post.action` field.
+///
+/// Subscribers of included lists (see [`crate::umbrella`]) receive the post
+/// too, and every address receives it at most once.
+///
+/// Subscribers who chose topics (see [`crate::topics`]) receive the post only
+/// if it is about one of them, and subscribers who muted its thread or don't
+/// follow it in follow-only mode (see [`crate::threads`]) don't receive it.
+/// Disabled subscriptions don't receive it either.
 pub struct FinalizeRecipients;
 impl PostFilter for FinalizeRecipients {
     fn feed<'p, 'list>(
@@ -359,11 +438,47 @@ impl PostFilter for FinalizeRecipients {
         let mut recipients = vec![];
         let mut digests = vec![];
         let email_from = post.from.get_email();
-        for subscription in ctx.subscriptions {
+        let post_topics = if ctx.subscription_topics.is_empty() {
+            vec![]
+        } else {
+            melib::Envelope::from_bytes(&post.bytes, None)
+                .map(|env| crate::topics::post_topics(ctx.list, &env))
+                .unwrap_or_default()
+        };
+        if !post_topics.is_empty() {
+            ctx.notes
+                .push(format!("topics: {}", post_topics.join(", ")));
+        }
+        let mut seen = std::collections::HashSet::new();
+        for subscription in ctx
+            .subscriptions
+            .iter()
+            .chain(ctx.member_subscriptions.iter())
+        {
             trace!("examining subscription {:?}", &subscription);
+            if !seen.insert(subscription.address.to_lowercase()) {
+                trace!("subscription address was already examined");
+                continue;
+            }
+            if !subscription.enabled {
+                trace!("subscription is disabled");
+                continue;
+            }
             if subscription.address == email_from {
                 trace!("subscription is submitter");
             }
+            if subscription.address != email_from
+                && ctx.thread_exclusions.contains(&subscription.pk)
+            {
+                trace!("subscription muted or does not follow the post's thread");
+                continue;
+            }
+            if let Some(topics) = ctx.subscription_topics.get(&subscription.pk) {
+                if !topics.wants(&post_topics) {
+                    trace!("subscription did not choose the post's topics");
+                    continue;
+                }
+            }
             if subscription.digest {
                 if subscription.address != email_from || subscription.receive_own_posts {
                     trace!("Subscription gets digest");
@@ -387,6 +502,434 @@ impl PostFilter for FinalizeRecipients {
     }
 }
 
+/// Decrypt posts to encrypted lists (see [`crate::openpgp`]).
+///
+/// Posts that are not PGP/MIME encrypted to the list key are rejected.
+pub struct DecryptPost;
+impl PostFilter for DecryptPost {
+    fn feed<'p, 'list>(
+        self: Box<Self>,
+        post: &'p mut PostEntry,
+        ctx: &'p mut ListContext<'list>,
+    ) -> std::result::Result<(&'p mut PostEntry, &'p mut ListContext<'list>), ()> {
+        let Some(ref key) = ctx.openpgp_key else {
+            return Ok((post, ctx));
+        };
+        trace!("Running DecryptPost filter");
+        match crate::openpgp::parse_secret_key(&key.secret_key)
+            .and_then(|key| crate::openpgp::decrypt_mime(&key, &post.bytes))
+        {
+            Ok(bytes) => {
+                post.bytes = bytes;
+                Ok((post, ctx))
+            }
+            Err(err) => {
+                trace!("Could not decrypt post: {err}");
+                post.action = PostAction::Reject {
+                    reason: format!(
+                        "Posts to this list must be OpenPGP/MIME encrypted to the list key {}.",
+                        key.fingerprint
+                    ),
+                };
+                Err(())
+            }
+        }
+    }
+}
+
+/// Require posts to be PGP/MIME signed with the OpenPGP key of the sender's
+/// [`Account`](crate::models::Account), if enabled in the list's
+/// `VerifySignatureSettings`.
+///
+/// Only keys of list owners are accepted, unless `allow_subscribers` is set.
+/// Unsigned or badly signed posts are rejected, or added to the `hold` queue
+/// if `on_failure` is `"hold"`. The verification result is recorded in the
+/// post's [`PostEvent`](crate::models::PostEvent) audit trail and in its
+/// [`SIGNATURE_HEADER`](crate::openpgp::SIGNATURE_HEADER) header, which
+/// replaces any such header of the submitted post.
+pub struct VerifySignature;
+impl PostFilter for VerifySignature {
+    fn feed<'p, 'list>(
+        self: Box<Self>,
+        post: &'p mut PostEntry,
+        ctx: &'p mut ListContext<'list>,
+    ) -> std::result::Result<(&'p mut PostEntry, &'p mut ListContext<'list>), ()> {
+        let Some(mut settings) = ctx.filter_settings.remove("VerifySignatureSettings") else {
+            trace!(
+                "No VerifySignature settings found for list.pk = {} skipping filter",
+                ctx.list.pk
+            );
+            return Ok((post, ctx));
+        };
+        let map = settings.as_object_mut().unwrap();
+        let enabled = serde_json::from_value::<bool>(map.remove("enabled").unwrap()).unwrap();
+        if !enabled {
+            trace!(
+                "VerifySignature is disabled from settings found for list.pk = {} skipping filter",
+                ctx.list.pk
+            );
+            return Ok((post, ctx));
+        }
+        let allow_subscribers = map
+            .remove("allow_subscribers")
+            .and_then(|v| serde_json::from_value::<bool>(v).ok())
+            .unwrap_or(false);
+        let hold = map
+            .remove("on_failure")
+            .and_then(|v| serde_json::from_value::<String>(v).ok())
+            .is_some_and(|v| v == "hold");
+        trace!("Running VerifySignature filter");
+
+        let email_from = post.from.get_email();
+        let result = if !allow_subscribers
+            && !ctx
+                .list_owners
+                .iter()
+                .any(|o| o.address.eq_ignore_ascii_case(&email_from))
+        {
+            Err(format!("{email_from} is not a list owner"))
+        } else if let Some(public_key) = ctx.public_keys.get(&email_from.to_lowercase()) {
+            crate::openpgp::parse_public_key(public_key)
+                .and_then(|key| crate::openpgp::verify_mime(&key, &post.bytes))
+                .map_err(|err| err.to_string())
+        } else {
+            Err(format!("{email_from} has no account with an OpenPGP key"))
+        };
+        let verdict = match result {
+            Ok(ref fingerprint) => format!("good, by {email_from} with key {fingerprint}"),
+            Err(ref err) => format!("bad, {err}"),
+        };
+        ctx.notes.push(format!("signature: {verdict}"));
+        post.bytes = replace_header(
+            &post.bytes,
+            crate::openpgp::SIGNATURE_HEADER,
+            &verdict.split_whitespace().collect::<Vec<&str>>().join(" "),
+        );
+        match result {
+            Ok(_) => Ok((post, ctx)),
+            Err(err) => {
+                trace!("Could not verify post signature: {err}");
+                post.action = if hold {
+                    PostAction::Hold
+                } else {
+                    PostAction::Reject {
+                        reason: "Posts to this list must be OpenPGP/MIME signed with the key of \
+                                 your account."
+                            .to_string(),
+                    }
+                };
+                Err(())
+            }
+        }
+    }
+}
+
+/// Replace all `name` header fields of `message` with a single field with
+/// `value`, appended after the other header fields.
+fn replace_header(message: &[u8], name: &str, value: &str) -> Vec<u8> {
+    let Ok((headers, body)) = melib::email::parser::mail(message) else {
+        return message.to_vec();
+    };
+    let value = crate::encode_header_owned(value.as_bytes().to_vec());
+    let mut new_vec = Vec::with_capacity(message.len() + name.len() + value.len() + 4);
+    for (h, v) in headers
+        .into_iter()
+        .filter(|(h, _)| !h.as_str().eq_ignore_ascii_case(name))
+    {
+        new_vec.extend_from_slice(h.as_str().as_bytes());
+        new_vec.extend_from_slice(b": ");
+        new_vec.extend_from_slice(v);
+        new_vec.extend_from_slice(b"\r\n");
+    }
+    new_vec.extend_from_slice(name.as_bytes());
+    new_vec.extend_from_slice(b": ");
+    new_vec.extend_from_slice(&value);
+    new_vec.extend_from_slice(b"\r\n\r\n");
+    new_vec.extend_from_slice(body);
+    new_vec
+}
+
+/// Re-encrypt posts to encrypted lists to each recipient's account public key
+/// (see [`crate::openpgp`]).
+///
+/// Recipients without a usable key are skipped and the list owners are
+/// notified. The post itself is encrypted to the list key before it is
+/// archived.
+pub struct EncryptForSubscribers;
+impl PostFilter for EncryptForSubscribers {
+    fn feed<'p, 'list>(
+        self: Box<Self>,
+        post: &'p mut PostEntry,
+        ctx: &'p mut ListContext<'list>,
+    ) -> std::result::Result<(&'p mut PostEntry, &'p mut ListContext<'list>), ()> {
+        let Some(ref key) = ctx.openpgp_key else {
+            return Ok((post, ctx));
+        };
+        trace!("Running EncryptForSubscribers filter");
+        let mut messages = vec![];
+        let mut skipped = vec![];
+        for job in std::mem::take(&mut ctx.scheduled_jobs) {
+            let MailJob::Send { recipients } = job else {
+                ctx.scheduled_jobs.push(job);
+                continue;
+            };
+            for recipient in recipients {
+                let encrypted = ctx
+                    .public_keys
+                    .get(&recipient.get_email().to_lowercase())
+                    .ok_or_else(|| crate::Error::new_external("no public key"))
+                    .and_then(|k| crate::openpgp::parse_public_key(k))
+                    .and_then(|k| crate::openpgp::encrypt_mime(&k, &post.bytes));
+                match encrypted {
+                    Ok(bytes) => messages.push((recipient, bytes)),
+                    Err(err) => {
+                        trace!("Skipping recipient {recipient}: {err}");
+                        skipped.push(format!("{recipient}: {err}"));
+                    }
+                }
+            }
+        }
+        ctx.scheduled_jobs
+            .push(MailJob::SendIndividual { messages });
+        if !skipped.is_empty() {
+            ctx.scheduled_jobs.push(MailJob::NotifyOwners {
+                description: format!(
+                    "Post {} was not delivered to the following subscriptions of the encrypted \
+                     list {} because they have no usable OpenPGP public key:\n\n{}",
+                    post.message_id,
+                    ctx.list.id,
+                    skipped.join("\n")
+                ),
+            });
+        }
+        match crate::openpgp::parse_secret_key(&key.secret_key)
+            .and_then(|k| crate::openpgp::encrypt_mime(&k.to_public_key(), &post.bytes))
+        {
+            Ok(bytes) => {
+                post.bytes = bytes;
+                Ok((post, ctx))
+            }
+            Err(err) => {
+                log::error!("Could not encrypt post to list key: {err}");
+                post.action = PostAction::Defer {
+                    reason: "The post could not be encrypted to the list key.".to_string(),
+                };
+                Err(())
+            }
+        }
+    }
+}
+
+/// Merge fields that [`Personalise`] substitutes, in the order of the values
+/// passed to [`merge_fields`].
+const MERGE_FIELDS: [&str; 4] = ["name", "address", "unsubscribe_url", "settings_url"];
+
+/// Replace the `{{ field }}` placeholders of [`MERGE_FIELDS`] in `text` with
+/// `values`. Any other text, including unknown placeholders, is kept as is.
+fn merge_fields(text: &str, values: &[&str; 4]) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        ret.push_str(&rest[..start]);
+        let after = &rest[start + "{{".len()..];
+        let field = MERGE_FIELDS.iter().zip(values).find_map(|(field, value)| {
+            let tail = after
+                .trim_start_matches(' ')
+                .strip_prefix(field)?
+                .trim_start_matches(' ')
+                .strip_prefix("}}")?;
+            Some((value, tail))
+        });
+        if let Some((value, tail)) = field {
+            ret.push_str(value);
+            rest = tail;
+        } else {
+            ret.push_str("{{");
+            rest = after;
+        }
+    }
+    ret.push_str(rest);
+    ret
+}
+
+/// Substitute merge fields in the decoded `text/plain` parts of the MIME
+/// entity `raw`, recursing into multipart entities.
+///
+/// Returns `None` if nothing was substituted. Changed parts are re-encoded
+/// as UTF-8, in base64 if they are not ASCII anymore.
+fn personalise_entity(raw: &[u8], values: &[&str; 4]) -> Option<Vec<u8>> {
+    let entity = AttachmentBuilder::new(raw).build();
+    match entity.content_type {
+        ContentType::Multipart { ref parts, .. } => {
+            let mut ret = Vec::with_capacity(raw.len());
+            let mut offset = 0;
+            let mut changed = false;
+            for part in parts.iter().filter(|p| !p.raw().is_empty()) {
+                let Some(start) = raw[offset..]
+                    .windows(part.raw().len())
+                    .position(|w| w == part.raw())
+                    .map(|pos| offset + pos)
+                else {
+                    continue;
+                };
+                let end = start + part.raw().len();
+                ret.extend_from_slice(&raw[offset..start]);
+                if let Some(part) = personalise_entity(part.raw(), values) {
+                    ret.extend_from_slice(&part);
+                    changed = true;
+                } else {
+                    ret.extend_from_slice(&raw[start..end]);
+                }
+                offset = end;
+            }
+            ret.extend_from_slice(&raw[offset..]);
+            changed.then_some(ret)
+        }
+        ContentType::Text {
+            kind: Text::Plain,
+            ref parameters,
+            ..
+        } if !entity.content_disposition.kind.is_attachment() => {
+            let text = String::from_utf8(entity.decode(Default::default())).ok()?;
+            let merged = merge_fields(&text, values);
+            if merged == text {
+                return None;
+            }
+            let (_, (headers, _)) = melib::email::parser::attachments::attachment(raw).ok()?;
+            let mut ret = Vec::with_capacity(raw.len());
+            for (h, v) in headers.into_iter().filter(|(h, _)| {
+                *h != HeaderName::CONTENT_TYPE && *h != HeaderName::CONTENT_TRANSFER_ENCODING
+            }) {
+                ret.extend_from_slice(h.as_str().as_bytes());
+                ret.extend_from_slice(b": ");
+                ret.extend_from_slice(v);
+                ret.extend_from_slice(b"\r\n");
+            }
+            ret.extend_from_slice(b"Content-Type: text/plain; charset=utf-8");
+            for (name, value) in parameters
+                .iter()
+                .filter(|(name, _)| !name.eq_ignore_ascii_case(b"charset"))
+            {
+                ret.extend_from_slice(b"; ");
+                ret.extend_from_slice(name);
+                ret.extend_from_slice(b"=\"");
+                ret.extend_from_slice(value);
+                ret.extend_from_slice(b"\"");
+            }
+            if merged.is_ascii() {
+                ret.extend_from_slice(b"\r\nContent-Transfer-Encoding: 7bit\r\n\r\n");
+                ret.extend_from_slice(merged.as_bytes());
+            } else {
+                ret.extend_from_slice(b"\r\nContent-Transfer-Encoding: base64\r\n\r\n");
+                ret.extend_from_slice(
+                    data_encoding::BASE64_MIME
+                        .encode(merged.as_bytes())
+                        .as_bytes(),
+                );
+                ret.extend_from_slice(b"\r\n");
+            }
+            Some(ret)
+        }
+        _ => None,
+    }
+}
+
+/// Substitute the merge fields of each recipient's copy of a post, if enabled
+/// in the list's `PersonaliseSettings`.
+///
+/// The placeholders `{{ name }}`, `{{ address }}`, `{{ unsubscribe_url }}`
+/// and `{{ settings_url }}` are replaced in the decoded `text/plain` parts of
+/// the post; any other text is left alone. The URLs point to the web
+/// interface at `base_url` and carry a signed
+/// [`SubscriptionToken`](crate::tokens::SubscriptionToken). The archived post
+/// gets empty values. Posts of encrypted lists, and posts without
+/// placeholders, are sent unchanged.
+pub struct Personalise;
+impl PostFilter for Personalise {
+    fn feed<'p, 'list>(
+        self: Box<Self>,
+        post: &'p mut PostEntry,
+        ctx: &'p mut ListContext<'list>,
+    ) -> std::result::Result<(&'p mut PostEntry, &'p mut ListContext<'list>), ()> {
+        let Some(mut settings) = ctx.filter_settings.remove("PersonaliseSettings") else {
+            trace!(
+                "No Personalise settings found for list.pk = {} skipping filter",
+                ctx.list.pk
+            );
+            return Ok((post, ctx));
+        };
+        let map = settings.as_object_mut().unwrap();
+        let enabled = serde_json::from_value::<bool>(map.remove("enabled").unwrap()).unwrap();
+        if !enabled {
+            trace!(
+                "Personalise is disabled from settings found for list.pk = {} skipping filter",
+                ctx.list.pk
+            );
+            return Ok((post, ctx));
+        }
+        if ctx.openpgp_key.is_some() {
+            ctx.notes
+                .push("not personalised: the list is encrypted".to_string());
+            return Ok((post, ctx));
+        }
+        let base_url = map
+            .remove("base_url")
+            .and_then(|v| serde_json::from_value::<String>(v).ok())
+            .unwrap_or_default();
+        let base_url = base_url.trim_end_matches('/');
+        trace!("Running Personalise filter");
+
+        let Some(archived) = personalise_entity(&post.bytes, &[""; 4]) else {
+            ctx.notes
+                .push("not personalised: the post has no merge fields".to_string());
+            return Ok((post, ctx));
+        };
+
+        let issued = chrono::offset::Utc::now().timestamp() as u64;
+        let mut personalised = 0;
+        for job in std::mem::take(&mut ctx.scheduled_jobs) {
+            let MailJob::Send { recipients } = job else {
+                ctx.scheduled_jobs.push(job);
+                continue;
+            };
+            let mut messages = Vec::with_capacity(recipients.len());
+            for recipient in recipients {
+                let address = recipient.get_email();
+                let subscription = ctx.recipient_subscription(&address);
+                let name = subscription
+                    .and_then(|s| s.name.clone())
+                    .or_else(|| recipient.get_display_name())
+                    .unwrap_or_default();
+                let url = |action| {
+                    let token = crate::tokens::SubscriptionToken {
+                        action,
+                        list: subscription.map_or(ctx.list.pk, |s| s.list),
+                        address: address.clone(),
+                        issued,
+                    }
+                    .sign(&ctx.token_secret);
+                    format!("{base_url}/subscription/{token}/")
+                };
+                let unsubscribe_url = url(crate::tokens::TokenAction::Unsubscribe);
+                let settings_url = url(crate::tokens::TokenAction::Settings);
+                let bytes = personalise_entity(
+                    &post.bytes,
+                    &[&name, &address, &unsubscribe_url, &settings_url],
+                )
+                .unwrap_or_else(|| archived.clone());
+                messages.push((recipient, bytes));
+                personalised += 1;
+            }
+            ctx.scheduled_jobs
+                .push(MailJob::SendIndividual { messages });
+        }
+        ctx.notes
+            .push(format!("personalised for {personalised} recipients"));
+        post.bytes = archived;
+        Ok((post, ctx))
+    }
+}
+
 /// Allow specific MIMEs only.
 pub struct MimeReject;
 
@@ -384,9 +384,31 @@ impl MailingList {
         subscription_policy: Option<&SubscriptionPolicy>,
     ) -> String {
         format!(
-            "Help for {list_name}\n\n{subscribe}\n\n{post}\n\nTo contact the list owners, send an \
-             e-mail to {contact}\n",
+            "Help for {list_name}\n\n{subscribe}\n\n{post}{topics}\n\n{threads}\n\nTo contact the \
+             list owners, send an e-mail to {contact}\n",
             list_name = self.name,
+            threads = format_args!(
+                "To stop receiving the posts of a thread, reply to one of them with an e-mail to \
+                 {request} with the subject `mute`, or `unmute` to receive them again. To receive \
+                 only the threads you start or reply to, send an e-mail to {request} with the \
+                 subject `follow-only`, reply with the subject `follow` to receive another \
+                 thread, and send the subject `follow-all` to receive every thread again.",
+                request = self.request_subaddr(),
+            ),
+            topics = if self.topics.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "\n\nPosts to this list can be about the topics: {topics}. To receive only \
+                     posts about some of them, send an e-mail to {request} with the subject \
+                     `topics` followed by the topics you want, and `{other}` for posts about none \
+                     of them. The subject `topics {all}` restores reception of every post.",
+                    topics = self.topics.join(", "),
+                    request = self.request_subaddr(),
+                    other = crate::topics::EVERYTHING_ELSE,
+                    all = crate::topics::ALL_TOPICS,
+                )
+            },
             subscribe = subscription_policy.map_or(
                 Cow::Borrowed("This list is not open to subscriptions."),
                 |p| if p.open {
@@ -640,6 +662,43 @@ pub struct Post {
     pub datetime: String,
     /// Month-year as a `YYYY-mm` formatted string, for use in archives.
     pub month_year: String,
+    /// Parsed headers of post, see [`Post::parse_headers`].
+    pub headers_json: Option<serde_json::Value>,
+}
+
+impl Post {
+    /// Parse the headers of a message into a JSON object that maps each
+    /// lowercase header name to the array of its unfolded and decoded values,
+    /// in message order.
+    pub fn parse_headers(message: &[u8]) -> Option<serde_json::Value> {
+        let (headers, _) = melib::email::parser::mail(message).ok()?;
+        let mut ret = serde_json::Map::new();
+        for (name, value) in headers {
+            let unfolded = value
+                .iter()
+                .copied()
+                .filter(|b| !matches!(b, b'\r' | b'\n'))
+                .collect::<Vec<u8>>();
+            let decoded = melib::email::parser::encodings::phrase(&unfolded, false)
+                .map_or_else(|_| unfolded.clone(), |(_, decoded)| decoded);
+            let values = ret
+                .entry(name.as_str().to_ascii_lowercase())
+                .or_insert_with(|| serde_json::Value::Array(vec![]));
+            if let serde_json::Value::Array(ref mut values) = values {
+                values.push(String::from_utf8_lossy(&decoded).trim().to_string().into());
+            }
+        }
+        Some(serde_json::Value::Object(ret))
+    }
+
+    /// The first value of a header, if the post's headers were parsed.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers_json
+            .as_ref()?
+            .get(name.to_ascii_lowercase())?
+            .get(0)?
+            .as_str()
+    }
 }
 
 impl std::fmt::Debug for Post {
@@ -654,6 +713,7 @@ impl std::fmt::Debug for Post {
             .field("timestamp", &self.timestamp)
             .field("datetime", &self.datetime)
             .field("month_year", &self.month_year)
+            .field("headers_json", &self.headers_json)
             .finish()
     }
 }
@@ -745,3 +805,653 @@ impl std::fmt::Display for ListCandidateSubscription {
         )
     }
 }
+
+/// Delivery failures recorded for a subscription.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Bounce {
+    /// Database primary key.
+    pub pk: i64,
+    /// Subscription foreign key (See [`ListSubscription`]).
+    pub subscription: i64,
+    /// Number of bounces.
+    pub count: u64,
+    /// Datetime of the last bounce.
+    pub last_bounce: String,
+}
+
+impl std::fmt::Display for Bounce {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{:?}", self)
+    }
+}
+
+/// Whether an [`AccessEntry`] allows or denies matching addresses.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AccessKind {
+    /// Matching addresses are allowed, bypassing the list policy.
+    Allow,
+    /// Matching addresses are denied.
+    Deny,
+}
+
+impl AccessKind {
+    /// Returns the name of the kind used in the database schema.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+        }
+    }
+}
+
+impl std::str::FromStr for AccessKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.trim() {
+            s if s.eq_ignore_ascii_case(stringify!(Allow)) => Self::Allow,
+            s if s.eq_ignore_ascii_case(stringify!(Deny)) => Self::Deny,
+            other => {
+                return Err(Error::new_external(format!(
+                    "Invalid access entry kind: {other}."
+                )))
+            }
+        })
+    }
+}
+
+impl rusqlite::types::ToSql for AccessKind {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl rusqlite::types::FromSql for AccessKind {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|err: Error| rusqlite::types::FromSqlError::Other(Box::new(err)))
+    }
+}
+
+impl std::fmt::Display for AccessKind {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.as_str())
+    }
+}
+
+/// How the pattern of an [`AccessEntry`] is matched against an address.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AddressMatch {
+    /// Case-insensitive comparison of the whole address.
+    Exact,
+    /// Case-insensitive glob where `*` matches any sequence of characters and
+    /// `?` matches a single character, e.g. `*+spam@example.com`.
+    Glob,
+    /// The address domain is the pattern or one of its subdomains, e.g.
+    /// `example.com` matches `user@example.com` and `user@lists.example.com`.
+    Domain,
+}
+
+impl AddressMatch {
+    /// Returns the name of the match type used in the database schema.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Exact => "exact",
+            Self::Glob => "glob",
+            Self::Domain => "domain",
+        }
+    }
+
+    /// Returns all possible variants as `&'static str`
+    pub const fn possible_values() -> &'static [&'static str] {
+        const VALUES: &[&str] = &[
+            AddressMatch::Exact.as_str(),
+            AddressMatch::Glob.as_str(),
+            AddressMatch::Domain.as_str(),
+        ];
+        VALUES
+    }
+
+    /// Whether `address` matches `pattern`.
+    pub fn matches(&self, pattern: &str, address: &str) -> bool {
+        let pattern = pattern.trim().to_ascii_lowercase();
+        let address = address.trim().to_ascii_lowercase();
+        match self {
+            Self::Exact => pattern == address,
+            Self::Glob => glob_match(pattern.as_bytes(), address.as_bytes()),
+            Self::Domain => {
+                let pattern = pattern.trim_start_matches('@');
+                let Some((_, domain)) = address.rsplit_once('@') else {
+                    return false;
+                };
+                domain == pattern
+                    || domain
+                        .strip_suffix(pattern)
+                        .is_some_and(|sub| sub.ends_with('.'))
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for AddressMatch {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.trim() {
+            s if s.eq_ignore_ascii_case(stringify!(Exact)) => Self::Exact,
+            s if s.eq_ignore_ascii_case(stringify!(Glob)) => Self::Glob,
+            s if s.eq_ignore_ascii_case(stringify!(Domain)) => Self::Domain,
+            other => {
+                return Err(Error::new_external(format!(
+                    "Invalid address match type: {other}."
+                )))
+            }
+        })
+    }
+}
+
+impl rusqlite::types::ToSql for AddressMatch {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl rusqlite::types::FromSql for AddressMatch {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|err: Error| rusqlite::types::FromSqlError::Other(Box::new(err)))
+    }
+}
+
+impl std::fmt::Display for AddressMatch {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.as_str())
+    }
+}
+
+fn glob_match(pattern: &[u8], input: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while i < input.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, i));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == input[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => {
+                let Some((bp, bi)) = backtrack else {
+                    return false;
+                };
+                p = bp + 1;
+                i = bi + 1;
+                backtrack = Some((bp, bi + 1));
+            }
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// An allow or deny entry for posting and/or subscribing.
+///
+/// Entries without a list apply to every list in the instance. When deciding
+/// on an address, instance-wide deny entries take precedence over everything,
+/// then entries of the list are considered before instance-wide allow
+/// entries (see [`AccessEntry::verdict`]).
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct AccessEntry {
+    /// Database primary key.
+    pub pk: i64,
+    /// Mailing list foreign key (See [`MailingList`]), or `None` if the entry
+    /// is instance-wide.
+    pub list: Option<i64>,
+    /// Whether matching addresses are allowed or denied.
+    pub kind: AccessKind,
+    /// How `pattern` is matched.
+    pub match_type: AddressMatch,
+    /// Address, glob or domain pattern.
+    pub pattern: String,
+    /// Whether this entry applies to posting.
+    pub post: bool,
+    /// Whether this entry applies to subscribing.
+    pub subscribe: bool,
+    /// Entry comment, optional.
+    pub comment: Option<String>,
+}
+
+impl std::fmt::Display for AccessEntry {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "[#{} {}] {} {} {}{}{}",
+            self.pk,
+            self.list.map_or_else(|| "*".to_string(), |l| l.to_string()),
+            self.kind,
+            self.match_type,
+            self.pattern,
+            match (self.post, self.subscribe) {
+                (true, true) => "",
+                (true, false) => " (post)",
+                (false, true) => " (subscribe)",
+                (false, false) => " (inactive)",
+            },
+            self.comment
+                .as_ref()
+                .map_or_else(String::new, |c| format!(" # {c}")),
+        )
+    }
+}
+
+/// What an [`AccessEntry`] applies to.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum AccessScope {
+    /// Posting to a list.
+    Post,
+    /// Subscribing to a list.
+    Subscribe,
+}
+
+impl AccessEntry {
+    /// Whether this entry applies to `scope` and matches `address`.
+    pub fn matches(&self, address: &str, scope: AccessScope) -> bool {
+        let applies = match scope {
+            AccessScope::Post => self.post,
+            AccessScope::Subscribe => self.subscribe,
+        };
+        applies && self.match_type.matches(&self.pattern, address)
+    }
+
+    /// Decide on `address` given the access entries of a list and the
+    /// instance-wide entries.
+    ///
+    /// A matching instance-wide [`AccessKind::Deny`] entry always wins, so
+    /// that list owners cannot lift an instance ban. Otherwise list entries
+    /// are considered before instance-wide allow entries, and within the same
+    /// scope a matching [`AccessKind::Deny`] entry wins over a matching
+    /// [`AccessKind::Allow`] entry. Returns `None` if no entry matches.
+    pub fn verdict<'e>(
+        entries: impl IntoIterator<Item = &'e Self> + Clone,
+        address: &str,
+        scope: AccessScope,
+    ) -> Option<AccessKind> {
+        let matching = |list: bool| {
+            entries
+                .clone()
+                .into_iter()
+                .filter(move |e| e.list.is_some() == list && e.matches(address, scope))
+                .map(|e| e.kind)
+                .collect::<Vec<AccessKind>>()
+        };
+        let instance = matching(false);
+        if instance.contains(&AccessKind::Deny) {
+            return Some(AccessKind::Deny);
+        }
+        let list = matching(true);
+        if list.contains(&AccessKind::Deny) {
+            return Some(AccessKind::Deny);
+        }
+        list.into_iter().chain(instance).next()
+    }
+}
+
+/// Kind of a [`PostEvent`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PostEventKind {
+    /// A post filter ran.
+    Filter,
+    /// The final post action of a list.
+    Action,
+    /// A mail job was scheduled.
+    Job,
+    /// A delivery attempt.
+    Delivery,
+    /// Processing failed.
+    Error,
+}
+
+impl PostEventKind {
+    /// Returns the name of the kind used in the database schema.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Filter => "filter",
+            Self::Action => "action",
+            Self::Job => "job",
+            Self::Delivery => "delivery",
+            Self::Error => "error",
+        }
+    }
+}
+
+impl std::str::FromStr for PostEventKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.trim() {
+            s if s.eq_ignore_ascii_case(stringify!(Filter)) => Self::Filter,
+            s if s.eq_ignore_ascii_case(stringify!(Action)) => Self::Action,
+            s if s.eq_ignore_ascii_case(stringify!(Job)) => Self::Job,
+            s if s.eq_ignore_ascii_case(stringify!(Delivery)) => Self::Delivery,
+            s if s.eq_ignore_ascii_case(stringify!(Error)) => Self::Error,
+            other => {
+                return Err(Error::new_external(format!(
+                    "Invalid post event kind: {other}."
+                )))
+            }
+        })
+    }
+}
+
+impl rusqlite::types::ToSql for PostEventKind {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl rusqlite::types::FromSql for PostEventKind {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|err: Error| rusqlite::types::FromSqlError::Other(Box::new(err)))
+    }
+}
+
+impl std::fmt::Display for PostEventKind {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.as_str())
+    }
+}
+
+/// An entry of the post processing audit trail.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PostEvent {
+    /// Database primary key.
+    pub pk: i64,
+    /// Mailing list foreign key (See [`MailingList`]), if the event concerns
+    /// a specific list.
+    pub list: Option<i64>,
+    /// `Message-ID` of the processed message.
+    pub message_id: String,
+    /// Event kind.
+    pub kind: PostEventKind,
+    /// Filter name, action, job or delivery outcome.
+    pub name: String,
+    /// Event details, optional.
+    pub detail: Option<String>,
+    /// Event timestamp.
+    pub timestamp: u64,
+    /// Event datetime.
+    pub datetime: String,
+}
+
+impl std::fmt::Display for PostEvent {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "{} {} {} {}: {}{}",
+            self.datetime,
+            self.message_id,
+            self.list.map_or_else(|| "*".to_string(), |l| l.to_string()),
+            self.kind,
+            self.name,
+            self.detail
+                .as_ref()
+                .map_or_else(String::new, |d| format!(" ({d})")),
+        )
+    }
+}
+
+/// Record of a post removed from the archive by the list owners.
+///
+/// The post row is kept so that its thread stays intact, but its message is
+/// replaced by a placeholder (See
+/// [`Connection::remove_post`](crate::Connection::remove_post)).
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PostTombstone {
+    /// Database primary key.
+    pub pk: i64,
+    /// Mailing list foreign key (See [`MailingList`]).
+    pub list: i64,
+    /// `Message-ID` of the removed post, without carets.
+    pub message_id: String,
+    /// Why the post was removed.
+    pub reason: String,
+    /// Who removed the post.
+    pub removed_by: String,
+    /// Removal timestamp.
+    pub timestamp: u64,
+    /// Removal datetime.
+    pub datetime: String,
+}
+
+impl std::fmt::Display for PostTombstone {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "<{}> removed by {}: {}",
+            self.message_id, self.removed_by, self.reason
+        )
+    }
+}
+
+/// How the archives of a list display e-mail addresses (See
+/// [`Redaction`](crate::redaction::Redaction)).
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ArchiveAddresses {
+    /// Addresses are shown, except those of subscribers who hide them.
+    #[default]
+    Show,
+    /// Addresses are obfuscated, e.g. `user at example.com`.
+    Obfuscate,
+    /// Addresses are hidden.
+    Hide,
+}
+
+impl ArchiveAddresses {
+    /// Returns the name of the setting used in the database schema.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Show => "show",
+            Self::Obfuscate => "obfuscate",
+            Self::Hide => "hide",
+        }
+    }
+
+    /// Returns all possible variants as `&'static str`
+    pub const fn possible_values() -> &'static [&'static str] {
+        const VALUES: &[&str] = &[
+            ArchiveAddresses::Show.as_str(),
+            ArchiveAddresses::Obfuscate.as_str(),
+            ArchiveAddresses::Hide.as_str(),
+        ];
+        VALUES
+    }
+}
+
+impl std::str::FromStr for ArchiveAddresses {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.trim() {
+            s if s.eq_ignore_ascii_case(stringify!(Show)) => Self::Show,
+            s if s.eq_ignore_ascii_case(stringify!(Obfuscate)) => Self::Obfuscate,
+            s if s.eq_ignore_ascii_case(stringify!(Hide)) => Self::Hide,
+            other => {
+                return Err(Error::new_external(format!(
+                    "Invalid archive addresses setting: {other}."
+                )))
+            }
+        })
+    }
+}
+
+impl rusqlite::types::ToSql for ArchiveAddresses {
+    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
+        Ok(self.as_str().into())
+    }
+}
+
+impl rusqlite::types::FromSql for ArchiveAddresses {
+    fn column_result(value: rusqlite::types::ValueRef<'_>) -> rusqlite::types::FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map_err(|err: Error| rusqlite::types::FromSqlError::Other(Box::new(err)))
+    }
+}
+
+impl std::fmt::Display for ArchiveAddresses {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.as_str())
+    }
+}
+
+/// The OpenPGP key of an encrypted list.
+///
+/// Posts to the list must be encrypted to this key. They are decrypted and
+/// re-encrypted to each subscriber's [`Account::public_key`].
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ListOpenPgpKey {
+    /// Database primary key.
+    pub pk: i64,
+    /// Mailing list foreign key (See [`MailingList`]).
+    pub list: i64,
+    /// Key fingerprint.
+    pub fingerprint: String,
+    /// ASCII-armored secret key.
+    pub secret_key: String,
+}
+
+impl std::fmt::Debug for ListOpenPgpKey {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.debug_struct(stringify!(ListOpenPgpKey))
+            .field("pk", &self.pk)
+            .field("list", &self.list)
+            .field("fingerprint", &self.fingerprint)
+            .finish_non_exhaustive()
+    }
+}
+
+impl std::fmt::Display for ListOpenPgpKey {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "[#{} {}] {}", self.pk, self.list, self.fingerprint)
+    }
+}
+
+/// Inclusion of a mailing list in an umbrella list.
+///
+/// Posts to the `parent` list are also sent to the subscribers of the `child`
+/// list, and of the lists it includes in turn.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ListInclusion {
+    /// Database primary key.
+    pub pk: i64,
+    /// Umbrella mailing list foreign key (See [`MailingList`]).
+    pub parent: i64,
+    /// Included mailing list foreign key (See [`MailingList`]).
+    pub child: i64,
+    /// Whether those who can post to the child list, i.e. its owners and
+    /// subscribers, can also post to the parent list as if they were its
+    /// owners and subscribers respectively.
+    pub inherit_post_rights: bool,
+}
+
+impl std::fmt::Display for ListInclusion {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "[#{} {}] includes list {}{}",
+            self.pk,
+            self.parent,
+            self.child,
+            if self.inherit_post_rights {
+                ", inheriting post rights"
+            } else {
+                ""
+            }
+        )
+    }
+}
+
+/// The topics a subscriber receives posts about, see [`crate::topics`].
+///
+/// Subscriptions without topics receive every post.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SubscriptionTopics {
+    /// Database primary key.
+    pub pk: i64,
+    /// Subscription foreign key (See [`ListSubscription`]).
+    pub subscription: i64,
+    /// Topics of the list the subscriber receives posts about.
+    pub topics: Vec<String>,
+    /// Whether the subscriber receives posts that are not about any of the
+    /// list's topics.
+    pub everything_else: bool,
+}
+
+impl std::fmt::Display for SubscriptionTopics {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "[#{} {}] topics: {}{}",
+            self.pk,
+            self.subscription,
+            self.topics.join(", "),
+            if self.everything_else {
+                ", everything else"
+            } else {
+                ""
+            }
+        )
+    }
+}
+
+impl SubscriptionTopics {
+    /// Whether the subscriber receives a post about `post_topics`, as returned
+    /// by [`crate::topics::post_topics`].
+    pub fn wants(&self, post_topics: &[String]) -> bool {
+        if post_topics.is_empty() {
+            return self.everything_else;
+        }
+        post_topics
+            .iter()
+            .any(|t| self.topics.iter().any(|s| s.eq_ignore_ascii_case(t)))
+    }
+}
+
+/// A thread a subscriber muted or follows, see [`crate::threads`].
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ThreadPreference {
+    /// Database primary key.
+    pub pk: i64,
+    /// Subscription foreign key (See [`ListSubscription`]).
+    pub subscription: i64,
+    /// `Message-ID` of the thread's root post.
+    pub thread: String,
+    /// Whether the thread is muted, otherwise it is followed.
+    pub muted: bool,
+}
+
+impl std::fmt::Display for ThreadPreference {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "[#{} {}] {} thread {}",
+            self.pk,
+            self.subscription,
+            if self.muted { "muted" } else { "follows" },
+            self.thread
+        )
+    }
+}
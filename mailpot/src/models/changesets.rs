@@ -45,6 +45,8 @@ pub struct MailingListChangeset {
     /// Optional new value.
     pub archive_url: Option<Option<String>>,
     /// Optional new value.
+    pub topics: Option<Vec<String>>,
+    /// Optional new value.
     pub owner_local_part: Option<Option<String>>,
     /// Optional new value.
     pub request_local_part: Option<Option<String>>,
@@ -54,6 +56,14 @@ pub struct MailingListChangeset {
     pub hidden: Option<bool>,
     /// Optional new value.
     pub enabled: Option<bool>,
+    /// Optional new value.
+    pub confirm_unsubscription: Option<bool>,
+    /// Optional new value.
+    pub post_retention_days: Option<Option<u64>>,
+    /// Optional new value.
+    pub archive_addresses: Option<super::ArchiveAddresses>,
+    /// Optional new value.
+    pub archive_raw_download: Option<bool>,
 }
 
 impl_display!(MailingListChangeset);
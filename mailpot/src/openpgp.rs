@@ -0,0 +1,508 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! OpenPGP encrypted lists.
+//!
+//! Posts to a list with a [`ListOpenPgpKey`] must be PGP/MIME encrypted
+//! ([RFC 3156](https://www.rfc-editor.org/rfc/rfc3156)) to the list key. The
+//! [`DecryptPost`](crate::message_filters::DecryptPost) filter decrypts them,
+//! and [`EncryptForSubscribers`](crate::message_filters::EncryptForSubscribers)
+//! re-encrypts them to each subscriber's
+//! [`Account::public_key`](crate::models::Account::public_key).
+//!
+//! Lists can also require posts to be PGP/MIME signed by a key of an owner or
+//! subscriber account, see
+//! [`VerifySignature`](crate::message_filters::VerifySignature).
+
+use std::collections::HashMap;
+
+use log::trace;
+use melib::{
+    email::{
+        attachment_types::{ContentType, MultipartType},
+        attachments::AttachmentBuilder,
+    },
+    HeaderName,
+};
+use pgp::{
+    composed::{
+        ArmorOptions, Deserializable, DetachedSignature, EncryptionCaps, KeyType, Message,
+        MessageBuilder, SecretKeyParamsBuilder, SignedPublicKey, SignedSecretKey,
+        SubkeyParamsBuilder,
+    },
+    crypto::{ecc_curve::ECCCurve, hash::HashAlgorithm, sym::SymmetricKeyAlgorithm},
+    types::{KeyDetails, Password},
+};
+use rusqlite::OptionalExtension;
+
+use crate::{
+    errors::{ErrorKind::*, *},
+    models::{DbVal, ListOpenPgpKey},
+    Connection,
+};
+
+/// Header with the signature verification result of a post, added by
+/// [`VerifySignature`](crate::message_filters::VerifySignature).
+pub const SIGNATURE_HEADER: &str = "X-Mailpot-Signature";
+
+/// Generate a new secret key for `user_id`, e.g. `List <list@example.com>`.
+///
+/// The key has an Ed25519 primary key for certification and signing and a
+/// Curve25519 subkey for encryption.
+pub fn generate_secret_key(user_id: &str) -> Result<SignedSecretKey> {
+    let mut encryption_key = SubkeyParamsBuilder::default();
+    encryption_key
+        .key_type(KeyType::ECDH(ECCCurve::Curve25519Legacy))
+        .can_sign(false)
+        .can_encrypt(EncryptionCaps::All);
+    let mut key_params = SecretKeyParamsBuilder::default();
+    key_params
+        .key_type(KeyType::Ed25519Legacy)
+        .can_certify(true)
+        .can_sign(true)
+        .primary_user_id(user_id.into())
+        .subkeys(vec![encryption_key
+            .build()
+            .map_err(|err| Error::new_external(err.to_string()))?]);
+    Ok(key_params
+        .build()
+        .map_err(|err| Error::new_external(err.to_string()))?
+        .generate(rand::thread_rng())?)
+}
+
+/// Parse an ASCII-armored secret key.
+pub fn parse_secret_key(armored: &str) -> Result<SignedSecretKey> {
+    let (key, _) = SignedSecretKey::from_string(armored.trim())?;
+    key.verify_bindings()?;
+    Ok(key)
+}
+
+/// Parse an ASCII-armored public key.
+pub fn parse_public_key(armored: &str) -> Result<SignedPublicKey> {
+    let (key, _) = SignedPublicKey::from_string(armored.trim())?;
+    key.verify_bindings()?;
+    Ok(key)
+}
+
+/// ASCII-armored public key of `key`.
+pub fn armored_public_key(key: &SignedSecretKey) -> Result<String> {
+    Ok(key
+        .to_public_key()
+        .to_armored_string(ArmorOptions::default())?)
+}
+
+/// Encrypt `data` to `key`, returning an ASCII-armored message.
+///
+/// The first subkey flagged for encryption is used, or the primary key if it
+/// is capable of encryption.
+pub fn encrypt(key: &SignedPublicKey, data: &[u8]) -> Result<String> {
+    let mut rng = rand::thread_rng();
+    let mut builder = MessageBuilder::from_bytes("", data.to_vec())
+        .seipd_v1(&mut rng, SymmetricKeyAlgorithm::AES256);
+    if let Some(subkey) = key.public_subkeys.iter().find(|k| {
+        k.algorithm().can_encrypt()
+            && k.signatures.iter().any(|s| {
+                let flags = s.key_flags();
+                flags.encrypt_comms() || flags.encrypt_storage()
+            })
+    }) {
+        builder.encrypt_to_key(&mut rng, subkey)?;
+    } else if key.algorithm().can_encrypt() {
+        builder.encrypt_to_key(&mut rng, key)?;
+    } else {
+        return Err(Error::new_external(format!(
+            "OpenPGP key {} has no encryption key.",
+            key.fingerprint()
+        )));
+    }
+    Ok(builder.to_armored_string(&mut rng, ArmorOptions::default())?)
+}
+
+/// Decrypt an ASCII-armored message with `key`.
+pub fn decrypt(key: &SignedSecretKey, armored: &[u8]) -> Result<Vec<u8>> {
+    let text = String::from_utf8_lossy(armored);
+    let (message, _) = Message::from_string(text.trim())?;
+    let mut message = message.decrypt(&Password::empty(), key)?;
+    if message.is_compressed() {
+        message = message.decompress()?;
+    }
+    Ok(message.as_data_vec()?)
+}
+
+/// Whether `name` is a MIME content header that belongs to the message body
+/// entity.
+fn is_content_header(name: &HeaderName) -> bool {
+    name.as_str()
+        .get(.."content-".len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("content-"))
+}
+
+/// The body entity of a message, which consists of its content headers and
+/// its body.
+fn body_entity(headers: &[(HeaderName, &[u8])], body: &[u8]) -> Vec<u8> {
+    let mut entity = Vec::with_capacity(body.len());
+    for (h, v) in headers.iter().filter(|(h, _)| is_content_header(h)) {
+        entity.extend_from_slice(h.as_str().as_bytes());
+        entity.extend_from_slice(b": ");
+        entity.extend_from_slice(v);
+        entity.extend_from_slice(b"\r\n");
+    }
+    entity.extend_from_slice(b"\r\n");
+    entity.extend_from_slice(body);
+    entity
+}
+
+/// Decrypt a PGP/MIME encrypted message.
+///
+/// The returned message has the outer headers of `raw` and the decrypted body
+/// entity.
+pub fn decrypt_mime(key: &SignedSecretKey, raw: &[u8]) -> Result<Vec<u8>> {
+    let (headers, _) = melib::email::parser::mail(raw)
+        .map_err(|err| Error::new_external(format!("Could not parse message: {err}")))?;
+    let attachment = AttachmentBuilder::new(raw).build();
+    let ContentType::Multipart {
+        kind: MultipartType::Encrypted,
+        ref parts,
+        ..
+    } = attachment.content_type
+    else {
+        return Err(Error::new_external(
+            "Message is not PGP/MIME encrypted (multipart/encrypted).",
+        ));
+    };
+    let Some(encrypted) = parts.iter().find(|p| match p.content_type {
+        ContentType::OctetStream { .. } => true,
+        ContentType::Other { ref tag, .. } => tag.eq_ignore_ascii_case(b"application/octet-stream"),
+        _ => false,
+    }) else {
+        return Err(Error::new_external(
+            "multipart/encrypted message without an application/octet-stream part.",
+        ));
+    };
+    let entity = decrypt(key, &encrypted.decode(Default::default()))?;
+
+    let mut ret = Vec::with_capacity(raw.len());
+    for (h, v) in headers.iter().filter(|(h, _)| !is_content_header(h)) {
+        ret.extend_from_slice(h.as_str().as_bytes());
+        ret.extend_from_slice(b": ");
+        ret.extend_from_slice(v);
+        ret.extend_from_slice(b"\r\n");
+    }
+    // If the decrypted entity has no headers, it starts with an empty line.
+    ret.extend_from_slice(&entity);
+    Ok(ret)
+}
+
+/// Encrypt a message to `key` in PGP/MIME format.
+///
+/// The content headers and the body of `raw` are encrypted, and the rest of
+/// the headers are kept in the clear.
+pub fn encrypt_mime(key: &SignedPublicKey, raw: &[u8]) -> Result<Vec<u8>> {
+    let (headers, body) = melib::email::parser::mail(raw)
+        .map_err(|err| Error::new_external(format!("Could not parse message: {err}")))?;
+    let entity = body_entity(&headers, body);
+    let encrypted = encrypt(key, &entity)?;
+
+    let boundary = melib::email::compose::random::gen_boundary();
+    let mut ret = Vec::with_capacity(encrypted.len() + raw.len());
+    for (h, v) in headers
+        .iter()
+        .filter(|(h, _)| !is_content_header(h) && h != HeaderName::MIME_VERSION)
+    {
+        ret.extend_from_slice(h.as_str().as_bytes());
+        ret.extend_from_slice(b": ");
+        ret.extend_from_slice(v);
+        ret.extend_from_slice(b"\r\n");
+    }
+    ret.extend_from_slice(
+        format!(
+            "MIME-Version: 1.0\r\nContent-Type: multipart/encrypted; \
+             protocol=\"application/pgp-encrypted\"; boundary=\"{boundary}\"\r\n\r\nThis is an \
+             OpenPGP/MIME encrypted message (RFC 4880 and 3156)\r\n--{boundary}\r\nContent-Type: \
+             application/pgp-encrypted\r\nContent-Description: PGP/MIME version \
+             identification\r\n\r\nVersion: 1\r\n\r\n--{boundary}\r\nContent-Type: \
+             application/octet-stream; name=\"encrypted.asc\"\r\nContent-Description: OpenPGP \
+             encrypted message\r\nContent-Disposition: inline; \
+             filename=\"encrypted.asc\"\r\n\r\n{}\r\n--{boundary}--\r\n",
+            encrypted.replace('\n', "\r\n").trim_end()
+        )
+        .as_bytes(),
+    );
+    Ok(ret)
+}
+
+/// Sign a message with `key` in PGP/MIME format.
+///
+/// The content headers and the body of `raw` are signed, and the rest of the
+/// headers are kept as they are.
+pub fn sign_mime(key: &SignedSecretKey, raw: &[u8]) -> Result<Vec<u8>> {
+    let (headers, body) = melib::email::parser::mail(raw)
+        .map_err(|err| Error::new_external(format!("Could not parse message: {err}")))?;
+    let entity = body_entity(&headers, body);
+    let entity = melib::email::pgp::convert_attachment_to_rfc_spec(&entity);
+    let signature = DetachedSignature::sign_text_data(
+        rand::thread_rng(),
+        &key.primary_key,
+        &Password::empty(),
+        HashAlgorithm::Sha256,
+        entity.as_slice(),
+    )?
+    .to_armored_string(ArmorOptions::default())?;
+
+    let boundary = melib::email::compose::random::gen_boundary();
+    let mut ret = Vec::with_capacity(signature.len() + raw.len());
+    for (h, v) in headers
+        .iter()
+        .filter(|(h, _)| !is_content_header(h) && h != HeaderName::MIME_VERSION)
+    {
+        ret.extend_from_slice(h.as_str().as_bytes());
+        ret.extend_from_slice(b": ");
+        ret.extend_from_slice(v);
+        ret.extend_from_slice(b"\r\n");
+    }
+    ret.extend_from_slice(
+        format!(
+            "MIME-Version: 1.0\r\nContent-Type: multipart/signed; micalg=pgp-sha256; \
+             protocol=\"application/pgp-signature\"; boundary=\"{boundary}\"\r\n\r\nThis is an \
+             OpenPGP/MIME signed message (RFC 4880 and 3156)\r\n--{boundary}\r\n"
+        )
+        .as_bytes(),
+    );
+    ret.extend_from_slice(&entity);
+    ret.extend_from_slice(
+        format!(
+            "\r\n--{boundary}\r\nContent-Type: application/pgp-signature; \
+             name=\"signature.asc\"\r\nContent-Description: OpenPGP digital \
+             signature\r\nContent-Disposition: attachment; \
+             filename=\"signature.asc\"\r\n\r\n{}\r\n--{boundary}--\r\n",
+            signature.replace('\n', "\r\n").trim_end()
+        )
+        .as_bytes(),
+    );
+    Ok(ret)
+}
+
+/// Verify the signature of a PGP/MIME signed message with `key`.
+///
+/// Returns the fingerprint of the primary key or subkey that made the
+/// signature.
+pub fn verify_mime(key: &SignedPublicKey, raw: &[u8]) -> Result<String> {
+    let attachment = AttachmentBuilder::new(raw).build();
+    if !matches!(
+        attachment.content_type,
+        ContentType::Multipart {
+            kind: MultipartType::Signed,
+            ..
+        }
+    ) {
+        return Err(Error::new_external(
+            "Message is not PGP/MIME signed (multipart/signed).",
+        ));
+    }
+    let (signed, signature) = melib::email::pgp::verify_signature(&attachment)
+        .map_err(|err| Error::new_external(err.to_string()))?;
+    let signature = signature.decode(Default::default());
+    let (signature, _) =
+        DetachedSignature::from_string(String::from_utf8_lossy(&signature).trim())?;
+    if signature.verify(key, &signed).is_ok() {
+        return Ok(key.fingerprint().to_string());
+    }
+    for subkey in &key.public_subkeys {
+        if signature.verify(subkey, &signed).is_ok() {
+            return Ok(subkey.fingerprint().to_string());
+        }
+    }
+    Err(Error::new_external(format!(
+        "Signature was not made by OpenPGP key {}.",
+        key.fingerprint()
+    )))
+}
+
+/// Whether processing posts of a list needs the accounts' public keys (see
+/// [`Connection::list_public_keys`]): the list is encrypted, or its
+/// `VerifySignatureSettings` are enabled.
+pub(crate) fn needs_public_keys(
+    encrypted: bool,
+    filter_settings: &HashMap<String, DbVal<serde_json::Value>>,
+) -> bool {
+    encrypted
+        || filter_settings
+            .get("VerifySignatureSettings")
+            .and_then(|s| s.get("enabled"))
+            .and_then(serde_json::Value::as_bool)
+            .unwrap_or(false)
+}
+
+impl Connection {
+    /// Fetch the OpenPGP key of an encrypted list.
+    ///
+    /// Secret keys cannot be read through an
+    /// [`untrusted`](Connection::untrusted) connection, so this returns an
+    /// error for encrypted lists in that case.
+    pub fn list_openpgp_key(&self, list_pk: i64) -> Result<Option<DbVal<ListOpenPgpKey>>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT * FROM list_openpgp_key WHERE list = ?;")?;
+        let Some((pk, list, fingerprint, secret_key)) = stmt
+            .query_row([&list_pk], |row| {
+                Ok((
+                    row.get::<_, i64>("pk")?,
+                    row.get("list")?,
+                    row.get("fingerprint")?,
+                    row.get::<_, Option<String>>("secret_key")?,
+                ))
+            })
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let Some(secret_key) = secret_key else {
+            return Err(Error::new_external(
+                "The OpenPGP secret key of this list cannot be read through an untrusted \
+                 connection.",
+            ));
+        };
+
+        Ok(Some(DbVal(
+            ListOpenPgpKey {
+                pk,
+                list,
+                fingerprint,
+                secret_key,
+            },
+            pk,
+        )))
+    }
+
+    /// Whether a list is encrypted, i.e. has an OpenPGP key.
+    ///
+    /// Unlike [`Connection::list_openpgp_key`], this works through
+    /// [`untrusted`](Connection::untrusted) connections.
+    pub fn list_is_encrypted(&self, list_pk: i64) -> Result<bool> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT count(*) FROM list_openpgp_key WHERE list = ?;")?;
+        let count: i64 = stmt.query_row([&list_pk], |row| row.get(0))?;
+        Ok(count > 0)
+    }
+
+    /// Set the OpenPGP key of a list from an ASCII-armored secret key, which
+    /// makes it an encrypted list. An existing key is replaced.
+    pub fn set_list_openpgp_key(
+        &self,
+        list_pk: i64,
+        secret_key: &str,
+    ) -> Result<DbVal<ListOpenPgpKey>> {
+        let key = parse_secret_key(secret_key)?;
+        self.insert_list_openpgp_key(list_pk, &key)
+    }
+
+    /// Generate a new OpenPGP key for a list, which makes it an encrypted
+    /// list. An existing key is replaced.
+    pub fn generate_list_openpgp_key(&self, list_pk: i64) -> Result<DbVal<ListOpenPgpKey>> {
+        let Some(list) = self.list(list_pk)? else {
+            return Err(NotFound("Could not find a list with this pk.").into());
+        };
+        let key = generate_secret_key(&list.address().to_string())?;
+        self.insert_list_openpgp_key(list_pk, &key)
+    }
+
+    fn insert_list_openpgp_key(
+        &self,
+        list_pk: i64,
+        key: &SignedSecretKey,
+    ) -> Result<DbVal<ListOpenPgpKey>> {
+        let fingerprint = key.fingerprint().to_string();
+        let armored = key.to_armored_string(ArmorOptions::default())?;
+        let mut stmt = self.connection.prepare(
+            "INSERT INTO list_openpgp_key(list, fingerprint, secret_key) VALUES (?, ?, ?) ON \
+             CONFLICT(list) DO UPDATE SET fingerprint = excluded.fingerprint, secret_key = \
+             excluded.secret_key RETURNING *;",
+        )?;
+        let ret = stmt
+            .query_row(rusqlite::params![&list_pk, &fingerprint, &armored], |row| {
+                let pk = row.get("pk")?;
+                Ok(DbVal(
+                    ListOpenPgpKey {
+                        pk,
+                        list: row.get("list")?,
+                        fingerprint: row.get("fingerprint")?,
+                        secret_key: row.get("secret_key")?,
+                    },
+                    pk,
+                ))
+            })
+            .map_err(|err| {
+                if matches!(
+                    err,
+                    rusqlite::Error::SqliteFailure(
+                        rusqlite::ffi::Error {
+                            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+                            extended_code: 787
+                        },
+                        _
+                    )
+                ) {
+                    Error::from(err).chain_err(|| NotFound("Could not find a list with this pk."))
+                } else {
+                    err.into()
+                }
+            })?;
+
+        trace!("insert_list_openpgp_key {:?}.", &ret);
+        Ok(ret)
+    }
+
+    /// Remove the OpenPGP key of a list, which makes it a plain list.
+    pub fn remove_list_openpgp_key(&self, list_pk: i64) -> Result<()> {
+        let mut stmt = self
+            .connection
+            .prepare("DELETE FROM list_openpgp_key WHERE list = ? RETURNING *;")?;
+        stmt.query_row(rusqlite::params![&list_pk], |_| Ok(()))
+            .map_err(|err| {
+                if matches!(err, rusqlite::Error::QueryReturnedNoRows) {
+                    Error::from(err).chain_err(|| NotFound("list OpenPGP key not found!"))
+                } else {
+                    err.into()
+                }
+            })?;
+
+        trace!("remove_list_openpgp_key {}.", list_pk);
+        Ok(())
+    }
+
+    /// Fetch the [`Account::public_key`](crate::models::Account::public_key)
+    /// values of a list's subscribers and owners, keyed by lowercase
+    /// address.
+    ///
+    /// Disabled accounts and accounts without a public key are not included.
+    pub fn list_public_keys(&self, list_pk: i64) -> Result<HashMap<String, String>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT address, public_key FROM account WHERE public_key IS NOT NULL AND enabled = 1 \
+             AND (address IN (SELECT address FROM subscription WHERE list = ?1) OR address IN \
+             (SELECT address FROM owner WHERE list = ?1));",
+        )?;
+        let iter = stmt.query_map([&list_pk], |row| {
+            let address: String = row.get("address")?;
+            let public_key: String = row.get("public_key")?;
+            Ok((address.to_lowercase(), public_key))
+        })?;
+        Ok(iter.collect::<std::result::Result<HashMap<String, String>, rusqlite::Error>>()?)
+    }
+}
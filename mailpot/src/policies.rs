@@ -61,17 +61,12 @@ mod post_policy {
         /// # Examples
         ///
         /// ```
-        /// # use mailpot::{models::*, Configuration, Connection, SendMail};
+        /// # use mailpot::{models::*, Configuration, Connection};
         /// # use tempfile::TempDir;
         /// #
         /// # let tmp_dir = TempDir::new().unwrap();
         /// # let db_path = tmp_dir.path().join("mpot.db");
-        /// # let config = Configuration {
-        /// #     send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        /// #     db_path: db_path.clone(),
-        /// #     data_path: tmp_dir.path().to_path_buf(),
-        /// #     administrators: vec![],
-        /// # };
+        /// # let config = Configuration::new(db_path.clone());
         /// #
         /// # fn do_test(config: Configuration) {
         /// let db = Connection::open_or_create_db(config).unwrap().trusted();
@@ -108,17 +103,12 @@ mod post_policy {
         /// ```
         ///
         /// ```should_panic
-        /// # use mailpot::{models::*, Configuration, Connection, SendMail};
+        /// # use mailpot::{models::*, Configuration, Connection};
         /// # use tempfile::TempDir;
         /// #
         /// # let tmp_dir = TempDir::new().unwrap();
         /// # let db_path = tmp_dir.path().join("mpot.db");
-        /// # let config = Configuration {
-        /// #     send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        /// #     db_path: db_path.clone(),
-        /// #     data_path: tmp_dir.path().to_path_buf(),
-        /// #     administrators: vec![],
-        /// # };
+        /// # let config = Configuration::new(db_path.clone());
         /// #
         /// # fn do_test(config: Configuration) {
         /// let db = Connection::open_or_create_db(config).unwrap().trusted();
@@ -256,17 +246,12 @@ mod subscription_policy {
         /// # Examples
         ///
         /// ```
-        /// # use mailpot::{models::*, Configuration, Connection, SendMail};
+        /// # use mailpot::{models::*, Configuration, Connection};
         /// # use tempfile::TempDir;
         /// #
         /// # let tmp_dir = TempDir::new().unwrap();
         /// # let db_path = tmp_dir.path().join("mpot.db");
-        /// # let config = Configuration {
-        /// #     send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        /// #     db_path: db_path.clone(),
-        /// #     data_path: tmp_dir.path().to_path_buf(),
-        /// #     administrators: vec![],
-        /// # };
+        /// # let config = Configuration::new(db_path.clone());
         /// #
         /// # fn do_test(config: Configuration) {
         /// let db = Connection::open_or_create_db(config).unwrap().trusted();
@@ -302,17 +287,12 @@ mod subscription_policy {
         /// ```
         ///
         /// ```should_panic
-        /// # use mailpot::{models::*, Configuration, Connection, SendMail};
+        /// # use mailpot::{models::*, Configuration, Connection};
         /// # use tempfile::TempDir;
         /// #
         /// # let tmp_dir = TempDir::new().unwrap();
         /// # let db_path = tmp_dir.path().join("mpot.db");
-        /// # let config = Configuration {
-        /// #     send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        /// #     db_path: db_path.clone(),
-        /// #     data_path: tmp_dir.path().to_path_buf(),
-        /// #     administrators: vec![],
-        /// # };
+        /// # let config = Configuration::new(db_path.clone());
         /// #
         /// # fn do_test(config: Configuration) {
         /// let db = Connection::open_or_create_db(config).unwrap().trusted();
@@ -402,3 +382,126 @@ mod subscription_policy {
         }
     }
 }
+
+mod access_entry {
+    use log::trace;
+
+    use crate::{
+        errors::{ErrorKind::*, *},
+        models::{AccessEntry, DbVal},
+        Connection,
+    };
+
+    impl Connection {
+        /// Fetch the access entries that apply to a mailing list, including
+        /// instance-wide entries.
+        pub fn list_access_entries(&self, list_pk: i64) -> Result<Vec<DbVal<AccessEntry>>> {
+            let mut stmt = self.connection.prepare(
+                "SELECT * FROM access_entry WHERE list = ? OR list IS NULL ORDER BY list IS NULL, \
+                 pk;",
+            )?;
+            let iter = stmt.query_map([&list_pk], Self::access_entry_from_row)?;
+
+            let mut ret = vec![];
+            for entry in iter {
+                ret.push(entry?);
+            }
+            Ok(ret)
+        }
+
+        /// Fetch the instance-wide access entries.
+        pub fn instance_access_entries(&self) -> Result<Vec<DbVal<AccessEntry>>> {
+            let mut stmt = self
+                .connection
+                .prepare("SELECT * FROM access_entry WHERE list IS NULL ORDER BY pk;")?;
+            let iter = stmt.query_map([], Self::access_entry_from_row)?;
+
+            let mut ret = vec![];
+            for entry in iter {
+                ret.push(entry?);
+            }
+            Ok(ret)
+        }
+
+        /// Add an access entry. If `entry.list` is `None`, the entry applies to
+        /// every list.
+        pub fn add_access_entry(&self, entry: AccessEntry) -> Result<DbVal<AccessEntry>> {
+            if entry.pattern.trim().is_empty() {
+                return Err(Error::new_external("Access entry pattern cannot be empty."));
+            }
+            let mut stmt = self.connection.prepare(
+                "INSERT INTO access_entry(list, kind, match_type, pattern, post, subscribe, \
+                 comment) VALUES (?, ?, ?, ?, ?, ?, ?) RETURNING *;",
+            )?;
+            let ret = stmt
+                .query_row(
+                    rusqlite::params![
+                        &entry.list,
+                        &entry.kind,
+                        &entry.match_type,
+                        entry.pattern.trim(),
+                        &entry.post,
+                        &entry.subscribe,
+                        &entry.comment,
+                    ],
+                    Self::access_entry_from_row,
+                )
+                .map_err(|err| {
+                    if matches!(
+                        err,
+                        rusqlite::Error::SqliteFailure(
+                            rusqlite::ffi::Error {
+                                code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+                                extended_code: 787
+                            },
+                            _
+                        )
+                    ) {
+                        Error::from(err)
+                            .chain_err(|| NotFound("Could not find a list with this pk."))
+                    } else {
+                        err.into()
+                    }
+                })?;
+
+            trace!("add_access_entry {:?}.", &ret);
+            Ok(ret)
+        }
+
+        /// Remove an access entry. If `list_pk` is `None`, only instance-wide
+        /// entries can be removed.
+        pub fn remove_access_entry(&self, list_pk: Option<i64>, entry_pk: i64) -> Result<()> {
+            let mut stmt = self
+                .connection
+                .prepare("DELETE FROM access_entry WHERE pk = ? AND list IS ? RETURNING *;")?;
+            stmt.query_row(rusqlite::params![&entry_pk, &list_pk], |_| Ok(()))
+                .map_err(|err| {
+                    if matches!(err, rusqlite::Error::QueryReturnedNoRows) {
+                        Error::from(err).chain_err(|| NotFound("access entry not found!"))
+                    } else {
+                        err.into()
+                    }
+                })?;
+
+            trace!("remove_access_entry {:?} {}.", list_pk, entry_pk);
+            Ok(())
+        }
+
+        fn access_entry_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<DbVal<AccessEntry>> {
+            let pk = row.get("pk")?;
+            Ok(DbVal(
+                AccessEntry {
+                    pk,
+                    list: row.get("list")?,
+                    kind: row.get("kind")?,
+                    match_type: row.get("match_type")?,
+                    pattern: row.get("pattern")?,
+                    post: row.get("post")?,
+                    subscribe: row.get("subscribe")?,
+                    comment: row.get("comment")?,
+                },
+                pk,
+            ))
+        }
+    }
+}
@@ -0,0 +1,120 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Audit trail of how each processed post was handled.
+
+use log::trace;
+
+use crate::{
+    errors::{ErrorKind::*, *},
+    models::{DbVal, PostEvent, PostEventKind},
+    Connection, StripCarets, StripCaretsInplace,
+};
+
+impl Connection {
+    /// Record a post processing event.
+    ///
+    /// `message_id` is stored without surrounding carets.
+    pub fn insert_post_event(
+        &self,
+        list_pk: Option<i64>,
+        message_id: &str,
+        kind: PostEventKind,
+        name: &str,
+        detail: Option<&str>,
+    ) -> Result<DbVal<PostEvent>> {
+        let mut stmt = self.connection.prepare(
+            "INSERT INTO post_event(list, message_id, kind, name, detail) VALUES (?, ?, ?, ?, ?) \
+             RETURNING *;",
+        )?;
+        let ret = stmt
+            .query_row(
+                rusqlite::params![&list_pk, message_id.strip_carets(), &kind, name, &detail],
+                Self::post_event_from_row,
+            )
+            .map_err(|err| {
+                if matches!(
+                    err,
+                    rusqlite::Error::SqliteFailure(
+                        rusqlite::ffi::Error {
+                            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+                            extended_code: 787
+                        },
+                        _
+                    )
+                ) {
+                    Error::from(err).chain_err(|| NotFound("Could not find a list with this pk."))
+                } else {
+                    err.into()
+                }
+            })?;
+
+        trace!("insert_post_event {:?}.", &ret);
+        Ok(ret)
+    }
+
+    /// Fetch post events, oldest first.
+    ///
+    /// Events can be filtered by list and by `Message-ID`. If `limit` is set,
+    /// only the `limit` most recent matching events are returned.
+    pub fn post_events(
+        &self,
+        list_pk: Option<i64>,
+        message_id: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Vec<DbVal<PostEvent>>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT * FROM (SELECT * FROM post_event WHERE (?1 IS NULL OR list = ?1) AND (?2 IS \
+             NULL OR message_id = ?2) ORDER BY pk DESC LIMIT ?3) ORDER BY pk;",
+        )?;
+        let iter = stmt.query_map(
+            rusqlite::params![
+                &list_pk,
+                &message_id.map(StripCaretsInplace::strip_carets_inplace),
+                &limit.map_or(-1, |l| i64::try_from(l).unwrap_or(i64::MAX)),
+            ],
+            Self::post_event_from_row,
+        )?;
+
+        let mut ret = vec![];
+        for event in iter {
+            ret.push(event?);
+        }
+        Ok(ret)
+    }
+
+    pub(crate) fn post_event_from_row(
+        row: &rusqlite::Row<'_>,
+    ) -> rusqlite::Result<DbVal<PostEvent>> {
+        let pk = row.get("pk")?;
+        Ok(DbVal(
+            PostEvent {
+                pk,
+                list: row.get("list")?,
+                message_id: row.get("message_id")?,
+                kind: row.get("kind")?,
+                name: row.get("name")?,
+                detail: row.get("detail")?,
+                timestamp: row.get("timestamp")?,
+                datetime: row.get("datetime")?,
+            },
+            pk,
+        ))
+    }
+}
@@ -30,9 +30,21 @@
 //! ## Relay domains (`relay_domains`)
 //!
 //! <http://www.postfix.org/postconf.5.html#relay_domains>
+//!
+//! ## Mailing sets
+//!
+//! Addresses of [mailing sets](crate::mailing_sets) can't be enumerated, so
+//! they are matched by a separate
+//! [`regexp_table(5)`](https://www.postfix.org/regexp_table.5.html) map, which
+//! must be listed after the `hash` map:
+//!
+//! ```text
+//! transport_maps = hash:/path/to/mailpot_postfix_map, regexp:/path/to/mailpot_postfix_set_map
+//! ```
 
 use std::{
     borrow::Cow,
+    collections::BTreeMap,
     convert::TryInto,
     fs::OpenOptions,
     io::{BufWriter, Read, Seek, Write},
@@ -186,6 +198,57 @@ flags=RX user={username}{group_sep}{groupname} directory={{{data_dir}}} argv={{{
         ret
     }
 
+    /// Generate a [`regexp_table(5)`](https://www.postfix.org/regexp_table.5.html)
+    /// map matching [mailing set](crate::mailing_sets) addresses, for
+    /// `transport_maps` and `local_recipient_maps`.
+    ///
+    /// There is one entry per domain, that matches set expressions of the
+    /// domain's lists that have a post policy. Unlike the `hash` maps of
+    /// [`PostfixConfiguration::generate_maps`], it does not need `postmap`.
+    pub fn generate_set_maps(
+        &self,
+        lists: &[(DbVal<MailingList>, Option<DbVal<PostPolicy>>)],
+    ) -> String {
+        fn escape(s: &str) -> String {
+            let mut ret = String::with_capacity(s.len());
+            for c in s.chars() {
+                if r".[]()*+?{}|^$\/".contains(c) {
+                    ret.push('\\');
+                }
+                ret.push(c);
+            }
+            ret
+        }
+
+        let transport_name = self.transport_name.as_deref().unwrap_or("mailpot");
+        let mut ret = String::new();
+        ret.push_str("# Automatically generated by mailpot.\n");
+        ret.push_str("# Mailing set addresses, see regexp_table(5).\n\n");
+
+        let mut domains: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for (list, _) in lists.iter().filter(|(_, p)| p.is_some()) {
+            if let Some((local_part, domain)) = list.address.rsplit_once('@') {
+                domains.entry(domain).or_default().push(local_part);
+            }
+        }
+        for (domain, local_parts) in domains {
+            let operand = format!(
+                "[{{]*({})[}}]*",
+                local_parts
+                    .iter()
+                    .map(|l| escape(l))
+                    .collect::<Vec<_>>()
+                    .join("|")
+            );
+            ret.push_str(&format!(
+                "/^({operand}[|&~])+{operand}@{}$/ {transport_name}:\n",
+                escape(domain)
+            ));
+        }
+
+        ret
+    }
+
     /// Save service to Postfix's [`master.cf`](https://www.postfix.org/master.5.html) file.
     ///
     /// If you wish to do it manually, get the text output from
@@ -280,6 +343,9 @@ flags=RX user={username}{group_sep}{groupname} directory={{{data_dir}}} argv={{{
     /// must be discoverable in your `PATH` environment variable.
     ///
     /// `postmap` is usually distributed along with the other Postfix binaries.
+    ///
+    /// The [mailing set](crate::mailing_sets) map is saved next to the other
+    /// maps as `mailpot_postfix_set_map`.
     pub fn save_maps(&self, config: &Configuration) -> Result<()> {
         let db = Connection::open_db(config.clone())?;
         let Some(postmap) = find_binary_in_path("postmap") else {
@@ -296,6 +362,13 @@ flags=RX user={username}{group_sep}{groupname} directory={{{data_dir}}} argv={{{
             })
             .collect::<Result<Vec<(DbVal<MailingList>, Option<DbVal<PostPolicy>>)>>>()?;
         let content = self.generate_maps(&lists_post_policies);
+        let set_path = self
+            .map_output_path
+            .as_deref()
+            .unwrap_or(&config.data_path)
+            .join("mailpot_postfix_set_map");
+        std::fs::write(&set_path, self.generate_set_maps(&lists_post_policies))
+            .context(format!("Could not write to {}", set_path.display()))?;
         let path = self
             .map_output_path
             .as_deref()
@@ -403,9 +476,7 @@ fn test_postfix_generation() -> Result<()> {
     let db_path = tmp_dir.path().join("mpot.db");
     let config = Configuration {
         send_mail: SendMail::Smtp(get_smtp_conf()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
+        ..Configuration::new(db_path)
     };
     let config_path = tmp_dir.path().join("conf.toml");
     {
@@ -519,6 +590,15 @@ third+owner@example.com        mailpot:
         expected
     );
 
+    let set_maps = postfix_conf.generate_set_maps(&lists_post_policies);
+    assert!(
+        set_maps.ends_with(
+            "/^([{]*(second|third)[}]*[|&~])+[{]*(second|third)[}]*@example\\.com$/ mailpot:\n"
+        ),
+        "set maps have unexpected contents: {:?}",
+        set_maps
+    );
+
     let master_edit_value = r#"#
 # Postfix master process configuration file.  For details on the format
 # of the file, see the master(5) manual page (command: "man 5 master" or
@@ -22,15 +22,23 @@
 use std::borrow::Cow;
 
 use log::{info, trace};
-use melib::Envelope;
+use melib::{Address, Envelope};
 use rusqlite::OptionalExtension;
 
 use crate::{
     errors::*,
-    mail::{ListContext, ListRequest, PostAction, PostEntry},
-    models::{changesets::AccountChangeset, Account, DbVal, ListSubscription, MailingList, Post},
+    mail::{
+        FilterTrace, ListContext, ListRequest, ListTrace, MailJob, PostAction, PostEntry,
+        PostTrace, QueueTrace,
+    },
+    message_filters::PostFilter,
+    models::{
+        changesets::AccountChangeset, AccessEntry, AccessKind, AccessScope, Account, DbVal,
+        ListOpenPgpKey, ListOwner, ListSubscription, MailingList, Post, PostEventKind,
+    },
     queue::{Queue, QueueEntry},
     templates::Template,
+    threads::ThreadAction,
     Connection, StripCarets,
 };
 
@@ -54,24 +62,39 @@ impl Connection {
             .into()
         };
         let message_id = env.message_id().to_string();
-        let mut stmt = self.connection.prepare(
-            "INSERT OR REPLACE INTO post(list, address, message_id, message, datetime, timestamp) \
-             VALUES(?, ?, ?, ?, ?, ?) RETURNING pk;",
+        let in_reply_to = crate::threads::in_reply_to(env);
+        let thread_root = self.list_thread_root(list_pk, env)?;
+        // Blobs are stored in the same transaction as the post, so that they
+        // are never collected before it references them.
+        let tx = self.savepoint(Some(stringify!(insert_post)))?;
+        let (stored, blobs) = tx.split_message(message)?;
+        let mut stmt = tx.connection.prepare(
+            "INSERT OR REPLACE INTO post(list, address, message_id, message, datetime, timestamp, \
+             in_reply_to, refs, thread_root, headers_json, blobs) VALUES(?, ?, ?, ?, ?, ?, ?, ?, \
+             ?, ?, ?) RETURNING pk;",
         )?;
         let pk = stmt.query_row(
             rusqlite::params![
                 &list_pk,
                 &address,
                 &message_id,
-                &message,
+                &stored,
                 &datetime,
-                &env.timestamp
+                &env.timestamp,
+                &in_reply_to,
+                &crate::threads::references(env),
+                &thread_root,
+                &Post::parse_headers(message),
+                &blobs,
             ],
             |row| {
                 let pk: i64 = row.get("pk")?;
                 Ok(pk)
             },
         )?;
+        drop(stmt);
+        tx.index_post(pk, message, env)?;
+        tx.commit()?;
 
         trace!(
             "insert_post list_pk {}, from {:?} message_id {:?} post_pk {}.",
@@ -83,14 +106,169 @@ impl Connection {
         Ok(pk)
     }
 
+    /// Build the [`ListContext`] of a post of `env` to `list`, whose owners and
+    /// confirmed subscriptions are `owners` and `subscriptions`.
+    pub(crate) fn list_context<'list>(
+        &self,
+        list: &'list MailingList,
+        owners: &'list [DbVal<ListOwner>],
+        subscriptions: &'list [DbVal<ListSubscription>],
+        env: &Envelope,
+        openpgp_key: Option<DbVal<ListOpenPgpKey>>,
+    ) -> Result<ListContext<'list>> {
+        let filter_settings = self.get_settings(list.pk)?;
+        let public_keys =
+            if crate::openpgp::needs_public_keys(openpgp_key.is_some(), &filter_settings) {
+                self.list_public_keys(list.pk)?
+            } else {
+                Default::default()
+            };
+        Ok(ListContext {
+            member_subscriptions: self.list_member_subscriptions(list.pk)?,
+            inherited_owners: self.list_inherited_owners(list.pk)?,
+            inherited_posters: self.list_inherited_posters(list.pk)?,
+            subscription_topics: self
+                .list_subscription_topics(list.pk)?
+                .into_iter()
+                .map(|t| (t.subscription, t.into_inner()))
+                .collect(),
+            thread_exclusions: self
+                .list_thread_exclusions(list.pk, &self.list_thread_root(list.pk, env)?)?,
+            post_policy: self.list_post_policy(list.pk)?,
+            subscription_policy: self.list_subscription_policy(list.pk)?,
+            access_entries: self.list_access_entries(list.pk)?,
+            openpgp_key,
+            public_keys,
+            token_secret: self.token_secret()?,
+            one_click_unsubscribe: None,
+            list_owners: owners,
+            subscriptions,
+            scheduled_jobs: vec![],
+            notes: vec![],
+            filter_settings,
+            list,
+        })
+    }
+
+    /// Feed `post` to `filters` in order until one stops processing, and
+    /// record a [`PostEvent`](crate::models::PostEvent) for each filter and
+    /// each job it scheduled. The detail of filter events starts with
+    /// `detail_prefix`.
+    ///
+    /// Returns a [`FilterTrace`] of each filter that ran if `trace` is set.
+    pub(crate) fn run_filters(
+        &self,
+        filters: Vec<Box<dyn PostFilter>>,
+        post: &mut PostEntry,
+        list_ctx: &mut ListContext<'_>,
+        message_id: &str,
+        detail_prefix: &str,
+        trace: bool,
+    ) -> Result<Vec<FilterTrace>> {
+        let mut filter_traces = vec![];
+        for f in filters {
+            let mut filter_trace = trace.then(|| {
+                (
+                    FilterTrace {
+                        filter: f.name().to_string(),
+                        headers_added: vec![],
+                        headers_removed: vec![],
+                        body_changed: false,
+                        action: PostAction::Hold,
+                        jobs_scheduled: vec![],
+                        notes: vec![],
+                        stopped: false,
+                    },
+                    post.bytes.clone(),
+                )
+            });
+            let jobs_before = list_ctx.scheduled_jobs.len();
+            let name = f.name();
+            let result = f.feed(post, list_ctx).map(|_| ());
+            trace!("result {:#?}", result);
+            let notes = std::mem::take(&mut list_ctx.notes);
+            self.insert_post_event(
+                Some(list_ctx.list.pk),
+                message_id,
+                PostEventKind::Filter,
+                name,
+                Some(&format!(
+                    "{detail_prefix}action: {}{}{}",
+                    post.action.name(),
+                    if result.is_err() {
+                        ", stopped processing"
+                    } else {
+                        ""
+                    },
+                    notes.iter().fold(String::new(), |acc, n| acc + "; " + n)
+                )),
+            )?;
+            for job in &list_ctx.scheduled_jobs[jobs_before..] {
+                self.insert_post_event(
+                    Some(list_ctx.list.pk),
+                    message_id,
+                    PostEventKind::Job,
+                    job.name(),
+                    Some(&job.to_string()),
+                )?;
+            }
+            if let Some((mut filter_trace, bytes)) = filter_trace.take() {
+                filter_trace.diff(&bytes, &post.bytes);
+                filter_trace.action = post.action.clone();
+                filter_trace.jobs_scheduled = list_ctx.scheduled_jobs[jobs_before..]
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                filter_trace.notes = notes;
+                filter_trace.stopped = result.is_err();
+                filter_traces.push(filter_trace);
+            }
+            if result.is_err() {
+                break;
+            }
+        }
+        Ok(filter_traces)
+    }
+
     /// Process a new mailing list post.
     ///
     /// In case multiple processes can access the database at any time, use an
     /// `EXCLUSIVE` transaction before calling this function.
     /// See [`Connection::transaction`].
-    pub fn post(&self, env: &Envelope, raw: &[u8], _dry_run: bool) -> Result<()> {
-        let result = self.inner_post(env, raw, _dry_run);
+    ///
+    /// If `dry_run` is true, the post is processed with
+    /// [`Connection::post_dry_run`] and no changes are made to the database.
+    pub fn post(&self, env: &Envelope, raw: &[u8], dry_run: bool) -> Result<()> {
+        if dry_run {
+            let trace = self.post_dry_run(env, raw)?;
+            info!("Dry run of post {}:\n{}", env.message_id(), trace);
+            return Ok(());
+        }
+        self.process_post(env, raw, None)
+    }
+
+    /// Process a new mailing list post, only for the list `only_list` if
+    /// given, and move it to the error queue if processing fails.
+    pub(crate) fn process_post(
+        &self,
+        env: &Envelope,
+        raw: &[u8],
+        only_list: Option<i64>,
+    ) -> Result<()> {
+        let result = self.inner_post(env, raw, only_list, None);
         if let Err(err) = result {
+            if let Err(err2) = self.insert_post_event(
+                None,
+                &env.message_id().to_string(),
+                PostEventKind::Error,
+                "error-queue",
+                Some(&err.to_string()),
+            ) {
+                log::error!(
+                    "Could not record error event for mail from {:?}: {err2}",
+                    env.from(),
+                );
+            }
             return match self.insert_to_queue(QueueEntry::new(
                 Queue::Error,
                 None,
@@ -119,7 +297,108 @@ impl Connection {
         result
     }
 
-    fn inner_post(&self, env: &Envelope, raw: &[u8], _dry_run: bool) -> Result<()> {
+    /// Simulate processing a new mailing list post.
+    ///
+    /// The post is processed inside a savepoint that is rolled back, and a
+    /// report of each [`PostFilter`](crate::message_filters::PostFilter)'s
+    /// effects and the queue entries that would have been created is
+    /// returned. Messages are stored whole, without writing their large parts
+    /// to the [blob store](crate::blobs).
+    pub fn post_dry_run(&self, env: &Envelope, raw: &[u8]) -> Result<PostTrace> {
+        let dry_run = self.dry_run.replace(true);
+        let ret = self.post_dry_run_inner(env, raw);
+        self.dry_run.set(dry_run);
+        ret
+    }
+
+    fn post_dry_run_inner(&self, env: &Envelope, raw: &[u8]) -> Result<PostTrace> {
+        let tx = self.savepoint(Some(stringify!(post_dry_run)))?;
+        let last_queue_pk: i64 = tx
+            .connection
+            .query_row(
+                "SELECT pk FROM queue ORDER BY pk DESC LIMIT 1;",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?
+            .unwrap_or(0);
+        let mut trace = PostTrace::default();
+        if let Err(err) = tx.inner_post(env, raw, None, Some(&mut trace)) {
+            trace.error = Some(err.to_string());
+        }
+        {
+            let mut stmt = tx.connection.prepare(
+                "SELECT which, to_addresses, subject, comment FROM queue WHERE pk > ? ORDER BY pk;",
+            )?;
+            let iter = stmt.query_map([&last_queue_pk], |row| {
+                Ok(QueueTrace {
+                    queue: row.get("which")?,
+                    to_addresses: row.get("to_addresses")?,
+                    subject: row.get("subject")?,
+                    comment: row.get("comment")?,
+                })
+            })?;
+            for entry in iter {
+                trace.queue_entries.push(entry?);
+            }
+        }
+        tx.rollback()?;
+        Ok(trace)
+    }
+
+    /// Render a post written by `from`, e.g. a list owner using the web
+    /// interface, into a message to `list` that can be submitted with
+    /// [`Connection::post`].
+    ///
+    /// `subject` and `body` are rendered with [`Template::render`], with the
+    /// list in their context. If `send_at` is given, the post has a
+    /// [`SEND_AT_HEADER`](crate::scheduled::SEND_AT_HEADER) header with it and
+    /// should be submitted with [`Connection::schedule_post`] instead, see
+    /// [`crate::scheduled`].
+    pub fn compose_post(
+        &self,
+        list: &DbVal<MailingList>,
+        from: &Address,
+        subject: &str,
+        body: &str,
+        send_at: Option<u64>,
+    ) -> Result<Vec<u8>> {
+        let template = Template {
+            pk: -1,
+            name: "compose".to_string(),
+            list: Some(list.pk),
+            subject: Some(subject.to_string()),
+            headers_json: None,
+            body: body.to_string(),
+        };
+        let mut draft = template.render(minijinja::context! {
+            list => &list,
+        })?;
+        draft.headers.remove(melib::HeaderName::CC);
+        draft.headers.remove(melib::HeaderName::BCC);
+        draft
+            .headers
+            .insert(melib::HeaderName::FROM, from.to_string());
+        draft
+            .headers
+            .insert(melib::HeaderName::TO, list.address().to_string());
+        if let Some(send_at) = send_at {
+            draft.headers.insert(
+                melib::HeaderName::try_from(crate::scheduled::SEND_AT_HEADER)
+                    .map_err(|err| Error::new_external(err.to_string()))?,
+                send_at.to_string(),
+            );
+        }
+        Ok(draft.finalise()?.into_bytes())
+    }
+
+    fn inner_post(
+        &self,
+        env: &Envelope,
+        raw: &[u8],
+        only_list: Option<i64>,
+        mut trace: Option<&mut PostTrace>,
+    ) -> Result<()> {
         trace!("Received envelope to post: {:#?}", &env);
         let tos = env.to().to_vec();
         if tos.is_empty() {
@@ -136,8 +415,13 @@ impl Connection {
                     if !addr.contains_address(&list.address()) {
                         return true;
                     }
-                    if let Err(err) = ListRequest::try_from((subaddr.as_str(), env))
-                        .and_then(|req| self.request(list, req, env, raw))
+                    if let Err(err) =
+                        ListRequest::try_from((subaddr.as_str(), env)).and_then(|req| {
+                            if let Some(trace) = trace.as_deref_mut() {
+                                trace.request = Some(req.clone());
+                            }
+                            self.request(list, req, env, raw)
+                        })
                     {
                         info!("Processing request returned error: {}", err);
                     }
@@ -159,7 +443,17 @@ impl Connection {
 
             tos.iter().any(|a| a.contains_address(&list.address()))
         });
+        if let Some(pk) = only_list {
+            lists.retain(|list| list.pk == pk);
+        }
         if lists.is_empty() {
+            if only_list.is_none() {
+                for t in &tos {
+                    if let Some(set) = self.mailing_set(t)? {
+                        return self.post_to_set(env, raw, &set, trace);
+                    }
+                }
+            }
             return Err(format!(
                 "No relevant mailing list found for these addresses: {:?}",
                 tos
@@ -168,21 +462,16 @@ impl Connection {
         }
 
         trace!("Configuration is {:#?}", &self.conf);
-        for mut list in lists {
+        let message_id = env.message_id().to_string();
+        for list in lists {
             trace!("Examining list {}", list.display_name());
             let filters = self.list_filters(&list);
-            let subscriptions = self.list_subscriptions(list.pk)?;
+            let subscriptions = self.list_confirmed_subscriptions(list.pk)?;
             let owners = self.list_owners(list.pk)?;
             trace!("List subscriptions {:#?}", &subscriptions);
-            let mut list_ctx = ListContext {
-                post_policy: self.list_post_policy(list.pk)?,
-                subscription_policy: self.list_subscription_policy(list.pk)?,
-                list_owners: &owners,
-                subscriptions: &subscriptions,
-                scheduled_jobs: vec![],
-                filter_settings: self.get_settings(list.pk)?,
-                list: &mut list,
-            };
+            let openpgp_key = self.list_openpgp_key(list.pk)?;
+            let mut list_ctx =
+                self.list_context(&list, &owners, &subscriptions, env, openpgp_key)?;
             let mut post = PostEntry {
                 message_id: env.message_id().clone(),
                 from: env.from()[0].clone(),
@@ -190,36 +479,113 @@ impl Connection {
                 to: env.to().to_vec(),
                 action: PostAction::Hold,
             };
-            let result = filters
-                .into_iter()
-                .try_fold((&mut post, &mut list_ctx), |(p, c), f| f.feed(p, c));
-            trace!("result {:#?}", result);
+            let filter_traces = self.run_filters(
+                filters,
+                &mut post,
+                &mut list_ctx,
+                &message_id,
+                "",
+                trace.is_some(),
+            )?;
 
             let PostEntry { bytes, action, .. } = post;
             trace!("Action is {:#?}", action);
+            self.insert_post_event(
+                Some(list_ctx.list.pk),
+                &message_id,
+                PostEventKind::Action,
+                action.name(),
+                action.reason(),
+            )?;
+            if let Some(trace) = trace.as_deref_mut() {
+                let mut recipients = vec![];
+                let mut digest_recipients = vec![];
+                for job in list_ctx.scheduled_jobs.iter() {
+                    match job {
+                        MailJob::Send { recipients: r } => {
+                            recipients.extend(r.iter().map(ToString::to_string))
+                        }
+                        MailJob::SendIndividual { messages } => {
+                            recipients.extend(messages.iter().map(|(r, _)| r.to_string()))
+                        }
+                        MailJob::StoreDigest { recipients: r } => {
+                            digest_recipients.extend(r.iter().map(ToString::to_string))
+                        }
+                        _ => {}
+                    }
+                }
+                trace.lists.push(ListTrace {
+                    list: list_ctx.list.id.clone(),
+                    filters: filter_traces,
+                    action: action.clone(),
+                    recipients,
+                    digest_recipients,
+                });
+            }
             let post_env = melib::Envelope::from_bytes(&bytes, None)?;
             match action {
                 PostAction::Accept => {
+                    // Posting to a thread joins it, see [`crate::threads`].
+                    let thread = self.list_thread_root(list_ctx.list.pk, &post_env)?;
                     let _post_pk = self.insert_post(list_ctx.list.pk, &bytes, &post_env)?;
                     trace!("post_pk is {:#?}", _post_pk);
+                    for f in post_env.from() {
+                        if let Ok(sub) =
+                            self.list_subscription_by_address(list_ctx.list.pk, &f.get_email())
+                        {
+                            self.join_thread(sub.pk(), &thread)?;
+                        }
+                    }
                     for job in list_ctx.scheduled_jobs.iter() {
                         trace!("job is {:#?}", &job);
-                        if let crate::mail::MailJob::Send { recipients } = job {
-                            trace!("recipients: {:?}", &recipients);
-                            if recipients.is_empty() {
-                                trace!("list has no recipients");
+                        match job {
+                            MailJob::Send { recipients } => {
+                                trace!("recipients: {:?}", &recipients);
+                                if recipients.is_empty() {
+                                    trace!("list has no recipients");
+                                }
+                                for recipient in recipients {
+                                    let mut env = post_env.clone();
+                                    env.set_to(melib::smallvec::smallvec![recipient.clone()]);
+                                    self.insert_to_queue(QueueEntry::new(
+                                        Queue::Out,
+                                        Some(list.pk),
+                                        Some(Cow::Owned(env)),
+                                        &list_ctx.recipient_copy(recipient, &bytes),
+                                        None,
+                                    )?)?;
+                                }
                             }
-                            for recipient in recipients {
-                                let mut env = post_env.clone();
-                                env.set_to(melib::smallvec::smallvec![recipient.clone()]);
-                                self.insert_to_queue(QueueEntry::new(
-                                    Queue::Out,
-                                    Some(list.pk),
-                                    Some(Cow::Owned(env)),
-                                    &bytes,
-                                    None,
-                                )?)?;
+                            MailJob::SendIndividual { messages } => {
+                                for (recipient, message) in messages {
+                                    let mut env = melib::Envelope::from_bytes(message, None)?;
+                                    env.set_to(melib::smallvec::smallvec![recipient.clone()]);
+                                    self.insert_to_queue(QueueEntry::new(
+                                        Queue::Out,
+                                        Some(list.pk),
+                                        Some(Cow::Owned(env)),
+                                        &list_ctx.recipient_copy(recipient, message),
+                                        None,
+                                    )?)?;
+                                }
+                            }
+                            MailJob::NotifyOwners { description } => {
+                                self.send_reply_with_list_template(
+                                    TemplateRenderContext {
+                                        template: Template::ADMIN_NOTICE,
+                                        default_fn: Some(Template::default_admin_notice),
+                                        list: &list,
+                                        context: minijinja::context! {
+                                            list => &list,
+                                            details => description,
+                                        },
+                                        queue: Queue::Out,
+                                        comment: description.clone().into(),
+                                    },
+                                    owners.iter().map(|owner| Cow::Owned(owner.address())),
+                                )?;
                             }
+                            _ => {}
                         }
                     }
                 }
@@ -343,8 +709,36 @@ impl Connection {
                     .as_ref()
                     .map(|p| !p.open)
                     .unwrap_or(false);
+                let access_entries = self.list_access_entries(list.pk)?;
                 for f in env.from() {
                     let email_from = f.get_email();
+                    let verdict = AccessEntry::verdict(
+                        access_entries.iter().map(|e| &**e),
+                        &email_from,
+                        AccessScope::Subscribe,
+                    );
+                    if verdict == Some(AccessKind::Deny) {
+                        trace!("subscription of {f:?} is denied by an access entry");
+                        /* send error notice to e-mail sender */
+                        self.send_reply_with_list_template(
+                            TemplateRenderContext {
+                                template: Template::GENERIC_FAILURE,
+                                default_fn: Some(Template::default_generic_failure),
+                                list,
+                                context: minijinja::context! {
+                                    list => &list,
+                                    subject => format!("Your subscription to {} was rejected.", list.id),
+                                    details => "You are not allowed to subscribe to this list.",
+                                },
+                                queue: Queue::Out,
+                                comment: format!("Address {} is denied subscription to list {}", f, list.id).into(),
+                            },
+                            std::iter::once(Cow::Borrowed(f)),
+                        )?;
+                        continue;
+                    }
+                    /* allowed addresses do not need approval */
+                    let approval_needed = approval_needed && verdict != Some(AccessKind::Allow);
                     if self
                         .list_subscription_by_address(list.pk, &email_from)
                         .is_ok()
@@ -449,46 +843,59 @@ impl Connection {
                                 )?;
                             }
                         }
-                    } else if let Err(err) = self.add_subscription(list.pk, subscription) {
-                        log::error!("Could not create subscription for {f:?}: {err}");
+                    } else {
+                        let subscription = match self.add_subscription(list.pk, subscription) {
+                            Ok(subscription) => subscription,
+                            Err(err) => {
+                                log::error!("Could not create subscription for {f:?}: {err}");
 
-                        /* send error notice to e-mail sender */
+                                /* send error notice to e-mail sender */
 
-                        self.send_reply_with_list_template(
-                            TemplateRenderContext {
-                                template: Template::GENERIC_FAILURE,
-                                default_fn: Some(Template::default_generic_failure),
-                                list,
-                                context: minijinja::context! {
-                                    list => &list,
-                                },
-                                queue: Queue::Out,
-                                comment: format!("Could not create subscription for {f:?}: {err}")
-                                    .into(),
-                            },
-                            std::iter::once(Cow::Borrowed(f)),
-                        )?;
+                                self.send_reply_with_list_template(
+                                    TemplateRenderContext {
+                                        template: Template::GENERIC_FAILURE,
+                                        default_fn: Some(Template::default_generic_failure),
+                                        list,
+                                        context: minijinja::context! {
+                                            list => &list,
+                                        },
+                                        queue: Queue::Out,
+                                        comment: format!(
+                                            "Could not create subscription for {f:?}: {err}"
+                                        )
+                                        .into(),
+                                    },
+                                    std::iter::once(Cow::Borrowed(f)),
+                                )?;
 
-                        /* send error details to list owners */
+                                /* send error details to list owners */
 
-                        let list_owners = self.list_owners(list.pk)?;
-                        self.send_reply_with_list_template(
-                            TemplateRenderContext {
-                                template: Template::ADMIN_NOTICE,
-                                default_fn: Some(Template::default_admin_notice),
-                                list,
-                                context: minijinja::context! {
-                                    list => &list,
-                                    details => err.to_string(),
-                                },
-                                queue: Queue::Out,
-                                comment: format!("Could not create subscription for {f:?}: {err}")
-                                    .into(),
-                            },
-                            list_owners.iter().map(|owner| Cow::Owned(owner.address())),
-                        )?;
-                    } else {
-                        self.send_subscription_confirmation(list, f)?;
+                                let list_owners = self.list_owners(list.pk)?;
+                                self.send_reply_with_list_template(
+                                    TemplateRenderContext {
+                                        template: Template::ADMIN_NOTICE,
+                                        default_fn: Some(Template::default_admin_notice),
+                                        list,
+                                        context: minijinja::context! {
+                                            list => &list,
+                                            details => err.to_string(),
+                                        },
+                                        queue: Queue::Out,
+                                        comment: format!(
+                                            "Could not create subscription for {f:?}: {err}"
+                                        )
+                                        .into(),
+                                    },
+                                    list_owners.iter().map(|owner| Cow::Owned(owner.address())),
+                                )?;
+                                continue;
+                            }
+                        };
+                        if subscription.verified {
+                            self.send_subscription_confirmation(list, f)?;
+                        } else {
+                            self.send_subscription_confirmation_request(list, &subscription)?;
+                        }
                     }
                 }
             }
@@ -498,7 +905,16 @@ impl Connection {
                     env.from(),
                     list
                 );
+                let confirm = self.list_confirms_unsubscription(list.pk)?;
                 for f in env.from() {
+                    if confirm {
+                        if let Ok(subscription) =
+                            self.list_subscription_by_address(list.pk, &f.get_email())
+                        {
+                            self.send_unsubscription_confirmation_request(list, &subscription)?;
+                            continue;
+                        }
+                    }
                     if let Err(err) = self.remove_subscription(list.pk, &f.get_email()) {
                         log::error!("Could not unsubscribe {f:?}: {err}");
                         /* send error notice to e-mail sender */
@@ -539,6 +955,22 @@ impl Connection {
                     }
                 }
             }
+            ListRequest::Confirm(ref token) => {
+                trace!(
+                    "confirm request for addresses {:?} in list {list}",
+                    env.from(),
+                );
+                for f in env.from() {
+                    let result = self.confirm_subscription_request(list, &f.get_email(), token);
+                    self.send_request_result(
+                        list,
+                        f,
+                        format!("Your subscription to {}", list.name),
+                        result,
+                        "Confirm request",
+                    )?;
+                }
+            }
             ListRequest::Other(ref req) if req == "owner" => {
                 trace!(
                     "list-owner mail action for addresses {:?} in list {}",
@@ -619,6 +1051,112 @@ impl Connection {
                 );
                 return Err("setting digest options via e-mail is not implemented yet.".into());
             }
+            ListRequest::ChangeTopics(ref words) => {
+                trace!(
+                    "change topics request with value {words:?} for addresses {:?} in list {list}",
+                    env.from(),
+                );
+                for f in env.from() {
+                    let result = self
+                        .list_subscription_by_address(list.pk, &f.get_email())
+                        .context("You are not subscribed to this list.")
+                        .and_then(|sub| {
+                            match crate::topics::parse_topics_request(list, sub.pk, words)? {
+                                Some(topics) => {
+                                    let topics = self.set_subscription_topics(topics)?;
+                                    Ok(format!(
+                                        "You will receive posts about: {}{}.",
+                                        topics.topics.join(", "),
+                                        if topics.everything_else {
+                                            ", and everything else"
+                                        } else {
+                                            ""
+                                        }
+                                    ))
+                                }
+                                None => {
+                                    if self.subscription_topics(sub.pk)?.is_some() {
+                                        self.remove_subscription_topics(sub.pk)?;
+                                    }
+                                    Ok("You will receive every post.".to_string())
+                                }
+                            }
+                        });
+                    self.send_request_result(
+                        list,
+                        f,
+                        format!("Your topics for {}", list.name),
+                        result,
+                        "Change topics request",
+                    )?;
+                }
+            }
+            ListRequest::ChangeThread(action) => {
+                trace!(
+                    "{action} thread request for addresses {:?} in list {list}",
+                    env.from(),
+                );
+                let thread = self.list_replied_thread_root(list.pk, env)?;
+                for f in env.from() {
+                    let result = self
+                        .list_subscription_by_address(list.pk, &f.get_email())
+                        .context("You are not subscribed to this list.")
+                        .and_then(|sub| {
+                            let thread = thread.as_deref().ok_or_else(|| {
+                                Error::new_external(format!(
+                                    "To {action} a thread, reply to one of its posts."
+                                ))
+                            })?;
+                            self.change_thread_preference(sub.pk(), thread, action)?;
+                            Ok(match action {
+                                ThreadAction::Mute => {
+                                    format!("You will not receive posts of the thread {thread}.")
+                                }
+                                ThreadAction::Unmute => {
+                                    format!("Your preference for the thread {thread} was removed.")
+                                }
+                                ThreadAction::Follow => {
+                                    format!("You will receive posts of the thread {thread}.")
+                                }
+                            })
+                        });
+                    self.send_request_result(
+                        list,
+                        f,
+                        format!("Your thread preferences for {}", list.name),
+                        result,
+                        "Change thread request",
+                    )?;
+                }
+            }
+            ListRequest::FollowOnly(value) => {
+                trace!(
+                    "follow-only {value} request for addresses {:?} in list {list}",
+                    env.from(),
+                );
+                for f in env.from() {
+                    let result = self
+                        .list_subscription_by_address(list.pk, &f.get_email())
+                        .context("You are not subscribed to this list.")
+                        .and_then(|sub| {
+                            self.set_subscription_follow_only(sub.pk(), value)?;
+                            Ok(if value {
+                                "You will receive only posts of the threads you started, joined or \
+                                 follow."
+                            } else {
+                                "You will receive posts of every thread you have not muted."
+                            }
+                            .to_string())
+                        });
+                    self.send_request_result(
+                        list,
+                        f,
+                        format!("Your thread preferences for {}", list.name),
+                        result,
+                        "Follow-only request",
+                    )?;
+                }
+            }
             ListRequest::Other(ref req) => {
                 trace!(
                     "unknown request action {req} for addresses {:?} in list {list}",
@@ -630,13 +1168,56 @@ impl Connection {
         Ok(())
     }
 
+    /// Reply to a request with its result, or with the error that prevented
+    /// it.
+    pub(crate) fn send_request_result(
+        &self,
+        list: &DbVal<MailingList>,
+        to: &Address,
+        subject: String,
+        result: Result<String>,
+        comment: &'static str,
+    ) -> Result<()> {
+        let (template, default_fn, details): (_, fn() -> Template, _) = match result {
+            Ok(details) => (
+                Template::GENERIC_SUCCESS,
+                Template::default_generic_success,
+                details,
+            ),
+            Err(err) => {
+                log::error!("{comment} of {to:?} failed: {err}");
+                (
+                    Template::GENERIC_FAILURE,
+                    Template::default_generic_failure,
+                    err.to_string(),
+                )
+            }
+        };
+        self.send_reply_with_list_template(
+            TemplateRenderContext {
+                template,
+                default_fn: Some(default_fn),
+                list,
+                context: minijinja::context! {
+                    list => &list,
+                    subject => subject,
+                    details => &details,
+                },
+                queue: Queue::Out,
+                comment: comment.into(),
+            },
+            std::iter::once(Cow::Borrowed(to)),
+        )
+    }
+
     /// Fetch all year and month values for which at least one post exists in
     /// `yyyy-mm` format.
     pub fn months(&self, list_pk: i64) -> Result<Vec<String>> {
-        let mut stmt = self.connection.prepare(
-            "SELECT DISTINCT strftime('%Y-%m', CAST(timestamp AS INTEGER), 'unixepoch') FROM post \
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT DISTINCT strftime('%Y-%m', CAST(timestamp AS INTEGER), 'unixepoch') FROM {} \
              WHERE list = ?;",
-        )?;
+            self.posts_source()?
+        ))?;
         let months_iter = stmt.query_map([list_pk], |row| {
             let val: String = row.get(0)?;
             Ok(val)
@@ -650,17 +1231,43 @@ impl Connection {
         Ok(ret)
     }
 
+    /// Fetch the posts of a list that have a header, optionally with the given
+    /// value, oldest first. Header names are case-insensitive; values must
+    /// match exactly after decoding.
+    pub fn list_posts_by_header(
+        &self,
+        list_pk: i64,
+        name: &str,
+        value: Option<&str>,
+    ) -> Result<Vec<DbVal<Post>>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT *, strftime('%Y-%m', CAST(timestamp AS INTEGER), 'unixepoch') AS month_year \
+             FROM post WHERE list = ?1 AND EXISTS (SELECT 1 FROM json_each(post.headers_json, ?2) \
+             AS h WHERE ?3 IS NULL OR h.value = ?3) ORDER BY timestamp, pk;",
+        )?;
+        let path = format!("$.\"{}\"", name.to_ascii_lowercase().replace('"', ""));
+        let iter = stmt.query_map(rusqlite::params![&list_pk, &path, &value], |row| {
+            self.post_from_row(row)
+        })?;
+        let mut ret = vec![];
+        for post in iter {
+            ret.push(post?);
+        }
+        Ok(ret)
+    }
+
     /// Find a post by its `Message-ID` email header.
     pub fn list_post_by_message_id(
         &self,
         list_pk: i64,
         message_id: &str,
     ) -> Result<Option<DbVal<Post>>> {
-        let mut stmt = self.connection.prepare(
+        let mut stmt = self.connection.prepare(&format!(
             "SELECT *, strftime('%Y-%m', CAST(timestamp AS INTEGER), 'unixepoch') AS month_year \
-             FROM post WHERE list = ?1 AND (message_id = ?2 OR message_id = ?3 OR concat('<', \
-             message_id, '>') = ?2 OR concat('<', ?3, '>') = message_id);",
-        )?;
+             FROM {} WHERE list = ?1 AND (message_id = ?2 OR message_id = ?3 OR '<' || message_id \
+             || '>' = ?2 OR '<' || ?3 || '>' = message_id);",
+            self.posts_source()?
+        ))?;
         let ret = stmt
             .query_row(
                 rusqlite::params![&list_pk, &message_id, message_id.strip_carets()],
@@ -673,10 +1280,11 @@ impl Connection {
                             envelope_from: row.get("envelope_from")?,
                             address: row.get("address")?,
                             message_id: row.get("message_id")?,
-                            message: row.get("message")?,
+                            message: self.message_from_row(row)?,
                             timestamp: row.get("timestamp")?,
                             datetime: row.get("datetime")?,
                             month_year: row.get("month_year")?,
+                            headers_json: row.get("headers_json")?,
                         },
                         pk,
                     ))
@@ -0,0 +1,468 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Export and erasure of the personal data of an e-mail address.
+//!
+//! [`Connection::personal_data`] collects everything stored about an address,
+//! and [`Connection::erase_personal_data`] removes it. Posts are not deleted:
+//! the address is replaced with a random pseudonym in their headers, and
+//! identity headers such as `From` lose their display name, while
+//! `Message-ID`, `In-Reply-To` and `References` are kept so that threads stay
+//! intact. The [post event](crate::post_events) audit trail is kept too, with
+//! the address replaced in the event details, such as delivery recipient
+//! lists. Addresses are compared case-insensitively.
+
+use log::info;
+use melib::Envelope;
+use rusqlite::OptionalExtension;
+
+use crate::{
+    errors::*,
+    models::{
+        Account, Bounce, DbVal, ListCandidateSubscription, ListOwner, ListSubscription, Post,
+        PostEvent,
+    },
+    queue::{Queue, QueueEntry},
+    Connection,
+};
+
+/// Headers whose whole value is replaced with the pseudonym if they mention
+/// an erased address.
+const IDENTITY_HEADERS: &[&str] = &["from", "sender", "reply-to"];
+
+/// Headers that are never rewritten, so that threads stay intact.
+pub(crate) const THREAD_HEADERS: &[&str] = &["message-id", "in-reply-to", "references"];
+
+/// Everything mailpot stores about an e-mail address.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PersonalData {
+    /// The e-mail address.
+    pub address: String,
+    /// The address's account, if any.
+    pub account: Option<DbVal<Account>>,
+    /// List subscriptions.
+    pub subscriptions: Vec<DbVal<ListSubscription>>,
+    /// Subscription requests.
+    pub candidate_subscriptions: Vec<DbVal<ListCandidateSubscription>>,
+    /// List ownerships.
+    pub list_ownerships: Vec<DbVal<ListOwner>>,
+    /// Bounces of the list subscriptions.
+    pub bounces: Vec<DbVal<Bounce>>,
+    /// Posts authored, including archived posts.
+    pub posts: Vec<DbVal<Post>>,
+    /// Queue entries sent from or to the address.
+    pub queue_entries: Vec<DbVal<QueueEntry>>,
+    /// Processing events of the posts authored, and events whose detail
+    /// mentions the address.
+    pub post_events: Vec<DbVal<PostEvent>>,
+}
+
+/// What [`Connection::erase_personal_data`] removed.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ErasureReport {
+    /// The address that replaced the erased address in posts.
+    pub pseudonym: String,
+    /// Whether an account was deleted.
+    pub account: bool,
+    /// Number of list subscriptions deleted.
+    pub subscriptions: usize,
+    /// Number of subscription requests deleted.
+    pub candidate_subscriptions: usize,
+    /// Number of list ownerships deleted.
+    pub list_ownerships: usize,
+    /// Number of bounce records deleted.
+    pub bounces: usize,
+    /// Number of posts whose headers were pseudonymised.
+    pub posts: usize,
+    /// Number of queue entries deleted.
+    pub queue_entries: usize,
+    /// Number of post events whose detail was pseudonymised.
+    pub post_events: usize,
+    /// Number of [blobs](crate::blobs) deleted because no message refers to
+    /// them any more.
+    pub blobs: usize,
+}
+
+pub(crate) const fn is_address_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'%' | b'+' | b'-')
+}
+
+/// Position of the first occurrence of `address` in `haystack` that is not
+/// part of a longer address, ignoring ASCII case.
+fn find_address(haystack: &[u8], address: &[u8]) -> Option<usize> {
+    if address.is_empty() || haystack.len() < address.len() {
+        return None;
+    }
+    (0..=haystack.len() - address.len()).find(|&i| {
+        let end = i + address.len();
+        haystack[i..end].eq_ignore_ascii_case(address)
+            && (i == 0 || !is_address_byte(haystack[i - 1]))
+            && (end == haystack.len() || !is_address_byte(haystack[end]))
+    })
+}
+
+/// Whether a header value such as `to_addresses` mentions `address`.
+pub fn mentions(value: &str, address: &str) -> bool {
+    find_address(value.as_bytes(), address.as_bytes()).is_some()
+}
+
+/// A `LIKE` pattern, with a backslash as its escape character, that matches
+/// values containing `address`.
+fn like_pattern(address: &str) -> String {
+    format!(
+        "%{}%",
+        address
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    )
+}
+
+/// Append `value` to `out` with every occurrence of `address` replaced with
+/// `pseudonym`.
+fn replace_address(out: &mut Vec<u8>, value: &[u8], address: &str, pseudonym: &str) {
+    let mut rest = value;
+    while let Some(pos) = find_address(rest, address.as_bytes()) {
+        out.extend_from_slice(&rest[..pos]);
+        out.extend_from_slice(pseudonym.as_bytes());
+        rest = &rest[pos + address.len()..];
+    }
+    out.extend_from_slice(rest);
+}
+
+/// Replace `address` with `pseudonym` in the headers of `message`.
+///
+/// Identity headers that mention `address` are replaced with the bare
+/// pseudonym, dropping any display name, and threading headers are left
+/// untouched. The body is kept as is.
+pub fn pseudonymise_headers(message: &[u8], address: &str, pseudonym: &str) -> Vec<u8> {
+    let (fields, body) = header_fields(message);
+
+    let mut ret = Vec::with_capacity(message.len());
+    for field in fields {
+        let name = field
+            .iter()
+            .position(|b| *b == b':')
+            .map(|colon| &field[..colon]);
+        let Some(name) = name.filter(|_| find_address(field, address.as_bytes()).is_some()) else {
+            ret.extend_from_slice(field);
+            continue;
+        };
+        let lowercase = String::from_utf8_lossy(name).trim().to_ascii_lowercase();
+        if THREAD_HEADERS.contains(&lowercase.as_str()) {
+            ret.extend_from_slice(field);
+        } else if IDENTITY_HEADERS.contains(&lowercase.as_str()) {
+            ret.extend_from_slice(name);
+            ret.extend_from_slice(format!(": <{pseudonym}>").as_bytes());
+            ret.extend_from_slice(if field.ends_with(b"\r\n") {
+                b"\r\n"
+            } else if field.ends_with(b"\n") {
+                b"\n"
+            } else {
+                b""
+            });
+        } else {
+            replace_address(&mut ret, field, address, pseudonym);
+        }
+    }
+    ret.extend_from_slice(body);
+    ret
+}
+
+/// Split `message` into its header fields, each with its folded lines and
+/// line ending, and its body. The empty line before the body is the last
+/// field.
+pub(crate) fn header_fields(message: &[u8]) -> (Vec<&[u8]>, &[u8]) {
+    let header_end = [&b"\r\n\r\n"[..], b"\n\n"]
+        .into_iter()
+        .filter_map(|sep| {
+            message
+                .windows(sep.len())
+                .position(|w| w == sep)
+                .map(|pos| pos + sep.len())
+        })
+        .min()
+        .unwrap_or(message.len());
+    let (headers, body) = message.split_at(header_end);
+
+    let mut fields: Vec<&[u8]> = vec![];
+    let mut start = 0;
+    for (i, _) in headers.iter().enumerate().filter(|(_, b)| **b == b'\n') {
+        let next = headers.get(i + 1).copied();
+        if !matches!(next, Some(b' ' | b'\t')) {
+            fields.push(&headers[start..=i]);
+            start = i + 1;
+        }
+    }
+    if start < headers.len() {
+        fields.push(&headers[start..]);
+    }
+    (fields, body)
+}
+
+impl Connection {
+    /// Primary keys of the rows of `sql`, which selects two integer columns
+    /// and takes the address as its only parameter.
+    fn address_rows(&self, sql: &str, address: &str) -> Result<Vec<(i64, i64)>> {
+        let mut stmt = self.connection.prepare(sql)?;
+        let iter = stmt.query_map([address], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(iter.collect::<std::result::Result<Vec<_>, _>>()?)
+    }
+
+    /// Collect everything stored about an e-mail address.
+    pub fn personal_data(&self, address: &str) -> Result<PersonalData> {
+        let account = match self
+            .connection
+            .query_row(
+                "SELECT address FROM account WHERE address = ? COLLATE NOCASE;",
+                [address],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+        {
+            Some(stored) => self.account_by_address(&stored)?,
+            None => None,
+        };
+
+        let mut subscriptions = vec![];
+        for (list, pk) in self.address_rows(
+            "SELECT list, pk FROM subscription WHERE address = ? COLLATE NOCASE ORDER BY pk;",
+            address,
+        )? {
+            subscriptions.push(self.list_subscription(list, pk)?);
+        }
+
+        let mut candidate_subscriptions = vec![];
+        for (_, pk) in self.address_rows(
+            "SELECT list, pk FROM candidate_subscription WHERE address = ? COLLATE NOCASE ORDER \
+             BY pk;",
+            address,
+        )? {
+            candidate_subscriptions.push(self.candidate_subscription(pk)?);
+        }
+
+        let mut list_ownerships = vec![];
+        for (list, pk) in self.address_rows(
+            "SELECT list, pk FROM owner WHERE address = ? COLLATE NOCASE ORDER BY pk;",
+            address,
+        )? {
+            list_ownerships.extend(
+                self.list_owners(list)?
+                    .into_iter()
+                    .filter(|owner| owner.pk() == pk),
+            );
+        }
+
+        let bounces = {
+            let mut stmt = self.connection.prepare(
+                "SELECT bounce.pk, bounce.subscription, bounce.count, bounce.last_bounce FROM \
+                 bounce JOIN subscription ON subscription.pk = bounce.subscription WHERE \
+                 subscription.address = ? COLLATE NOCASE ORDER BY bounce.pk;",
+            )?;
+            let iter = stmt.query_map([address], |row| {
+                let pk = row.get(0)?;
+                Ok(DbVal(
+                    Bounce {
+                        pk,
+                        subscription: row.get(1)?,
+                        count: row.get(2)?,
+                        last_bounce: row.get(3)?,
+                    },
+                    pk,
+                ))
+            })?;
+            iter.collect::<std::result::Result<Vec<_>, _>>()?
+        };
+
+        let posts = {
+            let mut stmt = self.connection.prepare(&format!(
+                "SELECT *, strftime('%Y-%m', CAST(timestamp AS INTEGER), 'unixepoch') AS \
+                 month_year FROM {} WHERE address = ? COLLATE NOCASE ORDER BY timestamp ASC;",
+                self.posts_source()?
+            ))?;
+            let iter = stmt.query_map([address], |row| self.post_from_row(row))?;
+            iter.collect::<std::result::Result<Vec<_>, _>>()?
+        };
+
+        let mut queue_entries = vec![];
+        for queue in Queue::possible_values() {
+            queue_entries.extend(self.queue(queue.parse()?)?.into_iter().filter(|entry| {
+                mentions(&entry.from_address, address) || mentions(&entry.to_addresses, address)
+            }));
+        }
+
+        let mut post_events = {
+            // `LIKE` is not available to untrusted connections.
+            let mut stmt = self
+                .connection
+                .prepare("SELECT * FROM post_event WHERE detail IS NOT NULL ORDER BY pk;")?;
+            let iter = stmt.query_map([], Self::post_event_from_row)?;
+            let mut ret = vec![];
+            for event in iter {
+                let event = event?;
+                if event
+                    .detail
+                    .as_deref()
+                    .is_some_and(|detail| mentions(detail, address))
+                {
+                    ret.push(event);
+                }
+            }
+            ret
+        };
+        for post in &posts {
+            post_events.extend(self.post_events(None, Some(&post.message_id), None)?);
+        }
+        post_events.sort_by_key(|event| event.pk());
+        post_events.dedup_by_key(|event| event.pk());
+
+        Ok(PersonalData {
+            address: address.to_string(),
+            account,
+            subscriptions,
+            candidate_subscriptions,
+            list_ownerships,
+            bounces,
+            posts,
+            queue_entries,
+            post_events,
+        })
+    }
+
+    /// Erase an e-mail address: delete its account, subscriptions,
+    /// subscription requests, list ownerships, bounces and queue entries,
+    /// replace it with a random pseudonym in the headers of every post and in
+    /// the detail of every post event that mentions it, and delete the
+    /// [blobs](crate::blobs) left unreferenced.
+    pub fn erase_personal_data(&self, address: &str) -> Result<ErasureReport> {
+        let data = self.personal_data(address)?;
+        let pseudonym = format!(
+            "anonymous-{}@invalid",
+            data_encoding::HEXLOWER.encode(&rand::random::<[u8; 8]>())
+        );
+        let mut report = ErasureReport {
+            pseudonym,
+            account: data.account.is_some(),
+            subscriptions: data.subscriptions.len(),
+            candidate_subscriptions: data.candidate_subscriptions.len(),
+            list_ownerships: data.list_ownerships.len(),
+            bounces: data.bounces.len(),
+            posts: 0,
+            queue_entries: data.queue_entries.len(),
+            post_events: 0,
+            blobs: 0,
+        };
+
+        let tx = self.savepoint(Some(stringify!(erase_personal_data)))?;
+        for entry in &data.queue_entries {
+            tx.connection
+                .execute("DELETE FROM queue WHERE pk = ?;", [entry.pk()])?;
+        }
+        for table in ["subscription", "candidate_subscription", "owner", "account"] {
+            tx.connection.execute(
+                &format!("DELETE FROM {table} WHERE address = ? COLLATE NOCASE;"),
+                [address],
+            )?;
+        }
+        for event in &data.post_events {
+            let Some(detail) = event
+                .detail
+                .as_deref()
+                .filter(|detail| mentions(detail, address))
+            else {
+                continue;
+            };
+            let mut pseudonymised = vec![];
+            replace_address(
+                &mut pseudonymised,
+                detail.as_bytes(),
+                address,
+                &report.pseudonym,
+            );
+            tx.connection.execute(
+                "UPDATE post_event SET detail = ? WHERE pk = ?;",
+                rusqlite::params![&String::from_utf8_lossy(&pseudonymised), &event.pk()],
+            )?;
+            report.post_events += 1;
+        }
+
+        let pattern = like_pattern(address);
+        for schema in self.post_schemas()? {
+            let posts = {
+                let mut stmt = tx.connection.prepare(&format!(
+                    "SELECT *, strftime('%Y-%m', CAST(timestamp AS INTEGER), 'unixepoch') AS \
+                     month_year FROM {schema}.post WHERE address = ?1 COLLATE NOCASE OR \
+                     envelope_from = ?1 COLLATE NOCASE OR headers_json LIKE ?2 ESCAPE '\\';"
+                ))?;
+                let iter = stmt.query_map(rusqlite::params![address, &pattern], |row| {
+                    self.post_from_row(row)
+                })?;
+                iter.collect::<std::result::Result<Vec<_>, _>>()?
+            };
+            for post in posts {
+                let message = pseudonymise_headers(&post.message, address, &report.pseudonym);
+                let authored = post.address.eq_ignore_ascii_case(address);
+                let envelope_from = post
+                    .envelope_from
+                    .as_deref()
+                    .filter(|from| !mentions(from, address));
+                if message == post.message
+                    && !authored
+                    && envelope_from == post.envelope_from.as_deref()
+                {
+                    continue;
+                }
+                let (stored, blobs) = self.split_message(&message)?;
+                tx.connection.execute(
+                    &format!(
+                        "UPDATE {schema}.post SET message = ?, blobs = ?, headers_json = ?, \
+                         address = ?, envelope_from = ? WHERE pk = ?;"
+                    ),
+                    rusqlite::params![
+                        &stored,
+                        &blobs,
+                        &Post::parse_headers(&message),
+                        if authored {
+                            &report.pseudonym
+                        } else {
+                            &post.address
+                        },
+                        &envelope_from.map_or_else(
+                            || post
+                                .envelope_from
+                                .as_ref()
+                                .map(|_| report.pseudonym.clone()),
+                            |from| Some(from.to_string())
+                        ),
+                        &post.pk(),
+                    ],
+                )?;
+                let env = Envelope::from_bytes(&message, None)?;
+                tx.reindex_post(&schema, post.pk(), &message, &env)?;
+                report.posts += 1;
+            }
+        }
+        tx.commit()?;
+        // Deleted queue entries may have been the last to refer to a blob.
+        report.blobs = self.gc_blobs(false)?;
+
+        info!("Erased personal data: {report:?}.");
+        Ok(report)
+    }
+}
@@ -49,6 +49,9 @@ pub enum Queue {
     Out,
     /// Error queue
     Error,
+    /// Accepted posts embargoed until their `send_at` timestamp. They are
+    /// moved to the `out` queue by a scheduling pass once the time passes.
+    Scheduled,
 }
 
 impl std::str::FromStr for Queue {
@@ -62,6 +65,7 @@ impl std::str::FromStr for Queue {
             s if s.eq_ignore_ascii_case(stringify!(Corrupt)) => Self::Corrupt,
             s if s.eq_ignore_ascii_case(stringify!(Out)) => Self::Out,
             s if s.eq_ignore_ascii_case(stringify!(Error)) => Self::Error,
+            s if s.eq_ignore_ascii_case(stringify!(Scheduled)) => Self::Scheduled,
             other => return Err(Error::new_external(format!("Invalid Queue name: {other}."))),
         })
     }
@@ -77,6 +81,7 @@ impl Queue {
             Self::Corrupt => "corrupt",
             Self::Out => "out",
             Self::Error => "error",
+            Self::Scheduled => "scheduled",
         }
     }
 
@@ -89,6 +94,7 @@ impl Queue {
             Queue::Corrupt.as_str(),
             Queue::Out.as_str(),
             Queue::Error.as_str(),
+            Queue::Scheduled.as_str(),
         ];
         VALUES
     }
@@ -125,6 +131,8 @@ pub struct QueueEntry {
     pub timestamp: u64,
     /// Datetime as string.
     pub datetime: DateTime,
+    /// Unix timestamp before which the entry must not be sent, optional.
+    pub send_at: Option<u64>,
 }
 
 impl std::fmt::Display for QueueEntry {
@@ -151,6 +159,7 @@ impl std::fmt::Debug for QueueEntry {
             )
             .field("timestamp", &self.timestamp)
             .field("datetime", &self.datetime)
+            .field("send_at", &self.send_at)
             .finish()
     }
 }
@@ -180,6 +189,7 @@ impl QueueEntry {
             message: raw.to_vec(),
             timestamp: now.timestamp() as u64,
             datetime: now,
+            send_at: None,
         })
     }
 }
@@ -188,10 +198,12 @@ impl Connection {
     /// Insert a received email into a queue.
     pub fn insert_to_queue(&self, mut entry: QueueEntry) -> Result<DbVal<QueueEntry>> {
         log::trace!("Inserting to queue: {entry}");
-        let mut stmt = self.connection.prepare(
+        let tx = self.savepoint(Some(stringify!(insert_to_queue)))?;
+        let (stored, blobs) = tx.split_message(&entry.message)?;
+        let mut stmt = tx.connection.prepare(
             "INSERT INTO queue(which, list, comment, to_addresses, from_address, subject, \
-             message_id, message, timestamp, datetime) VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?) \
-             RETURNING pk;",
+             message_id, message, timestamp, datetime, send_at, blobs) VALUES(?, ?, ?, ?, ?, ?, \
+             ?, ?, ?, ?, ?, ?) RETURNING pk;",
         )?;
         let pk = stmt.query_row(
             rusqlite::params![
@@ -202,15 +214,19 @@ impl Connection {
                 &entry.from_address,
                 &entry.subject,
                 &entry.message_id,
-                &entry.message,
+                &stored,
                 &entry.timestamp,
                 &entry.datetime,
+                &entry.send_at,
+                &blobs,
             ],
             |row| {
                 let pk: i64 = row.get("pk")?;
                 Ok(pk)
             },
         )?;
+        drop(stmt);
+        tx.commit()?;
         entry.pk = pk;
         Ok(DbVal(entry, pk))
     }
@@ -232,9 +248,10 @@ impl Connection {
                     from_address: row.get::<_, String>("from_address")?,
                     subject: row.get::<_, String>("subject")?,
                     message_id: row.get::<_, String>("message_id")?,
-                    message: row.get::<_, Vec<u8>>("message")?,
+                    message: self.message_from_row(row)?,
                     timestamp: row.get::<_, u64>("timestamp")?,
                     datetime: row.get::<_, DateTime>("datetime")?,
+                    send_at: row.get::<_, Option<u64>>("send_at")?,
                 },
                 pk,
             ))
@@ -262,9 +279,10 @@ impl Connection {
                 from_address: row.get::<_, String>("from_address")?,
                 subject: row.get::<_, String>("subject")?,
                 message_id: row.get::<_, String>("message_id")?,
-                message: row.get::<_, Vec<u8>>("message")?,
+                message: self.message_from_row(row)?,
                 timestamp: row.get::<_, u64>("timestamp")?,
                 datetime: row.get::<_, DateTime>("datetime")?,
+                send_at: row.get::<_, Option<u64>>("send_at")?,
             })
         };
         let mut stmt = if index.is_empty() {
@@ -309,12 +327,7 @@ mod tests {
 
         let tmp_dir = TempDir::new().unwrap();
         let db_path = tmp_dir.path().join("mpot.db");
-        let config = Configuration {
-            send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-            db_path,
-            data_path: tmp_dir.path().to_path_buf(),
-            administrators: vec![],
-        };
+        let config = Configuration::new(db_path);
 
         let db = Connection::open_or_create_db(config).unwrap().trusted();
         for i in 0..5 {
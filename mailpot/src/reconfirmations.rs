@@ -0,0 +1,292 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Re-confirmation campaigns for stale subscriptions.
+//!
+//! [`Connection::stale_subscriptions`] finds the enabled subscriptions of a
+//! list that were last confirmed before a given time, or that have not posted
+//! to the list since then. [`Connection::send_reconfirmation_request`] mails
+//! them a [`Reconfirmation`] token with the
+//! [`SUBSCRIPTION_RECONFIRMATION`](crate::Template::SUBSCRIPTION_RECONFIRMATION)
+//! template, which is confirmed like a subscription confirmation: by replying
+//! with a `confirm <token>` request or with the web interface link. Requests
+//! that are not confirmed before they expire disable their subscription when
+//! [`Connection::disable_unconfirmed_subscriptions`] is called, which the
+//! `flush-queue` command does.
+
+use std::borrow::Cow;
+
+use log::trace;
+use rusqlite::OptionalExtension;
+
+use crate::{
+    errors::*,
+    models::{changesets::ListSubscriptionChangeset, DbVal, ListSubscription, MailingList},
+    posts::TemplateRenderContext,
+    queue::Queue,
+    Connection, Template,
+};
+
+/// Default seconds a re-confirmation request waits for its confirmation.
+pub const RECONFIRMATION_GRACE_PERIOD: u64 = 14 * 24 * 60 * 60;
+
+/// A re-confirmation request of a subscription.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Reconfirmation {
+    /// Database primary key.
+    pub pk: i64,
+    /// Subscription foreign key (See [`ListSubscription`]).
+    pub subscription: i64,
+    /// Random token mailed to the subscriber.
+    pub token: String,
+    /// Unix timestamp after which the subscription is disabled if the request
+    /// is not confirmed.
+    pub expires: u64,
+    /// Unix timestamp of the confirmation, if confirmed.
+    pub confirmed: Option<u64>,
+    /// Whether the subscription was disabled because the request expired.
+    pub disabled: bool,
+}
+
+/// Progress of the re-confirmation requests of a list.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ReconfirmationStatus {
+    /// Requests that wait for their confirmation.
+    pub pending: usize,
+    /// Confirmed requests.
+    pub confirmed: usize,
+    /// Expired requests whose subscription was disabled.
+    pub disabled: usize,
+}
+
+impl Connection {
+    fn reconfirmation_from_row(row: &rusqlite::Row) -> rusqlite::Result<DbVal<Reconfirmation>> {
+        let pk = row.get("pk")?;
+        Ok(DbVal(
+            Reconfirmation {
+                pk,
+                subscription: row.get("subscription")?,
+                token: row.get("token")?,
+                expires: row.get("expires")?,
+                confirmed: row.get("confirmed")?,
+                disabled: row.get("disabled")?,
+            },
+            pk,
+        ))
+    }
+
+    /// Fetch the enabled subscriptions of a list that were last confirmed
+    /// before `older_than`, or that have not posted to the list since
+    /// `inactive_since`. Subscriptions with a pending confirmation or
+    /// re-confirmation are skipped.
+    pub fn stale_subscriptions(
+        &self,
+        list_pk: i64,
+        older_than: Option<u64>,
+        inactive_since: Option<u64>,
+    ) -> Result<Vec<DbVal<ListSubscription>>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT sub.pk FROM subscription AS sub LEFT JOIN reconfirmation AS r ON \
+             r.subscription = sub.pk WHERE sub.list = ?1 AND sub.enabled AND NOT (r.pk IS NOT \
+             NULL AND r.confirmed IS NULL AND NOT r.disabled) AND NOT EXISTS (SELECT 1 FROM \
+             subscription_confirmation AS conf WHERE conf.subscription = sub.pk) AND \
+             ((sub.created < ?2 AND (r.confirmed IS NULL OR r.confirmed < ?2)) OR (sub.created < \
+             ?3 AND (r.confirmed IS NULL OR r.confirmed < ?3) AND NOT EXISTS (SELECT 1 FROM post \
+             WHERE post.list = sub.list AND post.address = sub.address COLLATE NOCASE AND \
+             post.created >= ?3))) ORDER BY sub.pk;",
+        )?;
+        let pks = stmt
+            .query_map(
+                rusqlite::params![&list_pk, &older_than, &inactive_since],
+                |row| row.get::<_, i64>(0),
+            )?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        drop(stmt);
+        pks.into_iter()
+            .map(|pk| self.list_subscription(list_pk, pk))
+            .collect()
+    }
+
+    /// Create a re-confirmation request for a subscription, replacing any
+    /// previous one.
+    pub fn add_reconfirmation(
+        &self,
+        subscription_pk: i64,
+        expires: u64,
+    ) -> Result<DbVal<Reconfirmation>> {
+        let val = self.connection.query_row(
+            "INSERT OR REPLACE INTO reconfirmation(subscription, expires) VALUES(?, ?) RETURNING \
+             *;",
+            rusqlite::params![&subscription_pk, &expires],
+            Self::reconfirmation_from_row,
+        )?;
+        trace!("add_reconfirmation {:?}.", &val);
+        Ok(val)
+    }
+
+    /// Fetch the re-confirmation request of a subscription, if any.
+    pub fn reconfirmation(&self, subscription_pk: i64) -> Result<Option<DbVal<Reconfirmation>>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT * FROM reconfirmation WHERE subscription = ?;",
+                [&subscription_pk],
+                Self::reconfirmation_from_row,
+            )
+            .optional()?)
+    }
+
+    /// Fetch the subscription of a pending re-confirmation token that has not
+    /// expired by `now`.
+    pub fn pending_reconfirmation(
+        &self,
+        token: &str,
+        now: u64,
+    ) -> Result<Option<DbVal<ListSubscription>>> {
+        let Some((list, subscription_pk)) = self
+            .connection
+            .query_row(
+                "SELECT sub.list, sub.pk FROM reconfirmation AS r, subscription AS sub WHERE \
+                 r.subscription = sub.pk AND r.token = ? AND r.expires >= ? AND r.confirmed IS \
+                 NULL AND NOT r.disabled;",
+                rusqlite::params![&token.trim().to_ascii_lowercase(), &now],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)),
+            )
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        self.list_subscription(list, subscription_pk).map(Some)
+    }
+
+    /// Confirm the re-confirmation request of a token that has not expired by
+    /// `now`.
+    pub fn reconfirm_subscription(&self, token: &str, now: u64) -> Result<DbVal<ListSubscription>> {
+        let subscription = self
+            .pending_reconfirmation(token, now)?
+            .ok_or_else(|| Error::new_external("Invalid or expired confirmation."))?;
+        self.connection.execute(
+            "UPDATE reconfirmation SET confirmed = ? WHERE subscription = ?;",
+            rusqlite::params![&now, &subscription.pk],
+        )?;
+        Ok(subscription)
+    }
+
+    /// Disable the subscriptions whose re-confirmation request expired before
+    /// `now` without being confirmed, and return them.
+    pub fn disable_unconfirmed_subscriptions(
+        &self,
+        now: u64,
+    ) -> Result<Vec<DbVal<ListSubscription>>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT sub.list, sub.pk FROM reconfirmation AS r, subscription AS sub WHERE \
+             r.subscription = sub.pk AND r.expires < ? AND r.confirmed IS NULL AND NOT r.disabled;",
+        )?;
+        let expired = stmt
+            .query_map([&now], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        drop(stmt);
+        let mut ret = Vec::with_capacity(expired.len());
+        for (list, pk) in expired {
+            let subscription = self.list_subscription(list, pk)?;
+            self.update_subscription(ListSubscriptionChangeset {
+                list,
+                address: subscription.address.clone(),
+                account: None,
+                name: None,
+                digest: None,
+                enabled: Some(false),
+                verified: None,
+                hide_address: None,
+                receive_duplicates: None,
+                receive_own_posts: None,
+                receive_confirmation: None,
+            })?;
+            self.connection.execute(
+                "UPDATE reconfirmation SET disabled = 1 WHERE subscription = ?;",
+                [&pk],
+            )?;
+            trace!("disabled unconfirmed subscription {:?}", &subscription);
+            ret.push(self.list_subscription(list, pk)?);
+        }
+        Ok(ret)
+    }
+
+    /// Count the re-confirmation requests of a list by their state.
+    pub fn list_reconfirmation_status(&self, list_pk: i64) -> Result<ReconfirmationStatus> {
+        Ok(self.connection.query_row(
+            "SELECT count(*) FILTER (WHERE r.confirmed IS NULL AND NOT r.disabled), count(*) \
+             FILTER (WHERE r.confirmed IS NOT NULL), count(*) FILTER (WHERE r.disabled) FROM \
+             reconfirmation AS r, subscription AS sub WHERE r.subscription = sub.pk AND sub.list \
+             = ?;",
+            [&list_pk],
+            |row| {
+                Ok(ReconfirmationStatus {
+                    pending: row.get(0)?,
+                    confirmed: row.get(1)?,
+                    disabled: row.get(2)?,
+                })
+            },
+        )?)
+    }
+
+    /// Mail a re-confirmation request to a subscription, which expires at
+    /// `expires`.
+    pub fn send_reconfirmation_request(
+        &self,
+        list: &DbVal<MailingList>,
+        subscription: &DbVal<ListSubscription>,
+        expires: u64,
+    ) -> Result<DbVal<Reconfirmation>> {
+        let reconfirmation = self.add_reconfirmation(subscription.pk, expires)?;
+        let url = self.list_base_url(list.pk)?.map(|base_url| {
+            format!(
+                "{}/subscription/confirm/{}/",
+                base_url.trim_end_matches('/'),
+                reconfirmation.token
+            )
+        });
+        let expires = i64::try_from(reconfirmation.expires)
+            .ok()
+            .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+            .map(|d| d.to_rfc2822())
+            .unwrap_or_default();
+        self.send_reply_with_list_template(
+            TemplateRenderContext {
+                template: Template::SUBSCRIPTION_RECONFIRMATION,
+                default_fn: Some(Template::default_subscription_reconfirmation),
+                list,
+                context: minijinja::context! {
+                    list => &list,
+                    confirmation => minijinja::context! {
+                        token => &reconfirmation.token,
+                        url => url,
+                        expires => expires,
+                    },
+                },
+                queue: Queue::Out,
+                comment: format!("{} request", Template::SUBSCRIPTION_RECONFIRMATION).into(),
+            },
+            std::iter::once(Cow::Owned(subscription.address())),
+        )?;
+        Ok(reconfirmation)
+    }
+}
@@ -0,0 +1,222 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Obfuscation of e-mail addresses in public archives.
+//!
+//! Lists choose with [`ArchiveAddresses`] whether their archives show,
+//! obfuscate or hide e-mail addresses, and subscribers with
+//! [`ListSubscription::hide_address`](crate::models::ListSubscription::hide_address)
+//! always have theirs hidden. A [`Redaction`] applies both to the headers of
+//! archived posts and to the attribution lines of their bodies, such as
+//! `Alice <alice@example.com> wrote:`. `Message-ID`, `In-Reply-To` and
+//! `References` are left as is, since their values only look like addresses.
+
+use std::{borrow::Cow, collections::BTreeSet};
+
+use crate::{
+    errors::*,
+    models::ArchiveAddresses,
+    privacy::{header_fields, is_address_byte, THREAD_HEADERS},
+    Connection,
+};
+
+/// What hidden addresses are replaced with.
+pub const HIDDEN_ADDRESS: &str = "[address hidden]";
+
+/// Ranges of the e-mail addresses in `haystack`.
+fn addresses(haystack: &[u8]) -> Vec<std::ops::Range<usize>> {
+    let mut ret = vec![];
+    let mut from = 0;
+    while let Some(at) = haystack[from..]
+        .iter()
+        .position(|b| *b == b'@')
+        .map(|pos| pos + from)
+    {
+        let mut start = at;
+        while start > from && is_address_byte(haystack[start - 1]) {
+            start -= 1;
+        }
+        while start < at && haystack[start] == b'.' {
+            start += 1;
+        }
+        let mut end = at + 1;
+        while end < haystack.len() && is_address_byte(haystack[end]) {
+            end += 1;
+        }
+        while end > at + 1 && matches!(haystack[end - 1], b'.' | b'-') {
+            end -= 1;
+        }
+        if start < at && haystack[at + 1..end].contains(&b'.') {
+            ret.push(start..end);
+            from = end;
+        } else {
+            from = at + 1;
+        }
+    }
+    ret
+}
+
+/// Whether `line` introduces a quotation, e.g. `Alice <alice@example.com>
+/// wrote:`, possibly inside a quotation itself.
+fn is_attribution(line: &[u8]) -> bool {
+    let line = line.trim_ascii_end().to_ascii_lowercase();
+    line.ends_with(b"wrote:") || line.ends_with(b"writes:")
+}
+
+/// How to display the e-mail addresses of a list's archived posts.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Redaction {
+    /// The list setting.
+    pub mode: ArchiveAddresses,
+    /// Lowercase addresses of the subscribers who hide theirs.
+    pub hidden: BTreeSet<String>,
+}
+
+impl Redaction {
+    /// Whether every address is shown as is.
+    pub fn is_noop(&self) -> bool {
+        self.mode == ArchiveAddresses::Show && self.hidden.is_empty()
+    }
+
+    /// How to display `address`.
+    pub fn address<'a>(&self, address: &'a str) -> Cow<'a, str> {
+        if self.mode == ArchiveAddresses::Hide
+            || self.hidden.contains(&address.to_ascii_lowercase())
+        {
+            return Cow::Borrowed(HIDDEN_ADDRESS);
+        }
+        match (self.mode, address.rsplit_once('@')) {
+            (ArchiveAddresses::Obfuscate, Some((local_part, domain))) => {
+                Cow::Owned(format!("{local_part} at {domain}"))
+            }
+            _ => Cow::Borrowed(address),
+        }
+    }
+
+    fn redact_into(&self, input: &[u8], output: &mut Vec<u8>) {
+        let mut last = 0;
+        for range in addresses(input) {
+            output.extend_from_slice(&input[last..range.start]);
+            let address = String::from_utf8_lossy(&input[range.clone()]);
+            output.extend_from_slice(self.address(&address).as_bytes());
+            last = range.end;
+        }
+        output.extend_from_slice(&input[last..]);
+    }
+
+    fn redact_body_into(&self, body: &[u8], output: &mut Vec<u8>) {
+        for line in body.split_inclusive(|b| *b == b'\n') {
+            if is_attribution(line) {
+                self.redact_into(line, output);
+            } else {
+                output.extend_from_slice(line);
+            }
+        }
+    }
+
+    /// Redact every address in `text`, such as a header value.
+    pub fn text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.is_noop() {
+            return Cow::Borrowed(text);
+        }
+        let mut ret = Vec::with_capacity(text.len());
+        self.redact_into(text.as_bytes(), &mut ret);
+        Cow::Owned(String::from_utf8_lossy(&ret).into_owned())
+    }
+
+    /// Redact the addresses in the attribution lines of a post body.
+    pub fn body<'a>(&self, body: &'a str) -> Cow<'a, str> {
+        if self.is_noop() {
+            return Cow::Borrowed(body);
+        }
+        let mut ret = Vec::with_capacity(body.len());
+        self.redact_body_into(body.as_bytes(), &mut ret);
+        Cow::Owned(String::from_utf8_lossy(&ret).into_owned())
+    }
+
+    /// Redact the addresses in the headers, except threading headers, and
+    /// in the attribution lines of a raw message.
+    ///
+    /// The message is not decoded: addresses in encoded words of headers and
+    /// in base64 or quoted-printable body parts are left as is, which is why
+    /// archives that redact addresses do not offer raw messages (See
+    /// [`Connection::list_serves_raw_messages`]).
+    pub fn message<'a>(&self, message: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.is_noop() {
+            return Cow::Borrowed(message);
+        }
+        let (fields, body) = header_fields(message);
+        let mut ret = Vec::with_capacity(message.len());
+        for field in fields {
+            let name = field.iter().position(|b| *b == b':').map(|colon| {
+                String::from_utf8_lossy(&field[..colon])
+                    .trim()
+                    .to_ascii_lowercase()
+            });
+            if name.is_some_and(|name| THREAD_HEADERS.contains(&name.as_str())) {
+                ret.extend_from_slice(field);
+            } else {
+                self.redact_into(field, &mut ret);
+            }
+        }
+        self.redact_body_into(body, &mut ret);
+        Cow::Owned(ret)
+    }
+}
+
+impl Connection {
+    /// How the archives of a list display e-mail addresses.
+    pub fn list_archive_addresses(&self, list_pk: i64) -> Result<ArchiveAddresses> {
+        Ok(self.connection.query_row(
+            "SELECT archive_addresses FROM list WHERE pk = ?;",
+            [list_pk],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Whether the archives of a list offer raw messages for download.
+    pub fn list_archive_raw_download(&self, list_pk: i64) -> Result<bool> {
+        Ok(self.connection.query_row(
+            "SELECT archive_raw_download FROM list WHERE pk = ?;",
+            [list_pk],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Whether the archives of a list serve raw messages: raw downloads are
+    /// enabled and the list's [`Redaction`] shows every address as is.
+    pub fn list_serves_raw_messages(&self, list_pk: i64) -> Result<bool> {
+        Ok(self.list_archive_raw_download(list_pk)? && self.list_redaction(list_pk)?.is_noop())
+    }
+
+    /// The [`Redaction`] of a list's archives, from the list setting and its
+    /// subscribers' preferences.
+    pub fn list_redaction(&self, list_pk: i64) -> Result<Redaction> {
+        let mode = self.list_archive_addresses(list_pk)?;
+        let mut stmt = self.connection.prepare(
+            "SELECT address FROM subscription WHERE list = ? AND hide_address = 1 ORDER BY pk;",
+        )?;
+        let iter = stmt.query_map([list_pk], |row| row.get::<_, String>(0))?;
+        let mut hidden = BTreeSet::new();
+        for address in iter {
+            hidden.insert(address?.to_ascii_lowercase());
+        }
+        Ok(Redaction { mode, hidden })
+    }
+}
@@ -0,0 +1,212 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Retention policies for posts and queues.
+//!
+//! Lists may set a post retention period in days with
+//! [`MailingListChangeset::post_retention_days`](crate::models::changesets::MailingListChangeset::post_retention_days),
+//! and [`Configuration::queue_retention`](crate::Configuration::queue_retention)
+//! sets one for each [`Queue`]. [`Connection::gc`] deletes posts, including
+//! those in [archive databases](crate::archives), and queue entries that are
+//! older than their retention period, then the [blobs](crate::blobs) no
+//! message refers to any more.
+//!
+//! Posts are aged by their `Date` header, and queue entries by the time they
+//! were added to their queue, except [`Queue::Scheduled`] entries, which are
+//! aged by the time they were due to be sent: posts that are still embargoed
+//! are never deleted.
+
+use log::info;
+
+use crate::{errors::*, queue::Queue, Connection};
+
+/// Number of days to keep entries in each [`Queue`], or `None` to keep them
+/// forever.
+///
+/// In the configuration file:
+///
+/// ```toml
+/// [queue_retention]
+/// error = 30
+/// corrupt = 30
+/// deferred = 7
+/// ```
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct QueueRetention {
+    /// Retention of the [`Queue::Maildrop`] queue.
+    pub maildrop: Option<u64>,
+    /// Retention of the [`Queue::Hold`] queue.
+    pub hold: Option<u64>,
+    /// Retention of the [`Queue::Deferred`] queue.
+    pub deferred: Option<u64>,
+    /// Retention of the [`Queue::Corrupt`] queue.
+    pub corrupt: Option<u64>,
+    /// Retention of the [`Queue::Out`] queue.
+    pub out: Option<u64>,
+    /// Retention of the [`Queue::Error`] queue.
+    pub error: Option<u64>,
+    /// Retention of the [`Queue::Scheduled`] queue, counted from the time
+    /// entries were due to be sent.
+    pub scheduled: Option<u64>,
+}
+
+impl QueueRetention {
+    /// Number of days to keep entries in `queue`.
+    pub const fn days(&self, queue: Queue) -> Option<u64> {
+        match queue {
+            Queue::Maildrop => self.maildrop,
+            Queue::Hold => self.hold,
+            Queue::Deferred => self.deferred,
+            Queue::Corrupt => self.corrupt,
+            Queue::Out => self.out,
+            Queue::Error => self.error,
+            Queue::Scheduled => self.scheduled,
+        }
+    }
+}
+
+/// Posts of a list deleted by [`Connection::gc`].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PostsGc {
+    /// Mailing list foreign key (See
+    /// [`MailingList`](crate::models::MailingList)).
+    pub list: i64,
+    /// Mailing list List-ID.
+    pub list_id: String,
+    /// Number of posts.
+    pub posts: usize,
+}
+
+/// Entries of a queue deleted by [`Connection::gc`].
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct QueueGc {
+    /// The queue.
+    pub queue: Queue,
+    /// Number of entries.
+    pub entries: usize,
+}
+
+/// What [`Connection::gc`] deleted, or would delete in a dry run.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GcReport {
+    /// Posts, for each list that had posts to delete.
+    pub posts: Vec<PostsGc>,
+    /// Queue entries, for each queue that had entries to delete.
+    pub queues: Vec<QueueGc>,
+    /// Number of blobs (See [`Connection::gc_blobs`]).
+    pub blobs: usize,
+}
+
+impl GcReport {
+    /// Whether nothing was deleted.
+    pub fn is_empty(&self) -> bool {
+        self.posts.is_empty() && self.queues.is_empty() && self.blobs == 0
+    }
+}
+
+/// Unix timestamp `days` days before `now`.
+fn cutoff(now: u64, days: u64) -> i64 {
+    i64::try_from(now.saturating_sub(days.saturating_mul(24 * 60 * 60))).unwrap_or(i64::MAX)
+}
+
+impl Connection {
+    /// The post retention period of a list in days, if it has one.
+    pub fn list_post_retention(&self, list_pk: i64) -> Result<Option<u64>> {
+        Ok(self.connection.query_row(
+            "SELECT post_retention_days FROM list WHERE pk = ?;",
+            [list_pk],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Delete posts and queue entries older than their retention period at
+    /// unix timestamp `now` and the blobs no message refers to any more, and
+    /// report how many were deleted. With `dry_run`, only count them.
+    pub fn gc(&self, now: u64, dry_run: bool) -> Result<GcReport> {
+        let mut report = GcReport::default();
+        let tx = self.savepoint(Some(stringify!(gc)))?;
+
+        let lists = {
+            let mut stmt = tx.connection.prepare(
+                "SELECT pk, id, post_retention_days FROM list WHERE post_retention_days IS NOT \
+                 NULL ORDER BY pk;",
+            )?;
+            let iter = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, u64>(2)?,
+                ))
+            })?;
+            iter.collect::<std::result::Result<Vec<_>, _>>()?
+        };
+        let schemas = self.post_schemas()?;
+        for (list, list_id, days) in lists {
+            let cutoff = cutoff(now, days);
+            let mut posts = 0;
+            for schema in &schemas {
+                posts += tx.connection.execute(
+                    &format!("DELETE FROM {schema}.post WHERE list = ? AND timestamp < ?;"),
+                    rusqlite::params![&list, &cutoff],
+                )?;
+            }
+            if posts > 0 {
+                report.posts.push(PostsGc {
+                    list,
+                    list_id,
+                    posts,
+                });
+            }
+        }
+
+        for queue in Queue::possible_values() {
+            let queue: Queue = queue.parse()?;
+            let Some(days) = self.conf.queue_retention.days(queue) else {
+                continue;
+            };
+            let cutoff = cutoff(now, days);
+            let age = if queue == Queue::Scheduled {
+                "coalesce(send_at, timestamp)"
+            } else {
+                "timestamp"
+            };
+            let entries = tx.connection.execute(
+                &format!("DELETE FROM queue WHERE which = ? AND {age} < ?;"),
+                rusqlite::params![queue.as_str(), &cutoff],
+            )?;
+            if entries > 0 {
+                report.queues.push(QueueGc { queue, entries });
+            }
+        }
+
+        // A dry run counts the blobs that would be left unreferenced, then
+        // rolls back.
+        if dry_run {
+            report.blobs = tx.gc_blobs(true)?;
+            return Ok(report);
+        }
+        tx.commit()?;
+        report.blobs = self.gc_blobs(false)?;
+        if !report.is_empty() {
+            info!("gc deleted {report:?}.");
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,167 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Scheduled and embargoed posts.
+//!
+//! List owners can delay the delivery of a post until a release time, given
+//! as a date in RFC 2822 or RFC 3339 format or a unix timestamp (see
+//! [`parse_send_at`]). Since the `From:` header of e-mail can be forged,
+//! posts are only scheduled through authenticated paths: the web compose page
+//! and the `schedule-post` list command. A [`SEND_AT_HEADER`] header of posts
+//! received by e-mail is ignored.
+//!
+//! Scheduled posts are stored in the [`Queue::Scheduled`] queue without the
+//! header, and are neither archived nor delivered until
+//! [`Connection::release_scheduled_posts`] is called after the given time;
+//! the `flush-queue` command does so before sending the `out` queue.
+
+use std::borrow::Cow;
+
+use log::trace;
+use melib::Envelope;
+
+use crate::{
+    errors::{ErrorKind::*, *},
+    models::{DbVal, MailingList, PostEventKind},
+    queue::{Queue, QueueEntry},
+    Connection,
+};
+
+/// Header with the release time of a post.
+pub const SEND_AT_HEADER: &str = "X-Mailpot-Send-At";
+
+/// Parse a date in RFC 2822 or RFC 3339 format or a unix timestamp.
+pub fn parse_send_at(value: &str) -> Result<u64> {
+    let value = value.trim();
+    if let Ok(timestamp) = value.parse::<u64>() {
+        return Ok(timestamp);
+    }
+    chrono::DateTime::parse_from_rfc2822(value)
+        .or_else(|_| chrono::DateTime::parse_from_rfc3339(value))
+        .ok()
+        .and_then(|datetime| u64::try_from(datetime.timestamp()).ok())
+        .ok_or_else(|| {
+            Error::new_external(format!(
+                "Invalid {SEND_AT_HEADER} value {value:?}: expected an RFC 2822 or RFC 3339 date \
+                 or a unix timestamp."
+            ))
+        })
+}
+
+/// Remove the [`SEND_AT_HEADER`] header from a message.
+fn strip_send_at(raw: &[u8]) -> Result<Vec<u8>> {
+    let (headers, body) = melib::email::parser::mail(raw)
+        .map_err(|err| Error::new_external(format!("Could not parse message: {err}")))?;
+    let mut ret = Vec::with_capacity(raw.len());
+    for (h, v) in headers
+        .into_iter()
+        .filter(|(h, _)| !h.as_str().eq_ignore_ascii_case(SEND_AT_HEADER))
+    {
+        ret.extend_from_slice(h.as_str().as_bytes());
+        ret.extend_from_slice(b": ");
+        ret.extend_from_slice(v);
+        ret.extend_from_slice(b"\r\n");
+    }
+    ret.extend_from_slice(b"\r\n");
+    ret.extend_from_slice(body);
+    Ok(ret)
+}
+
+impl Connection {
+    /// Store a post in the [`Queue::Scheduled`] queue until `send_at`.
+    ///
+    /// Callers must have authenticated the post's author as a list owner.
+    pub fn schedule_post(
+        &self,
+        list: &DbVal<MailingList>,
+        env: &Envelope,
+        raw: &[u8],
+        send_at: u64,
+    ) -> Result<DbVal<QueueEntry>> {
+        let raw = strip_send_at(raw)?;
+        let mut entry = QueueEntry::new(
+            Queue::Scheduled,
+            Some(list.pk),
+            Some(Cow::Borrowed(env)),
+            &raw,
+            Some(format!("Scheduled post to {}", list.id)),
+        )?;
+        // A post to several lists is scheduled once for each of them.
+        entry.to_addresses = list.address.clone();
+        entry.send_at = Some(send_at);
+        let entry = self.insert_to_queue(entry)?;
+        trace!("Scheduled post {entry:?}");
+        self.insert_post_event(
+            Some(list.pk),
+            &env.message_id().to_string(),
+            PostEventKind::Job,
+            "schedule",
+            Some(&format!("send at {send_at}")),
+        )?;
+        Ok(entry)
+    }
+
+    /// Fetch the scheduled posts of a list, ordered by release time.
+    pub fn list_scheduled_posts(&self, list_pk: i64) -> Result<Vec<DbVal<QueueEntry>>> {
+        let mut ret = self.queue(Queue::Scheduled)?;
+        ret.retain(|entry| entry.list == Some(list_pk));
+        ret.sort_by_key(|entry| entry.send_at);
+        Ok(ret)
+    }
+
+    /// Cancel a scheduled post of a list, returning it.
+    pub fn cancel_scheduled_post(&self, list_pk: i64, pk: i64) -> Result<QueueEntry> {
+        if !self
+            .list_scheduled_posts(list_pk)?
+            .iter()
+            .any(|entry| entry.pk() == pk)
+        {
+            return Err(NotFound("Scheduled post not found.").into());
+        }
+        self.delete_from_queue(Queue::Scheduled, vec![pk])?
+            .pop()
+            .ok_or_else(|| NotFound("Scheduled post not found.").into())
+    }
+
+    /// Process the scheduled posts whose release time is not later than
+    /// `now`, returning them.
+    ///
+    /// Each post is processed again like a new post to its list, so that it
+    /// reaches the subscribers of the list at the time of its release. Posts
+    /// that fail are moved to the error queue.
+    pub fn release_scheduled_posts(&self, now: u64) -> Result<Vec<QueueEntry>> {
+        let due = self
+            .queue(Queue::Scheduled)?
+            .into_iter()
+            .filter(|entry| entry.send_at.is_some_and(|send_at| send_at <= now))
+            .map(|entry| entry.pk())
+            .collect::<Vec<i64>>();
+        if due.is_empty() {
+            return Ok(vec![]);
+        }
+        let released = self.delete_from_queue(Queue::Scheduled, due)?;
+        for entry in &released {
+            let env = Envelope::from_bytes(&entry.message, None)?;
+            if let Err(err) = self.process_post(&env, &entry.message, entry.list) {
+                log::error!("Could not release scheduled post {entry:?}: {err}");
+            }
+        }
+        Ok(released)
+    }
+}
@@ -36,6 +36,11 @@ dnl
 PRAGMA foreign_keys = true;
 PRAGMA encoding = 'UTF-8';
 
+-- post_retention_days is the number of days after which posts are deleted by
+-- the garbage collection pass, or NULL to keep them forever.
+-- archive_addresses is whether the archives show, obfuscate or hide the
+-- e-mail addresses of posts, and archive_raw_download whether they offer the
+-- raw messages for download, redacted accordingly.
 CREATE TABLE IF NOT EXISTS list (
   pk                    INTEGER PRIMARY KEY NOT NULL,
   name                  TEXT NOT NULL,
@@ -50,7 +55,11 @@ CREATE TABLE IF NOT EXISTS list (
   last_modified         INTEGER NOT NULL DEFAULT (unixepoch()),
   verify                BOOLEAN_TYPE(verify) DEFAULT BOOLEAN_TRUE(),BOOLEAN_DOCS()
   hidden                BOOLEAN_TYPE(hidden) DEFAULT BOOLEAN_FALSE(),
-  enabled               BOOLEAN_TYPE(enabled) DEFAULT BOOLEAN_TRUE()
+  enabled               BOOLEAN_TYPE(enabled) DEFAULT BOOLEAN_TRUE(),
+  confirm_unsubscription BOOLEAN_TYPE(confirm_unsubscription) DEFAULT BOOLEAN_FALSE(),
+  post_retention_days   INTEGER CHECK (post_retention_days IS NULL OR post_retention_days > 0),
+  archive_addresses     TEXT CHECK (archive_addresses IN ('show', 'obfuscate', 'hide')) NOT NULL DEFAULT 'show',
+  archive_raw_download  BOOLEAN_TYPE(archive_raw_download) DEFAULT BOOLEAN_TRUE()
 );
 
 CREATE TABLE IF NOT EXISTS owner (
@@ -115,6 +124,8 @@ CREATE TABLE IF NOT EXISTS subscription (
                           DEFAULT BOOLEAN_FALSE(),
   receive_confirmation    BOOLEAN_TYPE(receive_confirmation)
                           DEFAULT BOOLEAN_TRUE(),
+  follow_only             BOOLEAN_TYPE(follow_only)
+                          DEFAULT BOOLEAN_FALSE(),
   last_digest             INTEGER NOT NULL DEFAULT (unixepoch()),
   created                 INTEGER NOT NULL DEFAULT (unixepoch()),
   last_modified           INTEGER NOT NULL DEFAULT (unixepoch()),
@@ -147,17 +158,29 @@ CREATE TABLE IF NOT EXISTS candidate_subscription (
   UNIQUE (list, address) ON CONFLICT ROLLBACK
 );
 
+-- in_reply_to, refs and thread_root hold the parsed Message-IDs of the
+-- parent post, of the References header and of the thread root, without
+-- angle brackets, so that threads are fetched with indexed queries.
+-- headers_json maps each lowercase header name of the message to the array
+-- of its decoded values.
+-- blobs lists the message parts moved to the blob store, see the blob table.
+-- Primary keys are never reused, since posts moved to yearly archive
+-- databases keep theirs.
 CREATE TABLE IF NOT EXISTS post (
-  pk               INTEGER PRIMARY KEY NOT NULL,
+  pk               INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
   list             INTEGER NOT NULL,
   envelope_from    TEXT,
   address          TEXT NOT NULL,
   message_id       TEXT NOT NULL,
   message          BLOB NOT NULL,
   headers_json     TEXT,
+  in_reply_to      TEXT,
+  refs             TEXT,
+  thread_root      TEXT,
   timestamp        INTEGER NOT NULL DEFAULT (unixepoch()),
   datetime         TEXT NOT NULL DEFAULT (datetime()),
-  created          INTEGER NOT NULL DEFAULT (unixepoch())
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  blobs            JSON CHECK (blobs IS NULL OR json_type(blobs) = 'array')
 );
 
 CREATE TABLE IF NOT EXISTS template (
@@ -241,6 +264,11 @@ END;
 -- there until the administrator intervenes. No periodic delivery attempts
 -- are made for messages in the "hold" queue.
 
+-- ## The "scheduled" queue
+--
+-- Accepted posts whose delivery is embargoed until the unix timestamp in
+-- send_at. The flush-queue command releases them once the time passes.
+
 -- ## The "out" queue
 --
 -- Emails that must be sent as soon as possible.
@@ -254,7 +282,8 @@ CREATE TABLE IF NOT EXISTS queue (
                      'deferred',
                      'corrupt',
                      'error',
-                     'out')
+                     'out',
+                     'scheduled')
                   ) NOT NULL,
   list            INTEGER,
   comment         TEXT,
@@ -265,6 +294,8 @@ CREATE TABLE IF NOT EXISTS queue (
   message         BLOB NOT NULL,
   timestamp       INTEGER NOT NULL DEFAULT (unixepoch()),
   datetime        TEXT NOT NULL DEFAULT (datetime()),
+  send_at         INTEGER,
+  blobs           JSON CHECK (blobs IS NULL OR json_type(blobs) = 'array'),
   FOREIGN KEY (list) REFERENCES list(pk) ON DELETE CASCADE,
   UNIQUE (to_addresses, message_id) ON CONFLICT ROLLBACK
 );
@@ -277,10 +308,230 @@ CREATE TABLE IF NOT EXISTS bounce (
   FOREIGN KEY (subscription) REFERENCES subscription(pk) ON DELETE CASCADE
 );
 
+-- # Access entries
+--
+-- Allow and deny entries for posting and subscribing. Entries with a NULL
+-- 'list' value apply to every list of the instance.
+CREATE TABLE IF NOT EXISTS access_entry (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  list             INTEGER,
+  kind             TEXT CHECK (kind IN ('allow', 'deny')) NOT NULL,
+  match_type       TEXT CHECK (match_type IN ('exact', 'glob', 'domain')) NOT NULL,
+  pattern          TEXT NOT NULL,
+  post             BOOLEAN_TYPE(post) DEFAULT BOOLEAN_TRUE(),BOOLEAN_DOCS()
+  subscribe        BOOLEAN_TYPE(subscribe) DEFAULT BOOLEAN_TRUE(),
+  comment          TEXT,
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  last_modified    INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (list) REFERENCES list(pk) ON DELETE CASCADE,
+  UNIQUE (list, kind, match_type, pattern) ON CONFLICT ROLLBACK
+);
+
+-- # OpenPGP list keys
+--
+-- A list with a key is an encrypted list: posts must be encrypted to the list
+-- key, and they are re-encrypted to each subscriber's account public key
+-- before delivery.
+CREATE TABLE IF NOT EXISTS list_openpgp_key (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  list             INTEGER NOT NULL UNIQUE,
+  fingerprint      TEXT NOT NULL,
+  secret_key       TEXT NOT NULL,
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  last_modified    INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (list) REFERENCES list(pk) ON DELETE CASCADE
+);
+
+-- # Umbrella lists
+--
+-- A list includes its child lists: posts to it are also sent to the
+-- subscribers of its children, recursively. Cycles are refused on insertion.
+CREATE TABLE IF NOT EXISTS list_inclusion (
+  pk                   INTEGER PRIMARY KEY NOT NULL,
+  parent               INTEGER NOT NULL,
+  child                INTEGER NOT NULL,
+  inherit_post_rights  BOOLEAN_TYPE(inherit_post_rights)
+                       DEFAULT BOOLEAN_FALSE(),BOOLEAN_DOCS()
+  created              INTEGER NOT NULL DEFAULT (unixepoch()),
+  last_modified        INTEGER NOT NULL DEFAULT (unixepoch()),
+  CHECK (parent != child),
+  FOREIGN KEY (parent) REFERENCES list(pk) ON DELETE CASCADE,
+  FOREIGN KEY (child) REFERENCES list(pk) ON DELETE CASCADE,
+  UNIQUE (parent, child) ON CONFLICT ROLLBACK
+);
+
+-- # Subscription topics
+--
+-- The topics a subscriber chooses to receive posts about. Subscriptions
+-- without a row here receive every post.
+CREATE TABLE IF NOT EXISTS subscription_topics (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  subscription     INTEGER NOT NULL UNIQUE,
+  topics           JSON NOT NULL CHECK (json_type(topics) = 'array') DEFAULT '[]',
+  everything_else  BOOLEAN_TYPE(everything_else)
+                   DEFAULT BOOLEAN_TRUE(),BOOLEAN_DOCS()
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  last_modified    INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (subscription) REFERENCES subscription(pk) ON DELETE CASCADE
+);
+
+-- # Thread preferences
+--
+-- Threads a subscriber muted or follows, identified by the Message-ID of
+-- their root post. Subscriptions in follow-only mode only receive posts of
+-- the threads they follow.
+CREATE TABLE IF NOT EXISTS thread_preference (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  subscription     INTEGER NOT NULL,
+  thread           TEXT NOT NULL,
+  muted            BOOLEAN_TYPE(muted)
+                   DEFAULT BOOLEAN_FALSE(),BOOLEAN_DOCS()
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  last_modified    INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (subscription) REFERENCES subscription(pk) ON DELETE CASCADE,
+  UNIQUE (subscription, thread)
+);
+
+-- # Token secret
+--
+-- Key of the HMAC signatures of the subscription tokens in personalised
+-- links, which let subscribers manage their subscription without logging
+-- in. Only the most recent secret is used.
+CREATE TABLE IF NOT EXISTS token_secret (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  secret           BLOB NOT NULL DEFAULT (randomblob(32)),
+  created          INTEGER NOT NULL DEFAULT (unixepoch())
+);
+
+INSERT INTO token_secret DEFAULT VALUES;
+
+-- # Subscription confirmations
+--
+-- Pending confirmations of e-mail subscriptions to lists that require
+-- verified addresses (double opt-in). The token is mailed to the subscriber,
+-- who confirms by replying or by visiting a web link before it expires.
+CREATE TABLE IF NOT EXISTS subscription_confirmation (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  subscription     INTEGER NOT NULL UNIQUE,
+  token            TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+  expires          INTEGER NOT NULL,
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (subscription) REFERENCES subscription(pk) ON DELETE CASCADE
+);
+
+-- # Unsubscription confirmations
+--
+-- Pending confirmations of e-mail unsubscription requests to lists with the
+-- confirm_unsubscription flag, so that forged requests can't remove a
+-- subscription. The tokens are short-lived.
+CREATE TABLE IF NOT EXISTS unsubscription_confirmation (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  subscription     INTEGER NOT NULL UNIQUE,
+  token            TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+  expires          INTEGER NOT NULL,
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (subscription) REFERENCES subscription(pk) ON DELETE CASCADE
+);
+
+-- # Re-confirmation campaigns
+--
+-- Re-confirmation requests mailed to stale subscriptions. A request is
+-- pending until the subscriber confirms it, which sets confirmed to the
+-- time of the confirmation, or until it expires, after which the
+-- subscription is disabled and disabled is set.
+CREATE TABLE IF NOT EXISTS reconfirmation (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  subscription     INTEGER NOT NULL UNIQUE,
+  token            TEXT NOT NULL UNIQUE DEFAULT (lower(hex(randomblob(16)))),
+  expires          INTEGER NOT NULL,
+  confirmed        INTEGER,
+  disabled         BOOLEAN_TYPE(disabled) DEFAULT BOOLEAN_FALSE(),
+  created          INTEGER NOT NULL DEFAULT (unixepoch()),
+  FOREIGN KEY (subscription) REFERENCES subscription(pk) ON DELETE CASCADE
+);
+
+-- # Post events
+--
+-- Audit trail of how each processed message was handled: every filter
+-- decision, the final post action, the scheduled mail jobs and the delivery
+-- outcomes.
+CREATE TABLE IF NOT EXISTS post_event (
+  pk              INTEGER PRIMARY KEY NOT NULL,
+  list            INTEGER,
+  message_id      TEXT NOT NULL,
+  kind            TEXT
+                  CHECK (
+                    kind IN
+                    ('filter',
+                     'action',
+                     'job',
+                     'delivery',
+                     'error')
+                  ) NOT NULL,
+  name            TEXT NOT NULL,
+  detail          TEXT,
+  timestamp       INTEGER NOT NULL DEFAULT (unixepoch()),
+  datetime        TEXT NOT NULL DEFAULT (datetime()),
+  FOREIGN KEY (list) REFERENCES list(pk) ON DELETE CASCADE
+);
+
+-- # Post tombstones
+--
+-- Posts removed from the archive by the list owners. The post row is kept
+-- with its thread columns, but its message is replaced by a placeholder, so
+-- that replies still thread under it. Rows are keyed by list and Message-ID
+-- without angle brackets, since the post might be in an archive database.
+CREATE TABLE IF NOT EXISTS post_tombstone (
+  pk               INTEGER PRIMARY KEY NOT NULL,
+  list             INTEGER NOT NULL,
+  message_id       TEXT NOT NULL,
+  reason           TEXT NOT NULL,
+  removed_by       TEXT NOT NULL,
+  timestamp        INTEGER NOT NULL DEFAULT (unixepoch()),
+  datetime         TEXT NOT NULL DEFAULT (datetime()),
+  FOREIGN KEY (list) REFERENCES list(pk) ON DELETE CASCADE,
+  UNIQUE (list, message_id)
+);
+
+-- # Blob store
+--
+-- Large message parts stored once under the blobs directory of data_path,
+-- named by the hex SHA-256 hash of their bytes. The message column of a post
+-- or queue entry then omits those parts, and its blobs column is a JSON
+-- array of objects with the offset in message where each part is spliced
+-- back and the hash of the part.
+CREATE TABLE IF NOT EXISTS blob (
+  hash             TEXT PRIMARY KEY NOT NULL,
+  size             INTEGER NOT NULL,
+  created          INTEGER NOT NULL DEFAULT (unixepoch())
+);
+
+-- # Full-text search of posts
+--
+-- The subject, sender and decoded plain text body of each post, indexed by
+-- post primary key. Rows are added when a post is inserted and removed by
+-- the remove_post_fts trigger.
+CREATE VIRTUAL TABLE IF NOT EXISTS post_fts USING fts5 (
+  subject,
+  sender,
+  body,
+  tokenize = 'unicode61 remove_diacritics 2'
+);
+
 CREATE INDEX IF NOT EXISTS post_listpk_idx ON post(list);
 CREATE INDEX IF NOT EXISTS post_msgid_idx ON post(message_id);
+CREATE INDEX IF NOT EXISTS post_in_reply_to_idx ON post(list, in_reply_to);
+CREATE INDEX IF NOT EXISTS post_thread_root_idx ON post(list, thread_root);
 CREATE INDEX IF NOT EXISTS list_idx ON list(id);
 CREATE INDEX IF NOT EXISTS subscription_idx ON subscription(address);
+CREATE INDEX IF NOT EXISTS access_entry_list_idx ON access_entry(list);
+-- NULL values are distinct in UNIQUE constraints, so instance-wide access
+-- entries are deduplicated with a partial index instead.
+CREATE UNIQUE INDEX IF NOT EXISTS access_entry_instance_idx ON access_entry(kind, match_type, pattern) WHERE list IS NULL;
+CREATE INDEX IF NOT EXISTS post_event_msgid_idx ON post_event(message_id);
+CREATE INDEX IF NOT EXISTS post_event_list_idx ON post_event(list);
+CREATE INDEX IF NOT EXISTS list_inclusion_parent_idx ON list_inclusion(parent);
+CREATE INDEX IF NOT EXISTS thread_preference_thread_idx ON thread_preference(thread);
 
 -- TAG(accept_candidate): Update candidacy with 'subscription' foreign key on
 -- 'subscription' insert.
@@ -340,6 +591,19 @@ update_last_modified(`candidate_subscription')
 update_last_modified(`template')
 update_last_modified(`settings_json_schema')
 update_last_modified(`list_settings_json')
+update_last_modified(`access_entry')
+update_last_modified(`list_openpgp_key')
+update_last_modified(`list_inclusion')
+update_last_modified(`subscription_topics')
+update_last_modified(`thread_preference')
+
+CREATE TRIGGER
+IF NOT EXISTS remove_post_fts
+AFTER DELETE ON post
+FOR EACH ROW
+BEGIN
+  DELETE FROM post_fts WHERE rowid = OLD.pk;
+END;
 
 CREATE TRIGGER
 IF NOT EXISTS sort_topics_update_trigger
@@ -0,0 +1,234 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Full-text search of list archives.
+//!
+//! The subject, sender and decoded plain text body of every post are stored
+//! in the `post_fts` SQLite FTS5 table by [`Connection::insert_post`], and
+//! removed along with their post by a trigger. [Archives](crate::archives)
+//! have their own `post_fts` table, and [`Connection::search_posts`] queries
+//! all of them.
+
+use log::trace;
+use melib::Envelope;
+
+use crate::{
+    errors::*,
+    models::{DbVal, Post},
+    Connection,
+};
+
+/// A page of search results.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Paging {
+    /// Number of results to skip.
+    pub offset: usize,
+    /// Maximum number of results.
+    pub limit: usize,
+}
+
+impl Default for Paging {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            limit: 50,
+        }
+    }
+}
+
+/// A post matching a search query.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct PostSearchResult {
+    /// The matching post.
+    pub post: DbVal<Post>,
+    /// The post's subject.
+    pub subject: String,
+    /// An excerpt of the post's body around the matching terms.
+    pub snippet: String,
+}
+
+/// The subject of a post, as indexed.
+pub fn subject(env: &Envelope) -> Option<String> {
+    Some(env.subject().to_string())
+}
+
+/// The sender of a post, as indexed.
+pub fn sender(env: &Envelope) -> Option<String> {
+    Some(env.field_from_to_string())
+}
+
+/// The decoded plain text body of a post, as indexed.
+pub fn body_text(env: &Envelope, message: &[u8]) -> Option<String> {
+    Some(
+        env.body_bytes(message)
+            .text(melib::attachment_types::Text::Plain),
+    )
+}
+
+/// Turn free text into an FTS5 query that matches posts containing all its
+/// words. A trailing `*` on a word matches it as a prefix. Returns `None` if
+/// there are no words.
+pub fn fts_query(query: &str) -> Option<String> {
+    let terms = query
+        .split_whitespace()
+        .filter_map(|word| {
+            let (word, prefix) = word
+                .strip_suffix('*')
+                .map_or((word, ""), |word| (word, "*"));
+            if word.is_empty() {
+                return None;
+            }
+            Some(format!("\"{}\"{prefix}", word.replace('"', "\"\"")))
+        })
+        .collect::<Vec<_>>();
+    if terms.is_empty() {
+        return None;
+    }
+    Some(terms.join(" "))
+}
+
+impl Connection {
+    /// Add a post to the full-text search index.
+    pub(crate) fn index_post(&self, post_pk: i64, message: &[u8], env: &Envelope) -> Result<()> {
+        self.connection.execute(
+            "INSERT INTO post_fts(rowid, subject, sender, body) VALUES (?, ?, ?, ?);",
+            rusqlite::params![
+                &post_pk,
+                &subject(env),
+                &sender(env),
+                &body_text(env, message),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Replace the full-text search index entry of a post of `schema`, the
+    /// main database or an [archive](crate::archives), if the schema has an
+    /// index.
+    pub(crate) fn reindex_post(
+        &self,
+        schema: &str,
+        post_pk: i64,
+        message: &[u8],
+        env: &Envelope,
+    ) -> Result<()> {
+        if !self.has_post_fts(schema)? {
+            return Ok(());
+        }
+        self.connection.execute(
+            &format!("DELETE FROM {schema}.post_fts WHERE rowid = ?;"),
+            [post_pk],
+        )?;
+        self.connection.execute(
+            &format!(
+                "INSERT INTO {schema}.post_fts(rowid, subject, sender, body) VALUES (?, ?, ?, ?);"
+            ),
+            rusqlite::params![
+                &post_pk,
+                &subject(env),
+                &sender(env),
+                &body_text(env, message),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Search the posts of a list, best matches first. See [`fts_query`] for
+    /// the query syntax.
+    pub fn search_posts(
+        &self,
+        list_pk: i64,
+        query: &str,
+        paging: Paging,
+    ) -> Result<Vec<PostSearchResult>> {
+        self.search_posts_in(list_pk, fts_query(query), paging)
+    }
+
+    /// Like [`Connection::search_posts`], but only match the subject and body
+    /// of posts, for archives that hide or obfuscate the addresses of their
+    /// senders (See [`Redaction`](crate::redaction::Redaction)).
+    pub fn search_posts_without_sender(
+        &self,
+        list_pk: i64,
+        query: &str,
+        paging: Paging,
+    ) -> Result<Vec<PostSearchResult>> {
+        self.search_posts_in(
+            list_pk,
+            fts_query(query).map(|query| format!("{{subject body}} : ({query})")),
+            paging,
+        )
+    }
+
+    fn search_posts_in(
+        &self,
+        list_pk: i64,
+        query: Option<String>,
+        paging: Paging,
+    ) -> Result<Vec<PostSearchResult>> {
+        let Some(query) = query else {
+            return Ok(vec![]);
+        };
+        let mut selects = vec![];
+        for schema in self.post_schemas()? {
+            if !self.has_post_fts(&schema)? {
+                continue;
+            }
+            let Some(columns) = self.post_select_columns(&schema)? else {
+                continue;
+            };
+            selects.push(format!(
+                "SELECT {columns}, post_fts.subject AS fts_subject, snippet(post_fts, 2, '', '', \
+                 '...', 24) AS fts_snippet, post_fts.rank AS fts_rank FROM {schema}.post_fts JOIN \
+                 {schema}.post AS post ON post.pk = post_fts.rowid WHERE post_fts MATCH ?1 AND \
+                 post.list = ?2"
+            ));
+        }
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT *, strftime('%Y-%m', CAST(timestamp AS INTEGER), 'unixepoch') AS month_year \
+             FROM ({}) ORDER BY fts_rank, pk LIMIT ?3 OFFSET ?4;",
+            selects.join(" UNION ALL ")
+        ))?;
+        let iter = stmt.query_map(
+            rusqlite::params![
+                &query,
+                &list_pk,
+                &i64::try_from(paging.limit).unwrap_or(i64::MAX),
+                &i64::try_from(paging.offset).unwrap_or(i64::MAX),
+            ],
+            |row| {
+                Ok(PostSearchResult {
+                    post: self.post_from_row(row)?,
+                    subject: row
+                        .get::<_, Option<String>>("fts_subject")?
+                        .unwrap_or_default(),
+                    snippet: row
+                        .get::<_, Option<String>>("fts_snippet")?
+                        .unwrap_or_default(),
+                })
+            },
+        )?;
+        let mut ret = vec![];
+        for result in iter {
+            ret.push(result?);
+        }
+        trace!("search_posts {:?} returned {} results.", query, ret.len());
+        Ok(ret)
+    }
+}
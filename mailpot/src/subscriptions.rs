@@ -639,12 +639,7 @@ mod tests {
 
         let tmp_dir = TempDir::new().unwrap();
         let db_path = tmp_dir.path().join("mpot.db");
-        let config = Configuration {
-            send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-            db_path,
-            data_path: tmp_dir.path().to_path_buf(),
-            administrators: vec![],
-        };
+        let config = Configuration::new(db_path);
 
         let db = Connection::open_or_create_db(config).unwrap().trusted();
         let list = db
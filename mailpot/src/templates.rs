@@ -63,6 +63,11 @@ impl Template {
     pub const SUBSCRIPTION_CONFIRMATION: &'static str = "subscription-confirmation";
     /// Template name for unsubscription confirmation e-mail.
     pub const UNSUBSCRIPTION_CONFIRMATION: &'static str = "unsubscription-confirmation";
+    /// Template name for requests to confirm an unsubscription.
+    pub const UNSUBSCRIPTION_CONFIRMATION_REQUEST: &'static str =
+        "unsubscription-confirmation-request";
+    /// Template name for re-confirmation requests of stale subscriptions.
+    pub const SUBSCRIPTION_RECONFIRMATION: &'static str = "subscription-reconfirmation";
     /// Template name for subscription request notice e-mail (for list owners).
     pub const SUBSCRIPTION_REQUEST_NOTICE_OWNER: &'static str = "subscription-notice-owner";
     /// Template name for subscription request acceptance e-mail (for the
@@ -124,19 +129,31 @@ impl Template {
     }
 
     /// Create a plain template for subscription confirmation.
+    ///
+    /// If the context has a `confirmation`, the subscription must be confirmed
+    /// first (see [`confirmations`](crate::confirmations)), and the subject
+    /// ends with the `confirm <token>` command that a reply sends.
     pub fn default_subscription_confirmation() -> Self {
         Self {
             pk: -1,
             name: Self::SUBSCRIPTION_CONFIRMATION.to_string(),
             list: None,
             subject: Some(
-                "{% if list and (list.id or list.name) %}{% if list.id %}[{{ list.id }}] {% endif \
-                 %}You have successfully subscribed to {{ list.name if list.name else list.id \
-                 }}{% else %}You have successfully subscribed to this list{% endif %}."
+                "{% if list and list.id %}[{{ list.id }}] {% endif %}{% if confirmation %}Please \
+                 confirm your subscription to {{ list.name if list and list.name else \"this \
+                 list\" }}: confirm {{ confirmation.token }}{% elif list and (list.id or \
+                 list.name) %}You have successfully subscribed to {{ list.name if list.name else \
+                 list.id }}.{% else %}You have successfully subscribed to this list.{% endif %}"
                     .to_string(),
             ),
             headers_json: None,
-            body: "{{ details|safe if details else \"\" }}".to_string(),
+            body: "{% if confirmation %}Someone, hopefully you, asked to subscribe this address \
+                   to {{ list.name if list and list.name else \"this list\" }}.\n\nTo confirm, \
+                   reply to this e-mail without changing its subject{% if confirmation.url %}, or \
+                   visit {{ confirmation.url }}{% endif %}. The request expires on {{ \
+                   confirmation.expires }}.\n\nIf you did not ask to subscribe, ignore this \
+                   e-mail.{% else %}{{ details|safe if details else \"\" }}{% endif %}"
+                .to_string(),
         }
     }
 
@@ -157,6 +174,55 @@ impl Template {
         }
     }
 
+    /// Create a plain template for requests to confirm an unsubscription
+    /// (see [`confirmations`](crate::confirmations)).
+    ///
+    /// The subject ends with the `confirm <token>` command that a reply sends.
+    pub fn default_unsubscription_confirmation_request() -> Self {
+        Self {
+            pk: -1,
+            name: Self::UNSUBSCRIPTION_CONFIRMATION_REQUEST.to_string(),
+            list: None,
+            subject: Some(
+                "{% if list and list.id %}[{{ list.id }}] {% endif %}Please confirm your \
+                 unsubscription from {{ list.name if list and list.name else \"this list\" }}: \
+                 confirm {{ confirmation.token }}"
+                    .to_string(),
+            ),
+            headers_json: None,
+            body: "Someone, hopefully you, asked to unsubscribe this address from {{ list.name if \
+                   list and list.name else \"this list\" }}.\n\nTo confirm, reply to this e-mail \
+                   without changing its subject{% if confirmation.url %}, or visit {{ \
+                   confirmation.url }}{% endif %}. The request expires on {{ confirmation.expires \
+                   }}.\n\nIf you did not ask to unsubscribe, ignore this e-mail and you will stay \
+                   subscribed."
+                .to_string(),
+        }
+    }
+
+    /// Create a plain template for re-confirmation requests of stale
+    /// subscriptions.
+    pub fn default_subscription_reconfirmation() -> Self {
+        Self {
+            pk: -1,
+            name: Self::SUBSCRIPTION_RECONFIRMATION.to_string(),
+            list: None,
+            subject: Some(
+                "{% if list and list.id %}[{{ list.id }}] {% endif %}Do you still want to receive \
+                 {{ list.name if list and list.name else \"this list\" }}? confirm {{ \
+                 confirmation.token }}"
+                    .to_string(),
+            ),
+            headers_json: None,
+            body: "This address is subscribed to {{ list.name if list and list.name else \"this \
+                   list\" }}, but it has not been confirmed in a while.\n\nTo keep receiving the \
+                   list, reply to this e-mail without changing its subject{% if confirmation.url \
+                   %}, or visit {{ confirmation.url }}{% endif %}. If you do not confirm by {{ \
+                   confirmation.expires }}, the subscription will be disabled."
+                .to_string(),
+        }
+    }
+
     /// Create a plain template for admin notices.
     pub fn default_admin_notice() -> Self {
         Self {
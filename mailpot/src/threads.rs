@@ -0,0 +1,358 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Thread mute and follow-only subscriptions.
+//!
+//! Posts are stored with the `Message-ID`s of their parent ([`in_reply_to`])
+//! and thread root, so that threads are fetched with indexed queries (see
+//! [`Connection::list_thread`]).
+//!
+//! Subscribers can mute a thread so that they don't receive its posts, or
+//! switch their subscription to follow-only mode, so that they only receive
+//! the threads they started, joined by posting to them, or chose to follow.
+//! Threads are identified by the `Message-ID` of their root post, see
+//! [`thread_root`].
+//!
+//! Thread preferences can be changed from the web post page, or by replying
+//! to a post of the thread with an e-mail to the list's request address with
+//! one of the subjects `mute`, `unmute` or `follow` (see [`ThreadAction`]).
+//! The subjects `follow-only` and `follow-all` switch between follow-only
+//! mode and receiving every thread.
+
+use std::collections::HashSet;
+
+use log::trace;
+use melib::Envelope;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{ErrorKind::*, *},
+    models::{DbVal, ThreadPreference},
+    Connection,
+};
+
+/// A change to a subscriber's preference for a thread.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThreadAction {
+    /// Don't receive posts of the thread.
+    Mute,
+    /// Forget a previous preference for the thread.
+    Unmute,
+    /// Receive posts of the thread, even in follow-only mode.
+    Follow,
+}
+
+impl std::fmt::Display for ThreadAction {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            fmt,
+            "{}",
+            match self {
+                Self::Mute => "mute",
+                Self::Unmute => "unmute",
+                Self::Follow => "follow",
+            }
+        )
+    }
+}
+
+/// The `Message-ID` a post replies to, from its `In-Reply-To` header or the
+/// last entry of its `References` header.
+pub fn in_reply_to(env: &Envelope) -> Option<String> {
+    env.in_reply_to()
+        .and_then(|r| r.refs().first().map(ToString::to_string))
+        .or_else(|| env.references().last().map(ToString::to_string))
+}
+
+/// The `Message-ID`s of a post's `References` header, separated by spaces,
+/// or `None` if it has none.
+pub fn references(env: &Envelope) -> Option<String> {
+    let refs = env.references();
+    if refs.is_empty() {
+        return None;
+    }
+    Some(
+        refs.iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+/// The root `Message-ID` of the thread a reply belongs to, resolved from its
+/// `References` and `In-Reply-To` headers, or `None` if it is not a reply.
+pub fn replied_thread_root(env: &Envelope) -> Option<String> {
+    if let Some(root) = env.references().first() {
+        return Some(root.to_string());
+    }
+    env.in_reply_to()
+        .and_then(|r| r.refs().first().map(ToString::to_string))
+}
+
+/// The root `Message-ID` of the thread a post belongs to; posts that are not
+/// replies start their own thread.
+pub fn thread_root(env: &Envelope) -> String {
+    replied_thread_root(env).unwrap_or_else(|| env.message_id().to_string())
+}
+
+impl Connection {
+    /// The root `Message-ID` of the thread a reply to a list belongs to, or
+    /// `None` if it is not a reply.
+    ///
+    /// Replies inherit the thread root stored with their parent post, if it
+    /// is known, including archived posts; otherwise it is resolved from the
+    /// headers with [`replied_thread_root`].
+    pub fn list_replied_thread_root(&self, list_pk: i64, env: &Envelope) -> Result<Option<String>> {
+        let Some(parent) = in_reply_to(env) else {
+            return Ok(replied_thread_root(env));
+        };
+        let stored = self
+            .connection
+            .query_row(
+                &format!(
+                    "SELECT thread_root FROM {} WHERE list = ? AND message_id = ? AND \
+                     thread_root IS NOT NULL;",
+                    self.posts_source()?
+                ),
+                rusqlite::params![&list_pk, &parent],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        Ok(stored.or_else(|| replied_thread_root(env)))
+    }
+
+    /// The root `Message-ID` of the thread a post to a list belongs to, see
+    /// [`Connection::list_replied_thread_root`]; posts that are not replies
+    /// start their own thread.
+    pub fn list_thread_root(&self, list_pk: i64, env: &Envelope) -> Result<String> {
+        Ok(self
+            .list_replied_thread_root(list_pk, env)?
+            .unwrap_or_else(|| env.message_id().to_string()))
+    }
+
+    /// Fetch the subscriptions of a list that don't receive posts of a
+    /// thread, because they muted it, or because they are in follow-only mode
+    /// and don't follow it.
+    pub fn list_thread_exclusions(&self, list_pk: i64, thread: &str) -> Result<HashSet<i64>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT subscription.pk FROM subscription LEFT JOIN thread_preference ON \
+             thread_preference.subscription = subscription.pk AND thread_preference.thread = ?2 \
+             WHERE subscription.list = ?1 AND (thread_preference.muted = 1 OR \
+             (subscription.follow_only = 1 AND thread_preference.pk IS NULL));",
+        )?;
+        let iter = stmt.query_map(rusqlite::params![&list_pk, &thread], |row| {
+            row.get::<_, i64>(0)
+        })?;
+        let mut ret = HashSet::new();
+        for pk in iter {
+            ret.insert(pk?);
+        }
+        Ok(ret)
+    }
+
+    /// Fetch the thread preferences of a subscription.
+    pub fn subscription_thread_preferences(
+        &self,
+        subscription_pk: i64,
+    ) -> Result<Vec<DbVal<ThreadPreference>>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT * FROM thread_preference WHERE subscription = ? ORDER BY pk;")?;
+        let iter = stmt.query_map([&subscription_pk], Self::thread_preference_from_row)?;
+
+        let mut ret = vec![];
+        for entry in iter {
+            ret.push(entry?);
+        }
+        Ok(ret)
+    }
+
+    /// Fetch the preference of a subscription for a thread, if any.
+    pub fn thread_preference(
+        &self,
+        subscription_pk: i64,
+        thread: &str,
+    ) -> Result<Option<DbVal<ThreadPreference>>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT * FROM thread_preference WHERE subscription = ? AND thread = ?;")?;
+        let ret = match stmt.query_row(
+            rusqlite::params![&subscription_pk, &thread],
+            Self::thread_preference_from_row,
+        ) {
+            Ok(v) => Some(v),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(err) => return Err(err.into()),
+        };
+        Ok(ret)
+    }
+
+    /// Set the preference of a subscription for a thread, replacing any
+    /// previous one.
+    pub fn set_thread_preference(
+        &self,
+        new_val: ThreadPreference,
+    ) -> Result<DbVal<ThreadPreference>> {
+        let mut stmt = self.connection.prepare(
+            "INSERT INTO thread_preference(subscription, thread, muted) VALUES (?, ?, ?) ON \
+             CONFLICT(subscription, thread) DO UPDATE SET muted = excluded.muted RETURNING *;",
+        )?;
+        let ret = stmt
+            .query_row(
+                rusqlite::params![&new_val.subscription, &new_val.thread, &new_val.muted],
+                Self::thread_preference_from_row,
+            )
+            .map_err(|err| {
+                if matches!(
+                    err,
+                    rusqlite::Error::SqliteFailure(
+                        rusqlite::ffi::Error {
+                            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+                            extended_code: 787
+                        },
+                        _
+                    )
+                ) {
+                    Error::from(err)
+                        .chain_err(|| NotFound("Could not find a subscription with this pk."))
+                } else {
+                    err.into()
+                }
+            })?;
+
+        trace!("set_thread_preference {:?}.", &ret);
+        Ok(ret)
+    }
+
+    /// Remove the preference of a subscription for a thread.
+    pub fn remove_thread_preference(&self, subscription_pk: i64, thread: &str) -> Result<()> {
+        let mut stmt = self.connection.prepare(
+            "DELETE FROM thread_preference WHERE subscription = ? AND thread = ? RETURNING *;",
+        )?;
+        stmt.query_row(rusqlite::params![&subscription_pk, &thread], |_| Ok(()))
+            .map_err(|err| {
+                if matches!(err, rusqlite::Error::QueryReturnedNoRows) {
+                    Error::from(err).chain_err(|| NotFound("thread preference not found!"))
+                } else {
+                    err.into()
+                }
+            })?;
+
+        trace!("remove_thread_preference {} {}.", subscription_pk, thread);
+        Ok(())
+    }
+
+    /// Apply a [`ThreadAction`] of a subscription to a thread.
+    pub fn change_thread_preference(
+        &self,
+        subscription_pk: i64,
+        thread: &str,
+        action: ThreadAction,
+    ) -> Result<()> {
+        match action {
+            ThreadAction::Mute | ThreadAction::Follow => {
+                self.set_thread_preference(ThreadPreference {
+                    pk: -1,
+                    subscription: subscription_pk,
+                    thread: thread.to_string(),
+                    muted: action == ThreadAction::Mute,
+                })?;
+            }
+            ThreadAction::Unmute => {
+                if self.thread_preference(subscription_pk, thread)?.is_some() {
+                    self.remove_thread_preference(subscription_pk, thread)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Make a follow-only subscription follow a thread it posted to.
+    ///
+    /// Existing preferences for the thread are kept, so replying to a muted
+    /// thread does not unmute it.
+    pub fn join_thread(&self, subscription_pk: i64, thread: &str) -> Result<()> {
+        let mut stmt = self.connection.prepare(
+            "INSERT INTO thread_preference(subscription, thread, muted) SELECT pk, ?, 0 FROM \
+             subscription WHERE pk = ? AND follow_only = 1 ON CONFLICT(subscription, thread) DO \
+             NOTHING;",
+        )?;
+        stmt.execute(rusqlite::params![&thread, &subscription_pk])?;
+
+        trace!("join_thread {} {}.", subscription_pk, thread);
+        Ok(())
+    }
+
+    /// Whether a subscription is in follow-only mode.
+    pub fn subscription_follow_only(&self, subscription_pk: i64) -> Result<bool> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT follow_only FROM subscription WHERE pk = ?;")?;
+        let ret = stmt
+            .query_row([&subscription_pk], |row| row.get(0))
+            .map_err(|err| {
+                if matches!(err, rusqlite::Error::QueryReturnedNoRows) {
+                    Error::from(err).chain_err(|| NotFound("subscription not found!"))
+                } else {
+                    err.into()
+                }
+            })?;
+        Ok(ret)
+    }
+
+    /// Switch a subscription to follow-only mode, or back to receiving every
+    /// thread.
+    pub fn set_subscription_follow_only(&self, subscription_pk: i64, value: bool) -> Result<()> {
+        let mut stmt = self
+            .connection
+            .prepare("UPDATE subscription SET follow_only = ? WHERE pk = ? RETURNING pk;")?;
+        stmt.query_row(rusqlite::params![&value, &subscription_pk], |_| Ok(()))
+            .map_err(|err| {
+                if matches!(err, rusqlite::Error::QueryReturnedNoRows) {
+                    Error::from(err).chain_err(|| NotFound("subscription not found!"))
+                } else {
+                    err.into()
+                }
+            })?;
+
+        trace!(
+            "set_subscription_follow_only {} {}.",
+            subscription_pk,
+            value
+        );
+        Ok(())
+    }
+
+    fn thread_preference_from_row(
+        row: &rusqlite::Row<'_>,
+    ) -> rusqlite::Result<DbVal<ThreadPreference>> {
+        let pk = row.get("pk")?;
+        Ok(DbVal(
+            ThreadPreference {
+                pk,
+                subscription: row.get("subscription")?,
+                thread: row.get("thread")?,
+                muted: row.get("muted")?,
+            },
+            pk,
+        ))
+    }
+}
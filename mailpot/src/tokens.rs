@@ -0,0 +1,216 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Signed subscription tokens.
+//!
+//! Personalised posts (see
+//! [`Personalise`](crate::message_filters::Personalise)) carry links with a
+//! [`SubscriptionToken`] that lets their recipient manage their subscription
+//! in the web interface without logging in. Tokens are signed with
+//! HMAC-SHA256, keyed with the database's token secret, so they can be
+//! verified without storing them.
+//!
+//! Tokens record when they were issued and expire after [`TOKEN_MAX_AGE`]
+//! seconds, so that a forwarded link stops working eventually. A token issued
+//! before its subscription was created is also refused: an old link can't
+//! remove a subscription that was made again after it was used (see
+//! [`Connection::unsubscribe_with_token`]).
+
+use data_encoding::BASE64URL_NOPAD;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{errors::*, Connection};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// How long a [`SubscriptionToken`] stays valid after it was issued, in
+/// seconds (180 days).
+pub const TOKEN_MAX_AGE: u64 = 180 * 24 * 60 * 60;
+
+/// What a [`SubscriptionToken`] allows its bearer to do.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenAction {
+    /// Remove the subscription.
+    Unsubscribe,
+    /// Change the subscription's settings, or remove it.
+    Settings,
+}
+
+impl TokenAction {
+    /// Returns the name of the action used in tokens.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unsubscribe => "unsubscribe",
+            Self::Settings => "settings",
+        }
+    }
+}
+
+impl std::str::FromStr for TokenAction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "unsubscribe" => Ok(Self::Unsubscribe),
+            "settings" => Ok(Self::Settings),
+            other => Err(Error::new_external(format!(
+                "Invalid token action: {other}."
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for TokenAction {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.as_str())
+    }
+}
+
+/// A signed claim that its bearer receives posts of `list` at `address`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SubscriptionToken {
+    /// What the token allows.
+    pub action: TokenAction,
+    /// Mailing list foreign key.
+    pub list: i64,
+    /// Subscription address.
+    pub address: String,
+    /// Unix timestamp of when the token was issued.
+    pub issued: u64,
+}
+
+impl SubscriptionToken {
+    /// Encode and sign the token with `secret`.
+    pub fn sign(&self, secret: &[u8]) -> String {
+        let payload = format!(
+            "{}:{}:{}:{}",
+            self.action, self.list, self.issued, self.address
+        );
+        let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any size");
+        mac.update(payload.as_bytes());
+        format!(
+            "{}.{}",
+            BASE64URL_NOPAD.encode(payload.as_bytes()),
+            BASE64URL_NOPAD.encode(&mac.finalize().into_bytes())
+        )
+    }
+
+    /// Decode a token and verify its signature with `secret`.
+    ///
+    /// Tokens issued more than [`TOKEN_MAX_AGE`] seconds ago are refused.
+    pub fn verify(token: &str, secret: &[u8]) -> Result<Self> {
+        let invalid = || Error::new_external("Invalid or expired link.");
+        let (payload, signature) = token.split_once('.').ok_or_else(invalid)?;
+        let payload = BASE64URL_NOPAD
+            .decode(payload.as_bytes())
+            .map_err(|_| invalid())?;
+        let signature = BASE64URL_NOPAD
+            .decode(signature.as_bytes())
+            .map_err(|_| invalid())?;
+        let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any size");
+        mac.update(&payload);
+        mac.verify_slice(&signature).map_err(|_| invalid())?;
+        let payload = String::from_utf8(payload).map_err(|_| invalid())?;
+        let mut fields = payload.splitn(4, ':');
+        let (Some(action), Some(list), Some(issued), Some(address)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(invalid());
+        };
+        let issued: u64 = issued.parse().map_err(|_| invalid())?;
+        let now = chrono::offset::Utc::now().timestamp() as u64;
+        if issued.saturating_add(TOKEN_MAX_AGE) < now {
+            return Err(invalid());
+        }
+        Ok(Self {
+            action: action.parse()?,
+            list: list.parse().map_err(|_| invalid())?,
+            address: address.to_string(),
+            issued,
+        })
+    }
+}
+
+impl Connection {
+    /// Fetch the secret that signs [`SubscriptionToken`]s.
+    pub fn token_secret(&self) -> Result<Vec<u8>> {
+        Ok(self.connection.query_row(
+            "SELECT secret FROM token_secret ORDER BY pk DESC LIMIT 1;",
+            [],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Create a signed token for the subscription of `address` to a list.
+    pub fn subscription_token(
+        &self,
+        action: TokenAction,
+        list_pk: i64,
+        address: &str,
+    ) -> Result<String> {
+        Ok(SubscriptionToken {
+            action,
+            list: list_pk,
+            address: address.to_string(),
+            issued: chrono::offset::Utc::now().timestamp() as u64,
+        }
+        .sign(&self.token_secret()?))
+    }
+
+    /// Verify a signed subscription token.
+    pub fn verify_subscription_token(&self, token: &str) -> Result<SubscriptionToken> {
+        SubscriptionToken::verify(token, &self.token_secret()?)
+    }
+
+    /// Remove the subscription a token was issued for.
+    ///
+    /// Returns `Ok(None)` if the address is not subscribed to the list. The
+    /// token is refused if the subscription was created after the token was
+    /// issued, so that replaying an old token has no effect.
+    pub fn unsubscribe_with_token(&self, token: &str) -> Result<Option<SubscriptionToken>> {
+        let claim = self.verify_subscription_token(token)?;
+        let created = match self.connection.query_row(
+            "SELECT created FROM subscription WHERE list = ? AND address = ?;",
+            rusqlite::params![&claim.list, &claim.address],
+            |row| row.get::<_, u64>(0),
+        ) {
+            Ok(created) => created,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        if created > claim.issued {
+            return Err(Error::new_external("Invalid or expired link."));
+        }
+        self.remove_subscription(claim.list, &claim.address)?;
+        Ok(Some(claim))
+    }
+}
+
+/// Returns the URL that removes a subscription with a [`SubscriptionToken`]
+/// when it receives an [RFC 8058](https://www.rfc-editor.org/rfc/rfc8058)
+/// one-click unsubscription request.
+pub fn one_click_unsubscribe_url(base_url: &str, token: &str) -> String {
+    format!(
+        "{}/subscription/{token}/unsubscribe/",
+        base_url.trim_end_matches('/')
+    )
+}
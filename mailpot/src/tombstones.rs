@@ -0,0 +1,208 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Removal of posts from the archive.
+//!
+//! [`Connection::remove_post`] replaces the message of a post with a
+//! placeholder that keeps its `Message-ID`, `In-Reply-To`, `References` and
+//! `Date` headers, so that threads stay intact, and records a
+//! [`PostTombstone`] with the reason of the removal and who removed it. The
+//! placeholder gives the reason only. Archive renderers and the mbox export
+//! show the placeholder in place of the post.
+
+use log::info;
+use rusqlite::OptionalExtension;
+
+use crate::{
+    errors::{ErrorKind::*, *},
+    models::{DbVal, Post, PostTombstone},
+    Connection, StripCarets,
+};
+
+/// Subject of the placeholder message of a removed post.
+pub const TOMBSTONE_SUBJECT: &str = "[removed]";
+
+/// Headers of a removed post that are kept in its placeholder message.
+const KEPT_HEADERS: &[&str] = &["message-id", "in-reply-to", "references", "date"];
+
+/// The placeholder message that replaces a removed post.
+///
+/// Threading headers and `Date` are copied from `message`, the post is
+/// attributed to `list_address`, and the body gives the reason of the
+/// removal. Who removed it is only kept in the tombstone.
+pub fn tombstone_message(message: &[u8], list_address: &str, tombstone: &PostTombstone) -> Vec<u8> {
+    let mut ret = vec![];
+    if let Ok((headers, _)) = melib::email::parser::mail(message) {
+        for (name, value) in headers {
+            if KEPT_HEADERS.contains(&name.as_str().to_ascii_lowercase().as_str()) {
+                ret.extend_from_slice(name.as_str().as_bytes());
+                ret.extend_from_slice(b": ");
+                ret.extend_from_slice(value.trim_ascii());
+                ret.extend_from_slice(b"\r\n");
+            }
+        }
+    }
+    ret.extend_from_slice(
+        format!(
+            "From: <{list_address}>\r\nSubject: {TOMBSTONE_SUBJECT}\r\nMIME-Version: \
+             1.0\r\nContent-Type: text/plain; charset=utf-8\r\n\r\nThis post was removed by the \
+             moderators.\r\n\r\nReason: {}\r\n",
+            tombstone.reason
+        )
+        .as_bytes(),
+    );
+    ret
+}
+
+impl Connection {
+    /// Remove a post from the archive.
+    ///
+    /// The post is looked up by `message_id` in `list_pk`'s posts, including
+    /// those in [archive databases](crate::archives). Its message is replaced
+    /// by a [placeholder](tombstone_message), its envelope sender and
+    /// full-text search entry are dropped, and a tombstone is recorded with
+    /// `reason` and `removed_by`. Queue entries of the post, such as copies
+    /// waiting to be sent, are deleted, and so are the [blobs](crate::blobs)
+    /// no message refers to any more.
+    pub fn remove_post(
+        &self,
+        list_pk: i64,
+        message_id: &str,
+        reason: &str,
+        removed_by: &str,
+    ) -> Result<DbVal<PostTombstone>> {
+        let Some(list) = self.list(list_pk)? else {
+            return Err(NotFound("list").into());
+        };
+        let message_id = message_id.strip_carets();
+        if self.post_tombstone(list_pk, message_id)?.is_some() {
+            return Err(Error::new_external(format!(
+                "Post <{message_id}> has already been removed."
+            )));
+        }
+
+        let tx = self.savepoint(Some(stringify!(remove_post)))?;
+        let mut found = None;
+        for schema in self.post_schemas()? {
+            let post = tx
+                .connection
+                .query_row(
+                    &format!(
+                        "SELECT *, strftime('%Y-%m', CAST(timestamp AS INTEGER), 'unixepoch') AS \
+                         month_year FROM {schema}.post WHERE list = ?1 AND (message_id = ?2 OR \
+                         message_id = '<' || ?2 || '>');"
+                    ),
+                    rusqlite::params![&list_pk, message_id],
+                    |row| self.post_from_row(row),
+                )
+                .optional()?;
+            if let Some(post) = post {
+                found = Some((schema, post));
+                break;
+            }
+        }
+        let Some((schema, post)) = found else {
+            return Err(NotFound("post").into());
+        };
+
+        let tombstone = tx.connection.query_row(
+            "INSERT INTO post_tombstone(list, message_id, reason, removed_by) VALUES (?, ?, ?, ?) \
+             RETURNING *;",
+            rusqlite::params![&list_pk, message_id, reason, removed_by],
+            Self::post_tombstone_from_row,
+        )?;
+        let message = tombstone_message(&post.message, &list.address, &tombstone);
+        tx.connection.execute(
+            &format!(
+                "UPDATE {schema}.post SET message = ?, blobs = NULL, headers_json = ?, address = \
+                 ?, envelope_from = NULL WHERE pk = ?;"
+            ),
+            rusqlite::params![
+                &message,
+                &Post::parse_headers(&message),
+                &list.address,
+                &post.pk()
+            ],
+        )?;
+        if tx.has_post_fts(&schema)? {
+            tx.connection.execute(
+                &format!("DELETE FROM {schema}.post_fts WHERE rowid = ?;"),
+                [post.pk()],
+            )?;
+        }
+        let queue_entries = tx.connection.execute(
+            "DELETE FROM queue WHERE list = ?1 AND (message_id = ?2 OR message_id = '<' || ?2 || \
+             '>');",
+            rusqlite::params![&list_pk, message_id],
+        )?;
+        tx.commit()?;
+        if queue_entries > 0 {
+            info!("Deleted {queue_entries} queue entries of removed post <{message_id}>.");
+        }
+        self.gc_blobs(false)?;
+
+        info!("Removed post {tombstone}.");
+        Ok(tombstone)
+    }
+
+    /// The tombstone of a removed post, if it was removed.
+    pub fn post_tombstone(
+        &self,
+        list_pk: i64,
+        message_id: &str,
+    ) -> Result<Option<DbVal<PostTombstone>>> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT * FROM post_tombstone WHERE list = ? AND message_id = ?;",
+                rusqlite::params![&list_pk, message_id.strip_carets()],
+                Self::post_tombstone_from_row,
+            )
+            .optional()?)
+    }
+
+    /// The tombstones of the removed posts of a list, oldest first.
+    pub fn list_post_tombstones(&self, list_pk: i64) -> Result<Vec<DbVal<PostTombstone>>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT * FROM post_tombstone WHERE list = ? ORDER BY pk;")?;
+        let iter = stmt.query_map([&list_pk], Self::post_tombstone_from_row)?;
+        let mut ret = vec![];
+        for tombstone in iter {
+            ret.push(tombstone?);
+        }
+        Ok(ret)
+    }
+
+    fn post_tombstone_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<DbVal<PostTombstone>> {
+        let pk = row.get("pk")?;
+        Ok(DbVal(
+            PostTombstone {
+                pk,
+                list: row.get("list")?,
+                message_id: row.get("message_id")?,
+                reason: row.get("reason")?,
+                removed_by: row.get("removed_by")?,
+                timestamp: row.get("timestamp")?,
+                datetime: row.get("datetime")?,
+            },
+            pk,
+        ))
+    }
+}
@@ -0,0 +1,240 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Topic-based partial subscriptions.
+//!
+//! A post is about the [topics](crate::models::MailingList::topics) of its
+//! list that appear as `[topic]` markers in its subject, or in its `Keywords:`
+//! header as a comma separated list. Subscribers can choose which topics they
+//! receive posts about, and whether they receive posts about none of them,
+//! i.e. everything else (see [`SubscriptionTopics`]).
+//! [`FinalizeRecipients`](crate::message_filters::FinalizeRecipients) leaves
+//! out the subscribers who did not choose any of a post's topics.
+//!
+//! Topics can be chosen in the web subscription settings page, or by sending
+//! an e-mail to the list's request address with a subject such as `topics
+//! rust python other`, where [`EVERYTHING_ELSE`] stands for posts about none
+//! of the list's topics. The subject `topics all` (see [`ALL_TOPICS`])
+//! restores reception of every post.
+
+use log::trace;
+use melib::{Envelope, HeaderName};
+
+use crate::{
+    errors::{ErrorKind::*, *},
+    models::{DbVal, MailingList, SubscriptionTopics},
+    Connection,
+};
+
+/// Topic request keyword for posts that are not about any of the list's
+/// topics.
+pub const EVERYTHING_ELSE: &str = "other";
+
+/// Topic request keyword for receiving every post.
+pub const ALL_TOPICS: &str = "all";
+
+/// The topics of `list` a post is about, in the order they appear in the post.
+pub fn post_topics(list: &MailingList, env: &Envelope) -> Vec<String> {
+    if list.topics.is_empty() {
+        return vec![];
+    }
+    let subject = env.subject();
+    let markers = subject.split('[').skip(1).filter_map(|s| {
+        let (marker, _) = s.split_once(']')?;
+        Some(marker)
+    });
+    let keywords = env
+        .other_headers()
+        .get(HeaderName::KEYWORDS)
+        .unwrap_or_default();
+    let mut ret: Vec<String> = vec![];
+    for candidate in markers
+        .chain(std::iter::once(keywords))
+        .flat_map(|s| s.split(','))
+        .map(str::trim)
+    {
+        if let Some(topic) = list
+            .topics
+            .iter()
+            .find(|t| t.eq_ignore_ascii_case(candidate))
+        {
+            if !ret.contains(topic) {
+                ret.push(topic.clone());
+            }
+        }
+    }
+    ret
+}
+
+/// Parse the topics requested by a subscriber, e.g. with the words following
+/// the `topics` e-mail command.
+///
+/// Returns `None` if the subscriber requested every post with [`ALL_TOPICS`],
+/// or chose all of the list's topics and [`EVERYTHING_ELSE`].
+pub fn parse_topics_request<S: AsRef<str>>(
+    list: &MailingList,
+    subscription: i64,
+    request: &[S],
+) -> Result<Option<SubscriptionTopics>> {
+    let mut ret = SubscriptionTopics {
+        pk: -1,
+        subscription,
+        topics: vec![],
+        everything_else: false,
+    };
+    for word in request.iter().map(AsRef::as_ref) {
+        if word.eq_ignore_ascii_case(ALL_TOPICS) {
+            return Ok(None);
+        } else if word.eq_ignore_ascii_case(EVERYTHING_ELSE) {
+            ret.everything_else = true;
+        } else if let Some(topic) = list.topics.iter().find(|t| t.eq_ignore_ascii_case(word)) {
+            if !ret.topics.contains(topic) {
+                ret.topics.push(topic.clone());
+            }
+        } else {
+            return Err(Error::new_external(format!(
+                "{word} is not a topic of this list. Available topics are: {}.",
+                list.topics.join(", ")
+            )));
+        }
+    }
+    if ret.topics.is_empty() && !ret.everything_else {
+        return Err(Error::new_external(format!(
+            "No topics were chosen. Available topics are: {}, and {EVERYTHING_ELSE} for \
+             everything else.",
+            list.topics.join(", ")
+        )));
+    }
+    if ret.everything_else && ret.topics.len() == list.topics.len() {
+        return Ok(None);
+    }
+    Ok(Some(ret))
+}
+
+impl Connection {
+    /// Fetch the topics a subscription receives posts about, if the subscriber
+    /// has chosen any.
+    pub fn subscription_topics(
+        &self,
+        subscription_pk: i64,
+    ) -> Result<Option<DbVal<SubscriptionTopics>>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT * FROM subscription_topics WHERE subscription = ?;")?;
+        let ret = match stmt.query_row([&subscription_pk], Self::subscription_topics_from_row) {
+            Ok(v) => Some(v),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(err) => return Err(err.into()),
+        };
+        Ok(ret)
+    }
+
+    /// Fetch the topics chosen by the subscribers of a list.
+    pub fn list_subscription_topics(&self, list_pk: i64) -> Result<Vec<DbVal<SubscriptionTopics>>> {
+        let mut stmt = self.connection.prepare(
+            "SELECT subscription_topics.* FROM subscription_topics JOIN subscription ON \
+             subscription_topics.subscription = subscription.pk WHERE subscription.list = ? ORDER \
+             BY subscription_topics.pk;",
+        )?;
+        let iter = stmt.query_map([&list_pk], Self::subscription_topics_from_row)?;
+
+        let mut ret = vec![];
+        for entry in iter {
+            ret.push(entry?);
+        }
+        Ok(ret)
+    }
+
+    /// Set the topics a subscription receives posts about, replacing any
+    /// previous choice.
+    pub fn set_subscription_topics(
+        &self,
+        new_val: SubscriptionTopics,
+    ) -> Result<DbVal<SubscriptionTopics>> {
+        let mut stmt = self.connection.prepare(
+            "INSERT INTO subscription_topics(subscription, topics, everything_else) VALUES (?, ?, \
+             ?) ON CONFLICT(subscription) DO UPDATE SET topics = excluded.topics, everything_else \
+             = excluded.everything_else RETURNING *;",
+        )?;
+        let ret = stmt
+            .query_row(
+                rusqlite::params![
+                    &new_val.subscription,
+                    serde_json::json!(new_val.topics.as_slice()),
+                    &new_val.everything_else,
+                ],
+                Self::subscription_topics_from_row,
+            )
+            .map_err(|err| {
+                if matches!(
+                    err,
+                    rusqlite::Error::SqliteFailure(
+                        rusqlite::ffi::Error {
+                            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+                            extended_code: 787
+                        },
+                        _
+                    )
+                ) {
+                    Error::from(err)
+                        .chain_err(|| NotFound("Could not find a subscription with this pk."))
+                } else {
+                    err.into()
+                }
+            })?;
+
+        trace!("set_subscription_topics {:?}.", &ret);
+        Ok(ret)
+    }
+
+    /// Remove the topics chosen by a subscriber, who then receives every post.
+    pub fn remove_subscription_topics(&self, subscription_pk: i64) -> Result<()> {
+        let mut stmt = self
+            .connection
+            .prepare("DELETE FROM subscription_topics WHERE subscription = ? RETURNING *;")?;
+        stmt.query_row([&subscription_pk], |_| Ok(()))
+            .map_err(|err| {
+                if matches!(err, rusqlite::Error::QueryReturnedNoRows) {
+                    Error::from(err).chain_err(|| NotFound("subscription topics not found!"))
+                } else {
+                    err.into()
+                }
+            })?;
+
+        trace!("remove_subscription_topics {}.", subscription_pk);
+        Ok(())
+    }
+
+    fn subscription_topics_from_row(
+        row: &rusqlite::Row<'_>,
+    ) -> rusqlite::Result<DbVal<SubscriptionTopics>> {
+        let pk = row.get("pk")?;
+        let topics: serde_json::Value = row.get("topics")?;
+        let topics = MailingList::topics_from_json_value(topics)?;
+        Ok(DbVal(
+            SubscriptionTopics {
+                pk,
+                subscription: row.get("subscription")?,
+                topics,
+                everything_else: row.get("everything_else")?,
+            },
+            pk,
+        ))
+    }
+}
@@ -0,0 +1,234 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Umbrella lists, i.e. lists that include other lists.
+//!
+//! Posts to an umbrella list are sent to the subscribers of the lists it
+//! includes, recursively, once per address (see
+//! [`FinalizeRecipients`](crate::message_filters::FinalizeRecipients)). Each
+//! [`ListInclusion`] can also let those who can post to the included list post
+//! to the umbrella list (see
+//! [`PostRightsCheck`](crate::message_filters::PostRightsCheck)).
+
+use log::trace;
+
+use crate::{
+    errors::{ErrorKind::*, *},
+    models::{DbVal, ListInclusion, ListSubscription},
+    Connection,
+};
+
+/// Recursive common table expression of the lists included by list `?1`,
+/// optionally only through inclusions that inherit post rights. `UNION`
+/// discards already visited lists, so it terminates even with cycles.
+macro_rules! descendants_cte {
+    () => {
+        "WITH RECURSIVE descendants(pk) AS (SELECT child FROM list_inclusion WHERE parent = ?1 \
+         UNION SELECT list_inclusion.child FROM list_inclusion JOIN descendants ON \
+         list_inclusion.parent = descendants.pk) "
+    };
+    (inherit_post_rights) => {
+        "WITH RECURSIVE descendants(pk) AS (SELECT child FROM list_inclusion WHERE parent = ?1 AND \
+         inherit_post_rights = 1 UNION SELECT list_inclusion.child FROM list_inclusion JOIN \
+         descendants ON list_inclusion.parent = descendants.pk WHERE \
+         list_inclusion.inherit_post_rights = 1) "
+    };
+}
+
+impl Connection {
+    /// Fetch the lists directly included by an umbrella list.
+    pub fn list_inclusions(&self, list_pk: i64) -> Result<Vec<DbVal<ListInclusion>>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT * FROM list_inclusion WHERE parent = ? ORDER BY pk;")?;
+        let iter = stmt.query_map([&list_pk], Self::list_inclusion_from_row)?;
+
+        let mut ret = vec![];
+        for entry in iter {
+            ret.push(entry?);
+        }
+        Ok(ret)
+    }
+
+    /// Include a list in an umbrella list.
+    ///
+    /// Inclusions that would create a cycle are refused.
+    pub fn add_list_inclusion(&self, inclusion: ListInclusion) -> Result<DbVal<ListInclusion>> {
+        if inclusion.parent == inclusion.child {
+            return Err(Error::new_external("A list cannot include itself."));
+        }
+        let cycle: bool = self.connection.query_row(
+            concat!(
+                descendants_cte!(),
+                "SELECT count(*) > 0 FROM descendants WHERE pk = ?2;"
+            ),
+            rusqlite::params![&inclusion.child, &inclusion.parent],
+            |row| row.get(0),
+        )?;
+        if cycle {
+            return Err(Error::new_external(format!(
+                "List {} already includes list {}, including it back would create a cycle.",
+                inclusion.child, inclusion.parent
+            )));
+        }
+        let mut stmt = self.connection.prepare(
+            "INSERT INTO list_inclusion(parent, child, inherit_post_rights) VALUES (?, ?, ?) \
+             RETURNING *;",
+        )?;
+        let ret = stmt
+            .query_row(
+                rusqlite::params![
+                    &inclusion.parent,
+                    &inclusion.child,
+                    &inclusion.inherit_post_rights,
+                ],
+                Self::list_inclusion_from_row,
+            )
+            .map_err(|err| {
+                if matches!(
+                    err,
+                    rusqlite::Error::SqliteFailure(
+                        rusqlite::ffi::Error {
+                            code: rusqlite::ffi::ErrorCode::ConstraintViolation,
+                            extended_code: 787
+                        },
+                        _
+                    )
+                ) {
+                    Error::from(err).chain_err(|| NotFound("Could not find a list with this pk."))
+                } else {
+                    err.into()
+                }
+            })?;
+
+        trace!("add_list_inclusion {:?}.", &ret);
+        Ok(ret)
+    }
+
+    /// Remove a list from an umbrella list.
+    pub fn remove_list_inclusion(&self, list_pk: i64, child_pk: i64) -> Result<()> {
+        let mut stmt = self
+            .connection
+            .prepare("DELETE FROM list_inclusion WHERE parent = ? AND child = ? RETURNING *;")?;
+        stmt.query_row(rusqlite::params![&list_pk, &child_pk], |_| Ok(()))
+            .map_err(|err| {
+                if matches!(err, rusqlite::Error::QueryReturnedNoRows) {
+                    Error::from(err).chain_err(|| NotFound("list inclusion not found!"))
+                } else {
+                    err.into()
+                }
+            })?;
+
+        trace!("remove_list_inclusion {} {}.", list_pk, child_pk);
+        Ok(())
+    }
+
+    /// Fetch the enabled and confirmed subscriptions of the lists included by
+    /// an umbrella list, recursively.
+    ///
+    /// Each address appears once, and addresses subscribed to the umbrella
+    /// list itself are not included.
+    pub fn list_member_subscriptions(&self, list_pk: i64) -> Result<Vec<DbVal<ListSubscription>>> {
+        let mut stmt = self.connection.prepare(concat!(
+            descendants_cte!(),
+            "SELECT * FROM subscription WHERE list IN descendants AND list != ?1 AND enabled = 1 \
+             AND address NOT IN (SELECT address FROM subscription WHERE list = ?1) AND pk NOT IN \
+             (SELECT subscription FROM subscription_confirmation) ORDER BY pk;"
+        ))?;
+        let iter = stmt.query_map([&list_pk], |row| {
+            let pk = row.get("pk")?;
+            Ok(DbVal(
+                ListSubscription {
+                    pk,
+                    list: row.get("list")?,
+                    address: row.get("address")?,
+                    account: row.get("account")?,
+                    name: row.get("name")?,
+                    digest: row.get("digest")?,
+                    enabled: row.get("enabled")?,
+                    verified: row.get("verified")?,
+                    hide_address: row.get("hide_address")?,
+                    receive_duplicates: row.get("receive_duplicates")?,
+                    receive_own_posts: row.get("receive_own_posts")?,
+                    receive_confirmation: row.get("receive_confirmation")?,
+                },
+                pk,
+            ))
+        })?;
+
+        let mut seen = std::collections::HashSet::new();
+        let mut ret = vec![];
+        for subscription in iter {
+            let subscription = subscription?;
+            if seen.insert(subscription.address.to_lowercase()) {
+                ret.push(subscription);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Fetch the lowercase addresses of the owners of the lists whose post
+    /// rights are inherited by an umbrella list, recursively.
+    pub fn list_inherited_owners(&self, list_pk: i64) -> Result<Vec<String>> {
+        self.inherited_addresses(
+            list_pk,
+            concat!(
+                descendants_cte!(inherit_post_rights),
+                "SELECT DISTINCT address FROM owner WHERE list IN descendants;"
+            ),
+        )
+    }
+
+    /// Fetch the lowercase addresses of the owners and enabled subscribers of
+    /// the lists whose post rights are inherited by an umbrella list,
+    /// recursively.
+    pub fn list_inherited_posters(&self, list_pk: i64) -> Result<Vec<String>> {
+        self.inherited_addresses(
+            list_pk,
+            concat!(
+                descendants_cte!(inherit_post_rights),
+                "SELECT address FROM owner WHERE list IN descendants UNION SELECT address FROM \
+                 subscription WHERE list IN descendants AND enabled = 1;"
+            ),
+        )
+    }
+
+    fn inherited_addresses(&self, list_pk: i64, query: &str) -> Result<Vec<String>> {
+        let mut stmt = self.connection.prepare(query)?;
+        let iter = stmt.query_map([&list_pk], |row| row.get::<_, String>(0))?;
+        let mut ret = vec![];
+        for address in iter {
+            ret.push(address?.to_lowercase());
+        }
+        Ok(ret)
+    }
+
+    fn list_inclusion_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<DbVal<ListInclusion>> {
+        let pk = row.get("pk")?;
+        Ok(DbVal(
+            ListInclusion {
+                pk,
+                parent: row.get("parent")?,
+                child: row.get("child")?,
+                inherit_post_rights: row.get("inherit_post_rights")?,
+            },
+            pk,
+        ))
+    }
+}
@@ -0,0 +1,203 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{models::*, queue::Queue, Configuration, Connection};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_address_match() {
+    assert!(AddressMatch::Exact.matches("User@Example.com", "user@example.com"));
+    assert!(!AddressMatch::Exact.matches("user@example.com", "user2@example.com"));
+    assert!(AddressMatch::Glob.matches("*+spam@example.com", "user+spam@example.com"));
+    assert!(AddressMatch::Glob.matches("user?@*.example.com", "user1@lists.example.com"));
+    assert!(!AddressMatch::Glob.matches("user?@*.example.com", "user@lists.example.com"));
+    assert!(AddressMatch::Domain.matches("example.com", "user@example.com"));
+    assert!(AddressMatch::Domain.matches("@example.com", "user@lists.example.com"));
+    assert!(!AddressMatch::Domain.matches("example.com", "user@badexample.com"));
+
+    let entry = |pk, list, kind| AccessEntry {
+        pk,
+        list,
+        kind,
+        match_type: AddressMatch::Domain,
+        pattern: "example.com".into(),
+        post: true,
+        subscribe: false,
+        comment: None,
+    };
+    /* list allow entries cannot override an instance-wide deny */
+    let entries = [
+        entry(1, None, AccessKind::Deny),
+        entry(2, Some(1), AccessKind::Allow),
+    ];
+    assert_eq!(
+        AccessEntry::verdict(entries.iter(), "user@example.com", AccessScope::Post),
+        Some(AccessKind::Deny)
+    );
+    /* list deny entries override an instance-wide allow */
+    let entries = [
+        entry(1, None, AccessKind::Allow),
+        entry(2, Some(1), AccessKind::Deny),
+    ];
+    assert_eq!(
+        AccessEntry::verdict(entries.iter(), "user@example.com", AccessScope::Post),
+        Some(AccessKind::Deny)
+    );
+    let entries = [entry(1, None, AccessKind::Allow)];
+    assert_eq!(
+        AccessEntry::verdict(entries.iter(), "user@example.com", AccessScope::Post),
+        Some(AccessKind::Allow)
+    );
+    assert_eq!(
+        AccessEntry::verdict(entries.iter(), "user@example.com", AccessScope::Subscribe),
+        None
+    );
+    let entries = [
+        entry(1, Some(1), AccessKind::Allow),
+        entry(2, Some(1), AccessKind::Deny),
+    ];
+    assert_eq!(
+        AccessEntry::verdict(entries.iter(), "user@example.com", AccessScope::Post),
+        Some(AccessKind::Deny)
+    );
+}
+
+#[test]
+fn test_access_entries() {
+    init_stderr_logging();
+
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: 0,
+        list: foo_chat.pk(),
+        announce_only: false,
+        subscription_only: true,
+        approval_needed: false,
+        open: false,
+        custom: false,
+    })
+    .unwrap();
+
+    let allow = db
+        .add_access_entry(AccessEntry {
+            pk: 0,
+            list: Some(foo_chat.pk()),
+            kind: AccessKind::Allow,
+            match_type: AddressMatch::Exact,
+            pattern: "guest@example.com".into(),
+            post: true,
+            subscribe: false,
+            comment: Some("invited speaker".into()),
+        })
+        .unwrap();
+    let deny = db
+        .add_access_entry(AccessEntry {
+            pk: 0,
+            list: None,
+            kind: AccessKind::Deny,
+            match_type: AddressMatch::Domain,
+            pattern: "spam.example.com".into(),
+            post: true,
+            subscribe: true,
+            comment: None,
+        })
+        .unwrap();
+    assert_eq!(
+        db.list_access_entries(foo_chat.pk()).unwrap(),
+        vec![allow.clone(), deny.clone()]
+    );
+    assert_eq!(db.instance_access_entries().unwrap(), vec![deny.clone()]);
+    /* instance-wide entries are unique too */
+    db.add_access_entry(AccessEntry {
+        pk: 0,
+        comment: Some("duplicate".into()),
+        ..deny.clone().into_inner()
+    })
+    .unwrap_err();
+    db.add_access_entry(AccessEntry {
+        pk: 0,
+        list: Some(foo_chat.pk() + 1),
+        ..allow.clone().into_inner()
+    })
+    .unwrap_err();
+    // Instance-wide entries cannot be removed through a list.
+    db.remove_access_entry(Some(foo_chat.pk()), deny.pk())
+        .unwrap_err();
+
+    let db = db.untrusted();
+
+    /* allowed non-subscriber can post to a subscription-only list */
+    let post_bytes = b"From: Guest <guest@example.com>
+To: <foo-chat@example.com>
+Subject: This is a post
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <abcdefgh@sator.example.com>
+
+Hello
+";
+    let envelope = melib::Envelope::from_bytes(post_bytes, None).expect("Could not parse message");
+    db.post(&envelope, post_bytes, /* dry_run */ false)
+        .expect("Got unexpected error");
+    assert_eq!(db.list_posts(foo_chat.pk(), None).unwrap().len(), 1);
+    assert_eq!(db.queue(Queue::Out).unwrap().len(), 0);
+
+    /* denied domain cannot subscribe */
+    let subscribe_bytes = b"From: Name <user@lists.spam.example.com>
+To: <foo-chat+subscribe@example.com>
+Subject: subscribe
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <abcdefgh2@sator.example.com>
+
+";
+    let envelope =
+        melib::Envelope::from_bytes(subscribe_bytes, None).expect("Could not parse message");
+    db.post(&envelope, subscribe_bytes, /* dry_run */ false)
+        .unwrap();
+    assert_eq!(db.list_subscriptions(foo_chat.pk()).unwrap().len(), 0);
+    let out = db.queue(Queue::Out).unwrap();
+    assert_eq!(out.len(), 1);
+    assert!(out[0]
+        .comment
+        .as_deref()
+        .unwrap()
+        .contains("is denied subscription"));
+
+    let db = db.trusted();
+    db.remove_access_entry(None, deny.pk()).unwrap();
+    db.remove_access_entry(Some(foo_chat.pk()), allow.pk())
+        .unwrap();
+    assert!(db.list_access_entries(foo_chat.pk()).unwrap().is_empty());
+}
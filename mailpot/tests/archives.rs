@@ -0,0 +1,335 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{archives::ArchiveRotation, models::*, Configuration, Connection};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_archive_rotation() {
+    init_stderr_logging();
+
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config.clone())
+        .unwrap()
+        .trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: 0,
+        list: foo_chat.pk(),
+        announce_only: false,
+        subscription_only: false,
+        approval_needed: false,
+        open: true,
+        custom: false,
+    })
+    .unwrap();
+
+    for (year, n) in [(2018, 1), (2019, 2), (2021, 1)] {
+        for i in 0..n {
+            // Replies to the first post of 2019 are in its archive and in the
+            // main database.
+            let in_reply_to = if year == 2018 || (year, i) == (2019, 0) {
+                String::new()
+            } else {
+                "In-Reply-To: <2019-0@example.com>\n".to_string()
+            };
+            let bytes = format!(
+                "From: <user@example.com>
+To: <foo-chat@example.com>
+Subject: Post {i} of {year}
+Date: Thu, 1 Nov {year} 13:58:16 +0000
+Message-ID: <{year}-{i}@example.com>
+{in_reply_to}Content-Type: text/plain
+
+Hello from year{year}
+"
+            );
+            let envelope = melib::Envelope::from_bytes(bytes.as_bytes(), None).unwrap();
+            db.post(&envelope, bytes.as_bytes(), /* dry_run */ false)
+                .unwrap();
+        }
+    }
+
+    let expected = vec![
+        ArchiveRotation {
+            year: 2018,
+            posts: 1,
+        },
+        ArchiveRotation {
+            year: 2019,
+            posts: 2,
+        },
+    ];
+    assert_eq!(db.rotate_archives(2020, true).unwrap(), expected);
+    assert!(db.archives().unwrap().is_empty());
+    assert!(!db.archive_path(2018).exists());
+
+    assert_eq!(db.rotate_archives(2020, false).unwrap(), expected);
+    assert_eq!(db.archives().unwrap(), vec![2018, 2019]);
+    assert!(db.archive_path(2018).exists());
+    assert!(db.archive_path(2019).exists());
+    assert!(db.rotate_archives(2020, false).unwrap().is_empty());
+
+    let main_posts: i64 = db
+        .connection
+        .query_row("SELECT count(*) FROM main.post;", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(main_posts, 1);
+
+    // A new connection attaches the archives, and reads them along with the
+    // main database.
+    let db = Connection::open_db(config).unwrap();
+    assert_eq!(db.archives().unwrap(), vec![2018, 2019]);
+    let posts = db.list_posts(foo_chat.pk(), None).unwrap();
+    assert_eq!(
+        posts
+            .iter()
+            .map(|p| p.message_id.as_str())
+            .collect::<Vec<_>>(),
+        vec![
+            "2018-0@example.com",
+            "2019-0@example.com",
+            "2019-1@example.com",
+            "2021-0@example.com"
+        ]
+    );
+    let archived = db
+        .list_post_by_message_id(foo_chat.pk(), "<2019-1@example.com>")
+        .unwrap()
+        .unwrap();
+    assert_eq!(archived.month_year, "2019-11");
+    assert!(
+        String::from_utf8_lossy(&archived.message).contains("Subject: [foo-chat] Post 1 of 2019")
+    );
+    let mut months = db.months(foo_chat.pk()).unwrap();
+    months.sort();
+    assert_eq!(months, vec!["2018-11", "2019-11", "2021-11"]);
+    let mbox = String::from_utf8(db.export_mbox(foo_chat.pk(), None, false).unwrap()).unwrap();
+    assert_eq!(mbox.matches("Message-ID: <").count(), 4, "{mbox}");
+
+    // Archived posts keep their search index entries.
+    let search = |db: &Connection, query: &str| {
+        let mut ret = db
+            .search_posts(foo_chat.pk(), query, Default::default())
+            .unwrap()
+            .into_iter()
+            .map(|result| result.post.message_id.clone())
+            .collect::<Vec<_>>();
+        ret.sort();
+        ret
+    };
+    assert_eq!(search(&db, "year2018"), vec!["2018-0@example.com"]);
+    assert_eq!(search(&db, "hello").len(), 4);
+    let results = db
+        .search_posts(foo_chat.pk(), "year2019", Default::default())
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].post.month_year, "2019-11");
+    assert_eq!(results[0].snippet.trim_end(), "Hello from year2019");
+
+    // Threads span the archives and the main database.
+    let thread = db
+        .list_thread(foo_chat.pk(), "<2019-0@example.com>")
+        .unwrap()
+        .into_iter()
+        .map(|(depth, post)| (depth, post.message_id.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        thread,
+        vec![
+            (1, "2019-1@example.com".to_string()),
+            (1, "2021-0@example.com".to_string())
+        ]
+    );
+    let roots = db
+        .list_thread_roots(foo_chat.pk())
+        .unwrap()
+        .into_iter()
+        .map(|(root, length, _)| (root.message_id.clone(), length))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        roots,
+        vec![
+            ("2019-0@example.com".to_string(), 3),
+            ("2018-0@example.com".to_string(), 1)
+        ]
+    );
+
+    // Later posts go to the main database.
+    let db = db.trusted();
+    assert_eq!(
+        db.rotate_archives(2022, false).unwrap(),
+        vec![ArchiveRotation {
+            year: 2021,
+            posts: 1,
+        }]
+    );
+    assert_eq!(db.archives().unwrap(), vec![2018, 2019, 2021]);
+    assert_eq!(db.list_posts(foo_chat.pk(), None).unwrap().len(), 4);
+    assert_eq!(search(&db, "year2021"), vec!["2021-0@example.com"]);
+    assert_eq!(db.list_thread_roots(foo_chat.pk()).unwrap().len(), 2);
+
+    // Archives without a search index are indexed on the next rotation.
+    db.connection
+        .execute_batch(
+            "DROP TRIGGER archive_2018.remove_post_fts; DROP TABLE archive_2018.post_fts;",
+        )
+        .unwrap();
+    assert!(search(&db, "year2018").is_empty());
+    assert!(db.rotate_archives(2022, false).unwrap().is_empty());
+    assert_eq!(search(&db, "year2018"), vec!["2018-0@example.com"]);
+
+    // Replies to archived posts inherit their thread root.
+    let bytes = b"From: <user@example.com>
+To: <foo-chat@example.com>
+Subject: Re: Post 1 of 2019
+Date: Thu, 1 Nov 2022 13:58:16 +0000
+Message-ID: <2022-0@example.com>
+In-Reply-To: <2019-1@example.com>
+Content-Type: text/plain
+
+Hello from year2022
+";
+    let envelope = melib::Envelope::from_bytes(bytes, None).unwrap();
+    db.post(&envelope, bytes, /* dry_run */ false).unwrap();
+    let thread = db
+        .list_thread(foo_chat.pk(), "<2019-0@example.com>")
+        .unwrap()
+        .into_iter()
+        .map(|(depth, post)| (depth, post.message_id.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        thread,
+        vec![
+            (1, "2019-1@example.com".to_string()),
+            (1, "2021-0@example.com".to_string()),
+            (2, "2022-0@example.com".to_string())
+        ]
+    );
+}
+
+#[test]
+fn test_archive_rotation_new_posts() {
+    init_stderr_logging();
+
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: 0,
+        list: foo_chat.pk(),
+        announce_only: false,
+        subscription_only: false,
+        approval_needed: false,
+        open: true,
+        custom: false,
+    })
+    .unwrap();
+
+    let post = |year: i32, msg_id: &str, in_reply_to: Option<&str>| {
+        let bytes = format!(
+            "From: <user@example.com>
+To: <foo-chat@example.com>
+Subject: Thread
+Date: Thu, 1 Nov {year} 13:58:16 +0000
+Message-ID: <{msg_id}@example.com>
+{}Content-Type: text/plain
+
+Hello
+",
+            in_reply_to.map_or_else(String::new, |r| format!("In-Reply-To: <{r}@example.com>\n"))
+        );
+        let envelope = melib::Envelope::from_bytes(bytes.as_bytes(), None).unwrap();
+        db.post(&envelope, bytes.as_bytes(), /* dry_run */ false)
+            .unwrap();
+    };
+    post(2019, "root", None);
+    post(2019, "a", Some("root"));
+    // Every post is moved out of the main database.
+    assert_eq!(
+        db.rotate_archives(2020, false).unwrap(),
+        vec![ArchiveRotation {
+            year: 2019,
+            posts: 2,
+        }]
+    );
+    post(2021, "b", Some("a"));
+    post(2021, "c", Some("root"));
+
+    let mut pks = db
+        .list_posts(foo_chat.pk(), None)
+        .unwrap()
+        .into_iter()
+        .map(|p| p.pk())
+        .collect::<Vec<_>>();
+    pks.sort();
+    pks.dedup();
+    assert_eq!(pks.len(), 4, "{pks:?}");
+
+    let thread = db
+        .list_thread(foo_chat.pk(), "<root@example.com>")
+        .unwrap()
+        .into_iter()
+        .map(|(depth, post)| (depth, post.message_id.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        thread,
+        vec![
+            (1, "a@example.com".to_string()),
+            (1, "c@example.com".to_string()),
+            (2, "b@example.com".to_string())
+        ]
+    );
+    let roots = db
+        .list_thread_roots(foo_chat.pk())
+        .unwrap()
+        .into_iter()
+        .map(|(root, length, _)| (root.message_id.clone(), length))
+        .collect::<Vec<_>>();
+    assert_eq!(roots, vec![("root@example.com".to_string(), 4)]);
+}
@@ -0,0 +1,193 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{blobs::BLOB_MIN_SIZE, models::*, queue::Queue, Configuration, Connection};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_blob_store() {
+    init_stderr_logging();
+
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration {
+        blob_store: true,
+        ..Configuration::new(db_path)
+    };
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: 0,
+        list: foo_chat.pk(),
+        announce_only: false,
+        subscription_only: false,
+        approval_needed: false,
+        open: true,
+        custom: false,
+    })
+    .unwrap();
+    for address in ["a@example.com", "b@example.com"] {
+        db.add_subscription(
+            foo_chat.pk(),
+            ListSubscription {
+                pk: 0,
+                list: foo_chat.pk(),
+                address: address.into(),
+                name: None,
+                account: None,
+                enabled: true,
+                verified: true,
+                digest: false,
+                hide_address: false,
+                receive_duplicates: true,
+                receive_own_posts: true,
+                receive_confirmation: false,
+            },
+        )
+        .unwrap();
+    }
+
+    let attachment = "QUJDRA==\r\n".repeat(BLOB_MIN_SIZE / 8);
+    let bytes = format!(
+        "From: <a@example.com>\r
+To: <foo-chat@example.com>\r
+Subject: a large attachment\r
+Date: Thu, 29 Oct 2020 13:58:16 +0000\r
+Message-ID: <large@example.com>\r
+MIME-Version: 1.0\r
+Content-Type: multipart/mixed; boundary=\"bound\"\r
+\r
+--bound\r
+Content-Type: text/plain\r
+\r
+See attached.\r
+--bound\r
+Content-Type: application/octet-stream\r
+Content-Transfer-Encoding: base64\r
+\r
+{attachment}--bound--\r
+"
+    );
+    let db = db.untrusted();
+    let envelope = melib::Envelope::from_bytes(bytes.as_bytes(), None).unwrap();
+    db.post(&envelope, bytes.as_bytes(), /* dry_run */ false)
+        .unwrap();
+
+    // The attachment is stored once, for the post and all its copies.
+    let db = db.trusted();
+    let blob_count: usize = db
+        .connection
+        .query_row("SELECT count(*) FROM blob;", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(blob_count, 1);
+    let stored_size: usize = db
+        .connection
+        .query_row(
+            "SELECT max(length(message)) FROM post UNION ALL SELECT max(length(message)) FROM \
+             queue ORDER BY 1 DESC LIMIT 1;",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert!(stored_size < BLOB_MIN_SIZE, "{stored_size}");
+
+    // Messages are read back whole.
+    let posts = db.list_posts(foo_chat.pk(), None).unwrap();
+    assert_eq!(posts.len(), 1);
+    let message = String::from_utf8(posts[0].message.clone()).unwrap();
+    assert!(message.contains(&attachment));
+    let by_id = db
+        .list_post_by_message_id(foo_chat.pk(), "large@example.com")
+        .unwrap()
+        .unwrap();
+    assert_eq!(by_id.message, posts[0].message);
+    let out = db.queue(Queue::Out).unwrap();
+    assert!(!out.is_empty());
+    for entry in &out {
+        assert!(String::from_utf8_lossy(&entry.message).contains(&attachment));
+    }
+    let mbox = db.export_mbox(foo_chat.pk(), None, false).unwrap();
+    // mbox lines end with LF.
+    assert!(String::from_utf8_lossy(&mbox).contains(&attachment.replace("\r\n", "\n")));
+
+    // Undoing the migration restores whole messages.
+    let version = db.schema_version().unwrap();
+    db.migrate(version, 24).unwrap();
+    let message: Vec<u8> = db
+        .connection
+        .query_row("SELECT message FROM post;", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(message, posts[0].message);
+    db.migrate(24, version).unwrap();
+    assert_eq!(db.list_posts(foo_chat.pk(), None).unwrap(), posts);
+
+    // Dry runs don't write to the blob store.
+    let blob_files = || {
+        std::fs::read_dir(db.blob_directory())
+            .unwrap()
+            .flat_map(|dir| std::fs::read_dir(dir.unwrap().path()).unwrap())
+            .count()
+    };
+    let other = bytes
+        .replace("large@example.com", "other@example.com")
+        .replace("QUJDRA==", "RUZHSA==");
+    let envelope = melib::Envelope::from_bytes(other.as_bytes(), None).unwrap();
+    db.post_dry_run(&envelope, other.as_bytes()).unwrap();
+    assert_eq!(blob_files(), 1);
+
+    // Blobs are deleted once no post or queue entry refers to them. The blob
+    // of the first post has no row since the migration was undone, and its
+    // file is too recent to be deleted.
+    db.post(&envelope, other.as_bytes(), /* dry_run */ false)
+        .unwrap();
+    assert_eq!(blob_files(), 2);
+    assert_eq!(db.gc_blobs(false).unwrap(), 0);
+    db.delete_from_queue(Queue::Out, vec![]).unwrap();
+    assert_eq!(db.gc_blobs(false).unwrap(), 0);
+    db.connection.execute("DELETE FROM post;", []).unwrap();
+    assert_eq!(db.gc_blobs(true).unwrap(), 1);
+    assert_eq!(blob_files(), 2);
+    assert_eq!(db.gc_blobs(false).unwrap(), 1);
+    assert_eq!(blob_files(), 1);
+    let blob_count: usize = db
+        .connection
+        .query_row("SELECT count(*) FROM blob;", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(blob_count, 0);
+
+    // Storing a blob writes its file even if its row already exists.
+    let hash = db.put_blob(b"blob contents").unwrap();
+    let path = db.blob_directory().join(&hash[..2]).join(&hash);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(db.put_blob(b"blob contents").unwrap(), hash);
+    assert_eq!(db.blob(&hash).unwrap(), b"blob contents");
+}
@@ -17,7 +17,7 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use mailpot::{models::*, Configuration, Connection, SendMail};
+use mailpot::{models::*, Configuration, Connection};
 use mailpot_tests::init_stderr_logging;
 use tempfile::TempDir;
 
@@ -27,12 +27,7 @@ fn test_init_empty() {
     let tmp_dir = TempDir::new().unwrap();
 
     let db_path = tmp_dir.path().join("mpot.db");
-    let config = Configuration {
-        send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
-    };
+    let config = Configuration::new(db_path);
 
     let db = Connection::open_or_create_db(config).unwrap();
 
@@ -45,12 +40,7 @@ fn test_list_creation() {
     let tmp_dir = TempDir::new().unwrap();
 
     let db_path = tmp_dir.path().join("mpot.db");
-    let config = Configuration {
-        send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
-    };
+    let config = Configuration::new(db_path);
 
     let db = Connection::open_or_create_db(config).unwrap().trusted();
     assert!(db.lists().unwrap().is_empty());
@@ -0,0 +1,176 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{mail::PostAction, models::*, queue::Queue, Configuration, Connection};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_post_dry_run() {
+    init_stderr_logging();
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: -1,
+        list: foo_chat.pk(),
+        announce_only: false,
+        subscription_only: true,
+        approval_needed: false,
+        open: false,
+        custom: false,
+    })
+    .unwrap();
+    for address in ["user@example.com", "user2@example.com"] {
+        db.add_subscription(
+            foo_chat.pk(),
+            ListSubscription {
+                pk: -1,
+                list: foo_chat.pk(),
+                address: address.into(),
+                name: None,
+                account: None,
+                digest: false,
+                enabled: true,
+                verified: true,
+                hide_address: false,
+                receive_duplicates: true,
+                receive_own_posts: false,
+                receive_confirmation: false,
+            },
+        )
+        .unwrap();
+    }
+    let db = db.untrusted();
+
+    let post_bytes = b"From: Name <user@example.com>
+To: <foo-chat@example.com>
+Subject: This is a post
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <abcdefgh@sator.example.com>
+
+Hello
+";
+    let envelope = melib::Envelope::from_bytes(post_bytes, None).expect("Could not parse message");
+    let trace = db.post_dry_run(&envelope, post_bytes).unwrap();
+    assert_eq!(trace.error, None);
+    assert_eq!(trace.lists.len(), 1);
+    let list_trace = &trace.lists[0];
+    assert_eq!(list_trace.action, PostAction::Accept);
+    assert_eq!(list_trace.recipients, vec!["user2@example.com".to_string()]);
+    assert_eq!(
+        list_trace
+            .filters
+            .iter()
+            .map(|f| f.filter.as_str())
+            .collect::<Vec<_>>(),
+        vec![
+            "PostRightsCheck",
+            "DecryptPost",
+            "VerifySignature",
+            "MimeReject",
+            "FixCRLF",
+            "AddListHeaders",
+            "ArchivedAtLink",
+            "AddSubjectTagPrefix",
+            "FinalizeRecipients",
+            "Personalise",
+            "EncryptForSubscribers"
+        ]
+    );
+    let fix_crlf = &list_trace.filters[4];
+    assert!(fix_crlf.body_changed);
+    let add_subject = &list_trace.filters[7];
+    assert_eq!(
+        add_subject.headers_added,
+        vec!["Subject: [foo-chat] This is a post".to_string()]
+    );
+    assert_eq!(
+        add_subject.headers_removed,
+        vec!["Subject: This is a post".to_string()]
+    );
+    assert!(list_trace.filters[5]
+        .headers_added
+        .iter()
+        .any(|h| h.to_ascii_lowercase().starts_with("list-id: ")));
+    assert_eq!(list_trace.filters[8].jobs_scheduled.len(), 1);
+    assert_eq!(trace.queue_entries.len(), 1);
+    assert_eq!(trace.queue_entries[0].queue, Queue::Out.as_str());
+    assert!(trace.to_string().contains("Final action: Accept"));
+    serde_json::to_string(&trace).unwrap();
+
+    /* nothing was stored */
+    assert_eq!(db.list_posts(foo_chat.pk(), None).unwrap().len(), 0);
+    assert_eq!(db.queue(Queue::Out).unwrap().len(), 0);
+    db.post(&envelope, post_bytes, /* dry_run */ true).unwrap();
+    assert_eq!(db.list_posts(foo_chat.pk(), None).unwrap().len(), 0);
+    assert_eq!(db.queue(Queue::Out).unwrap().len(), 0);
+
+    /* rejected post */
+    let post_bytes = b"From: Name <nobody@example.com>
+To: <foo-chat@example.com>
+Subject: This is a post
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <abcdefgh2@sator.example.com>
+
+Hello
+";
+    let envelope = melib::Envelope::from_bytes(post_bytes, None).expect("Could not parse message");
+    let trace = db.post_dry_run(&envelope, post_bytes).unwrap();
+    let list_trace = &trace.lists[0];
+    assert_eq!(list_trace.filters.len(), 1);
+    assert!(list_trace.filters[0].stopped);
+    assert!(matches!(list_trace.action, PostAction::Reject { .. }));
+    assert!(list_trace.recipients.is_empty());
+    assert_eq!(trace.queue_entries.len(), 1);
+    assert_eq!(
+        trace.queue_entries[0].to_addresses,
+        "Name <nobody@example.com>"
+    );
+    assert_eq!(db.queue(Queue::Out).unwrap().len(), 0);
+
+    /* unknown list */
+    let post_bytes = b"From: Name <user@example.com>
+To: <bar-chat@example.com>
+Subject: This is a post
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <abcdefgh3@sator.example.com>
+
+Hello
+";
+    let envelope = melib::Envelope::from_bytes(post_bytes, None).expect("Could not parse message");
+    let trace = db.post_dry_run(&envelope, post_bytes).unwrap();
+    assert!(trace.error.is_some());
+    assert!(trace.lists.is_empty());
+    assert_eq!(db.queue(Queue::Error).unwrap().len(), 0);
+}
@@ -0,0 +1,227 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{
+    mail::PostAction, mailing_sets::SetExpr, melib, models::*, queue::Queue, Configuration,
+    Connection,
+};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_set_expr_parse() {
+    let expr = SetExpr::parse("rust|python&go~interns").unwrap();
+    assert_eq!(expr.to_string(), "{{rust|{python&go}}~interns}");
+    assert_eq!(expr.operands(), vec!["rust", "python", "go", "interns"]);
+    let expr = SetExpr::parse("{foo-chat|bar}~{baz}").unwrap();
+    assert_eq!(expr.to_string(), "{{foo-chat|bar}~baz}");
+    assert!(SetExpr::is_set_expression("a&b"));
+    assert!(!SetExpr::is_set_expression("foo-chat"));
+    for invalid in ["a|", "|a", "{a|b", "a}b", "a&&b", "{}"] {
+        SetExpr::parse(invalid).unwrap_err();
+    }
+}
+
+#[test]
+fn test_mailing_sets() {
+    init_stderr_logging();
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let mut lists = vec![];
+    for (id, members) in [
+        ("rust", &["a", "b", "c"][..]),
+        ("python", &["b", "c", "d"][..]),
+        ("interns", &["c", "sender"][..]),
+    ] {
+        let list = db
+            .create_list(MailingList {
+                pk: 0,
+                name: id.into(),
+                id: id.into(),
+                address: format!("{id}@example.com"),
+                description: None,
+                topics: vec![],
+                archive_url: None,
+            })
+            .unwrap();
+        db.set_list_post_policy(PostPolicy {
+            pk: -1,
+            list: list.pk(),
+            announce_only: false,
+            subscription_only: false,
+            approval_needed: false,
+            open: true,
+            custom: false,
+        })
+        .unwrap();
+        for member in members {
+            db.add_subscription(
+                list.pk(),
+                ListSubscription {
+                    pk: -1,
+                    list: list.pk(),
+                    address: format!("{member}@example.com"),
+                    name: None,
+                    account: None,
+                    digest: false,
+                    enabled: true,
+                    verified: true,
+                    hide_address: false,
+                    receive_duplicates: true,
+                    receive_own_posts: false,
+                    receive_confirmation: false,
+                },
+            )
+            .unwrap();
+        }
+        lists.push(list);
+    }
+
+    let post = |to: &str, msg_id: &str| -> Vec<u8> {
+        format!(
+            "From: Sender <sender@example.com>
+To: <{to}@example.com>
+Subject: Set post
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <{msg_id}@example.com>
+
+Hello
+"
+        )
+        .into_bytes()
+    };
+    let recipients_of = |to: &str, msg_id: &str| -> Vec<String> {
+        let bytes = post(to, msg_id);
+        let envelope = melib::Envelope::from_bytes(&bytes, None).unwrap();
+        db.post(&envelope, &bytes, false).unwrap();
+        let mut ret = db
+            .queue(Queue::Out)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.to_addresses.clone())
+            .collect::<Vec<_>>();
+        ret.sort();
+        db.delete_from_queue(Queue::Out, vec![]).unwrap();
+        ret
+    };
+
+    assert_eq!(
+        recipients_of("rust|python", "union"),
+        vec![
+            "a@example.com",
+            "b@example.com",
+            "c@example.com",
+            "d@example.com"
+        ]
+    );
+    assert_eq!(
+        recipients_of("rust&python", "intersection"),
+        vec!["b@example.com", "c@example.com"]
+    );
+    assert_eq!(
+        recipients_of("{rust|python}~interns", "difference"),
+        vec!["a@example.com", "b@example.com", "d@example.com"]
+    );
+
+    // Posts are archived in the lists that had subscribers in the recipients.
+    for (list, count) in lists.iter().zip([3, 3, 0]) {
+        assert_eq!(db.list_posts(list.pk(), None).unwrap().len(), count);
+    }
+
+    // Recipients are chosen as for posts to each list: digest subscribers get
+    // the post in their digest, and queued copies belong to a list.
+    db.add_subscription(
+        lists[1].pk(),
+        ListSubscription {
+            pk: -1,
+            list: lists[1].pk(),
+            address: "e@example.com".into(),
+            name: None,
+            account: None,
+            digest: true,
+            enabled: true,
+            verified: true,
+            hide_address: false,
+            receive_duplicates: true,
+            receive_own_posts: false,
+            receive_confirmation: false,
+        },
+    )
+    .unwrap();
+    let bytes = post("python|interns", "digest");
+    let envelope = melib::Envelope::from_bytes(&bytes, None).unwrap();
+    let trace = db.post_dry_run(&envelope, &bytes).unwrap();
+    assert_eq!(trace.lists[0].digest_recipients, vec!["e@example.com"]);
+    db.post(&envelope, &bytes, false).unwrap();
+    let out_queue = db.queue(Queue::Out).unwrap();
+    let mut recipients = out_queue
+        .iter()
+        .map(|e| e.to_addresses.as_str())
+        .collect::<Vec<_>>();
+    recipients.sort_unstable();
+    assert_eq!(
+        recipients,
+        vec!["b@example.com", "c@example.com", "d@example.com"]
+    );
+    assert!(out_queue.iter().all(|e| e.list.is_some()));
+    db.delete_from_queue(Queue::Out, vec![]).unwrap();
+
+    // Encrypted lists cannot be part of a mailing set.
+    db.generate_list_openpgp_key(lists[2].pk()).unwrap();
+    let bytes = post("rust|interns", "encrypted");
+    let envelope = melib::Envelope::from_bytes(&bytes, None).unwrap();
+    let trace = db.post_dry_run(&envelope, &bytes).unwrap();
+    assert!(matches!(
+        trace.lists[0].action,
+        PostAction::Reject { ref reason } if reason.contains("encrypted")
+    ));
+    db.remove_list_openpgp_key(lists[2].pk()).unwrap();
+
+    // Unknown lists and invalid expressions are errors.
+    let bytes = post("rust|golang", "unknown");
+    let envelope = melib::Envelope::from_bytes(&bytes, None).unwrap();
+    db.post(&envelope, &bytes, false).unwrap_err();
+    assert_eq!(db.queue(Queue::Error).unwrap().len(), 1);
+
+    // Posting rights are checked against every list.
+    db.set_list_post_policy(PostPolicy {
+        pk: -1,
+        list: lists[1].pk(),
+        announce_only: true,
+        subscription_only: false,
+        approval_needed: false,
+        open: false,
+        custom: false,
+    })
+    .unwrap();
+    let bytes = post("rust|python", "rejected");
+    let envelope = melib::Envelope::from_bytes(&bytes, None).unwrap();
+    let trace = db.post_dry_run(&envelope, &bytes).unwrap();
+    assert_eq!(trace.lists.len(), 1);
+    assert_eq!(trace.lists[0].list, "{rust|python}");
+    assert!(matches!(trace.lists[0].action, PostAction::Reject { .. }));
+    db.post(&envelope, &bytes, false).unwrap();
+    let out_queue = db.queue(Queue::Out).unwrap();
+    assert_eq!(out_queue.len(), 1);
+    assert_eq!(out_queue[0].to_addresses, "Sender <sender@example.com>");
+}
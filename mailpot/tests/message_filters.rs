@@ -17,7 +17,7 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use mailpot::{models::*, queue::Queue, Configuration, Connection, SendMail};
+use mailpot::{models::*, queue::Queue, Configuration, Connection};
 use mailpot_tests::init_stderr_logging;
 use serde_json::json;
 use tempfile::TempDir;
@@ -37,12 +37,7 @@ fn test_post_filters() {
         custom: false,
     };
     let db_path = tmp_dir.path().join("mpot.db");
-    let config = Configuration {
-        send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
-    };
+    let config = Configuration::new(db_path);
 
     let db = Connection::open_or_create_db(config).unwrap().trusted();
     let foo_chat = db
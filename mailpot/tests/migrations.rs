@@ -19,7 +19,7 @@
 
 use std::{fs::File, io::Write};
 
-use mailpot::{Configuration, Connection, SendMail};
+use mailpot::{Configuration, Connection};
 use mailpot_tests::init_stderr_logging;
 use tempfile::TempDir;
 
@@ -31,12 +31,7 @@ fn test_init_empty() {
     let tmp_dir = TempDir::new().unwrap();
 
     let db_path = tmp_dir.path().join("mpot.db");
-    let config = Configuration {
-        send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
-    };
+    let config = Configuration::new(db_path);
 
     let db = Connection::open_or_create_db(config).unwrap().trusted();
 
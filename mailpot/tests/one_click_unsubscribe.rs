@@ -0,0 +1,143 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{melib, models::*, queue::Queue, tokens::TokenAction, Configuration, Connection};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_one_click_unsubscribe() {
+    init_stderr_logging();
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let list = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: -1,
+        list: list.pk(),
+        announce_only: false,
+        subscription_only: false,
+        approval_needed: false,
+        open: true,
+        custom: false,
+    })
+    .unwrap();
+    db.set_list_subscription_policy(SubscriptionPolicy {
+        pk: -1,
+        list: list.pk(),
+        send_confirmation: false,
+        open: true,
+        manual: false,
+        request: false,
+        custom: false,
+    })
+    .unwrap();
+    for member in ["a", "b"] {
+        db.add_subscription(
+            list.pk(),
+            ListSubscription {
+                pk: -1,
+                list: list.pk(),
+                address: format!("{member}@example.com"),
+                name: None,
+                account: None,
+                digest: false,
+                enabled: true,
+                verified: true,
+                hide_address: false,
+                receive_duplicates: true,
+                receive_own_posts: false,
+                receive_confirmation: false,
+            },
+        )
+        .unwrap();
+    }
+    db.set_settings(
+        list.pk(),
+        "AddListHeadersSettings",
+        serde_json::json!({ "one_click_unsubscribe": true, "base_url": "https://lists.example.com" }),
+    )
+    .unwrap();
+
+    let bytes = b"From: <user@example.com>
+To: <foo-chat@example.com>
+Subject: Hello
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <hello@example.com>
+
+Hello.
+";
+    let envelope = melib::Envelope::from_bytes(bytes, None).unwrap();
+    db.post(&envelope, bytes, false).unwrap();
+
+    let mut out = db.queue(Queue::Out).unwrap();
+    out.sort_by(|a, b| a.to_addresses.cmp(&b.to_addresses));
+    assert_eq!(out.len(), 2);
+    let mut tokens = vec![];
+    for (entry, address) in out.iter().zip(["a@example.com", "b@example.com"]) {
+        let env = melib::Envelope::from_bytes(&entry.message, None).unwrap();
+        let headers = env.other_headers();
+        assert_eq!(
+            headers.get("List-Unsubscribe-Post"),
+            Some("List-Unsubscribe=One-Click")
+        );
+        let value = headers.get("List-Unsubscribe").unwrap();
+        let (url, mailto) = value.split_once(", ").unwrap();
+        assert_eq!(
+            mailto,
+            "<mailto:foo-chat+request@example.com?subject=unsubscribe>"
+        );
+        let token = url
+            .strip_prefix("<https://lists.example.com/subscription/")
+            .and_then(|t| t.strip_suffix("/unsubscribe/>"))
+            .unwrap();
+        let claim = db.verify_subscription_token(token).unwrap();
+        assert_eq!(claim.action, TokenAction::Unsubscribe);
+        assert_eq!(claim.address, address);
+        tokens.push(token.to_string());
+    }
+
+    // The archived post has no personal link.
+    let posts = db.list_posts(list.pk(), None).unwrap();
+    assert_eq!(posts.len(), 1);
+    let archived = String::from_utf8_lossy(&posts[0].message);
+    assert!(archived.contains("List-Unsubscribe: <mailto:"));
+    assert!(!archived.contains("List-Unsubscribe-Post"));
+
+    let claim = db.unsubscribe_with_token(&tokens[0]).unwrap().unwrap();
+    assert_eq!(claim.address, "a@example.com");
+    assert!(db
+        .list_subscription_by_address(list.pk(), "a@example.com")
+        .is_err());
+    assert!(db.unsubscribe_with_token(&tokens[0]).unwrap().is_none());
+    assert_eq!(db.list_subscriptions(list.pk()).unwrap().len(), 1);
+}
@@ -0,0 +1,321 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{
+    mail::PostAction, melib, models::*, openpgp, queue::Queue, Configuration, Connection,
+};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_encrypted_list() {
+    init_stderr_logging();
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: -1,
+        list: foo_chat.pk(),
+        announce_only: false,
+        subscription_only: true,
+        approval_needed: false,
+        open: false,
+        custom: false,
+    })
+    .unwrap();
+    db.add_list_owner(ListOwner {
+        pk: 0,
+        list: foo_chat.pk(),
+        address: "owner@example.com".into(),
+        name: None,
+    })
+    .unwrap();
+    let list_key = db.generate_list_openpgp_key(foo_chat.pk()).unwrap();
+    assert_eq!(
+        db.list_openpgp_key(foo_chat.pk()).unwrap().unwrap(),
+        list_key
+    );
+    let list_public_key = openpgp::parse_secret_key(&list_key.secret_key)
+        .unwrap()
+        .to_public_key();
+
+    let user_key = openpgp::generate_secret_key("user <user@example.com>").unwrap();
+    let user2_key = openpgp::generate_secret_key("user2 <user2@example.com>").unwrap();
+    for (address, key) in [
+        ("user@example.com", Some(&user_key)),
+        ("user2@example.com", Some(&user2_key)),
+        ("user3@example.com", None),
+    ] {
+        if let Some(key) = key {
+            db.add_account(Account {
+                pk: 0,
+                name: None,
+                address: address.into(),
+                public_key: Some(openpgp::armored_public_key(key).unwrap()),
+                password: String::new(),
+                enabled: true,
+            })
+            .unwrap();
+        }
+        db.add_subscription(
+            foo_chat.pk(),
+            ListSubscription {
+                pk: -1,
+                list: foo_chat.pk(),
+                address: address.into(),
+                name: None,
+                account: None,
+                digest: false,
+                enabled: true,
+                verified: true,
+                hide_address: false,
+                receive_duplicates: true,
+                receive_own_posts: false,
+                receive_confirmation: false,
+            },
+        )
+        .unwrap();
+    }
+    assert_eq!(db.list_public_keys(foo_chat.pk()).unwrap().len(), 2);
+
+    // The list secret key cannot be read through untrusted connections.
+    let db = db.untrusted();
+    db.list_openpgp_key(foo_chat.pk()).unwrap_err();
+    let db = db.trusted();
+
+    let plaintext = b"From: Name <user@example.com>
+To: <foo-chat@example.com>
+Subject: This is a post
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <abcdefgh@sator.example.com>
+Content-Type: text/plain; charset=utf-8
+
+Secret hello
+";
+
+    // Plaintext posts are rejected.
+    let envelope = melib::Envelope::from_bytes(plaintext, None).expect("Could not parse message");
+    let trace = db.post_dry_run(&envelope, plaintext).unwrap();
+    assert!(matches!(
+        trace.lists[0].action,
+        PostAction::Reject { ref reason } if reason.contains(&list_key.fingerprint)
+    ));
+
+    let encrypted = openpgp::encrypt_mime(&list_public_key, plaintext).unwrap();
+    assert!(!String::from_utf8_lossy(&encrypted).contains("Secret hello"));
+    let envelope = melib::Envelope::from_bytes(&encrypted, None).expect("Could not parse message");
+    db.post(&envelope, &encrypted, false).unwrap();
+
+    let out_queue = db.queue(Queue::Out).unwrap();
+    assert_eq!(out_queue.len(), 2);
+    let subscriber_copy = out_queue
+        .iter()
+        .find(|e| e.to_addresses.contains("user2@example.com"))
+        .unwrap();
+    let message = String::from_utf8_lossy(&subscriber_copy.message);
+    assert!(message.contains("multipart/encrypted"));
+    assert!(message.contains("Subject: [foo-chat] This is a post"));
+    assert!(!message.contains("Secret hello"));
+    let decrypted =
+        openpgp::decrypt_mime(&user2_key, &subscriber_copy.message).expect("Could not decrypt");
+    let decrypted = String::from_utf8_lossy(&decrypted);
+    assert!(decrypted.contains("Secret hello"));
+    assert!(decrypted
+        .to_ascii_lowercase()
+        .contains("list-id: <foo-chat.example.com>"));
+    openpgp::decrypt_mime(&user_key, &subscriber_copy.message).unwrap_err();
+
+    let owner_notice = out_queue
+        .iter()
+        .find(|e| e.to_addresses.contains("owner@example.com"))
+        .unwrap();
+    assert!(String::from_utf8_lossy(&owner_notice.message).contains("user3@example.com"));
+
+    // The archived post is encrypted to the list key.
+    let posts = db.list_posts(foo_chat.pk(), None).unwrap();
+    assert_eq!(posts.len(), 1);
+    let post = &posts[0];
+    assert!(!String::from_utf8_lossy(&post.message).contains("Secret hello"));
+    let list_secret_key = openpgp::parse_secret_key(&list_key.secret_key).unwrap();
+    assert!(String::from_utf8_lossy(
+        &openpgp::decrypt_mime(&list_secret_key, &post.message).unwrap()
+    )
+    .contains("Secret hello"));
+}
+
+#[test]
+fn test_signed_announce_list() {
+    init_stderr_logging();
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: -1,
+        list: foo_chat.pk(),
+        announce_only: true,
+        subscription_only: false,
+        approval_needed: false,
+        open: false,
+        custom: false,
+    })
+    .unwrap();
+    db.add_list_owner(ListOwner {
+        pk: 0,
+        list: foo_chat.pk(),
+        address: "owner@example.com".into(),
+        name: None,
+    })
+    .unwrap();
+    db.set_settings(
+        foo_chat.pk(),
+        "VerifySignatureSettings",
+        serde_json::json!({ "enabled": true }),
+    )
+    .unwrap();
+
+    let owner_key = openpgp::generate_secret_key("owner <owner@example.com>").unwrap();
+    let other_key = openpgp::generate_secret_key("owner <owner@example.com>").unwrap();
+    db.add_account(Account {
+        pk: 0,
+        name: None,
+        address: "owner@example.com".into(),
+        public_key: Some(openpgp::armored_public_key(&owner_key).unwrap()),
+        password: String::new(),
+        enabled: true,
+    })
+    .unwrap();
+    db.add_subscription(
+        foo_chat.pk(),
+        ListSubscription {
+            pk: -1,
+            list: foo_chat.pk(),
+            address: "user@example.com".into(),
+            name: None,
+            account: None,
+            digest: false,
+            enabled: true,
+            verified: true,
+            hide_address: false,
+            receive_duplicates: true,
+            receive_own_posts: false,
+            receive_confirmation: false,
+        },
+    )
+    .unwrap();
+    let db = db.untrusted();
+
+    let plaintext = b"From: Owner <owner@example.com>
+To: <foo-chat@example.com>
+Subject: Announcement
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <abcdefgh@sator.example.com>
+Content-Type: text/plain; charset=utf-8
+
+Hello
+";
+
+    // Unsigned posts with a forged owner `From:` are rejected.
+    let envelope = melib::Envelope::from_bytes(plaintext, None).expect("Could not parse message");
+    let trace = db.post_dry_run(&envelope, plaintext).unwrap();
+    assert!(matches!(trace.lists[0].action, PostAction::Reject { .. }));
+    let verify = trace.lists[0]
+        .filters
+        .iter()
+        .find(|f| f.filter == "VerifySignature")
+        .unwrap();
+    assert!(verify.stopped);
+    assert!(verify.notes[0].starts_with("signature: bad"));
+
+    // So are posts signed by a key that is not on the owner's account.
+    let signed = openpgp::sign_mime(&other_key, plaintext).unwrap();
+    let envelope = melib::Envelope::from_bytes(&signed, None).expect("Could not parse message");
+    let trace = db.post_dry_run(&envelope, &signed).unwrap();
+    assert!(matches!(trace.lists[0].action, PostAction::Reject { .. }));
+
+    let signed = openpgp::sign_mime(&owner_key, plaintext).unwrap();
+    openpgp::verify_mime(&owner_key.to_public_key(), &signed).unwrap();
+    openpgp::verify_mime(&other_key.to_public_key(), &signed).unwrap_err();
+    let envelope = melib::Envelope::from_bytes(&signed, None).expect("Could not parse message");
+    db.post(&envelope, &signed, false).unwrap();
+    let out_queue = db.queue(Queue::Out).unwrap();
+    assert_eq!(out_queue.len(), 1);
+    // The verification result is added to delivered and archived posts.
+    let audit_header = "x-mailpot-signature: good, by owner@example.com with key";
+    assert!(String::from_utf8_lossy(&out_queue[0].message)
+        .to_lowercase()
+        .contains(audit_header));
+    let archived = db.list_posts(foo_chat.pk(), None).unwrap();
+    assert_eq!(archived.len(), 1);
+    assert!(String::from_utf8_lossy(&archived[0].message)
+        .to_lowercase()
+        .contains(audit_header));
+    let events = db
+        .post_events(
+            Some(foo_chat.pk()),
+            Some("abcdefgh@sator.example.com"),
+            None,
+        )
+        .unwrap();
+    let verify = events.iter().find(|e| e.name == "VerifySignature").unwrap();
+    assert!(verify
+        .detail
+        .as_ref()
+        .unwrap()
+        .contains("signature: good, by owner@example.com"));
+
+    // On failure, posts can be held instead.
+    let db = db.trusted();
+    db.set_settings(
+        foo_chat.pk(),
+        "VerifySignatureSettings",
+        serde_json::json!({ "enabled": true, "on_failure": "hold" }),
+    )
+    .unwrap();
+    let envelope = melib::Envelope::from_bytes(plaintext, None).expect("Could not parse message");
+    let trace = db.post_dry_run(&envelope, plaintext).unwrap();
+    assert_eq!(trace.lists[0].action, PostAction::Hold);
+}
@@ -0,0 +1,211 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{
+    melib,
+    models::*,
+    queue::Queue,
+    tokens::{SubscriptionToken, TokenAction, TOKEN_MAX_AGE},
+    Configuration, Connection,
+};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_personalised_posts() {
+    init_stderr_logging();
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let list = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar newsletter".into(),
+            id: "foo-news".into(),
+            address: "foo-news@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: -1,
+        list: list.pk(),
+        announce_only: false,
+        subscription_only: false,
+        approval_needed: false,
+        open: true,
+        custom: false,
+    })
+    .unwrap();
+    for (member, name) in [("a", Some("Alice")), ("b", None)] {
+        db.add_subscription(
+            list.pk(),
+            ListSubscription {
+                pk: -1,
+                list: list.pk(),
+                address: format!("{member}@example.com"),
+                name: name.map(str::to_string),
+                account: None,
+                digest: false,
+                enabled: true,
+                verified: true,
+                hide_address: false,
+                receive_duplicates: true,
+                receive_own_posts: false,
+                receive_confirmation: false,
+            },
+        )
+        .unwrap();
+    }
+    db.set_settings(
+        list.pk(),
+        "PersonaliseSettings",
+        serde_json::json!({ "enabled": true, "base_url": "https://lists.example.com/" }),
+    )
+    .unwrap();
+    let db = db.untrusted();
+
+    let bytes = b"From: <owner@example.com>
+To: <foo-news@example.com>
+Subject: News
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <news@example.com>
+
+Hello {{ name }} ({{address}}).
+Unsubscribe: {{ unsubscribe_url }}
+Settings: {{ settings_url }}
+Literal: {% for x in range(9999) %}{{ x }}{% endfor %} {{ name or \"reader\" }}
+";
+    let envelope = melib::Envelope::from_bytes(bytes, None).unwrap();
+    db.post(&envelope, bytes, false).unwrap();
+
+    let mut out = db.queue(Queue::Out).unwrap();
+    out.sort_by(|a, b| a.to_addresses.cmp(&b.to_addresses));
+    assert_eq!(out.len(), 2);
+    let messages = out
+        .iter()
+        .map(|e| String::from_utf8_lossy(&e.message).into_owned())
+        .collect::<Vec<_>>();
+    assert!(messages[0].contains("Hello Alice (a@example.com)."));
+    assert!(messages[1].contains("Hello  (b@example.com)."));
+    // Only the fixed merge fields are substituted, everything else is text.
+    for message in &messages {
+        assert!(message.contains(
+            "Literal: {% for x in range(9999) %}{{ x }}{% endfor %} {{ name or \"reader\" }}"
+        ));
+    }
+
+    let url = messages[0]
+        .lines()
+        .find_map(|l| l.strip_prefix("Unsubscribe: https://lists.example.com/subscription/"))
+        .unwrap();
+    let token = url.strip_suffix('/').unwrap();
+    assert_eq!(
+        db.verify_subscription_token(token).unwrap(),
+        SubscriptionToken {
+            action: TokenAction::Unsubscribe,
+            list: list.pk(),
+            address: "a@example.com".to_string(),
+            issued: db.verify_subscription_token(token).unwrap().issued,
+        }
+    );
+    let url = messages[1]
+        .lines()
+        .find_map(|l| l.strip_prefix("Settings: https://lists.example.com/subscription/"))
+        .unwrap();
+    let token = url.strip_suffix('/').unwrap();
+    assert_eq!(
+        db.verify_subscription_token(token).unwrap().action,
+        TokenAction::Settings
+    );
+
+    // Tokens can't be forged or altered.
+    let forged = SubscriptionToken {
+        action: TokenAction::Settings,
+        list: list.pk(),
+        address: "a@example.com".to_string(),
+        issued: 0,
+    }
+    .sign(b"not the secret");
+    assert!(db.verify_subscription_token(&forged).is_err());
+    let (_, signature) = token.split_once('.').unwrap();
+    let (payload, _) = forged.split_once('.').unwrap();
+    assert!(db
+        .verify_subscription_token(&format!("{payload}.{signature}"))
+        .is_err());
+
+    // Tokens expire.
+    let expired = SubscriptionToken {
+        action: TokenAction::Settings,
+        list: list.pk(),
+        address: "a@example.com".to_string(),
+        issued: chrono::Utc::now().timestamp() as u64 - TOKEN_MAX_AGE - 1,
+    }
+    .sign(&db.token_secret().unwrap());
+    assert!(db.verify_subscription_token(&expired).is_err());
+
+    // The archived post gets empty values.
+    let posts = db.list_posts(list.pk(), None).unwrap();
+    assert_eq!(posts.len(), 1);
+    let archived = String::from_utf8_lossy(&posts[0].message);
+    assert!(archived.contains("Hello  ()."));
+    assert!(!archived.contains("{{ name }}"));
+
+    // Encoded text parts are decoded before substitution, other parts are left
+    // alone.
+    db.delete_from_queue(Queue::Out, vec![]).unwrap();
+    let bytes = b"From: <owner@example.com>\r
+To: <foo-news@example.com>\r
+Subject: More news\r
+Date: Thu, 29 Oct 2020 13:58:16 +0000\r
+Message-ID: <news2@example.com>\r
+MIME-Version: 1.0\r
+Content-Type: multipart/alternative; boundary=\"=_boundary\"\r
+\r
+--=_boundary\r
+Content-Type: text/plain; charset=utf-8; format=flowed\r
+Content-Transfer-Encoding: base64\r
+\r
+SGVsbG8ge3sgbmFtZSB9fSDimLoK\r
+--=_boundary\r
+Content-Type: text/html\r
+\r
+<p>Hello {{ name }}</p>\r
+--=_boundary--\r
+";
+    let envelope = melib::Envelope::from_bytes(bytes, None).unwrap();
+    db.post(&envelope, bytes, false).unwrap();
+    let mut out = db.queue(Queue::Out).unwrap();
+    out.sort_by(|a, b| a.to_addresses.cmp(&b.to_addresses));
+    assert_eq!(out.len(), 2);
+    let envelope = melib::Envelope::from_bytes(&out[0].message, None).unwrap();
+    let body = envelope.body_bytes(&out[0].message);
+    let texts = body
+        .attachments()
+        .into_iter()
+        .filter(|part| *part.content_type() == "text/plain")
+        .map(|part| String::from_utf8(part.decode(Default::default())).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(texts, vec!["Hello Alice \u{263a}\n".to_string()]);
+    assert!(String::from_utf8_lossy(&out[0].message).contains("<p>Hello {{ name }}</p>"));
+}
@@ -0,0 +1,163 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{models::*, Configuration, Connection};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_post_events() {
+    init_stderr_logging();
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: -1,
+        list: foo_chat.pk(),
+        announce_only: false,
+        subscription_only: true,
+        approval_needed: false,
+        open: false,
+        custom: false,
+    })
+    .unwrap();
+    db.add_subscription(
+        foo_chat.pk(),
+        ListSubscription {
+            pk: -1,
+            list: foo_chat.pk(),
+            address: "user@example.com".into(),
+            name: None,
+            account: None,
+            digest: false,
+            enabled: true,
+            verified: true,
+            hide_address: false,
+            receive_duplicates: true,
+            receive_own_posts: false,
+            receive_confirmation: false,
+        },
+    )
+    .unwrap();
+    let db = db.untrusted();
+
+    let accepted = b"From: Name <user@example.com>
+To: <foo-chat@example.com>
+Subject: This is a post
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <abcdefgh@sator.example.com>
+
+Hello
+";
+    let envelope = melib::Envelope::from_bytes(accepted, None).expect("Could not parse message");
+    db.post(&envelope, accepted, false).unwrap();
+
+    let events = db
+        .post_events(None, Some("<abcdefgh@sator.example.com>"), None)
+        .unwrap();
+    assert_eq!(
+        events,
+        db.post_events(
+            Some(foo_chat.pk()),
+            Some("abcdefgh@sator.example.com"),
+            None
+        )
+        .unwrap()
+    );
+    let filters = events
+        .iter()
+        .filter(|e| e.kind == PostEventKind::Filter)
+        .map(|e| e.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(filters.first(), Some(&"PostRightsCheck"));
+    assert_eq!(filters.last(), Some(&"EncryptForSubscribers"));
+    let jobs = events
+        .iter()
+        .filter(|e| e.kind == PostEventKind::Job)
+        .collect::<Vec<_>>();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].name, "send");
+    let action = events.last().unwrap();
+    assert_eq!(action.kind, PostEventKind::Action);
+    assert_eq!(action.name, "accept");
+    assert_eq!(action.list, Some(foo_chat.pk()));
+
+    let rejected = b"From: Name <stranger@example.com>
+To: <foo-chat@example.com>
+Subject: This is a post
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <ijklmnop@sator.example.com>
+
+Hello
+";
+    let envelope = melib::Envelope::from_bytes(rejected, None).expect("Could not parse message");
+    db.post(&envelope, rejected, false).unwrap();
+    let events = db
+        .post_events(None, Some("ijklmnop@sator.example.com"), None)
+        .unwrap();
+    assert_eq!(events[0].kind, PostEventKind::Filter);
+    assert_eq!(events[0].name, "PostRightsCheck");
+    assert_eq!(
+        events[0].detail.as_deref(),
+        Some("action: reject, stopped processing")
+    );
+    let action = events.last().unwrap();
+    assert_eq!(action.name, "reject");
+    assert_eq!(
+        action.detail.as_deref(),
+        Some("Only subscriptions can post to this list.")
+    );
+
+    let no_list = b"From: Name <user@example.com>
+To: <nobody@example.com>
+Subject: This is a post
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <qrstuvwx@sator.example.com>
+
+Hello
+";
+    let envelope = melib::Envelope::from_bytes(no_list, None).expect("Could not parse message");
+    db.post(&envelope, no_list, false).unwrap_err();
+    let events = db
+        .post_events(None, Some("qrstuvwx@sator.example.com"), None)
+        .unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].kind, PostEventKind::Error);
+    assert_eq!(events[0].list, None);
+
+    assert_eq!(db.post_events(None, None, Some(2)).unwrap().len(), 2);
+    assert_eq!(
+        db.post_events(None, None, Some(1)).unwrap()[0].kind,
+        PostEventKind::Error
+    );
+}
@@ -0,0 +1,127 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{models::*, Configuration, Connection};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_post_headers_json() {
+    init_stderr_logging();
+
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: 0,
+        list: foo_chat.pk(),
+        announce_only: false,
+        subscription_only: false,
+        approval_needed: false,
+        open: true,
+        custom: false,
+    })
+    .unwrap();
+
+    let db = db.untrusted();
+    for (i, mailer) in ["mutt", "meli"].into_iter().enumerate() {
+        let bytes = format!(
+            "From: <user@example.com>
+To: <foo-chat@example.com>
+Subject: =?UTF-8?Q?Caf=C3=A9?=
+ news {i}
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <{mailer}@example.com>
+X-Mailer: {mailer}
+Keywords: coffee
+Keywords: beans
+Content-Type: text/plain
+
+Hello
+"
+        );
+        let envelope = melib::Envelope::from_bytes(bytes.as_bytes(), None).unwrap();
+        db.post(&envelope, bytes.as_bytes(), /* dry_run */ false)
+            .unwrap();
+    }
+
+    let mut posts = db.list_posts(foo_chat.pk(), None).unwrap();
+    posts.sort_by_key(|p| p.pk());
+    assert_eq!(posts.len(), 2);
+    let headers = posts[0].headers_json.as_ref().unwrap();
+    assert_eq!(
+        headers["keywords"],
+        serde_json::json!(["coffee", "beans"]),
+        "{headers}"
+    );
+    assert_eq!(posts[0].header("X-Mailer"), Some("mutt"));
+    assert_eq!(posts[0].header("Subject"), Some("[foo-chat] Café news 0"));
+    assert_eq!(posts[0].header("List-Id"), Some("<foo-chat.example.com>"));
+    assert_eq!(posts[0].header("X-Not-There"), None);
+
+    let by_mailer = db
+        .list_posts_by_header(foo_chat.pk(), "x-mailer", Some("meli"))
+        .unwrap();
+    assert_eq!(by_mailer.len(), 1);
+    assert_eq!(by_mailer[0].message_id, "meli@example.com");
+    assert_eq!(
+        db.list_posts_by_header(foo_chat.pk(), "KEYWORDS", Some("beans"))
+            .unwrap()
+            .len(),
+        2
+    );
+    assert_eq!(
+        db.list_posts_by_header(foo_chat.pk(), "List-Id", None)
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(db
+        .list_posts_by_header(foo_chat.pk(), "X-Mailer", Some("pine"))
+        .unwrap()
+        .is_empty());
+
+    // Existing posts are parsed by the migration.
+    let db = db.trusted();
+    let version = db.schema_version().unwrap();
+    db.migrate(version, 23).unwrap();
+    assert!(db
+        .list_posts(foo_chat.pk(), None)
+        .unwrap()
+        .iter()
+        .all(|p| p.headers_json.is_none()));
+    db.migrate(23, version).unwrap();
+    let mut migrated = db.list_posts(foo_chat.pk(), None).unwrap();
+    migrated.sort_by_key(|p| p.pk());
+    assert_eq!(migrated, posts);
+}
@@ -0,0 +1,303 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{
+    blobs::BLOB_MIN_SIZE,
+    models::*,
+    privacy::{mentions, pseudonymise_headers},
+    queue::{Queue, QueueEntry},
+    Configuration, Connection,
+};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_pseudonymise_headers() {
+    let message = b"From: Alice <Alice@example.com>\r\nTo: foo-chat@example.com,\r\n jalice@example.com, alice@example.com\r\nMessage-ID: <1.alice@example.com>\r\nSubject: alice@example.com\r\n\r\nBody alice@example.com\r\n";
+    let ret = pseudonymise_headers(message, "alice@example.com", "anon@invalid");
+    assert_eq!(
+        String::from_utf8(ret).unwrap(),
+        "From: <anon@invalid>\r\nTo: foo-chat@example.com,\r\n jalice@example.com, \
+         anon@invalid\r\nMessage-ID: <1.alice@example.com>\r\nSubject: anon@invalid\r\n\r\nBody \
+         alice@example.com\r\n"
+    );
+    assert!(mentions("Alice <ALICE@example.com>", "alice@example.com"));
+    assert!(!mentions("<jalice@example.com>", "alice@example.com"));
+}
+
+#[test]
+fn test_personal_data_export_and_erasure() {
+    init_stderr_logging();
+
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration {
+        blob_store: true,
+        ..Configuration::new(db_path)
+    };
+
+    let db = Connection::open_or_create_db(config.clone())
+        .unwrap()
+        .trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: 0,
+        list: foo_chat.pk(),
+        announce_only: false,
+        subscription_only: false,
+        approval_needed: false,
+        open: true,
+        custom: false,
+    })
+    .unwrap();
+    db.add_account(Account {
+        pk: 0,
+        name: Some("Alice".into()),
+        address: "alice@example.com".into(),
+        public_key: None,
+        password: "ssh-ed25519 AAAA".into(),
+        enabled: true,
+    })
+    .unwrap();
+    let subscription = |address: &str| ListSubscription {
+        pk: 0,
+        list: foo_chat.pk(),
+        address: address.into(),
+        name: None,
+        account: None,
+        enabled: true,
+        verified: true,
+        digest: false,
+        hide_address: false,
+        receive_duplicates: true,
+        receive_own_posts: false,
+        receive_confirmation: true,
+    };
+    let alice_sub = db
+        .add_subscription(foo_chat.pk(), subscription("Alice@Example.com"))
+        .unwrap();
+    db.add_subscription(foo_chat.pk(), subscription("bob@example.com"))
+        .unwrap();
+    db.connection
+        .execute(
+            "INSERT INTO bounce(subscription, count) VALUES (?, 2);",
+            [alice_sub.pk()],
+        )
+        .unwrap();
+    db.add_list_owner(ListOwner {
+        pk: 0,
+        list: foo_chat.pk(),
+        address: "alice@example.com".into(),
+        name: None,
+    })
+    .unwrap();
+
+    let root = b"From: Alice <alice@example.com>
+To: <foo-chat@example.com>
+Subject: Hello from Alice
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <root@example.com>
+Content-Type: text/plain
+
+Hi all
+";
+    let reply = b"From: Bob <bob@example.com>
+To: <foo-chat@example.com>
+Cc: Alice <alice@example.com>
+Subject: Re: Hello from Alice
+Date: Thu, 29 Oct 2020 14:58:16 +0000
+Message-ID: <reply@example.com>
+In-Reply-To: <root@example.com>
+References: <root@example.com>
+Content-Type: text/plain
+
+alice@example.com wrote:
+> Hi all
+";
+    for bytes in [&root[..], &reply[..]] {
+        let envelope = melib::Envelope::from_bytes(bytes, None).unwrap();
+        db.post(&envelope, bytes, /* dry_run */ false).unwrap();
+    }
+    // The large attachment of a queue entry goes to the blob store.
+    let attachment = "QUJDRA==\r\n".repeat(BLOB_MIN_SIZE / 8);
+    db.insert_to_queue(
+        QueueEntry::new(
+            Queue::Error,
+            Some(foo_chat.pk()),
+            None,
+            format!(
+                "From: <alice@example.com>\r\nTo: <foo-chat@example.com>\r\nSubject: \
+                 Broken\r\nMessage-ID: <broken@example.com>\r\nMIME-Version: 1.0\r\nContent-Type: \
+                 multipart/mixed; boundary=\"=_boundary\"\r\n\r\n--=_boundary\r\nContent-Type: \
+                 text/plain\r\n\r\nHello\r\n--=_boundary\r\nContent-Type: \
+                 application/octet-stream\r\nContent-Transfer-Encoding: \
+                 base64\r\n\r\n{attachment}--=_boundary--\r\n"
+            )
+            .as_bytes(),
+            None,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let blob_count = || -> usize {
+        db.connection
+            .query_row("SELECT count(*) FROM blob;", [], |row| row.get(0))
+            .unwrap()
+    };
+    assert_eq!(blob_count(), 1);
+    // Delivery events list their recipients.
+    let delivery = db
+        .insert_post_event(
+            Some(foo_chat.pk()),
+            "reply@example.com",
+            PostEventKind::Delivery,
+            "delivered",
+            Some("bob@example.com, Alice@example.com"),
+        )
+        .unwrap();
+
+    let mentioning_events = {
+        let db = Connection::open_db(config).unwrap();
+        let data = db.personal_data("ALICE@example.com").unwrap();
+        assert_eq!(data.account.as_ref().unwrap().address, "alice@example.com");
+        assert_eq!(data.subscriptions.len(), 1);
+        assert_eq!(data.subscriptions[0].pk(), alice_sub.pk());
+        assert_eq!(data.list_ownerships.len(), 1);
+        assert_eq!(data.bounces.len(), 1);
+        assert_eq!(data.bounces[0].count, 2);
+        assert_eq!(data.posts.len(), 1);
+        assert_eq!(data.posts[0].message_id, "root@example.com");
+        assert!(data
+            .queue_entries
+            .iter()
+            .any(|e| e.queue == Queue::Error && e.subject == "Broken"));
+        assert!(data.queue_entries.iter().all(|e| {
+            mentions(&e.from_address, "alice@example.com")
+                || mentions(&e.to_addresses, "alice@example.com")
+        }));
+        // The processing events of Alice's post, and the delivery of Bob's.
+        assert!(data.post_events.iter().any(|e| e.pk() == delivery.pk()));
+        assert!(data
+            .post_events
+            .iter()
+            .any(|e| e.message_id == "root@example.com" && e.kind == PostEventKind::Action));
+        assert!(data.post_events.iter().all(|e| {
+            e.message_id == "root@example.com"
+                || e.detail
+                    .as_deref()
+                    .is_some_and(|d| mentions(d, "alice@example.com"))
+        }));
+        data.post_events
+            .iter()
+            .filter(|e| {
+                e.detail
+                    .as_deref()
+                    .is_some_and(|d| mentions(d, "alice@example.com"))
+            })
+            .count()
+    };
+
+    let report = db.erase_personal_data("alice@example.com").unwrap();
+    assert!(report.account);
+    assert_eq!(report.subscriptions, 1);
+    assert_eq!(report.list_ownerships, 1);
+    assert_eq!(report.bounces, 1);
+    assert_eq!(report.posts, 2);
+    assert_eq!(report.post_events, mentioning_events);
+    assert_eq!(report.blobs, 1);
+    assert_eq!(blob_count(), 0);
+    assert!(report.pseudonym.ends_with("@invalid"));
+
+    let data = db.personal_data("alice@example.com").unwrap();
+    assert!(data.account.is_none());
+    assert!(data.subscriptions.is_empty());
+    assert!(data.list_ownerships.is_empty());
+    assert!(data.bounces.is_empty());
+    assert!(data.posts.is_empty());
+    assert!(data.queue_entries.is_empty());
+    assert!(data.post_events.is_empty());
+    let delivery = db
+        .post_events(None, Some("reply@example.com"), None)
+        .unwrap()
+        .into_iter()
+        .find(|e| e.pk() == delivery.pk())
+        .unwrap();
+    assert_eq!(
+        delivery.detail.as_deref(),
+        Some(format!("bob@example.com, {}", report.pseudonym).as_str())
+    );
+    assert!(db
+        .list_subscription_by_address(foo_chat.pk(), "bob@example.com")
+        .is_ok());
+
+    // Posts are kept, with the address replaced in their headers.
+    let root = db
+        .list_post_by_message_id(foo_chat.pk(), "root@example.com")
+        .unwrap()
+        .unwrap();
+    assert_eq!(root.address, report.pseudonym);
+    let text = String::from_utf8(root.message.clone()).unwrap();
+    assert!(
+        text.contains(&format!("From: <{}>", report.pseudonym)),
+        "{text}"
+    );
+    assert!(!text.contains("Alice <"), "{text}");
+    assert_eq!(
+        root.header("from").unwrap(),
+        format!("<{}>", report.pseudonym)
+    );
+    let reply = db
+        .list_post_by_message_id(foo_chat.pk(), "reply@example.com")
+        .unwrap()
+        .unwrap();
+    assert_eq!(reply.address, "bob@example.com");
+    let text = String::from_utf8(reply.message.clone()).unwrap();
+    assert!(
+        text.contains(&format!("Cc: Alice <{}>", report.pseudonym)),
+        "{text}"
+    );
+    assert!(text.contains("In-Reply-To: <root@example.com>"), "{text}");
+
+    // Threads stay intact.
+    let thread = db.list_thread(foo_chat.pk(), "root@example.com").unwrap();
+    assert_eq!(thread.len(), 1);
+    assert_eq!(thread[0].1.message_id, "reply@example.com");
+
+    // The search index no longer knows the sender.
+    let indexed: i64 = db
+        .connection
+        .query_row(
+            "SELECT count(*) FROM post_fts WHERE post_fts MATCH 'sender:alice';",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(indexed, 0);
+}
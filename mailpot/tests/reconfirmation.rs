@@ -0,0 +1,206 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{
+    models::*, queue::Queue, reconfirmations::ReconfirmationStatus, Configuration, Connection,
+};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+fn post(db: &Connection, from: &str, subject: &str) {
+    let bytes = format!(
+        "From: <{from}>
+To: <foo-chat@example.com>
+Subject: {subject}
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <{from}.{}@example.com>
+Content-Type: text/plain
+
+Hello
+",
+        subject.len()
+    );
+    let envelope = melib::Envelope::from_bytes(bytes.as_bytes(), None).unwrap();
+    db.post(&envelope, bytes.as_bytes(), /* dry_run */ false)
+        .unwrap();
+}
+
+#[test]
+fn test_reconfirmation_campaign() {
+    init_stderr_logging();
+
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: 0,
+        list: foo_chat.pk(),
+        announce_only: false,
+        subscription_only: true,
+        approval_needed: false,
+        open: false,
+        custom: false,
+    })
+    .unwrap();
+    let mut subscriptions = vec![];
+    for address in ["a@example.com", "b@example.com", "c@example.com"] {
+        subscriptions.push(
+            db.add_subscription(
+                foo_chat.pk(),
+                ListSubscription {
+                    pk: 0,
+                    list: foo_chat.pk(),
+                    address: address.into(),
+                    name: None,
+                    account: None,
+                    enabled: true,
+                    verified: true,
+                    digest: false,
+                    hide_address: false,
+                    receive_duplicates: true,
+                    receive_own_posts: false,
+                    receive_confirmation: false,
+                },
+            )
+            .unwrap(),
+        );
+    }
+    // The subscriptions are an hour old.
+    db.connection
+        .execute("UPDATE subscription SET created = created - 3600;", [])
+        .unwrap();
+    let db = db.untrusted();
+    // "c" is active, so only "a" and "b" are stale by inactivity.
+    post(&db, "c@example.com", "hello");
+    let now = chrono::offset::Utc::now().timestamp() as u64;
+    let stale = db
+        .stale_subscriptions(foo_chat.pk(), None, Some(now - 60))
+        .unwrap();
+    assert_eq!(
+        stale.iter().map(|s| s.address.as_str()).collect::<Vec<_>>(),
+        vec!["a@example.com", "b@example.com"]
+    );
+    let stale = db
+        .stale_subscriptions(foo_chat.pk(), Some(now - 60), None)
+        .unwrap();
+    assert_eq!(stale.len(), 3);
+
+    let db = db.trusted();
+    let stale: Vec<_> = stale
+        .into_iter()
+        .filter(|s| s.address != "c@example.com")
+        .collect();
+    let expires = now + 60;
+    for s in &stale {
+        db.send_reconfirmation_request(&foo_chat, s, expires)
+            .unwrap();
+    }
+    assert_eq!(
+        db.list_reconfirmation_status(foo_chat.pk()).unwrap(),
+        ReconfirmationStatus {
+            pending: 2,
+            confirmed: 0,
+            disabled: 0,
+        }
+    );
+    // Pending requests are not sent again.
+    assert_eq!(
+        db.stale_subscriptions(foo_chat.pk(), Some(now - 60), None)
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // "a" confirms by replying to the request.
+    let request = db
+        .queue(Queue::Out)
+        .unwrap()
+        .into_iter()
+        .find(|e| {
+            e.to_addresses == "a@example.com"
+                && e.comment.as_deref() == Some("subscription-reconfirmation request")
+        })
+        .unwrap();
+    let db = db.untrusted();
+    let reply = format!(
+        "From: <a@example.com>
+To: <foo-chat+request@example.com>
+Subject: Re: {}
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <reconfirm@example.com>
+Content-Type: text/plain
+
+",
+        request.subject
+    );
+    let envelope = melib::Envelope::from_bytes(reply.as_bytes(), None).unwrap();
+    db.post(&envelope, reply.as_bytes(), /* dry_run */ false)
+        .unwrap();
+    assert!(db
+        .reconfirmation(subscriptions[0].pk())
+        .unwrap()
+        .unwrap()
+        .confirmed
+        .is_some());
+
+    // "b" does not answer and is disabled after the grace period.
+    let db = db.trusted();
+    assert!(db
+        .disable_unconfirmed_subscriptions(expires)
+        .unwrap()
+        .is_empty());
+    let disabled = db.disable_unconfirmed_subscriptions(expires + 1).unwrap();
+    assert_eq!(disabled.len(), 1);
+    assert_eq!(disabled[0].address, "b@example.com");
+    assert!(!disabled[0].enabled);
+    assert_eq!(
+        db.list_reconfirmation_status(foo_chat.pk()).unwrap(),
+        ReconfirmationStatus {
+            pending: 0,
+            confirmed: 1,
+            disabled: 1,
+        }
+    );
+
+    // Disabled subscriptions no longer receive posts.
+    let db = db.untrusted();
+    let before = db.queue(Queue::Out).unwrap().len();
+    post(&db, "a@example.com", "hello again");
+    let out = db.queue(Queue::Out).unwrap();
+    assert!(out[before..]
+        .iter()
+        .all(|e| !e.to_addresses.contains("b@example.com")));
+    assert!(out[before..]
+        .iter()
+        .any(|e| e.to_addresses.contains("c@example.com")));
+}
@@ -0,0 +1,157 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{
+    models::{changesets::MailingListChangeset, *},
+    redaction::{Redaction, HIDDEN_ADDRESS},
+    Configuration, Connection,
+};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+const MESSAGE: &str = "From: Alice <alice@example.com>
+To: <foo-chat@example.com>
+Cc: bob@example.com
+Subject: Re: Hello
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <reply@example.com>
+In-Reply-To: <root@example.com>
+Content-Type: text/plain
+
+On Thu, 29 Oct 2020, Bob <bob@example.com> wrote:
+> Carol <carol@example.com> wrote:
+>> Hi
+> Hello
+Write to help@example.com for help.
+";
+
+#[test]
+fn test_redaction() {
+    let redaction = Redaction::default();
+    assert!(redaction.is_noop());
+    assert_eq!(redaction.message(MESSAGE.as_bytes()), MESSAGE.as_bytes());
+
+    let redaction = Redaction {
+        mode: ArchiveAddresses::Obfuscate,
+        hidden: ["bob@example.com".to_string()].into_iter().collect(),
+    };
+    assert_eq!(
+        redaction.text("Alice <alice@example.com>"),
+        "Alice <alice at example.com>"
+    );
+    assert_eq!(
+        redaction.text("Bob <Bob@Example.com>"),
+        format!("Bob <{HIDDEN_ADDRESS}>")
+    );
+    let message = redaction.message(MESSAGE.as_bytes());
+    let message = String::from_utf8_lossy(&message);
+    assert!(message.contains("From: Alice <alice at example.com>\n"));
+    assert!(message.contains("To: <foo-chat at example.com>\n"));
+    assert!(message.contains(&format!("Cc: {HIDDEN_ADDRESS}\n")));
+    // Threading headers are kept.
+    assert!(message.contains("Message-ID: <reply@example.com>\n"));
+    assert!(message.contains("In-Reply-To: <root@example.com>\n"));
+    // Attribution lines are redacted, including quoted ones, but not the
+    // rest of the body.
+    assert!(message.contains(&format!("Bob <{HIDDEN_ADDRESS}> wrote:\n")));
+    assert!(message.contains("> Carol <carol at example.com> wrote:\n"));
+    assert!(message.contains("Write to help@example.com for help.\n"));
+
+    let redaction = Redaction {
+        mode: ArchiveAddresses::Hide,
+        ..Default::default()
+    };
+    assert_eq!(
+        redaction.text("alice@example.com, bob@example.com"),
+        format!("{HIDDEN_ADDRESS}, {HIDDEN_ADDRESS}")
+    );
+    assert_eq!(redaction.text("Not an address: a@b"), "Not an address: a@b");
+}
+
+#[test]
+fn test_list_redaction() {
+    init_stderr_logging();
+
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+
+    assert_eq!(
+        db.list_archive_addresses(foo_chat.pk()).unwrap(),
+        ArchiveAddresses::Show
+    );
+    assert!(db.list_archive_raw_download(foo_chat.pk()).unwrap());
+    assert!(db.list_redaction(foo_chat.pk()).unwrap().is_noop());
+
+    for (address, hide_address) in [("Alice@Example.com", true), ("bob@example.com", false)] {
+        db.add_subscription(
+            foo_chat.pk(),
+            ListSubscription {
+                pk: 0,
+                list: foo_chat.pk(),
+                address: address.into(),
+                name: None,
+                account: None,
+                enabled: true,
+                verified: true,
+                digest: false,
+                hide_address,
+                receive_duplicates: true,
+                receive_own_posts: false,
+                receive_confirmation: true,
+            },
+        )
+        .unwrap();
+    }
+    db.update_list(MailingListChangeset {
+        pk: foo_chat.pk(),
+        archive_addresses: Some(ArchiveAddresses::Obfuscate),
+        archive_raw_download: Some(false),
+        ..Default::default()
+    })
+    .unwrap();
+    assert!(!db.list_archive_raw_download(foo_chat.pk()).unwrap());
+
+    let redaction = db.list_redaction(foo_chat.pk()).unwrap();
+    assert_eq!(
+        redaction,
+        Redaction {
+            mode: ArchiveAddresses::Obfuscate,
+            hidden: ["alice@example.com".to_string()].into_iter().collect(),
+        }
+    );
+    assert_eq!(
+        redaction.text("alice@example.com, bob@example.com"),
+        format!("{HIDDEN_ADDRESS}, bob at example.com")
+    );
+}
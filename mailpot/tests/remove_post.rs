@@ -0,0 +1,227 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{
+    blobs::BLOB_MIN_SIZE, models::*, queue::Queue, search::Paging, Configuration, Connection,
+};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_remove_post() {
+    init_stderr_logging();
+
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration {
+        blob_store: true,
+        ..Configuration::new(db_path)
+    };
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: 0,
+        list: foo_chat.pk(),
+        announce_only: false,
+        subscription_only: false,
+        approval_needed: false,
+        open: true,
+        custom: false,
+    })
+    .unwrap();
+    db.add_subscription(
+        foo_chat.pk(),
+        ListSubscription {
+            pk: 0,
+            list: foo_chat.pk(),
+            address: "bob@example.com".into(),
+            name: None,
+            account: None,
+            enabled: true,
+            verified: true,
+            digest: false,
+            hide_address: false,
+            receive_duplicates: true,
+            receive_own_posts: true,
+            receive_confirmation: false,
+        },
+    )
+    .unwrap();
+
+    // The secret post has a large attachment, which goes to the blob store.
+    let attachment = "QUJDRA==\n".repeat(BLOB_MIN_SIZE / 8);
+    let secret = format!(
+        "MIME-Version: 1.0
+Content-Type: multipart/mixed; boundary=\"=_boundary\"
+
+--=_boundary
+Content-Type: text/plain
+
+The password is hunter2
+--=_boundary
+Content-Type: application/octet-stream
+Content-Transfer-Encoding: base64
+
+{attachment}--=_boundary--"
+    );
+    for (date, message_id, headers, body) in [
+        (
+            "Sun, 1 Nov 2020",
+            "old@example.com",
+            "",
+            "Content-Type: text/plain\n\nAn old post",
+        ),
+        ("Wed, 1 Mar 2023", "root@example.com", "", secret.as_str()),
+        (
+            "Thu, 2 Mar 2023",
+            "reply@example.com",
+            "In-Reply-To: <root@example.com>\nReferences: <root@example.com>\n",
+            "Content-Type: text/plain\n\nPlease remove that",
+        ),
+    ] {
+        let bytes = format!(
+            "From: Alice <alice@example.com>
+To: <foo-chat@example.com>
+Subject: Post of {date}
+Date: {date} 13:58:16 +0000
+Message-ID: <{message_id}>
+{headers}{body}
+"
+        );
+        let envelope = melib::Envelope::from_bytes(bytes.as_bytes(), None).unwrap();
+        db.post(&envelope, bytes.as_bytes(), /* dry_run */ false)
+            .unwrap();
+    }
+    db.rotate_archives(2021, false).unwrap();
+    let blob_count = || -> usize {
+        db.connection
+            .query_row("SELECT count(*) FROM blob;", [], |row| row.get(0))
+            .unwrap()
+    };
+    assert_eq!(blob_count(), 1);
+    let queued = |message_id: &str| {
+        db.queue(Queue::Out)
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.message_id == message_id)
+            .count()
+    };
+    assert_eq!(queued("root@example.com"), 1);
+    assert_eq!(queued("reply@example.com"), 1);
+    assert_eq!(
+        db.search_posts(foo_chat.pk(), "hunter2", Paging::default())
+            .unwrap()
+            .len(),
+        1
+    );
+
+    let tombstone = db
+        .remove_post(
+            foo_chat.pk(),
+            "<root@example.com>",
+            "Leaked secret",
+            "admin",
+        )
+        .unwrap();
+    assert_eq!(tombstone.message_id, "root@example.com");
+    assert_eq!(tombstone.reason, "Leaked secret");
+    assert_eq!(tombstone.removed_by, "admin");
+    assert_eq!(
+        db.post_tombstone(foo_chat.pk(), "root@example.com")
+            .unwrap(),
+        Some(tombstone.clone())
+    );
+
+    // The message is replaced, but the thread stays intact.
+    let post = db
+        .list_post_by_message_id(foo_chat.pk(), "root@example.com")
+        .unwrap()
+        .unwrap();
+    let message = String::from_utf8_lossy(&post.message);
+    assert!(!message.contains("hunter2"));
+    assert!(!message.contains("alice@example.com"));
+    assert!(message.contains("This post was removed by the moderators."));
+    assert!(message.contains("Reason: Leaked secret"));
+    assert!(!message.contains("admin"));
+    assert_eq!(post.address, "foo-chat@example.com");
+    assert_eq!(post.envelope_from, None);
+    assert_eq!(post.header("subject"), Some("[removed]"));
+    assert_eq!(post.header("message-id"), Some("<root@example.com>"));
+    let thread = db.list_thread(foo_chat.pk(), "root@example.com").unwrap();
+    assert_eq!(thread.len(), 1);
+    assert_eq!(thread[0].1.message_id, "reply@example.com");
+    assert!(db
+        .search_posts(foo_chat.pk(), "hunter2", Paging::default())
+        .unwrap()
+        .is_empty());
+
+    // Its copies waiting to be sent and its attachment are deleted.
+    assert_eq!(queued("root@example.com"), 0);
+    assert_eq!(queued("reply@example.com"), 1);
+    assert_eq!(blob_count(), 0);
+
+    // The mbox export shows the placeholder.
+    let mbox = db.export_mbox(foo_chat.pk(), None, false).unwrap();
+    let mbox = String::from_utf8_lossy(&mbox);
+    assert!(mbox.contains("This post was removed by the moderators."));
+    assert!(!mbox.contains("hunter2"));
+
+    // Posts are removed only once.
+    db.remove_post(foo_chat.pk(), "root@example.com", "Again", "admin")
+        .unwrap_err();
+    db.remove_post(foo_chat.pk(), "missing@example.com", "Spam", "admin")
+        .unwrap_err();
+
+    // Posts in archive databases can be removed too.
+    db.remove_post(foo_chat.pk(), "old@example.com", "Spam", "admin")
+        .unwrap();
+    let post = db
+        .list_post_by_message_id(foo_chat.pk(), "old@example.com")
+        .unwrap()
+        .unwrap();
+    assert!(!String::from_utf8_lossy(&post.message).contains("An old post"));
+    assert!(db
+        .search_posts(foo_chat.pk(), "old post", Paging::default())
+        .unwrap()
+        .is_empty());
+
+    assert_eq!(
+        db.list_post_tombstones(foo_chat.pk())
+            .unwrap()
+            .into_iter()
+            .map(|t| t.into_inner().message_id)
+            .collect::<Vec<_>>(),
+        vec![
+            "root@example.com".to_string(),
+            "old@example.com".to_string()
+        ]
+    );
+}
@@ -0,0 +1,205 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{
+    blobs::BLOB_MIN_SIZE,
+    models::{changesets::MailingListChangeset, *},
+    queue::{Queue, QueueEntry},
+    retention::{GcReport, PostsGc, QueueGc, QueueRetention},
+    Configuration, Connection,
+};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+const DAY: u64 = 24 * 60 * 60;
+
+#[test]
+fn test_gc() {
+    init_stderr_logging();
+
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration {
+        queue_retention: QueueRetention {
+            error: Some(30),
+            scheduled: Some(30),
+            ..Default::default()
+        },
+        blob_store: true,
+        ..Configuration::new(db_path)
+    };
+
+    // 2023-06-01T00:00:00Z
+    let now: u64 = 1_685_577_600;
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let mut lists = vec![];
+    for id in ["foo-chat", "bar-chat"] {
+        let list = db
+            .create_list(MailingList {
+                pk: 0,
+                name: id.into(),
+                id: id.into(),
+                address: format!("{id}@example.com"),
+                description: None,
+                topics: vec![],
+                archive_url: None,
+            })
+            .unwrap();
+        db.set_list_post_policy(PostPolicy {
+            pk: 0,
+            list: list.pk(),
+            announce_only: false,
+            subscription_only: false,
+            approval_needed: false,
+            open: true,
+            custom: false,
+        })
+        .unwrap();
+        for date in ["Sun, 1 Nov 2020", "Wed, 1 Mar 2023"] {
+            let bytes = format!(
+                "From: <user@example.com>
+To: <{id}@example.com>
+Subject: Post of {date}
+Date: {date} 13:58:16 +0000
+Message-ID: <{id}-{}@example.com>
+Content-Type: text/plain
+
+Hello
+",
+                &date[date.len() - 4..]
+            );
+            let envelope = melib::Envelope::from_bytes(bytes.as_bytes(), None).unwrap();
+            db.post(&envelope, bytes.as_bytes(), /* dry_run */ false)
+                .unwrap();
+        }
+        lists.push(list);
+    }
+    let (foo_chat, bar_chat) = (&lists[0], &lists[1]);
+
+    assert_eq!(db.list_post_retention(foo_chat.pk()).unwrap(), None);
+    db.update_list(MailingListChangeset {
+        pk: foo_chat.pk(),
+        post_retention_days: Some(Some(365)),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(db.list_post_retention(foo_chat.pk()).unwrap(), Some(365));
+
+    // Old posts in archives are collected too.
+    db.rotate_archives(2021, false).unwrap();
+
+    // The large attachment of the oldest error goes to the blob store.
+    let attachment = "QUJDRA==\r\n".repeat(BLOB_MIN_SIZE / 8);
+    // Scheduled entries are aged by their send time.
+    for (queue, age, send_at) in [
+        (Queue::Error, 40, None),
+        (Queue::Error, 10, None),
+        (Queue::Deferred, 400, None),
+        (Queue::Scheduled, 400, Some(now + 10 * DAY)),
+        (Queue::Scheduled, 50, Some(now - 40 * DAY)),
+    ] {
+        let body = if age == 40 {
+            format!(
+                "MIME-Version: 1.0\r\nContent-Type: multipart/mixed; \
+                 boundary=\"=_boundary\"\r\n\r\n--=_boundary\r\nContent-Type: \
+                 text/plain\r\n\r\nHello\r\n--=_boundary\r\nContent-Type: \
+                 application/octet-stream\r\nContent-Transfer-Encoding: \
+                 base64\r\n\r\n{attachment}--=_boundary--\r\n"
+            )
+        } else {
+            "\r\nHello\r\n".to_string()
+        };
+        let mut entry = QueueEntry::new(
+            queue,
+            Some(foo_chat.pk()),
+            None,
+            format!(
+                "From: <user@example.com>\r\nTo: <foo-chat@example.com>\r\nSubject: {queue} \
+                 {age}\r\nMessage-ID: <{queue}-{age}@example.com>\r\n{body}"
+            )
+            .as_bytes(),
+            None,
+        )
+        .unwrap();
+        entry.timestamp = now - age * DAY;
+        entry.send_at = send_at;
+        db.insert_to_queue(entry).unwrap();
+    }
+    let blob_count = || -> usize {
+        db.connection
+            .query_row("SELECT count(*) FROM blob;", [], |row| row.get(0))
+            .unwrap()
+    };
+    assert_eq!(blob_count(), 1);
+
+    let expected = GcReport {
+        posts: vec![PostsGc {
+            list: foo_chat.pk(),
+            list_id: "foo-chat".to_string(),
+            posts: 1,
+        }],
+        queues: vec![
+            QueueGc {
+                queue: Queue::Error,
+                entries: 1,
+            },
+            QueueGc {
+                queue: Queue::Scheduled,
+                entries: 1,
+            },
+        ],
+        blobs: 1,
+    };
+    assert_eq!(db.gc(now, true).unwrap(), expected);
+    assert_eq!(db.list_posts(foo_chat.pk(), None).unwrap().len(), 2);
+    assert_eq!(db.queue(Queue::Error).unwrap().len(), 2);
+    assert_eq!(blob_count(), 1);
+
+    assert_eq!(db.gc(now, false).unwrap(), expected);
+    assert!(db.gc(now, false).unwrap().is_empty());
+    assert_eq!(
+        db.list_posts(foo_chat.pk(), None)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.message_id.clone())
+            .collect::<Vec<_>>(),
+        vec!["foo-chat-2023@example.com".to_string()]
+    );
+    assert_eq!(db.list_posts(bar_chat.pk(), None).unwrap().len(), 2);
+    let errors = db.queue(Queue::Error).unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].subject, "error 10");
+    assert_eq!(db.queue(Queue::Deferred).unwrap().len(), 1);
+    let scheduled = db.queue(Queue::Scheduled).unwrap();
+    assert_eq!(scheduled.len(), 1);
+    assert_eq!(scheduled[0].subject, "scheduled 400");
+    assert_eq!(blob_count(), 0);
+
+    // Removing the retention period keeps posts forever.
+    db.update_list(MailingListChangeset {
+        pk: foo_chat.pk(),
+        post_retention_days: Some(None),
+        ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(db.list_post_retention(foo_chat.pk()).unwrap(), None);
+    assert!(db.gc(now + 1000 * DAY, false).unwrap().posts.is_empty());
+}
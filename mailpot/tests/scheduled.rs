@@ -0,0 +1,173 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{
+    melib,
+    models::*,
+    queue::Queue,
+    scheduled::{parse_send_at, SEND_AT_HEADER},
+    Configuration, Connection,
+};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_scheduled_posts() {
+    init_stderr_logging();
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let list = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar announcements".into(),
+            id: "foo-announce".into(),
+            address: "foo-announce@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: -1,
+        list: list.pk(),
+        announce_only: false,
+        subscription_only: false,
+        approval_needed: false,
+        open: true,
+        custom: false,
+    })
+    .unwrap();
+    db.add_list_owner(ListOwner {
+        pk: 0,
+        list: list.pk(),
+        address: "owner@example.com".into(),
+        name: None,
+    })
+    .unwrap();
+    for member in ["a", "b"] {
+        db.add_subscription(
+            list.pk(),
+            ListSubscription {
+                pk: -1,
+                list: list.pk(),
+                address: format!("{member}@example.com"),
+                name: None,
+                account: None,
+                digest: false,
+                enabled: true,
+                verified: true,
+                hide_address: false,
+                receive_duplicates: true,
+                receive_own_posts: false,
+                receive_confirmation: false,
+            },
+        )
+        .unwrap();
+    }
+    let db = db.untrusted();
+
+    let message = |from: &str, msg_id: &str, send_at: &str| {
+        format!(
+            "From: <{from}@example.com>
+To: <foo-announce@example.com>
+Subject: Announcement
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <{msg_id}@example.com>
+{SEND_AT_HEADER}: {send_at}
+
+Hello
+"
+        )
+        .into_bytes()
+    };
+    let out_queue = || {
+        let mut ret = db
+            .queue(Queue::Out)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.to_addresses.clone())
+            .collect::<Vec<_>>();
+        ret.sort();
+        db.delete_from_queue(Queue::Out, vec![]).unwrap();
+        ret
+    };
+    let schedule = |msg_id: &str, send_at: &str| {
+        let bytes = message("owner", msg_id, send_at);
+        let envelope = melib::Envelope::from_bytes(&bytes, None).unwrap();
+        db.schedule_post(&list, &envelope, &bytes, parse_send_at(send_at).unwrap())
+            .unwrap()
+    };
+
+    // Scheduled posts are held back until their release time.
+    schedule("first", "Fri, 01 Jan 2100 00:00:00 +0000");
+    schedule("second", "4102444800");
+    schedule("third", "2100-01-02T00:00:00Z");
+    assert!(out_queue().is_empty());
+    let scheduled = db.list_scheduled_posts(list.pk()).unwrap();
+    assert_eq!(scheduled.len(), 3);
+    assert_eq!(scheduled[0].message_id, "first@example.com");
+    assert_eq!(scheduled[0].send_at, Some(4102444800));
+    assert_eq!(scheduled[1].send_at, Some(4102444800));
+    assert_eq!(scheduled[2].message_id, "third@example.com");
+    assert_eq!(scheduled[2].send_at, Some(4102531200));
+    assert!(!String::from_utf8_lossy(&scheduled[0].message).contains(SEND_AT_HEADER));
+    assert!(db.list_posts(list.pk(), None).unwrap().is_empty());
+    parse_send_at("tomorrow").unwrap_err();
+
+    // The header of posts received by e-mail is ignored, since their sender
+    // can be forged.
+    let bytes = message("owner", "fourth", "4102444800");
+    let envelope = melib::Envelope::from_bytes(&bytes, None).unwrap();
+    db.post(&envelope, &bytes, false).unwrap();
+    assert_eq!(out_queue(), vec!["a@example.com", "b@example.com"]);
+    assert_eq!(db.list_scheduled_posts(list.pk()).unwrap().len(), 3);
+
+    db.cancel_scheduled_post(list.pk(), scheduled[2].pk())
+        .unwrap();
+    assert!(db
+        .cancel_scheduled_post(list.pk(), scheduled[2].pk())
+        .is_err());
+
+    assert!(db.release_scheduled_posts(4102444799).unwrap().is_empty());
+    let released = db.release_scheduled_posts(4102444800).unwrap();
+    assert_eq!(released.len(), 2);
+    assert!(db.list_scheduled_posts(list.pk()).unwrap().is_empty());
+    let mut out = db
+        .queue(Queue::Out)
+        .unwrap()
+        .into_iter()
+        .map(|e| e.to_addresses.clone())
+        .collect::<Vec<_>>();
+    out.sort();
+    assert_eq!(
+        out,
+        vec![
+            "a@example.com",
+            "a@example.com",
+            "b@example.com",
+            "b@example.com"
+        ]
+    );
+    assert_eq!(db.list_posts(list.pk(), None).unwrap().len(), 3);
+    assert_eq!(db.queue(Queue::Error).unwrap().len(), 0);
+}
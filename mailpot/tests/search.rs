@@ -0,0 +1,179 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{models::*, search::Paging, Configuration, Connection};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+fn post(db: &Connection, from: &str, subject: &str, body: &str) {
+    let bytes = format!(
+        "From: Sender <{from}>
+To: <foo-chat@example.com>
+Subject: {subject}
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <{}@example.com>
+Content-Type: text/plain; charset=utf-8
+Content-Transfer-Encoding: quoted-printable
+
+{body}
+",
+        subject.replace(' ', ".")
+    );
+    let envelope = melib::Envelope::from_bytes(bytes.as_bytes(), None).unwrap();
+    db.post(&envelope, bytes.as_bytes(), /* dry_run */ false)
+        .unwrap();
+}
+
+#[test]
+fn test_search_posts() {
+    init_stderr_logging();
+
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: 0,
+        list: foo_chat.pk(),
+        announce_only: false,
+        subscription_only: false,
+        approval_needed: false,
+        open: true,
+        custom: false,
+    })
+    .unwrap();
+
+    let db = db.untrusted();
+    post(
+        &db,
+        "a@example.com",
+        "Release plans",
+        "We ship the caf=C3=A9 build on Monday.",
+    );
+    post(
+        &db,
+        "b@example.com",
+        "Re: Release plans",
+        "Monday works for me.",
+    );
+    post(&db, "c@example.com", "Unrelated", "Nothing to see here.");
+
+    // Bodies are decoded and diacritics are folded.
+    let results = db
+        .search_posts(foo_chat.pk(), "cafe", Paging::default())
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].post.address, "a@example.com");
+    assert_eq!(results[0].subject, "[foo-chat] Release plans");
+    assert!(results[0].snippet.contains("Monday"), "{:?}", results[0]);
+
+    // All words must match, in any field.
+    assert_eq!(
+        db.search_posts(foo_chat.pk(), "monday release", Paging::default())
+            .unwrap()
+            .len(),
+        2
+    );
+    assert_eq!(
+        db.search_posts(foo_chat.pk(), "monday unrelated", Paging::default())
+            .unwrap()
+            .len(),
+        0
+    );
+    // Senders are indexed, and a trailing star matches a prefix.
+    assert_eq!(
+        db.search_posts(foo_chat.pk(), "c@example.com", Paging::default())
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(db
+        .search_posts_without_sender(foo_chat.pk(), "c@example.com", Paging::default())
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        db.search_posts_without_sender(foo_chat.pk(), "unrel*", Paging::default())
+            .unwrap()
+            .len(),
+        db.search_posts(foo_chat.pk(), "unrel*", Paging::default())
+            .unwrap()
+            .len()
+    );
+    assert_eq!(
+        db.search_posts(foo_chat.pk(), "unrel*", Paging::default())
+            .unwrap()
+            .len(),
+        1
+    );
+    // Query syntax is not interpreted.
+    assert!(db
+        .search_posts(foo_chat.pk(), "\"release AND (", Paging::default())
+        .unwrap()
+        .is_empty());
+    assert!(db
+        .search_posts(foo_chat.pk(), "  ", Paging::default())
+        .unwrap()
+        .is_empty());
+    // Paging.
+    let page = db
+        .search_posts(
+            foo_chat.pk(),
+            "monday",
+            Paging {
+                offset: 1,
+                limit: 10,
+            },
+        )
+        .unwrap();
+    assert_eq!(page.len(), 1);
+
+    // Removed posts are removed from the index.
+    let db = db.trusted();
+    db.connection
+        .execute("DELETE FROM post WHERE address = 'c@example.com';", [])
+        .unwrap();
+    assert!(db
+        .search_posts(foo_chat.pk(), "unrelated", Paging::default())
+        .unwrap()
+        .is_empty());
+
+    // Existing posts are indexed by the migration.
+    let version = db.schema_version().unwrap();
+    db.migrate(version, 22).unwrap();
+    db.migrate(22, version).unwrap();
+    assert_eq!(
+        db.search_posts(foo_chat.pk(), "monday", Paging::default())
+            .unwrap()
+            .len(),
+        2
+    );
+}
@@ -18,7 +18,7 @@
  */
 
 use jsonschema::JSONSchema;
-use mailpot::{rusqlite, Configuration, Connection, SendMail};
+use mailpot::{rusqlite, Configuration, Connection};
 use mailpot_tests::init_stderr_logging;
 use serde_json::{json, Value};
 use tempfile::TempDir;
@@ -35,12 +35,7 @@ fn test_settings_json_schemas_are_valid() {
             .unwrap();
         conn.close().unwrap();
     }
-    let config = Configuration {
-        send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
-    };
+    let config = Configuration::new(db_path);
     let db = Connection::open_or_create_db(config).unwrap().trusted();
 
     let schemas: Vec<String> = {
@@ -79,12 +74,7 @@ fn test_settings_json_triggers() {
             .unwrap();
         conn.close().unwrap();
     }
-    let config = Configuration {
-        send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
-    };
+    let config = Configuration::new(db_path);
     let db = Connection::open_or_create_db(config).unwrap().trusted();
     let list = db.lists().unwrap().remove(0);
 
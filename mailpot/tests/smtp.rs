@@ -34,9 +34,7 @@ fn test_smtp() {
     let db_path = tmp_dir.path().join("mpot.db");
     let config = Configuration {
         send_mail: SendMail::Smtp(smtp_handler.smtp_conf()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
+        ..Configuration::new(db_path)
     };
 
     let db = Connection::open_or_create_db(config).unwrap().trusted();
@@ -189,9 +187,7 @@ fn test_smtp_mailcrab() {
     let db_path = tmp_dir.path().join("mpot.db");
     let config = Configuration {
         send_mail: SendMail::Smtp(get_smtp_conf()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
+        ..Configuration::new(db_path)
     };
 
     let db = Connection::open_or_create_db(config).unwrap().trusted();
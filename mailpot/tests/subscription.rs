@@ -17,7 +17,7 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use mailpot::{models::*, queue::Queue, Configuration, Connection, SendMail};
+use mailpot::{models::*, queue::Queue, Configuration, Connection};
 use mailpot_tests::init_stderr_logging;
 use tempfile::TempDir;
 
@@ -28,12 +28,7 @@ fn test_list_subscription() {
     let tmp_dir = TempDir::new().unwrap();
 
     let db_path = tmp_dir.path().join("mpot.db");
-    let config = Configuration {
-        send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
-    };
+    let config = Configuration::new(db_path);
 
     let db = Connection::open_or_create_db(config).unwrap().trusted();
     assert!(db.lists().unwrap().is_empty());
@@ -115,14 +110,204 @@ MIME-Version: 1.0
         melib::Envelope::from_bytes(subscribe_bytes, None).expect("Could not parse message");
     db.post(&envelope, subscribe_bytes, /* dry_run */ false)
         .unwrap();
-    assert_eq!(db.list_subscriptions(foo_chat.pk()).unwrap().len(), 1);
-    assert_eq!(db.queue(Queue::Out).unwrap().len(), 2);
+    let subscriptions = db.list_subscriptions(foo_chat.pk()).unwrap();
+    assert_eq!(subscriptions.len(), 1);
+    assert!(!subscriptions[0].verified);
+    let out = db.queue(Queue::Out).unwrap();
+    assert_eq!(out.len(), 2);
+
+    // The subscription is confirmed by replying to the confirmation request.
+    let token = db
+        .subscription_confirmation(subscriptions[0].pk())
+        .unwrap()
+        .unwrap()
+        .token
+        .clone();
+    assert!(out[1].subject.ends_with(&format!("confirm {token}")));
+    let confirm_bytes = format!(
+        "From: Name <user@example.com>
+To: <foo-chat+request@example.com>
+Subject: Re: {}
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <confirm@sator.example.com>
+Content-Type: text/plain
+
+",
+        out[1].subject
+    );
+    let envelope = melib::Envelope::from_bytes(confirm_bytes.as_bytes(), None)
+        .expect("Could not parse message");
+    db.post(
+        &envelope,
+        confirm_bytes.as_bytes(),
+        /* dry_run */ false,
+    )
+    .unwrap();
+    let subscriptions = db.list_subscriptions(foo_chat.pk()).unwrap();
+    assert!(subscriptions[0].verified);
+    assert!(db
+        .subscription_confirmation(subscriptions[0].pk())
+        .unwrap()
+        .is_none());
+    assert_eq!(db.queue(Queue::Out).unwrap().len(), 3);
+
     let envelope = melib::Envelope::from_bytes(post_bytes, None).expect("Could not parse message");
     db.post(&envelope, post_bytes, /* dry_run */ false).unwrap();
-    assert_eq!(db.queue(Queue::Out).unwrap().len(), 2);
+    assert_eq!(db.queue(Queue::Out).unwrap().len(), 3);
     assert_eq!(db.list_posts(foo_chat.pk(), None).unwrap().len(), 1);
 }
 
+#[test]
+fn test_unconfirmed_subscription_expiry() {
+    init_stderr_logging();
+
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    let subscribe = |address: &str| {
+        let bytes = format!(
+            "From: <{address}>
+To: <foo-chat+subscribe@example.com>
+Subject: subscribe
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <{address}>
+
+"
+        );
+        let envelope = melib::Envelope::from_bytes(bytes.as_bytes(), None).unwrap();
+        db.post(&envelope, bytes.as_bytes(), /* dry_run */ false)
+            .unwrap();
+        db.list_subscription_by_address(foo_chat.pk(), address)
+            .unwrap()
+    };
+    let a = subscribe("a@example.com");
+    let b = subscribe("b@example.com");
+    let token = db.subscription_confirmation(a.pk()).unwrap().unwrap();
+    let expires = token.expires;
+    assert!(db.confirm_subscription(&token.token, expires + 1).is_err());
+    db.confirm_subscription(&token.token, expires).unwrap();
+
+    assert!(db
+        .purge_unconfirmed_subscriptions(expires)
+        .unwrap()
+        .is_empty());
+    let purged = db.purge_unconfirmed_subscriptions(expires + 1).unwrap();
+    assert_eq!(purged.len(), 1);
+    assert_eq!(purged[0].address, b.address);
+    let subscriptions = db.list_subscriptions(foo_chat.pk()).unwrap();
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0].address, "a@example.com");
+}
+
+#[test]
+fn test_confirmed_unsubscription() {
+    init_stderr_logging();
+
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let foo_chat = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.update_list(changesets::MailingListChangeset {
+        pk: foo_chat.pk(),
+        confirm_unsubscription: Some(true),
+        ..Default::default()
+    })
+    .unwrap();
+    let subscription = db
+        .add_subscription(
+            foo_chat.pk(),
+            ListSubscription {
+                pk: 0,
+                list: foo_chat.pk(),
+                address: "user@example.com".into(),
+                name: None,
+                account: None,
+                enabled: true,
+                verified: true,
+                digest: false,
+                hide_address: false,
+                receive_duplicates: true,
+                receive_own_posts: false,
+                receive_confirmation: true,
+            },
+        )
+        .unwrap();
+
+    let db = db.untrusted();
+    let request = |subject: &str| {
+        let bytes = format!(
+            "From: Name <user@example.com>
+To: <foo-chat+request@example.com>
+Subject: {subject}
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <{}@sator.example.com>
+Content-Type: text/plain
+
+",
+            subject.len()
+        );
+        let envelope = melib::Envelope::from_bytes(bytes.as_bytes(), None).unwrap();
+        db.post(&envelope, bytes.as_bytes(), /* dry_run */ false)
+            .unwrap();
+    };
+
+    // An unsubscription request only mails a confirmation token.
+    request("unsubscribe");
+    assert_eq!(db.list_subscriptions(foo_chat.pk()).unwrap().len(), 1);
+    let token = db
+        .unsubscription_confirmation(subscription.pk())
+        .unwrap()
+        .unwrap()
+        .token
+        .clone();
+    let out = db.queue(Queue::Out).unwrap();
+    assert_eq!(out.len(), 1);
+    assert_eq!(
+        out[0].comment.as_deref(),
+        Some("unsubscription-confirmation-request")
+    );
+    assert!(out[0].subject.ends_with(&format!("confirm {token}")));
+
+    // A wrong token changes nothing.
+    request(&format!("confirm {}", "0".repeat(32)));
+    assert_eq!(db.list_subscriptions(foo_chat.pk()).unwrap().len(), 1);
+
+    // Replying to the confirmation request removes the subscription.
+    request(&format!("Re: {}", out[0].subject));
+    assert!(db.list_subscriptions(foo_chat.pk()).unwrap().is_empty());
+    assert!(db
+        .unsubscription_confirmation(subscription.pk())
+        .unwrap()
+        .is_none());
+}
+
 #[test]
 fn test_post_rejection() {
     init_stderr_logging();
@@ -163,12 +348,7 @@ fn test_post_rejection() {
         let tmp_dir = TempDir::new().unwrap();
 
         let db_path = tmp_dir.path().join("mpot.db");
-        let config = Configuration {
-            send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-            db_path,
-            data_path: tmp_dir.path().to_path_buf(),
-            administrators: vec![],
-        };
+        let config = Configuration::new(db_path);
 
         let db = Connection::open_or_create_db(config).unwrap().trusted();
         assert!(db.lists().unwrap().is_empty());
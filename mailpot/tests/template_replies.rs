@@ -17,7 +17,7 @@
  * along with this program. If not, see <https://www.gnu.org/licenses/>.
  */
 
-use mailpot::{models::*, queue::Queue, Configuration, Connection, SendMail, Template};
+use mailpot::{models::*, queue::Queue, Configuration, Connection, Template};
 use mailpot_tests::init_stderr_logging;
 use tempfile::TempDir;
 
@@ -51,12 +51,7 @@ MIME-Version: 1.0
     let tmp_dir = TempDir::new().unwrap();
 
     let db_path = tmp_dir.path().join("mpot.db");
-    let config = Configuration {
-        send_mail: SendMail::ShellCommand("/usr/bin/false".to_string()),
-        db_path,
-        data_path: tmp_dir.path().to_path_buf(),
-        administrators: vec![],
-    };
+    let config = Configuration::new(db_path);
 
     let mut db = Connection::open_or_create_db(config).unwrap().trusted();
     assert!(db.lists().unwrap().is_empty());
@@ -73,6 +68,14 @@ MIME-Version: 1.0
         .unwrap();
 
     assert_eq!(foo_chat.pk(), 1);
+    /* subscriptions take effect immediately, without a confirmation request */
+    db.update_list(changesets::MailingListChangeset {
+        pk: foo_chat.pk(),
+        verify: Some(false),
+        ..Default::default()
+    })
+    .unwrap();
+    let foo_chat = db.list(foo_chat.pk()).unwrap().unwrap();
     let lists = db.lists().unwrap();
     assert_eq!(lists.len(), 1);
     assert_eq!(lists[0], foo_chat);
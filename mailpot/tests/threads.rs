@@ -0,0 +1,439 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{melib, models::*, queue::Queue, Configuration, Connection};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_thread_mute_and_follow() {
+    init_stderr_logging();
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let list = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: -1,
+        list: list.pk(),
+        announce_only: false,
+        subscription_only: true,
+        approval_needed: false,
+        open: false,
+        custom: false,
+    })
+    .unwrap();
+    let mut subscriptions = vec![];
+    for member in ["a", "b", "c", "d"] {
+        subscriptions.push(
+            db.add_subscription(
+                list.pk(),
+                ListSubscription {
+                    pk: -1,
+                    list: list.pk(),
+                    address: format!("{member}@example.com"),
+                    name: None,
+                    account: None,
+                    digest: false,
+                    enabled: true,
+                    verified: true,
+                    hide_address: false,
+                    receive_duplicates: true,
+                    receive_own_posts: false,
+                    receive_confirmation: false,
+                },
+            )
+            .unwrap(),
+        );
+    }
+    let db = db.untrusted();
+
+    let send = |from: &str, to: &str, subject: &str, msg_id: &str, reply_to: Option<&str>| {
+        let bytes = format!(
+            "From: <{from}@example.com>
+To: <{to}@example.com>
+Subject: {subject}
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <{msg_id}@example.com>
+{}
+Hello
+",
+            reply_to.map_or_else(String::new, |r| format!(
+                "In-Reply-To: <{r}@example.com>\nReferences: <{r}@example.com>\n"
+            ))
+        )
+        .into_bytes();
+        let envelope = melib::Envelope::from_bytes(&bytes, None).unwrap();
+        db.post(&envelope, &bytes, false).unwrap();
+        let mut ret = db
+            .queue(Queue::Out)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.to_addresses.clone())
+            .collect::<Vec<_>>();
+        ret.sort();
+        db.delete_from_queue(Queue::Out, vec![]).unwrap();
+        ret
+    };
+
+    assert_eq!(
+        send("a", "foo-chat", "Thread", "root", None),
+        vec!["b@example.com", "c@example.com", "d@example.com"]
+    );
+    // Posting only records a preference for follow-only subscriptions.
+    assert!(db
+        .thread_preference(subscriptions[0].pk(), "root@example.com")
+        .unwrap()
+        .is_none());
+
+    // `b` mutes the thread, `c` switches to follow-only mode.
+    assert_eq!(
+        send("b", "foo-chat+request", "Re: mute", "mute", Some("root")),
+        vec!["b@example.com"]
+    );
+    assert_eq!(
+        send("c", "foo-chat+request", "follow-only", "follow-only", None),
+        vec!["c@example.com"]
+    );
+    assert!(db.subscription_follow_only(subscriptions[2].pk()).unwrap());
+    // Muting requires replying to a post of the thread.
+    assert_eq!(
+        send("d", "foo-chat+request", "mute", "no-thread", None),
+        vec!["d@example.com"]
+    );
+    assert!(db
+        .subscription_thread_preferences(subscriptions[3].pk())
+        .unwrap()
+        .is_empty());
+
+    assert_eq!(
+        send("d", "foo-chat", "Re: Thread", "reply-1", Some("root")),
+        vec!["a@example.com"]
+    );
+    assert!(db
+        .subscription_thread_preferences(subscriptions[3].pk())
+        .unwrap()
+        .is_empty());
+    // Replying to a muted thread does not unmute it.
+    assert_eq!(
+        send("b", "foo-chat", "Re: Thread", "reply-muted", Some("root")),
+        vec!["a@example.com", "d@example.com"]
+    );
+    assert!(
+        db.thread_preference(subscriptions[1].pk(), "root@example.com")
+            .unwrap()
+            .unwrap()
+            .muted
+    );
+
+    // `c` follows the thread explicitly, `b` unmutes it.
+    send("c", "foo-chat+follow", "Re: Thread", "follow", Some("root"));
+    send("b", "foo-chat+request", "unmute", "unmute", Some("root"));
+    assert_eq!(
+        send("a", "foo-chat", "Re: Thread", "reply-2", Some("root")),
+        vec!["b@example.com", "c@example.com", "d@example.com"]
+    );
+
+    // New threads are not sent to follow-only subscriptions.
+    assert_eq!(
+        send("d", "foo-chat", "Other thread", "other", None),
+        vec!["a@example.com", "b@example.com"]
+    );
+    // Follow-only subscriptions join the threads they post to.
+    assert_eq!(
+        send(
+            "c",
+            "foo-chat",
+            "Re: Other thread",
+            "other-c",
+            Some("other")
+        ),
+        vec!["a@example.com", "b@example.com", "d@example.com"]
+    );
+    assert!(
+        !db.thread_preference(subscriptions[2].pk(), "other@example.com")
+            .unwrap()
+            .unwrap()
+            .muted
+    );
+    send("c", "foo-chat+request", "follow-all", "follow-all", None);
+    assert_eq!(
+        send(
+            "d",
+            "foo-chat",
+            "Re: Other thread",
+            "other-reply",
+            Some("other")
+        ),
+        vec!["a@example.com", "b@example.com", "c@example.com"]
+    );
+}
+
+#[test]
+fn test_thread_index() {
+    init_stderr_logging();
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let mut lists = vec![];
+    for id in ["foo-chat", "bar-chat"] {
+        lists.push(
+            db.create_list(MailingList {
+                pk: 0,
+                name: id.into(),
+                id: id.into(),
+                address: format!("{id}@example.com"),
+                description: None,
+                topics: vec![],
+                archive_url: None,
+            })
+            .unwrap(),
+        );
+    }
+    let insert = |list: i64, headers: &str, timestamp: u64| {
+        let bytes = format!(
+            "From: <user@example.com>
+To: <foo-chat@example.com>
+Subject: thread
+Date: {}
+{headers}
+
+Hello
+",
+            melib::utils::datetime::timestamp_to_string(
+                timestamp,
+                Some(melib::utils::datetime::formats::RFC822_DATE),
+                true,
+            )
+        );
+        let envelope = melib::Envelope::from_bytes(bytes.as_bytes(), None).unwrap();
+        db.insert_post(list, bytes.as_bytes(), &envelope).unwrap();
+    };
+    // Without an `In-Reply-To` header, the parent is the last reference.
+    let envelope = melib::Envelope::from_bytes(
+        b"Message-ID: <b@example.com>\nReferences: <root@example.com> <a@example.com>\n\nHello\n",
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        mailpot::threads::in_reply_to(&envelope).as_deref(),
+        Some("a@example.com")
+    );
+    let (foo, bar) = (lists[0].pk(), lists[1].pk());
+    insert(foo, "Message-ID: <root@example.com>", 1_600_000_000);
+    // Lower-case and folded header.
+    insert(
+        foo,
+        "Message-ID: <a@example.com>\nin-reply-to:\n <root@example.com>",
+        1_600_000_100,
+    );
+    // Only a References header.
+    insert(
+        foo,
+        "Message-ID: <b@example.com>\nReferences: <root@example.com>\n <a@example.com>",
+        1_600_000_200,
+    );
+    // Reply whose parent is missing from the list.
+    insert(
+        foo,
+        "Message-ID: <c@example.com>\nIn-Reply-To: <missing@example.com>\nReferences: \
+         <root@example.com> <missing@example.com>",
+        1_600_000_300,
+    );
+    insert(foo, "Message-ID: <other@example.com>", 1_600_000_050);
+    // Posts of other lists are not part of the thread.
+    insert(
+        bar,
+        "Message-ID: <d@example.com>\nIn-Reply-To: <root@example.com>",
+        1_600_000_400,
+    );
+
+    let thread_of = |db: &Connection| {
+        db.list_thread(foo, "<root@example.com>")
+            .unwrap()
+            .into_iter()
+            .map(|(depth, post)| (depth, post.message_id.to_string()))
+            .collect::<Vec<_>>()
+    };
+    let expected = vec![
+        (1, "a@example.com".to_string()),
+        (1, "c@example.com".to_string()),
+        (2, "b@example.com".to_string()),
+    ];
+    assert_eq!(thread_of(&db), expected);
+    assert_eq!(
+        db.list_thread(foo, "a@example.com")
+            .unwrap()
+            .into_iter()
+            .map(|(depth, post)| (depth, post.message_id.to_string()))
+            .collect::<Vec<_>>(),
+        vec![(1, "b@example.com".to_string())]
+    );
+
+    let roots = db
+        .list_thread_roots(foo)
+        .unwrap()
+        .into_iter()
+        .map(|(post, length, last_active)| (post.message_id.to_string(), length, last_active))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        roots,
+        vec![
+            ("root@example.com".to_string(), 4, 1_600_000_300),
+            ("other@example.com".to_string(), 1, 1_600_000_050),
+        ]
+    );
+
+    // Existing posts are indexed by the migration.
+    let version = db.schema_version().unwrap();
+    db.migrate(version, 21).unwrap();
+    db.migrate(21, version).unwrap();
+    assert_eq!(thread_of(&db), expected);
+}
+
+#[test]
+fn test_thread_mute_nested_reply() {
+    init_stderr_logging();
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let list = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.set_list_post_policy(PostPolicy {
+        pk: -1,
+        list: list.pk(),
+        announce_only: false,
+        subscription_only: true,
+        approval_needed: false,
+        open: false,
+        custom: false,
+    })
+    .unwrap();
+    let mut subscriptions = vec![];
+    for member in ["a", "b", "c"] {
+        subscriptions.push(
+            db.add_subscription(
+                list.pk(),
+                ListSubscription {
+                    pk: -1,
+                    list: list.pk(),
+                    address: format!("{member}@example.com"),
+                    name: None,
+                    account: None,
+                    digest: false,
+                    enabled: true,
+                    verified: true,
+                    hide_address: false,
+                    receive_duplicates: true,
+                    receive_own_posts: false,
+                    receive_confirmation: false,
+                },
+            )
+            .unwrap(),
+        );
+    }
+    let db = db.untrusted();
+
+    // Replies only carry an `In-Reply-To` header, so their thread root is only
+    // known from the stored parent.
+    let send = |from: &str, to: &str, subject: &str, msg_id: &str, reply_to: Option<&str>| {
+        let bytes = format!(
+            "From: <{from}@example.com>
+To: <{to}@example.com>
+Subject: {subject}
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <{msg_id}@example.com>
+{}
+Hello
+",
+            reply_to.map_or_else(String::new, |r| format!("In-Reply-To: <{r}@example.com>\n"))
+        )
+        .into_bytes();
+        let envelope = melib::Envelope::from_bytes(&bytes, None).unwrap();
+        db.post(&envelope, &bytes, false).unwrap();
+        let mut ret = db
+            .queue(Queue::Out)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.to_addresses.clone())
+            .collect::<Vec<_>>();
+        ret.sort();
+        db.delete_from_queue(Queue::Out, vec![]).unwrap();
+        ret
+    };
+
+    assert_eq!(
+        send("a", "foo-chat", "Thread", "root", None),
+        vec!["b@example.com", "c@example.com"]
+    );
+    assert_eq!(
+        send("b", "foo-chat", "Re: Thread", "reply", Some("root")),
+        vec!["a@example.com", "c@example.com"]
+    );
+    // Muting by replying to the nested reply mutes the whole thread.
+    send("c", "foo-chat+request", "mute", "mute", Some("reply"));
+    assert!(
+        db.thread_preference(subscriptions[2].pk(), "root@example.com")
+            .unwrap()
+            .unwrap()
+            .muted
+    );
+    assert_eq!(
+        send("a", "foo-chat", "Re: Thread", "nested", Some("reply")),
+        vec!["b@example.com"]
+    );
+    assert_eq!(
+        db.list_thread(list.pk(), "root@example.com")
+            .unwrap()
+            .into_iter()
+            .map(|(depth, post)| (depth, post.message_id.to_string()))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, "reply@example.com".to_string()),
+            (2, "nested@example.com".to_string()),
+        ]
+    );
+}
@@ -0,0 +1,209 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{
+    melib,
+    models::{changesets::MailingListChangeset, *},
+    queue::Queue,
+    topics::post_topics,
+    Configuration, Connection,
+};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_topic_subscriptions() {
+    init_stderr_logging();
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let list = db
+        .create_list(MailingList {
+            pk: 0,
+            name: "foobar chat".into(),
+            id: "foo-chat".into(),
+            address: "foo-chat@example.com".into(),
+            description: None,
+            topics: vec![],
+            archive_url: None,
+        })
+        .unwrap();
+    db.update_list(MailingListChangeset {
+        pk: list.pk(),
+        topics: Some(vec!["rust".into(), "python".into()]),
+        ..Default::default()
+    })
+    .unwrap();
+    let list = db.list(list.pk()).unwrap().unwrap();
+    assert_eq!(list.topics, vec!["python", "rust"]);
+    db.set_list_post_policy(PostPolicy {
+        pk: -1,
+        list: list.pk(),
+        announce_only: false,
+        subscription_only: false,
+        approval_needed: false,
+        open: true,
+        custom: false,
+    })
+    .unwrap();
+
+    let mut subscriptions = vec![];
+    for member in ["rust", "other", "all", "python"] {
+        subscriptions.push(
+            db.add_subscription(
+                list.pk(),
+                ListSubscription {
+                    pk: -1,
+                    list: list.pk(),
+                    address: format!("{member}@example.com"),
+                    name: None,
+                    account: None,
+                    digest: false,
+                    enabled: true,
+                    verified: true,
+                    hide_address: false,
+                    receive_duplicates: true,
+                    receive_own_posts: false,
+                    receive_confirmation: false,
+                },
+            )
+            .unwrap(),
+        );
+    }
+    db.set_subscription_topics(SubscriptionTopics {
+        pk: -1,
+        subscription: subscriptions[0].pk(),
+        topics: vec!["rust".into()],
+        everything_else: false,
+    })
+    .unwrap();
+    db.set_subscription_topics(SubscriptionTopics {
+        pk: -1,
+        subscription: subscriptions[1].pk(),
+        topics: vec![],
+        everything_else: true,
+    })
+    .unwrap();
+    assert_eq!(db.list_subscription_topics(list.pk()).unwrap().len(), 2);
+
+    let post = |headers: &str| -> Vec<u8> {
+        format!(
+            "From: Sender <sender@example.com>
+To: <foo-chat@example.com>
+{headers}
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <abcdefgh@sator.example.com>
+
+Hello
+"
+        )
+        .into_bytes()
+    };
+    let recipients_of = |headers: &str| -> (Vec<String>, Vec<String>) {
+        let bytes = post(headers);
+        let envelope = melib::Envelope::from_bytes(&bytes, None).unwrap();
+        let topics = post_topics(&list, &envelope);
+        let mut trace = db.post_dry_run(&envelope, &bytes).unwrap();
+        let mut recipients = trace.lists.remove(0).recipients;
+        recipients.sort();
+        (topics, recipients)
+    };
+
+    assert_eq!(
+        recipients_of("Subject: [Rust] [RFC] Hello"),
+        (
+            vec!["rust".to_string()],
+            vec![
+                "all@example.com".to_string(),
+                "python@example.com".to_string(),
+                "rust@example.com".to_string(),
+            ]
+        )
+    );
+    assert_eq!(
+        recipients_of("Subject: Hello\nKeywords: python, rust"),
+        (
+            vec!["python".to_string(), "rust".to_string()],
+            vec![
+                "all@example.com".to_string(),
+                "python@example.com".to_string(),
+                "rust@example.com".to_string(),
+            ]
+        )
+    );
+    assert_eq!(
+        recipients_of("Subject: [foo-chat] Hello"),
+        (
+            vec![],
+            vec![
+                "all@example.com".to_string(),
+                "other@example.com".to_string(),
+                "python@example.com".to_string(),
+            ]
+        )
+    );
+
+    // Topics can be chosen by e-mail.
+    let db = db.untrusted();
+    let request = |subject: &str| {
+        let bytes = format!(
+            "From: <python@example.com>
+To: <foo-chat+request@example.com>
+Subject: {subject}
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <request@example.com>
+
+"
+        )
+        .into_bytes();
+        let envelope = melib::Envelope::from_bytes(&bytes, None).unwrap();
+        db.post(&envelope, &bytes, false).unwrap();
+        let out_queue = db.queue(Queue::Out).unwrap();
+        assert_eq!(out_queue.len(), 1);
+        db.delete_from_queue(Queue::Out, vec![]).unwrap();
+        db.subscription_topics(subscriptions[3].pk())
+            .unwrap()
+            .map(DbVal::into_inner)
+    };
+    assert_eq!(
+        request("topics python"),
+        Some(SubscriptionTopics {
+            pk: 3,
+            subscription: subscriptions[3].pk(),
+            topics: vec!["python".into()],
+            everything_else: false,
+        })
+    );
+    assert_eq!(
+        request("topics python, Other"),
+        Some(SubscriptionTopics {
+            pk: 3,
+            subscription: subscriptions[3].pk(),
+            topics: vec!["python".into()],
+            everything_else: true,
+        })
+    );
+    // Unknown topics are refused and the previous choice is kept.
+    assert!(request("topics golang").is_some());
+    assert_eq!(request("topics all"), None);
+    assert_eq!(request("topics python rust other"), None);
+}
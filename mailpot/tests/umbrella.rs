@@ -0,0 +1,153 @@
+/*
+ * This file is part of mailpot
+ *
+ * Copyright 2020 - Manos Pitsidianakis
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of the
+ * License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use mailpot::{mail::PostAction, melib, models::*, Configuration, Connection};
+use mailpot_tests::init_stderr_logging;
+use tempfile::TempDir;
+
+#[test]
+fn test_umbrella_lists() {
+    init_stderr_logging();
+    let tmp_dir = TempDir::new().unwrap();
+
+    let db_path = tmp_dir.path().join("mpot.db");
+    let config = Configuration::new(db_path);
+
+    let db = Connection::open_or_create_db(config).unwrap().trusted();
+    let mut lists = vec![];
+    for (id, members) in [
+        ("all", &["a"][..]),
+        ("rust", &["b", "c"][..]),
+        ("python", &["c", "d"][..]),
+        ("interns", &["e"][..]),
+    ] {
+        let list = db
+            .create_list(MailingList {
+                pk: 0,
+                name: id.into(),
+                id: id.into(),
+                address: format!("{id}@example.com"),
+                description: None,
+                topics: vec![],
+                archive_url: None,
+            })
+            .unwrap();
+        db.set_list_post_policy(PostPolicy {
+            pk: -1,
+            list: list.pk(),
+            announce_only: false,
+            subscription_only: true,
+            approval_needed: false,
+            open: false,
+            custom: false,
+        })
+        .unwrap();
+        for member in members {
+            db.add_subscription(
+                list.pk(),
+                ListSubscription {
+                    pk: -1,
+                    list: list.pk(),
+                    address: format!("{member}@example.com"),
+                    name: None,
+                    account: None,
+                    digest: false,
+                    enabled: true,
+                    verified: true,
+                    hide_address: false,
+                    receive_duplicates: true,
+                    receive_own_posts: true,
+                    receive_confirmation: false,
+                },
+            )
+            .unwrap();
+        }
+        lists.push(list);
+    }
+    let (all, rust, python, interns) = (&lists[0], &lists[1], &lists[2], &lists[3]);
+
+    let include = |parent: i64, child: i64, inherit_post_rights: bool| {
+        db.add_list_inclusion(ListInclusion {
+            pk: -1,
+            parent,
+            child,
+            inherit_post_rights,
+        })
+        .is_ok()
+    };
+    assert!(include(all.pk(), rust.pk(), true));
+    assert!(include(all.pk(), python.pk(), false));
+    assert!(include(python.pk(), interns.pk(), false));
+    assert_eq!(db.list_inclusions(all.pk()).unwrap().len(), 2);
+
+    // Inclusions can't form cycles.
+    assert!(!include(all.pk(), all.pk(), false));
+    assert!(!include(interns.pk(), all.pk(), false));
+    assert!(!include(all.pk(), rust.pk(), false));
+
+    let post = |from: &str, msg_id: &str| -> (Vec<String>, PostAction) {
+        let bytes = format!(
+            "From: <{from}@example.com>
+To: <all@example.com>
+Subject: Umbrella post
+Date: Thu, 29 Oct 2020 13:58:16 +0000
+Message-ID: <{msg_id}@example.com>
+
+Hello
+"
+        )
+        .into_bytes();
+        let envelope = melib::Envelope::from_bytes(&bytes, None).unwrap();
+        let mut trace = db.post_dry_run(&envelope, &bytes).unwrap();
+        assert_eq!(trace.lists.len(), 1);
+        let list_trace = trace.lists.remove(0);
+        let mut recipients = list_trace.recipients;
+        recipients.sort();
+        (recipients, list_trace.action)
+    };
+
+    // Subscribers of included lists receive posts once, recursively.
+    let (recipients, action) = post("a", "direct");
+    assert_eq!(action, PostAction::Accept);
+    assert_eq!(
+        recipients,
+        vec![
+            "a@example.com",
+            "b@example.com",
+            "c@example.com",
+            "d@example.com",
+            "e@example.com"
+        ]
+    );
+
+    // Subscribers of `rust` inherit post rights, subscribers of `python` don't.
+    assert_eq!(post("b", "inherited").1, PostAction::Accept);
+    assert!(matches!(
+        post("d", "not-inherited").1,
+        PostAction::Reject { .. }
+    ));
+
+    db.remove_list_inclusion(all.pk(), python.pk()).unwrap();
+    db.remove_list_inclusion(all.pk(), python.pk()).unwrap_err();
+    let (recipients, _) = post("a", "removed");
+    assert_eq!(
+        recipients,
+        vec!["a@example.com", "b@example.com", "c@example.com"]
+    );
+}